@@ -0,0 +1,85 @@
+//! Sending messages to the mock broker: bodies under 4K share the memory of their messages,
+//! larger ones are compressed into pooled buffers and batches are sent as is.
+//!
+//! Run with `cargo bench --features test-util`
+
+use std::time::Instant;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rocketmq::test_util::MockBroker;
+use rocketmq::{Message, Producer, ProducerOptions};
+use tokio::runtime::{Builder, Runtime};
+
+const TOPIC: &str = "TopicBench";
+
+/// Started producer of a new mock broker, so messages stored by previous samples are freed
+async fn producer() -> (MockBroker, Producer) {
+    let broker = MockBroker::start().await.unwrap();
+    broker.create_topic(TOPIC, 4);
+    let mut options = ProducerOptions::default();
+    options.set_name_server(vec![broker.addr().to_string()]);
+    let producer = Producer::with_options(options).unwrap();
+    producer.start();
+    (broker, producer)
+}
+
+fn message(size: usize) -> Message {
+    Message::builder()
+        .topic(TOPIC)
+        .body(vec![b'x'; size])
+        .build()
+        .unwrap()
+}
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+fn bench_send(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("send");
+    group.sample_size(10);
+    for &size in &[1024, 64 * 1024, 1024 * 1024] {
+        let msg = message(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &msg, |b, msg| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let (_broker, producer) = producer().await;
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        producer.send(msg.clone()).await.unwrap();
+                    }
+                    start.elapsed()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_send_batch(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("send_batch");
+    group.sample_size(10);
+    for &size in &[1024, 64 * 1024] {
+        let msgs: Vec<Message> = (0..16).map(|_| message(size)).collect();
+        group.throughput(Throughput::Bytes((size * msgs.len()) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &msgs, |b, msgs| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let (_broker, producer) = producer().await;
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        producer.send_batch(msgs).await.unwrap();
+                    }
+                    start.elapsed()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_send, bench_send_batch);
+criterion_main!(benches);
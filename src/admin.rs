@@ -0,0 +1,1041 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::client::model::{
+    decode_queue_table, ResetOffsetBody, SubscriptionGroupConfig, TopicConfig,
+};
+use crate::client::{Client, ClientOptions};
+use crate::consumer::{ConsumeType, MessageModel};
+use crate::message::{MessageExt, MessageQueue};
+use crate::protocol::{
+    request::{
+        GetConsumeStatsRequestHeader, GetConsumerConnectionListRequestHeader,
+        GetTopicStatsInfoRequestHeader, QueryMessageRequestHeader, ResetOffsetRequestHeader,
+    },
+    RemotingCommand, RequestCode, ResponseCode,
+};
+use crate::resolver::{HttpResolver, PassthroughResolver, Resolver, SrvResolver};
+use crate::route::{TopicRouteData, MASTER_ID};
+use crate::topic;
+use crate::Error;
+
+// brokers look messages up in their index files, slower than other requests
+const QUERY_MESSAGE_TIMEOUT: Duration = Duration::from_secs(9);
+
+/// RocketMQ admin options
+#[derive(Debug, Clone, Default)]
+pub struct AdminOptions {
+    client_options: ClientOptions,
+    resolver: Resolver,
+}
+
+impl AdminOptions {
+    pub fn new() -> Self {
+        AdminOptions::default()
+    }
+
+    pub fn with_client_options(client_options: ClientOptions) -> Self {
+        Self {
+            client_options,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_resolver(&mut self, resolver: Resolver) -> &mut Self {
+        self.resolver = resolver;
+        self
+    }
+
+    pub fn set_name_server(&mut self, addrs: Vec<String>) -> &mut Self {
+        self.resolver = Resolver::PassthroughHttp(PassthroughResolver::new(
+            addrs,
+            HttpResolver::new("DEFAULT".to_string()),
+        ));
+        self
+    }
+
+    pub fn set_name_server_domain(&mut self, url: &str) -> &mut Self {
+        self.resolver = Resolver::Http(HttpResolver::with_domain(
+            "DEFAULT".to_string(),
+            url.to_string(),
+        ));
+        self
+    }
+
+    /// Resolve name servers from DNS SRV records of `name`, e.g. `_rocketmq._tcp.cluster.internal`
+    pub fn set_name_server_srv(&mut self, name: &str) -> &mut Self {
+        self.resolver = Resolver::Srv(SrvResolver::new(name.to_string()));
+        self
+    }
+}
+
+/// Connection of a client to a broker
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Connection {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "clientAddr")]
+    pub client_addr: String,
+    pub language: String,
+    pub version: i32,
+}
+
+/// Subscription of a consumer group to a topic
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Subscription {
+    pub topic: String,
+    /// Tags like `TagA || TagB` or a SQL92 expression
+    #[serde(rename = "subString")]
+    pub expression: String,
+    #[serde(default, rename = "expressionType")]
+    pub expression_type: String,
+    #[serde(default, rename = "tagsSet")]
+    pub tags: Vec<String>,
+    #[serde(default, rename = "subVersion")]
+    pub sub_version: i64,
+}
+
+/// Online consumers of a group, response body of `GET_CONSUMER_CONNECTION_LIST`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConsumerConnection {
+    #[serde(default, rename = "connectionSet")]
+    pub connections: Vec<Connection>,
+    // topic -> subscription
+    #[serde(default, rename = "subscriptionTable")]
+    pub subscriptions: HashMap<String, Subscription>,
+    #[serde(rename = "consumeType")]
+    pub consume_type: ConsumeType,
+    #[serde(rename = "messageModel")]
+    pub message_model: MessageModel,
+}
+
+impl ConsumerConnection {
+    /// IDs of the connected clients
+    pub fn client_ids(&self) -> Vec<&str> {
+        self.connections
+            .iter()
+            .map(|conn| conn.client_id.as_str())
+            .collect()
+    }
+}
+
+/// Progress of a consumer group on a queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct QueueProgress {
+    /// Max offset of the queue
+    #[serde(default, rename = "brokerOffset")]
+    pub broker_offset: i64,
+    #[serde(default, rename = "consumerOffset")]
+    pub consumer_offset: i64,
+    /// Store timestamp of the last consumed message in milliseconds, 0 if none was
+    #[serde(default, rename = "lastTimestamp")]
+    pub last_timestamp: i64,
+}
+
+impl QueueProgress {
+    /// Messages not consumed yet
+    pub fn lag(&self) -> i64 {
+        (self.broker_offset - self.consumer_offset).max(0)
+    }
+}
+
+/// Progress of a consumer group on its queues, the data behind `mqadmin consumerProgress`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumeStats {
+    pub offsets: BTreeMap<MessageQueue, QueueProgress>,
+    /// Messages consumed per second by the group on all the brokers
+    pub consume_tps: f64,
+}
+
+impl ConsumeStats {
+    pub fn total_lag(&self) -> i64 {
+        self.offsets.values().map(QueueProgress::lag).sum()
+    }
+
+    /// Stats of a broker, encoded by fastjson with message queues as map keys
+    fn decode(body: &[u8]) -> Result<Self, Error> {
+        let s = String::from_utf8(body.to_vec())?;
+        let offsets = decode_queue_table(&s, "offsetTable")?.into_iter().collect();
+        let consume_tps = s
+            .find("\"consumeTps\"")
+            .and_then(|pos| {
+                let rest = s[pos + "\"consumeTps\"".len()..]
+                    .trim_start()
+                    .strip_prefix(':')?;
+                serde_json::Deserializer::from_str(rest)
+                    .into_iter::<f64>()
+                    .next()?
+                    .ok()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            offsets,
+            consume_tps,
+        })
+    }
+}
+
+/// Offsets of a queue of a topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct TopicOffset {
+    #[serde(default, rename = "minOffset")]
+    pub min_offset: i64,
+    #[serde(default, rename = "maxOffset")]
+    pub max_offset: i64,
+    /// Store timestamp of the last message in milliseconds, 0 if the queue is empty
+    #[serde(default, rename = "lastUpdateTimestamp")]
+    pub last_update_timestamp: i64,
+}
+
+impl TopicOffset {
+    /// Messages kept in the queue
+    pub fn message_count(&self) -> i64 {
+        (self.max_offset - self.min_offset).max(0)
+    }
+}
+
+/// Runtime stats of a broker, response body of `GET_BROKER_RUNTIME_INFO`. TPS are those of
+/// the last 10 seconds, stats missing on the broker are zero
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrokerRuntimeInfo {
+    pub version_desc: String,
+    pub version: i32,
+    pub put_tps: f64,
+    pub get_found_tps: f64,
+    pub get_miss_tps: f64,
+    pub get_transferred_tps: f64,
+    pub get_total_tps: f64,
+    pub msg_put_total_today_now: i64,
+    pub msg_get_total_today_now: i64,
+    /// Used space of the disk of the commit log, between 0 and 1
+    pub commit_log_disk_ratio: f64,
+    pub consume_queue_disk_ratio: f64,
+    pub commit_log_min_offset: i64,
+    pub commit_log_max_offset: i64,
+    /// Bytes of the commit log not dispatched to consume queues yet
+    pub dispatch_behind_bytes: i64,
+    pub send_thread_pool_queue_size: i64,
+    pub pull_thread_pool_queue_size: i64,
+    /// How long the page cache was locked by the message being put, 0 if none is
+    pub page_cache_lock_time_millis: i64,
+    pub earliest_message_timestamp: i64,
+    /// All the stats by their names on the broker, including those above
+    pub table: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct KvTable {
+    #[serde(default)]
+    table: HashMap<String, String>,
+}
+
+impl BrokerRuntimeInfo {
+    fn from_table(table: HashMap<String, String>) -> Self {
+        // TPS are of the last 10 seconds, 1 and 10 minutes separated by spaces
+        fn stat<T: FromStr + Default>(table: &HashMap<String, String>, key: &str) -> T {
+            table
+                .get(key)
+                .and_then(|value| value.split_whitespace().next())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default()
+        }
+        Self {
+            version_desc: table.get("brokerVersionDesc").cloned().unwrap_or_default(),
+            version: stat(&table, "brokerVersion"),
+            put_tps: stat(&table, "putTps"),
+            get_found_tps: stat(&table, "getFoundTps"),
+            get_miss_tps: stat(&table, "getMissTps"),
+            // the misspelling comes from the broker
+            get_transferred_tps: stat(&table, "getTransferedTps"),
+            get_total_tps: stat(&table, "getTotalTps"),
+            msg_put_total_today_now: stat(&table, "msgPutTotalTodayNow"),
+            msg_get_total_today_now: stat(&table, "msgGetTotalTodayNow"),
+            commit_log_disk_ratio: stat(&table, "commitLogDiskRatio"),
+            consume_queue_disk_ratio: stat(&table, "consumeQueueDiskRatio"),
+            commit_log_min_offset: stat(&table, "commitLogMinOffset"),
+            commit_log_max_offset: stat(&table, "commitLogMaxOffset"),
+            dispatch_behind_bytes: stat(&table, "dispatchBehindBytes"),
+            send_thread_pool_queue_size: stat(&table, "sendThreadPoolQueueSize"),
+            pull_thread_pool_queue_size: stat(&table, "pullThreadPoolQueueSize"),
+            page_cache_lock_time_millis: stat(&table, "pageCacheLockTimeMills"),
+            earliest_message_timestamp: stat(&table, "earliestMessageTimeStamp"),
+            table,
+        }
+    }
+}
+
+/// RocketMQ admin, manages topics of clusters like `mqadmin`, e.g. to provision topics or
+/// set them up in integration tests. Topics are wrapped in the namespace of the client
+#[derive(Debug)]
+pub struct Admin {
+    // shared with producers and consumers
+    client: Client<Resolver>,
+    // whether the shared client was released
+    released: AtomicBool,
+}
+
+impl Admin {
+    pub fn new() -> Result<Self, Error> {
+        Self::with_options(AdminOptions::default())
+    }
+
+    pub fn with_options(options: AdminOptions) -> Result<Self, Error> {
+        let client = Client::get_or_create(&options.client_options, &options.resolver)?;
+        Ok(Self {
+            client,
+            released: AtomicBool::new(false),
+        })
+    }
+
+    /// Shut down the admin, waits at most `timeout` for the client to shut down unless it's
+    /// shared with producers or consumers
+    pub async fn shutdown(&self, timeout: Duration) {
+        if !self.released.swap(true, Ordering::SeqCst) {
+            self.client.shutdown("", "", timeout).await;
+        }
+    }
+
+    /// Create `config` on every master broker of `cluster` with `UPDATE_AND_CREATE_TOPIC`,
+    /// or update it on brokers having the topic already
+    pub async fn create_topic(&self, mut config: TopicConfig, cluster: &str) -> Result<(), Error> {
+        config.topic_name = self.client.wrap_namespace(&config.topic_name);
+        for addr in self.master_addrs(cluster).await? {
+            self.client.update_and_create_topic(&addr, &config).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete `topic` from every master broker of `cluster` with `DELETE_TOPIC_IN_BROKER`,
+    /// then from the name servers with `DELETE_TOPIC_IN_NAMESRV` so clients stop routing to it
+    pub async fn delete_topic(&self, topic: &str, cluster: &str) -> Result<(), Error> {
+        let topic = self.client.wrap_namespace(topic);
+        for addr in self.master_addrs(cluster).await? {
+            self.client.delete_topic_in_broker(&addr, &topic).await?;
+        }
+        self.client.name_server.delete_topic(&topic, cluster).await
+    }
+
+    /// Messages of `topic` with `key` stored between `begin` and `end`, timestamps in
+    /// milliseconds, from every broker of the topic. At most `max_num` messages ordered by
+    /// their store timestamps are returned, those of brokers failing the query are left out
+    /// unless all of them fail
+    pub async fn query_message(
+        &self,
+        topic: &str,
+        key: &str,
+        max_num: usize,
+        begin: i64,
+        end: i64,
+    ) -> Result<Vec<MessageExt>, Error> {
+        let topic = self.client.wrap_namespace(topic);
+        let route = self
+            .client
+            .name_server
+            .query_topic_route_info(&topic)
+            .await?;
+        let requests = route
+            .broker_datas
+            .iter()
+            .filter_map(|broker| broker.select_addr())
+            .map(|addr| {
+                let header = QueryMessageRequestHeader {
+                    topic: topic.clone(),
+                    key: key.to_string(),
+                    max_num: max_num.min(i32::MAX as usize) as i32,
+                    begin_timestamp: begin,
+                    end_timestamp: end,
+                    unique_key_query: false,
+                };
+                self.client
+                    .query_message(addr, header, QUERY_MESSAGE_TIMEOUT)
+            });
+        let mut msgs = Vec::new();
+        let mut last_err = None;
+        for res in futures::future::join_all(requests).await {
+            match res {
+                Ok(found) => msgs.extend(found),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        if let Some(err) = last_err {
+            if msgs.is_empty() {
+                return Err(err);
+            }
+        }
+        // the index is keyed by hashes, messages of other keys and topics may come along
+        msgs.retain(|msg| msg.topic() == topic && msg.keys().contains(&key));
+        msgs.sort_by_key(|msg| msg.store_timestamp());
+        msgs.truncate(max_num);
+        for msg in &mut msgs {
+            msg.message.topic = self.client.unwrap_namespace(&msg.message.topic);
+        }
+        Ok(msgs)
+    }
+
+    /// Consumers of `group` online, with their subscriptions, from a broker of its retry
+    /// topic with `GET_CONSUMER_CONNECTION_LIST`. Fails with the `CONSUMER_NOT_ONLINE` code
+    /// of the broker if none of them is
+    pub async fn examine_consumer_connections(
+        &self,
+        group: &str,
+    ) -> Result<ConsumerConnection, Error> {
+        let group = self.client.wrap_namespace(group);
+        let retry_topic = topic::retry_topic(&group);
+        let route = self
+            .client
+            .name_server
+            .query_topic_route_info(&retry_topic)
+            .await?;
+        let addr = route
+            .broker_datas
+            .iter()
+            .find_map(|broker| broker.select_addr())
+            .ok_or(Error::EmptyRouteData)?;
+        let header = GetConsumerConnectionListRequestHeader {
+            consumer_group: group,
+        };
+        let cmd = RemotingCommand::with_header(
+            RequestCode::GetConsumerConnectionList,
+            header,
+            Vec::new(),
+        );
+        let res = self.invoke_broker(addr, cmd).await?;
+        let mut conn: ConsumerConnection = serde_json::from_slice(res.body())?;
+        conn.subscriptions = conn
+            .subscriptions
+            .into_iter()
+            .map(|(topic, mut sub)| {
+                sub.topic = self.client.unwrap_namespace(&sub.topic);
+                (self.client.unwrap_namespace(&topic), sub)
+            })
+            .collect();
+        Ok(conn)
+    }
+
+    /// Reset offsets of `group` on `topic` to those of the first messages stored at or after
+    /// `timestamp` in milliseconds with `INVOKE_BROKER_TO_RESET_OFFSET`, to skip a backlog
+    /// or replay messages. Without `force` brokers only move offsets back. Returns the new
+    /// offsets, brokers tell online consumers to pull from them
+    pub async fn reset_offset(
+        &self,
+        topic: &str,
+        group: &str,
+        timestamp: i64,
+        force: bool,
+    ) -> Result<HashMap<MessageQueue, i64>, Error> {
+        let topic = self.client.wrap_namespace(topic);
+        let group = self.client.wrap_namespace(group);
+        let route = self
+            .client
+            .name_server
+            .query_topic_route_info(&topic)
+            .await?;
+        let mut offsets = HashMap::new();
+        for broker in &route.broker_datas {
+            let addr = match broker.broker_addrs.get(&MASTER_ID) {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let header = ResetOffsetRequestHeader {
+                topic: topic.clone(),
+                group: group.clone(),
+                timestamp,
+                is_force: force,
+            };
+            let cmd = RemotingCommand::with_header(
+                RequestCode::InvokeBrokerToResetOffset,
+                header,
+                Vec::new(),
+            );
+            let res = self.invoke_broker(addr, cmd).await?;
+            let body = ResetOffsetBody::decode(res.body())?;
+            offsets.extend(
+                body.offset_table
+                    .into_iter()
+                    .map(|(mq, offset)| (self.client.unwrap_queue(&mq), offset)),
+            );
+        }
+        Ok(offsets)
+    }
+
+    /// Progress of `group` on the queues of `topic`, or of every topic it consumes if `topic`
+    /// is empty, from the master brokers of its retry topic with `GET_CONSUME_STATS`
+    pub async fn consume_stats(&self, group: &str, topic: &str) -> Result<ConsumeStats, Error> {
+        let group = self.client.wrap_namespace(group);
+        let topic = self.client.wrap_namespace(topic);
+        let retry_topic = topic::retry_topic(&group);
+        let route = self
+            .client
+            .name_server
+            .query_topic_route_info(&retry_topic)
+            .await?;
+        let mut stats = ConsumeStats::default();
+        for broker in &route.broker_datas {
+            let addr = match broker.broker_addrs.get(&MASTER_ID) {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let header = GetConsumeStatsRequestHeader {
+                consumer_group: group.clone(),
+                topic: topic.clone(),
+            };
+            let cmd =
+                RemotingCommand::with_header(RequestCode::GetConsumeStats, header, Vec::new());
+            let res = self.invoke_broker(addr, cmd).await?;
+            let broker_stats = ConsumeStats::decode(res.body())?;
+            stats.consume_tps += broker_stats.consume_tps;
+            stats.offsets.extend(
+                broker_stats
+                    .offsets
+                    .into_iter()
+                    .map(|(mq, progress)| (self.client.unwrap_queue(&mq), progress)),
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Offsets of the queues of `topic` on its master brokers with `GET_TOPIC_STATS_INFO`
+    pub async fn topic_stats(
+        &self,
+        topic: &str,
+    ) -> Result<BTreeMap<MessageQueue, TopicOffset>, Error> {
+        let topic = self.client.wrap_namespace(topic);
+        let route = self
+            .client
+            .name_server
+            .query_topic_route_info(&topic)
+            .await?;
+        let mut stats = BTreeMap::new();
+        for broker in &route.broker_datas {
+            let addr = match broker.broker_addrs.get(&MASTER_ID) {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let header = GetTopicStatsInfoRequestHeader {
+                topic: topic.clone(),
+            };
+            let cmd =
+                RemotingCommand::with_header(RequestCode::GetTopicStatsInfo, header, Vec::new());
+            let res = self.invoke_broker(addr, cmd).await?;
+            // encoded by fastjson with message queues as map keys
+            let s = String::from_utf8(res.body().to_vec())?;
+            let offsets: HashMap<MessageQueue, TopicOffset> =
+                decode_queue_table(&s, "offsetTable")?;
+            stats.extend(
+                offsets
+                    .into_iter()
+                    .map(|(mq, offset)| (self.client.unwrap_queue(&mq), offset)),
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Route of `topic` from the name servers, its brokers and their queues
+    pub async fn topic_route(&self, topic: &str) -> Result<TopicRouteData, Error> {
+        let topic = self.client.wrap_namespace(topic);
+        self.client.name_server.query_topic_route_info(&topic).await
+    }
+
+    /// Topics of every cluster from the name servers, sorted. Those of the namespace of the
+    /// client are unwrapped, others are returned as is
+    pub async fn topic_list(&self) -> Result<Vec<String>, Error> {
+        let topics = self.client.name_server.topic_list().await?;
+        Ok(self.unwrap_topics(topics))
+    }
+
+    /// Topics of `cluster` from the name servers, like [`topic_list`](Self::topic_list)
+    pub async fn topic_list_by_cluster(&self, cluster: &str) -> Result<Vec<String>, Error> {
+        let topics = self.client.name_server.topics_by_cluster(cluster).await?;
+        Ok(self.unwrap_topics(topics))
+    }
+
+    fn unwrap_topics(&self, topics: Vec<String>) -> Vec<String> {
+        let mut topics: Vec<String> = topics
+            .iter()
+            .map(|topic| self.client.unwrap_namespace(topic))
+            .collect();
+        topics.sort();
+        topics
+    }
+
+    /// Runtime stats of the broker at `addr` with `GET_BROKER_RUNTIME_INFO`
+    pub async fn broker_runtime_info(&self, addr: &str) -> Result<BrokerRuntimeInfo, Error> {
+        let cmd = request(RequestCode::GetBrokerRuntimeInfo, Vec::new());
+        let res = self.invoke_broker(addr, cmd).await?;
+        let kv: KvTable = serde_json::from_slice(res.body())?;
+        Ok(BrokerRuntimeInfo::from_table(kv.table))
+    }
+
+    /// Update `key` of the config of the broker at `addr` to `value` with
+    /// `UPDATE_BROKER_CONFIG`, e.g. `slaveReadEnable`. Brokers persist the new value
+    pub async fn update_broker_config(
+        &self,
+        addr: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        let mut config = HashMap::new();
+        config.insert(key.to_string(), value.to_string());
+        let cmd = request(RequestCode::UpdateBrokerConfig, encode_properties(&config));
+        self.invoke_broker(addr, cmd).await?;
+        Ok(())
+    }
+
+    /// Config of the broker at `addr` with `GET_BROKER_CONFIG`
+    pub async fn get_broker_config(&self, addr: &str) -> Result<HashMap<String, String>, Error> {
+        let cmd = request(RequestCode::GetBrokerConfig, Vec::new());
+        let res = self.invoke_broker(addr, cmd).await?;
+        Ok(decode_properties(&String::from_utf8(res.body().to_vec())?))
+    }
+
+    /// Send `cmd` to the broker at `addr`, fails unless it succeeds
+    async fn invoke_broker(
+        &self,
+        addr: &str,
+        cmd: RemotingCommand,
+    ) -> Result<RemotingCommand, Error> {
+        let res = self.client.invoke(addr, cmd).await?;
+        if ResponseCode::try_from(res.code()) != Ok(ResponseCode::Success) {
+            return Err(res.into_broker_error(addr));
+        }
+        Ok(res)
+    }
+
+    /// Create `config` on every master broker of `cluster` with
+    /// `UPDATE_AND_CREATE_SUBSCRIPTIONGROUP`, or update it on brokers having the group
+    /// already, e.g. before consumers of the group are deployed
+    pub async fn create_subscription_group(
+        &self,
+        mut config: SubscriptionGroupConfig,
+        cluster: &str,
+    ) -> Result<(), Error> {
+        config.group_name = self.client.wrap_namespace(&config.group_name);
+        let body = serde_json::to_vec(&config)?;
+        for addr in self.master_addrs(cluster).await? {
+            let cmd = request(RequestCode::UpdateAndCreateSubscriptionGroup, body.clone());
+            self.invoke_broker(&addr, cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Addresses of master brokers of `cluster` from the name servers
+    async fn master_addrs(&self, cluster: &str) -> Result<Vec<String>, Error> {
+        let mut clusters = self.client.name_server.cluster_info().await?;
+        let brokers = clusters.remove(cluster).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cluster {} not found", cluster),
+            )
+        })?;
+        let mut addrs: Vec<String> = brokers
+            .values()
+            .filter_map(|broker| broker.broker_addrs.get(&MASTER_ID).cloned())
+            .collect();
+        if addrs.is_empty() {
+            return Err(Error::EmptyRouteData);
+        }
+        addrs.sort();
+        Ok(addrs)
+    }
+}
+
+/// Request of `code` without header
+fn request(code: RequestCode, body: Vec<u8>) -> RemotingCommand {
+    RemotingCommand::new(code.into(), 0, String::new(), HashMap::new(), body)
+}
+
+/// Encode `properties` like Java `Properties`, one `key=value` per line
+fn encode_properties(properties: &HashMap<String, String>) -> Vec<u8> {
+    let mut s = String::new();
+    for (key, value) in properties {
+        s.push_str(key);
+        s.push('=');
+        s.push_str(value);
+        s.push('\n');
+    }
+    s.into_bytes()
+}
+
+/// Decode `key=value` lines of Java `Properties`, comments and blank lines are skipped
+fn decode_properties(s: &str) -> HashMap<String, String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(['=', ':'])?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+impl Drop for Admin {
+    fn drop(&mut self) {
+        if !self.released.swap(true, Ordering::SeqCst) {
+            self.client.release("", "");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_util::codec::Framed;
+
+    use super::{decode_properties, Admin, AdminOptions};
+    use crate::client::model::{SubscriptionGroupConfig, TopicConfig};
+    use crate::client::ClientOptions;
+    use crate::consumer::{ConsumeType, MessageModel};
+    use crate::message::test::encode_message_ext;
+    use crate::message::MessageQueue;
+    use crate::protocol::{MqCodec, RemotingCommand, RequestCode, ResponseCode};
+
+    /// Name server of a cluster whose only master broker is the server itself, requests
+    /// other than cluster and route queries are forwarded and answered with `body`
+    async fn fake_cluster(body: Vec<u8>) -> (String, mpsc::UnboundedReceiver<RemotingCommand>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let cluster_info = format!(
+            r#"{{"brokerAddrTable":{{"broker-a":{{"brokerAddrs":{{0:"{0}",1:"127.0.0.1:1"}},"brokerName":"broker-a","cluster":"DefaultCluster"}}}},"clusterAddrTable":{{"DefaultCluster":["broker-a"]}}}}"#,
+            addr
+        );
+        let route = format!(
+            r#"{{"queueDatas":[],"brokerDatas":[{{"cluster":"DefaultCluster","brokerName":"broker-a","brokerAddrs":{{"0":"{}"}}}}],"filterServerTable":{{}}}}"#,
+            addr
+        );
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let (cluster_info, route, body) =
+                    (cluster_info.clone(), route.clone(), body.clone());
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut framed = Framed::new(socket, MqCodec::default());
+                    while let Some(Ok(req)) = framed.next().await {
+                        let body = if req.code() == RequestCode::GetBrokerClusterInfo {
+                            cluster_info.clone().into_bytes()
+                        } else if req.code() == RequestCode::GetRouteInfoByTopic {
+                            route.clone().into_bytes()
+                        } else {
+                            let _ = tx.send(req.clone());
+                            body.clone()
+                        };
+                        let mut res =
+                            RemotingCommand::response(ResponseCode::Success, String::new(), body);
+                        res.set_opaque(req.opaque());
+                        framed.send(res).await.unwrap();
+                    }
+                });
+            }
+        });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_create_topic() {
+        let (addr, mut requests) = fake_cluster(Vec::new()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_create_topic")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let mut config = TopicConfig::new("TopicA");
+        config.set_read_queue_nums(8).set_write_queue_nums(4);
+        admin
+            .create_topic(config.clone(), "DefaultCluster")
+            .await
+            .unwrap();
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::UpdateAndCreateTopic);
+        assert_eq!("ns%TopicA", req.ext_fields()["topic"]);
+        assert_eq!("8", req.ext_fields()["readQueueNums"]);
+        assert_eq!("4", req.ext_fields()["writeQueueNums"]);
+        // only the master broker is asked
+        assert!(requests.try_recv().is_err());
+
+        assert!(admin.create_topic(config, "OtherCluster").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_topic() {
+        let (addr, mut requests) = fake_cluster(Vec::new()).await;
+        let mut client_options = ClientOptions::default();
+        client_options.set_instance_name("test_admin_delete_topic");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        admin
+            .delete_topic("TopicA", "DefaultCluster")
+            .await
+            .unwrap();
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::DeleteTopicInBroker);
+        assert_eq!("TopicA", req.ext_fields()["topic"]);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::DeleteTopicInNameServer);
+        assert_eq!("TopicA", req.ext_fields()["topic"]);
+        assert_eq!("DefaultCluster", req.ext_fields()["clusterName"]);
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_message() {
+        let mut body = Vec::new();
+        for (topic, keys, store_timestamp) in &[
+            ("ns%TopicA", "k1", 200),
+            ("ns%TopicA", "k0 k1", 100),
+            ("ns%TopicA", "k2", 150),
+            ("ns%TopicB", "k1", 50),
+        ] {
+            let properties = format!("KEYS\u{1}{}\u{2}", keys);
+            body.extend(encode_message_ext(
+                topic,
+                &properties,
+                *store_timestamp,
+                b"hello",
+            ));
+        }
+        let (addr, mut requests) = fake_cluster(body).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_query_message")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let msgs = admin
+            .query_message("TopicA", "k1", 32, 0, 1000)
+            .await
+            .unwrap();
+        // messages of other keys and topics sharing hashes are left out
+        assert_eq!(
+            vec![100, 200],
+            msgs.iter()
+                .map(|msg| msg.store_timestamp())
+                .collect::<Vec<_>>()
+        );
+        assert!(msgs.iter().all(|msg| msg.topic() == "TopicA"));
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::QueryMessage);
+        assert_eq!("ns%TopicA", req.ext_fields()["topic"]);
+        assert_eq!("k1", req.ext_fields()["key"]);
+        assert_eq!("32", req.ext_fields()["maxNum"]);
+        assert_eq!("1000", req.ext_fields()["endTimestamp"]);
+        assert_eq!("false", req.ext_fields()["_UNIQUE_KEY_QUERY"]);
+
+        let msgs = admin
+            .query_message("TopicA", "k1", 1, 0, 1000)
+            .await
+            .unwrap();
+        assert_eq!(1, msgs.len());
+        assert_eq!(100, msgs[0].store_timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_examine_consumer_connections() {
+        let body = br#"{"connectionSet":[{"clientAddr":"10.0.0.1:50212","clientId":"10.0.0.1@1","language":"JAVA","version":413},{"clientAddr":"10.0.0.2:50213","clientId":"10.0.0.2@1","language":"RUST","version":413}],"consumeFromWhere":"CONSUME_FROM_LAST_OFFSET","consumeType":"CONSUME_PASSIVELY","messageModel":"CLUSTERING","subscriptionTable":{"ns%TopicA":{"classFilterMode":false,"codeSet":[2598919],"expressionType":"TAG","subString":"TagA","subVersion":1618922213000,"tagsSet":["TagA"],"topic":"ns%TopicA"}}}"#;
+        let (addr, mut requests) = fake_cluster(body.to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_examine_consumer_connections")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let conn = admin.examine_consumer_connections("GroupA").await.unwrap();
+        assert_eq!(vec!["10.0.0.1@1", "10.0.0.2@1"], conn.client_ids());
+        assert_eq!("RUST", conn.connections[1].language);
+        assert_eq!(ConsumeType::Passively, conn.consume_type);
+        assert_eq!(MessageModel::Clustering, conn.message_model);
+        let sub = &conn.subscriptions["TopicA"];
+        assert_eq!("TopicA", sub.topic);
+        assert_eq!("TagA", sub.expression);
+        assert_eq!(vec!["TagA"], sub.tags);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::GetConsumerConnectionList);
+        assert_eq!("ns%GroupA", req.ext_fields()["consumerGroup"]);
+    }
+
+    #[tokio::test]
+    async fn test_reset_offset() {
+        let body = br#"{"offsetTable":{{"brokerName":"broker-a","queueId":0,"topic":"ns%TopicA"}:100,{"brokerName":"broker-a","queueId":1,"topic":"ns%TopicA"}:200}}"#;
+        let (addr, mut requests) = fake_cluster(body.to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_reset_offset")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let offsets = admin
+            .reset_offset("TopicA", "GroupA", 1618922213000, true)
+            .await
+            .unwrap();
+        let mq = MessageQueue {
+            topic: "TopicA".to_string(),
+            broker_name: "broker-a".to_string(),
+            queue_id: 1,
+        };
+        assert_eq!(2, offsets.len());
+        assert_eq!(Some(&200), offsets.get(&mq));
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::InvokeBrokerToResetOffset);
+        assert_eq!("ns%TopicA", req.ext_fields()["topic"]);
+        assert_eq!("ns%GroupA", req.ext_fields()["group"]);
+        assert_eq!("1618922213000", req.ext_fields()["timestamp"]);
+        assert_eq!("true", req.ext_fields()["isForce"]);
+    }
+
+    #[tokio::test]
+    async fn test_broker_runtime_info() {
+        let body = br#"{"table":{"brokerVersionDesc":"V4_9_4","brokerVersion":"413","putTps":"12.5 10.0 8.25","getTransferedTps":"3.0 2.0 1.0","commitLogDiskRatio":"0.35","commitLogMaxOffset":"1048576","pageCacheLockTimeMills":"0","bootTimestamp":"1618922213000"}}"#;
+        let (addr, mut requests) = fake_cluster(body.to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options.set_instance_name("test_admin_broker_runtime_info");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr.clone()]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let info = admin.broker_runtime_info(&addr).await.unwrap();
+        assert_eq!("V4_9_4", info.version_desc);
+        assert_eq!(413, info.version);
+        assert_eq!(12.5, info.put_tps);
+        assert_eq!(3.0, info.get_transferred_tps);
+        assert_eq!(0.0, info.get_miss_tps);
+        assert_eq!(0.35, info.commit_log_disk_ratio);
+        assert_eq!(1048576, info.commit_log_max_offset);
+        assert_eq!("1618922213000", info.table["bootTimestamp"]);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::GetBrokerRuntimeInfo);
+    }
+
+    #[tokio::test]
+    async fn test_consume_stats() {
+        let body = br#"{"consumeTps":12.5,"offsetTable":{{"brokerName":"broker-a","queueId":0,"topic":"ns%TopicA"}:{"brokerOffset":120,"consumerOffset":100,"lastTimestamp":1618922213000},{"brokerName":"broker-a","queueId":1,"topic":"ns%TopicA"}:{"brokerOffset":80,"consumerOffset":80,"lastTimestamp":0}}}"#;
+        let (addr, mut requests) = fake_cluster(body.to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_consume_stats")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let stats = admin.consume_stats("GroupA", "TopicA").await.unwrap();
+        assert_eq!(12.5, stats.consume_tps);
+        assert_eq!(20, stats.total_lag());
+        let mq = MessageQueue {
+            topic: "TopicA".to_string(),
+            broker_name: "broker-a".to_string(),
+            queue_id: 0,
+        };
+        let progress = stats.offsets[&mq];
+        assert_eq!(120, progress.broker_offset);
+        assert_eq!(100, progress.consumer_offset);
+        assert_eq!(1618922213000, progress.last_timestamp);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::GetConsumeStats);
+        assert_eq!("ns%GroupA", req.ext_fields()["consumerGroup"]);
+        assert_eq!("ns%TopicA", req.ext_fields()["topic"]);
+    }
+
+    #[test]
+    fn test_decode_properties() {
+        let config = decode_properties(
+            "#comment\nbrokerName=broker-a\nslaveReadEnable = true\n\nnamesrvAddr=127.0.0.1:9876\n",
+        );
+        assert_eq!(3, config.len());
+        assert_eq!("broker-a", config["brokerName"]);
+        assert_eq!("true", config["slaveReadEnable"]);
+        assert_eq!("127.0.0.1:9876", config["namesrvAddr"]);
+    }
+
+    #[tokio::test]
+    async fn test_broker_config() {
+        let (addr, mut requests) = fake_cluster(b"brokerName=broker-a\n".to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options.set_instance_name("test_admin_broker_config");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr.clone()]);
+        let admin = Admin::with_options(options).unwrap();
+
+        admin
+            .update_broker_config(&addr, "slaveReadEnable", "true")
+            .await
+            .unwrap();
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::UpdateBrokerConfig);
+        assert_eq!(b"slaveReadEnable=true\n", req.body());
+
+        let config = admin.get_broker_config(&addr).await.unwrap();
+        assert_eq!("broker-a", config["brokerName"]);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::GetBrokerConfig);
+    }
+
+    #[tokio::test]
+    async fn test_create_subscription_group() {
+        let (addr, mut requests) = fake_cluster(Vec::new()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_create_subscription_group")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let mut config = SubscriptionGroupConfig::new("GroupA");
+        config.set_retry_max_times(3);
+        admin
+            .create_subscription_group(config, "DefaultCluster")
+            .await
+            .unwrap();
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::UpdateAndCreateSubscriptionGroup);
+        let body: serde_json::Value = serde_json::from_slice(req.body()).unwrap();
+        assert_eq!("ns%GroupA", body["groupName"]);
+        assert_eq!(3, body["retryMaxTimes"]);
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_topic_stats() {
+        let body = br#"{"offsetTable":{{"brokerName":"broker-a","queueId":0,"topic":"ns%TopicA"}:{"lastUpdateTimestamp":1618922213000,"maxOffset":120,"minOffset":20},{"brokerName":"broker-a","queueId":1,"topic":"ns%TopicA"}:{"lastUpdateTimestamp":0,"maxOffset":0,"minOffset":0}}}"#;
+        let (addr, mut requests) = fake_cluster(body.to_vec()).await;
+        let mut client_options = ClientOptions::default();
+        client_options
+            .set_instance_name("test_admin_topic_stats")
+            .set_namespace("ns");
+        let mut options = AdminOptions::with_client_options(client_options);
+        options.set_name_server(vec![addr]);
+        let admin = Admin::with_options(options).unwrap();
+
+        let stats = admin.topic_stats("TopicA").await.unwrap();
+        assert_eq!(2, stats.len());
+        let (mq, offset) = stats.iter().next().unwrap();
+        assert_eq!("TopicA", mq.topic);
+        assert_eq!(0, mq.queue_id);
+        assert_eq!(20, offset.min_offset);
+        assert_eq!(120, offset.max_offset);
+        assert_eq!(100, offset.message_count());
+        assert_eq!(1618922213000, offset.last_update_timestamp);
+        let req = requests.recv().await.unwrap();
+        assert!(req.code() == RequestCode::GetTopicStatsInfo);
+        assert_eq!("ns%TopicA", req.ext_fields()["topic"]);
+    }
+}
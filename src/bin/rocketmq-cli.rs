@@ -0,0 +1,302 @@
+//! Smoke test connectivity and ACL against RocketMQ clusters
+//!
+//! ```text
+//! rocketmq-cli [options] send -t <topic> [--tag <tag>] [--keys <k1,k2>] [-c <count>] <body>
+//! rocketmq-cli [options] consume -t <topic> [-e <tag expression>] [--from-first] [-c <count>]
+//! rocketmq-cli [options] route -t <topic>
+//! rocketmq-cli [options] reset-offset -t <topic> --timestamp <ms> [--force]
+//! ```
+//!
+//! Options are `-n <name servers separated by ;>`, `-g <group>`, `--namespace <namespace>`,
+//! `--access-key <key>` and `--secret-key <key>`, on top of the `ROCKETMQ_*` variables read
+//! by `ClientOptions::from_env` and `NAMESRV_ADDR`
+
+use std::collections::HashMap;
+use std::env;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocketmq::admin::{Admin, AdminOptions};
+use rocketmq::consumer::{ConsumeFrom, ConsumerOptions, MessageSelector, PushConsumer};
+use rocketmq::producer::{Producer, ProducerOptions};
+use rocketmq::{ClientOptions, ConsumeResult, Credentials, Error, Message};
+use tokio::sync::mpsc;
+
+const USAGE: &str = "\
+usage: rocketmq-cli [options] <command> [args]
+
+commands:
+    send -t <topic> [--tag <tag>] [--keys <k1,k2>] [-c <count>] <body>
+    consume -t <topic> [-e <tag expression>] [--from-first] [-c <count>]
+    route -t <topic>
+    reset-offset -t <topic> --timestamp <ms> [--force]
+
+options:
+    -n, --namesrv <addrs>     name servers separated by `;`, NAMESRV_ADDR by default
+    -g, --group <group>       producer or consumer group
+    --namespace <namespace>   namespace of topics and groups
+    --access-key <key>        ACL access key
+    --secret-key <key>        ACL secret key
+
+ROCKETMQ_* variables like ROCKETMQ_ACCESS_KEY are read before options";
+
+// flags taking no value
+const SWITCHES: &[&str] = &["--from-first", "--force", "-h", "--help"];
+
+/// How long shutting down waits for groups to be unregistered and offsets to be persisted
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Command line split into positional arguments, flags with values and switches
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            if SWITCHES.contains(&arg.as_str()) {
+                parsed.switches.push(arg);
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value of {}", arg))?;
+                parsed.flags.insert(arg, value);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, short: &str, long: &str) -> Option<&str> {
+        self.flags
+            .get(short)
+            .or_else(|| self.flags.get(long))
+            .map(String::as_str)
+    }
+
+    fn required(&self, short: &str, long: &str) -> Result<&str, String> {
+        self.get(short, long)
+            .ok_or_else(|| format!("{} is required", long))
+    }
+
+    fn parse_flag<T: std::str::FromStr>(
+        &self,
+        short: &str,
+        long: &str,
+    ) -> Result<Option<T>, String> {
+        self.get(short, long)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid {}: {}", long, value))
+            })
+            .transpose()
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    fn name_servers(&self) -> Option<Vec<String>> {
+        self.get("-n", "--namesrv").map(|addrs| {
+            addrs
+                .split(';')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+    }
+}
+
+fn client_options(args: &Args, default_group: &str) -> Result<ClientOptions, Error> {
+    let mut options = ClientOptions::from_env()?;
+    if let Some(group) = args.get("-g", "--group") {
+        options.set_group_name(group);
+    } else if env::var("ROCKETMQ_GROUP").is_err() {
+        options.set_group_name(default_group);
+    }
+    if let Some(namespace) = args.get("", "--namespace") {
+        options.set_namespace(namespace);
+    }
+    if let (Some(access_key), Some(secret_key)) =
+        (args.get("", "--access-key"), args.get("", "--secret-key"))
+    {
+        options.set_credentials(Credentials::new(access_key, secret_key));
+    }
+    Ok(options)
+}
+
+async fn send(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let topic = args.required("-t", "--topic")?;
+    let body = args.positional.get(1).ok_or("message body is required")?;
+    let count: usize = args.parse_flag("-c", "--count")?.unwrap_or(1);
+    let mut options =
+        ProducerOptions::with_client_options(client_options(args, "rocketmq_cli_producer")?);
+    if let Some(addrs) = args.name_servers() {
+        options.set_name_server(addrs);
+    }
+    let producer = Producer::with_options(options)?;
+    producer.start();
+    for _ in 0..count {
+        let mut builder = Message::builder();
+        builder.topic(topic).body(body.as_bytes().to_vec());
+        if let Some(tag) = args.get("", "--tag") {
+            builder.tag(tag);
+        }
+        if let Some(keys) = args.get("", "--keys") {
+            builder.keys(keys.split(','));
+        }
+        let result = producer.send(builder.build()?).await?;
+        println!(
+            "{:?} msg_id={} broker={} queue={} offset={}",
+            result.status,
+            result.msg_id,
+            result.message_queue.broker_name,
+            result.message_queue.queue_id,
+            result.queue_offset
+        );
+    }
+    producer.shutdown(SHUTDOWN_TIMEOUT).await;
+    Ok(())
+}
+
+async fn consume(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let topic = args.required("-t", "--topic")?;
+    let expression = args.get("-e", "--expression").unwrap_or("*");
+    let count: Option<usize> = args.parse_flag("-c", "--count")?;
+    let mut options =
+        ConsumerOptions::with_client_options(client_options(args, "rocketmq_cli_consumer")?);
+    if let Some(addrs) = args.name_servers() {
+        options.set_name_server(addrs);
+    }
+    if args.switch("--from-first") {
+        options.set_consume_from(ConsumeFrom::FirstOffset);
+    }
+    let consumer = PushConsumer::with_options(options)?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    consumer.subscribe(topic, MessageSelector::tag(expression), move |msgs| {
+        for msg in msgs {
+            println!("{} body={}", msg, String::from_utf8_lossy(msg.body()));
+            let _ = tx.send(());
+        }
+        ConsumeResult::Success
+    });
+    consumer.start();
+    let mut received = 0;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            msg = rx.recv() => {
+                if msg.is_none() {
+                    break;
+                }
+                received += 1;
+                if count.map(|count| received >= count).unwrap_or(false) {
+                    break;
+                }
+            }
+        }
+    }
+    consumer.shutdown(SHUTDOWN_TIMEOUT).await;
+    eprintln!("received {} messages", received);
+    Ok(())
+}
+
+fn admin(args: &Args) -> Result<Admin, Error> {
+    let mut options =
+        AdminOptions::with_client_options(client_options(args, "rocketmq_cli_admin")?);
+    if let Some(addrs) = args.name_servers() {
+        options.set_name_server(addrs);
+    }
+    Admin::with_options(options)
+}
+
+async fn route(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let topic = args.required("-t", "--topic")?;
+    let admin = admin(args)?;
+    let route = admin.topic_route(topic).await?;
+    for broker in &route.broker_datas {
+        let mut addrs: Vec<_> = broker.broker_addrs.iter().collect();
+        addrs.sort();
+        let addrs: Vec<String> = addrs
+            .iter()
+            .map(|(id, addr)| format!("{}={}", id, addr))
+            .collect();
+        println!(
+            "broker {} cluster={} addrs={}",
+            broker.broker_name,
+            broker.cluster,
+            addrs.join(",")
+        );
+    }
+    for queue in &route.queue_datas {
+        println!(
+            "queues {} read={} write={} perm={}",
+            queue.broker_name, queue.read_queue_nums, queue.write_queue_nums, queue.perm
+        );
+    }
+    admin.shutdown(SHUTDOWN_TIMEOUT).await;
+    Ok(())
+}
+
+async fn reset_offset(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let topic = args.required("-t", "--topic")?;
+    let group = args.required("-g", "--group")?;
+    let timestamp = match args.parse_flag("", "--timestamp")? {
+        Some(timestamp) => timestamp,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::from_secs(0))
+            .as_millis() as i64,
+    };
+    let admin = admin(args)?;
+    let offsets = admin
+        .reset_offset(topic, group, timestamp, args.switch("--force"))
+        .await?;
+    let mut offsets: Vec<_> = offsets.into_iter().collect();
+    offsets
+        .sort_by(|(a, _), (b, _)| (&a.broker_name, a.queue_id).cmp(&(&b.broker_name, b.queue_id)));
+    for (mq, offset) in offsets {
+        println!(
+            "{} {} queue={} offset={}",
+            mq.topic, mq.broker_name, mq.queue_id, offset
+        );
+    }
+    admin.shutdown(SHUTDOWN_TIMEOUT).await;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if args.switch("-h") || args.switch("--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let result = match args.positional.first().map(String::as_str) {
+        Some("send") => send(&args).await,
+        Some("consume") => consume(&args).await,
+        Some("route") => route(&args).await,
+        Some("reset-offset") => reset_offset(&args).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
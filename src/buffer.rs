@@ -0,0 +1,143 @@
+//! Pool of byte buffers reused for bodies compressed by producers and the content ACL
+//! signatures are computed over. Buffers turned into `Bytes` go back to the pool once the
+//! command owning them is encoded and dropped, so sending at a steady rate stops allocating
+//! them. Uncompressed bodies share the memory of their messages instead
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// Buffers kept by the pool, those released when it's full are freed
+const MAX_POOLED_BUFFERS: usize = 256;
+/// Buffers grown larger than it are freed instead of pooled, so a few large messages don't
+/// keep their memory around
+const MAX_POOLED_CAPACITY: usize = 256 * 1024;
+
+static POOL: Lazy<BufferPool> =
+    Lazy::new(|| BufferPool::new(MAX_POOLED_BUFFERS, MAX_POOLED_CAPACITY));
+
+/// Empty buffer of the shared pool
+pub(crate) fn get() -> PooledBuf {
+    POOL.get()
+}
+
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    pub fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    pub fn get(&'static self) -> PooledBuf {
+        let buf = self.buffers.lock().pop().unwrap_or_default();
+        PooledBuf { buf, pool: self }
+    }
+
+    fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > self.max_capacity {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.buffers.lock();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// Buffer going back to its pool when dropped
+pub(crate) struct PooledBuf {
+    buf: Vec<u8>,
+    pool: &'static BufferPool,
+}
+
+impl PooledBuf {
+    /// `Bytes` sharing the buffer, which goes back to the pool once all of them are dropped
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl fmt::Debug for PooledBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuf")
+            .field("len", &self.buf.len())
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for PooledBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BufferPool;
+
+    fn leak_pool(max_buffers: usize, max_capacity: usize) -> &'static BufferPool {
+        Box::leak(Box::new(BufferPool::new(max_buffers, max_capacity)))
+    }
+
+    #[test]
+    fn test_buffers_reused() {
+        let pool = leak_pool(1, 1024);
+        let mut buf = pool.get();
+        buf.extend_from_slice(b"hello");
+        let ptr = buf.as_ptr();
+        let bytes = buf.into_bytes();
+        let clone = bytes.clone();
+        drop(bytes);
+        assert!(pool.buffers.lock().is_empty());
+        drop(clone);
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(ptr, buf.as_ptr());
+        let other = pool.get();
+        assert_eq!(0, other.capacity());
+        drop(buf);
+        drop(other);
+        // only one buffer is kept
+        assert_eq!(1, pool.buffers.lock().len());
+
+        let mut large = pool.get();
+        large.resize(2048, 0);
+        drop(large);
+        assert_eq!(0, pool.buffers.lock().len());
+    }
+}
@@ -1,38 +1,65 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::io;
 use std::net::IpAddr;
 use std::process;
 use std::sync::{
-    atomic::{AtomicU8, Ordering},
+    atomic::{AtomicU8, AtomicUsize, Ordering},
     Arc,
 };
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
+use futures::Future;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use parking_lot::Mutex;
+use serde::Deserialize;
 use tokio::sync::broadcast;
-use tokio::time;
 use tracing::{debug, error, info, info_span, warn};
 use tracing_futures::Instrument;
 
+use crate::config::{self, ClientConfig};
 use crate::consumer::ConsumerInner;
-use crate::message::MessageExt;
+#[cfg(feature = "grpc")]
+use crate::grpc::Transport;
+use crate::message::{MessageExt, MessageExtIter, MessageQueue, Property};
 use crate::namesrv::NameServer;
-use crate::producer::ProducerInner;
+use crate::producer::{LocalTransactionState, ProducerInner};
 use crate::protocol::{
     request::{
-        ConsumerSendMsgBackRequestHeader, CreateTopicRequestHeader, PullMessageRequestHeader,
-        UnregisterClientRequestHeader,
+        CheckTransactionStateRequestHeader, ConsumeMessageDirectlyRequestHeader,
+        ConsumerSendMsgBackRequestHeader, CreateTopicRequestHeader, DeleteTopicRequestHeader,
+        EndTransactionRequestHeader, GetConsumerRunningInfoRequestHeader, PullMessageRequestHeader,
+        QueryMessageRequestHeader, ResetOffsetRequestHeader, UnregisterClientRequestHeader,
     },
-    RemotingCommand, RequestCode, ResponseCode,
+    response::PullMessageResponseHeader,
+    RemotingCommand, RequestCode, ResponseCode, DEFAULT_MAX_FRAME_LENGTH, MQ_VERSION,
 };
-use crate::remoting::RemotingClient;
+#[cfg(feature = "tls")]
+use crate::remoting::TlsConfig;
+use crate::remoting::{
+    ConnectionEvents, ConnectionListener, ProxyConfig, RateLimit, RemotingClient, RemotingOptions,
+    RpcHook, RpcHooks, DEFAULT_CONNECTION_IDLE_TIMEOUT, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+};
+#[cfg(feature = "fault-injection")]
+use crate::remoting::{FaultInjector, Faults};
 use crate::resolver::NsResolver;
 use crate::route::{TopicRouteData, MASTER_ID};
+use crate::runtime::{self, JoinHandle};
+use crate::topic::{AUTO_CREATE_TOPIC_KEY, CLOUD_TRACE_TOPIC_PREFIX, TRACE_TOPIC};
+#[cfg(feature = "trace-context")]
+use crate::trace_context::{TracePropagation, TracePropagator};
 use crate::utils::client_ip_addr;
 use crate::Error;
 
-mod model;
+pub(crate) mod model;
+mod namespace;
+mod processor;
+mod registry;
+
+use model::ResetOffsetBody;
+use processor::ProcessorTable;
+pub use processor::RequestProcessor;
 
 #[derive(Debug, Clone)]
 pub struct Credentials {
@@ -68,8 +95,41 @@ pub struct PullResult {
     pub max_offset: i64,
     pub status: PullStatus,
     pub suggest_which_broker_id: i64,
-    pub message_exts: Vec<MessageExt>,
-    pub body: Vec<u8>,
+    /// Messages stored back to back, decoded by [`messages`](Self::messages)
+    pub body: Bytes,
+}
+
+impl PullResult {
+    /// Messages found, decoded as they are iterated
+    pub fn messages(&self) -> MessageExtIter<'_> {
+        match self.status {
+            PullStatus::Found => MessageExtIter::new(&self.body),
+            _ => MessageExtIter::new(&[]),
+        }
+    }
+}
+
+/// Where brokers are hosted, enabling behaviors specific to Aliyun-hosted RocketMQ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessChannel {
+    /// Self-hosted brokers
+    #[default]
+    Local,
+    /// Aliyun-hosted instances, reached through instance endpoints like
+    /// `http://MQ_INST_xxx_yyy.region.mq.aliyuncs.com:80`. The instance ID is the namespace
+    /// unless one is set, so topics, groups and their retry topics are wrapped with it
+    Cloud,
+}
+
+impl AccessChannel {
+    /// Topic message traces are sent to, cloud instances have one per region
+    pub fn trace_topic(self, region_id: &str) -> String {
+        match self {
+            AccessChannel::Local => TRACE_TOPIC.to_string(),
+            AccessChannel::Cloud => format!("{}{}", CLOUD_TRACE_TOPIC_PREFIX, region_id),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -84,6 +144,29 @@ pub struct ClientOptions {
     retry_times: usize,
     pub(crate) credentials: Option<Credentials>,
     pub(crate) namespace: String,
+    pub(crate) access_channel: AccessChannel,
+    pub(crate) version: i16,
+    pub(crate) max_frame_length: usize,
+    pub(crate) connection_idle_timeout: Duration,
+    pub(crate) max_in_flight_requests: usize,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) tcp_nodelay: bool,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) send_buffer_size: Option<u32>,
+    pub(crate) recv_buffer_size: Option<u32>,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) connection_events: ConnectionEvents,
+    pub(crate) rpc_hooks: RpcHooks,
+    pub(crate) processors: ProcessorTable,
+    pub(crate) proxy: Option<ProxyConfig>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls_config: Option<TlsConfig>,
+    #[cfg(feature = "fault-injection")]
+    pub(crate) faults: Faults,
+    #[cfg(feature = "trace-context")]
+    pub(crate) trace_propagation: TracePropagation,
+    #[cfg(feature = "grpc")]
+    pub(crate) transport: Transport,
 }
 
 impl ClientOptions {
@@ -99,6 +182,243 @@ impl ClientOptions {
             retry_times: 3,
             credentials: None,
             namespace: String::new(),
+            access_channel: AccessChannel::Local,
+            version: MQ_VERSION,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            connection_idle_timeout: DEFAULT_CONNECTION_IDLE_TIMEOUT,
+            max_in_flight_requests: DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            rate_limit: None,
+            connection_events: ConnectionEvents::default(),
+            rpc_hooks: RpcHooks::default(),
+            processors: ProcessorTable::default(),
+            proxy: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            #[cfg(feature = "fault-injection")]
+            faults: Faults::default(),
+            #[cfg(feature = "trace-context")]
+            trace_propagation: TracePropagation::default(),
+            #[cfg(feature = "grpc")]
+            transport: Transport::default(),
+        }
+    }
+
+    /// Options of the `ROCKETMQ_GROUP` group from `ROCKETMQ_*` variables, e.g.
+    /// `ROCKETMQ_NAMESPACE`, `ROCKETMQ_ACCESS_KEY`, `ROCKETMQ_SECRET_KEY` or
+    /// `ROCKETMQ_CONNECT_TIMEOUT_MS`, unset variables keep their defaults. Name servers are
+    /// read from `NAMESRV_ADDR` by the default resolver
+    pub fn from_env() -> Result<Self, Error> {
+        let group: Option<String> = config::env_var("GROUP")?;
+        Ok(ClientConfig::from_env()?.client_options(group.as_deref()))
+    }
+
+    /// Set the producer or consumer group
+    pub fn set_group_name(&mut self, group: &str) -> &mut Self {
+        self.group_name = group.to_string();
+        self
+    }
+
+    /// Set the instance name, part of the client ID. Producers and consumers with the same
+    /// client ID and name servers share a client
+    pub fn set_instance_name(&mut self, name: &str) -> &mut Self {
+        self.instance_name = name.to_string();
+        self
+    }
+
+    /// Set the unit name, part of the client ID. HTTP resolvers fetch name servers of the
+    /// unit from `<url>-<unit name>?nofix=1` like the Java client
+    pub fn set_unit_name(&mut self, unit_name: &str) -> &mut Self {
+        self.unit_name = unit_name.to_string();
+        self
+    }
+
+    /// Set the namespace topics and groups are prefixed with, isolating them from those of
+    /// other namespaces on the same brokers
+    pub fn set_namespace(&mut self, namespace: &str) -> &mut Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Sign requests with `credentials` for brokers with ACL enabled
+    pub fn set_credentials(&mut self, credentials: Credentials) -> &mut Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Set where brokers are hosted, `AccessChannel::Local` by default
+    pub fn set_access_channel(&mut self, access_channel: AccessChannel) -> &mut Self {
+        self.access_channel = access_channel;
+        self
+    }
+
+    /// Set the max length of frames received from brokers, connections receiving
+    /// longer frames are closed
+    pub fn set_max_frame_length(&mut self, max_frame_length: usize) -> &mut Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// Set how long a broker connection may stay idle before it's closed, it's dialed
+    /// again when needed
+    pub fn set_connection_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.connection_idle_timeout = timeout;
+        self
+    }
+
+    /// Set the max number of requests waiting for responses on a single connection,
+    /// further requests wait until one of them completes
+    pub fn set_max_in_flight_requests(&mut self, max_in_flight_requests: usize) -> &mut Self {
+        self.max_in_flight_requests = max_in_flight_requests;
+        self
+    }
+
+    /// Set how long connecting to a broker or name server may take, including the proxy
+    /// handshake
+    pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Enable or disable `TCP_NODELAY`, enabled by default
+    pub fn set_tcp_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Enable TCP keepalive, probing idle connections every `interval`
+    pub fn set_tcp_keepalive(&mut self, interval: Duration) -> &mut Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Set the socket send buffer size, `SO_SNDBUF`
+    pub fn set_send_buffer_size(&mut self, size: u32) -> &mut Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Set the socket receive buffer size, `SO_RCVBUF`
+    pub fn set_recv_buffer_size(&mut self, size: u32) -> &mut Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Limit the rate of requests sent to brokers
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit) -> &mut Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Notify `listener` of connections to brokers and name servers opened, closed and
+    /// failed
+    pub fn set_connection_listener(&mut self, listener: Arc<dyn ConnectionListener>) -> &mut Self {
+        self.connection_events = ConnectionEvents::new(listener);
+        self
+    }
+
+    /// Call `hook` around requests sent to brokers and name servers, after hooks added
+    /// before. `LoggingRpcHook` logs commands with ACL signatures redacted
+    pub fn add_rpc_hook(&mut self, hook: Arc<dyn RpcHook>) -> &mut Self {
+        self.rpc_hooks.push(hook);
+        self
+    }
+
+    /// Handle requests of `code` sent by brokers with `processor` instead of the built-in
+    /// handling, replacing the processor registered before
+    pub fn register_processor(
+        &mut self,
+        code: RequestCode,
+        processor: Arc<dyn RequestProcessor>,
+    ) -> &mut Self {
+        self.processors.register(code, processor);
+        self
+    }
+
+    /// Inject the trace context of `propagator` into messages sent, and hand it the trace
+    /// context of messages consumed. Listeners are called in a `consume` span recording
+    /// the context either way
+    #[cfg(feature = "trace-context")]
+    pub fn set_trace_propagator(&mut self, propagator: Arc<dyn TracePropagator>) -> &mut Self {
+        self.trace_propagation = TracePropagation::new(propagator);
+        self
+    }
+
+    /// Connect to brokers and name servers, and resolve name servers over HTTP, through
+    /// a SOCKS5 proxy
+    pub fn set_proxy(&mut self, proxy: ProxyConfig) -> &mut Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connect to brokers and name servers with TLS
+    #[cfg(feature = "tls")]
+    pub fn set_tls_config(&mut self, tls_config: TlsConfig) -> &mut Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Inject faults decided by `injector` into requests sent to brokers and name servers,
+    /// to verify how the application behaves when they fail
+    #[cfg(feature = "fault-injection")]
+    pub fn set_fault_injector(&mut self, injector: Arc<dyn FaultInjector>) -> &mut Self {
+        self.faults = Faults::new(injector);
+        self
+    }
+
+    /// Speak to 5.x proxies over gRPC with `Transport::Grpc`, instead of name servers and
+    /// brokers over the remoting protocol
+    #[cfg(feature = "grpc")]
+    pub fn set_transport(&mut self, transport: Transport) -> &mut Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Set the version reported to brokers, brokers enable some features according to it
+    pub fn set_version(&mut self, version: i16) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    /// Client ID reported to brokers, `ip@pid` unless an instance name is set
+    pub(crate) fn client_id(&self) -> String {
+        let mut client_id = self.client_ip.clone() + "@";
+        if self.instance_name == "DEFAULT" {
+            client_id.push_str(&process::id().to_string());
+        } else {
+            client_id.push_str(&self.instance_name);
+        }
+        if !self.unit_name.is_empty() {
+            client_id.push_str(&self.unit_name);
+        }
+        client_id
+    }
+
+    /// Transport options shared by broker and name server connections
+    pub(crate) fn remoting_options(&self) -> RemotingOptions {
+        RemotingOptions {
+            version: self.version,
+            max_frame_length: self.max_frame_length,
+            idle_timeout: self.connection_idle_timeout,
+            max_in_flight_requests: self.max_in_flight_requests,
+            connect_timeout: self.connect_timeout,
+            tcp_nodelay: self.tcp_nodelay,
+            tcp_keepalive: self.tcp_keepalive,
+            send_buffer_size: self.send_buffer_size,
+            recv_buffer_size: self.recv_buffer_size,
+            // requests to name servers aren't limited
+            rate_limit: None,
+            events: self.connection_events.clone(),
+            hooks: self.rpc_hooks.clone(),
+            proxy: self.proxy.clone(),
+            #[cfg(feature = "tls")]
+            tls: self.tls_config.clone(),
+            #[cfg(feature = "fault-injection")]
+            faults: self.faults.clone(),
         }
     }
 }
@@ -116,6 +436,29 @@ impl Default for ClientOptions {
             retry_times: 3,
             credentials: None,
             namespace: String::new(),
+            access_channel: AccessChannel::Local,
+            version: MQ_VERSION,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            connection_idle_timeout: DEFAULT_CONNECTION_IDLE_TIMEOUT,
+            max_in_flight_requests: DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            rate_limit: None,
+            connection_events: ConnectionEvents::default(),
+            rpc_hooks: RpcHooks::default(),
+            processors: ProcessorTable::default(),
+            proxy: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            #[cfg(feature = "fault-injection")]
+            faults: Faults::default(),
+            #[cfg(feature = "trace-context")]
+            trace_propagation: TracePropagation::default(),
+            #[cfg(feature = "grpc")]
+            transport: Transport::default(),
         }
     }
 }
@@ -129,6 +472,9 @@ fn client_ip() -> String {
         .unwrap_or_else(|| "127.0.0.1".to_string())
 }
 
+// how long shutting down a client released by all of its users may take
+pub(crate) const RELEASE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum ClientState {
@@ -142,11 +488,15 @@ pub enum ClientState {
 pub struct Client<R: NsResolver + Clone> {
     options: ClientOptions,
     remote_client: RemotingClient,
-    consumers: Arc<Mutex<HashMap<String, Arc<Mutex<ConsumerInner>>>>>,
+    consumers: Arc<Mutex<HashMap<String, Arc<ConsumerInner>>>>,
     producers: Arc<Mutex<HashMap<String, Arc<Mutex<ProducerInner>>>>>,
     pub(crate) name_server: NameServer<R>,
     state: Arc<AtomicU8>,
     shutdown_tx: Arc<Mutex<Option<broadcast::Sender<()>>>>,
+    // background tasks spawned on start
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    // producers and consumers sharing the client
+    refs: Arc<AtomicUsize>,
 }
 
 impl<R> Client<R>
@@ -154,30 +504,71 @@ where
     R: NsResolver + Clone + Send + Sync + 'static,
 {
     pub fn new(options: ClientOptions, name_server: NameServer<R>) -> Self {
-        let credentials = options.credentials.clone();
+        let mut remoting_options = options.remoting_options();
+        remoting_options.rate_limit = options.rate_limit.clone();
+        let remote_client =
+            RemotingClient::with_options(options.credentials.clone(), remoting_options);
         Self {
             options,
-            remote_client: RemotingClient::new(credentials),
+            remote_client,
             consumers: Arc::new(Mutex::new(HashMap::new())),
             producers: Arc::new(Mutex::new(HashMap::new())),
             name_server,
             state: Arc::new(AtomicU8::new(ClientState::Created.into())),
             shutdown_tx: Arc::new(Mutex::new(None)),
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            refs: Arc::new(AtomicUsize::new(1)),
         }
     }
 
     /// Get Client ID
     pub fn id(&self) -> String {
-        let mut client_id = self.options.client_ip.clone() + "@";
-        if self.options.instance_name == "DEFAULT" {
-            client_id.push_str(&process::id().to_string());
-        } else {
-            client_id.push_str(&self.options.instance_name);
+        self.options.client_id()
+    }
+
+    /// Take another reference of a client shared by producers and consumers, fails if it
+    /// was released by all of its users or shut down
+    fn retain(&self) -> bool {
+        if matches!(self.state(), ClientState::Shutdown) {
+            return false;
         }
-        if !self.options.unit_name.is_empty() {
-            client_id.push_str(&self.options.unit_name);
+        self.refs
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |refs| {
+                if refs == 0 {
+                    None
+                } else {
+                    Some(refs + 1)
+                }
+            })
+            .is_ok()
+    }
+
+    /// Prefix a topic or group with the namespace of the client
+    pub(crate) fn wrap_namespace(&self, resource: &str) -> String {
+        namespace::wrap(&self.options.namespace, resource)
+    }
+
+    /// Remove the namespace prefix of a topic or group
+    pub(crate) fn unwrap_namespace(&self, resource: &str) -> String {
+        namespace::unwrap(&self.options.namespace, resource)
+    }
+
+    pub(crate) fn wrap_queue(&self, mq: &MessageQueue) -> MessageQueue {
+        MessageQueue {
+            topic: self.wrap_namespace(&mq.topic),
+            ..mq.clone()
         }
-        client_id
+    }
+
+    pub(crate) fn unwrap_queue(&self, mq: &MessageQueue) -> MessageQueue {
+        MessageQueue {
+            topic: self.unwrap_namespace(&mq.topic),
+            ..mq.clone()
+        }
+    }
+
+    fn is_same_instance(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
     }
 
     pub fn start(&self) {
@@ -188,34 +579,41 @@ where
                 let (shutdown_tx, mut shutdown_rx1) = broadcast::channel(1);
                 let mut shutdown_rx2 = shutdown_tx.subscribe();
                 let mut shutdown_rx3 = shutdown_tx.subscribe();
+                let mut shutdown_rx4 = shutdown_tx.subscribe();
+                let mut shutdown_rx5 = shutdown_tx.subscribe();
+                let mut shutdown_rx6 = shutdown_tx.subscribe();
+                let mut shutdown_rx7 = shutdown_tx.subscribe();
+                let mut shutdown_rx8 = shutdown_tx.subscribe();
                 self.shutdown_tx.lock().replace(shutdown_tx);
 
                 // Schedule update name server address
                 let name_server = self.name_server.clone();
-                tokio::spawn(async move {
-                    let mut interval = time::interval(time::Duration::from_secs(2 * 60));
-                    loop {
-                        tokio::select! {
-                            _ = interval.tick() => {
-                                match name_server.update_name_server_address().await {
-                                    Ok(_) => info!("name server addresses update succeed"),
-                                    Err(err) => error!("name server address update failed: {:?}", err),
-                                };
-                            }
-                            _ = shutdown_rx1.recv() => {
-                                info!("client shutdown, stop updating name server domain info");
-                                break;
+                self.spawn_task(
+                    async move {
+                        loop {
+                            match name_server.update_name_server_address().await {
+                                Ok(_) => info!("name server addresses update succeed"),
+                                Err(err) => error!("name server address update failed: {:?}", err),
+                            };
+                            // resolvers like the SRV one change their interval as they resolve
+                            tokio::select! {
+                                _ = runtime::sleep(name_server.refresh_interval()) => {}
+                                _ = shutdown_rx1.recv() => {
+                                    info!("client shutdown, stop updating name server domain info");
+                                    break;
+                                }
                             }
                         }
                     }
-                }.instrument(info_span!("update_name_server_address")));
+                    .instrument(info_span!("update_name_server_address")),
+                );
 
                 // Schedule update route info
                 let client = self.clone();
-                tokio::spawn(
+                self.spawn_task(
                     async move {
                         // time::delay_for(time::Duration::from_millis(10)).await;
-                        let mut interval = time::interval(time::Duration::from_secs(30));
+                        let mut interval = runtime::interval(Duration::from_secs(30));
                         loop {
                             tokio::select! {
                                 _ = interval.tick() => {
@@ -233,10 +631,10 @@ where
 
                 // Schedule send heartbeat to all brokers
                 let client = self.clone();
-                tokio::spawn(
+                self.spawn_task(
                     async move {
                         // time::delay_for(time::Duration::from_secs(1)).await;
-                        let mut interval = time::interval(time::Duration::from_secs(30));
+                        let mut interval = runtime::interval(Duration::from_secs(30));
                         loop {
                             tokio::select! {
                                 _ = interval.tick() => {
@@ -253,8 +651,109 @@ where
                 );
 
                 // Persist offset
+                let client = self.clone();
+                self.spawn_task(
+                    async move {
+                        let mut interval = runtime::interval(Duration::from_secs(5));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    client.persist_consumer_offset().await;
+                                }
+                                _ = shutdown_rx4.recv() => {
+                                    info!("client shutdown, stop persisting consumer offset");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    .instrument(info_span!("persist_consumer_offset")),
+                );
 
                 // Rebalance
+                let client = self.clone();
+                self.spawn_task(
+                    async move {
+                        let mut interval = runtime::interval(Duration::from_secs(20));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    client.rebalance_immediately().await;
+                                }
+                                _ = shutdown_rx5.recv() => {
+                                    info!("client shutdown, stop rebalancing");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    .instrument(info_span!("rebalance")),
+                );
+
+                // Evict idle connections
+                let remote_client = self.remote_client.clone();
+                self.spawn_task(
+                    async move {
+                        let mut interval = runtime::interval(Duration::from_secs(10));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    remote_client.evict_idle_connections();
+                                }
+                                _ = shutdown_rx7.recv() => {
+                                    info!("client shutdown, stop evicting idle connections");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    .instrument(info_span!("evict_idle_connections")),
+                );
+
+                // Resolve broker host names again, reconnecting to those moved
+                let remote_client = self.remote_client.clone();
+                self.spawn_task(
+                    async move {
+                        let mut interval = runtime::interval(Duration::from_secs(30));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    remote_client.refresh_resolved_addresses().await;
+                                }
+                                _ = shutdown_rx8.recv() => {
+                                    info!("client shutdown, stop resolving broker addresses");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    .instrument(info_span!("refresh_resolved_addresses")),
+                );
+
+                // Process requests initiated by brokers
+                if let Some(mut requests) = self.remote_client.take_request_receiver() {
+                    let client = self.clone();
+                    self.spawn_task(
+                        async move {
+                            loop {
+                                tokio::select! {
+                                    Some((addr, request)) = requests.recv() => {
+                                        let client = client.clone();
+                                        runtime::spawn(async move {
+                                            client.process_request(&addr, request).await;
+                                        });
+                                    }
+                                    _ = shutdown_rx6.recv() => {
+                                        info!("client shutdown, stop processing requests");
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        .instrument(info_span!("process_request")),
+                    );
+                }
+
                 self.state
                     .store(ClientState::Running.into(), Ordering::SeqCst);
             }
@@ -262,19 +761,130 @@ where
         }
     }
 
-    pub fn shutdown(&self) {
+    fn spawn_task<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.lock().push(runtime::spawn(task));
+    }
+
+    /// Stop consumers, persist their offsets and wait for background tasks to finish until
+    /// `deadline`, then close connections
+    async fn close(&self, deadline: Instant) {
+        if runtime::timeout_at(deadline, self.persist_consumer_offset())
+            .await
+            .is_err()
+        {
+            warn!("persist consumer offset timed out on shutdown");
+        }
+        let consumers: Vec<Arc<ConsumerInner>> = self.consumers.lock().values().cloned().collect();
+        for consumer in consumers {
+            consumer.shutdown();
+        }
+        let tasks: Vec<JoinHandle<()>> = self.tasks.lock().drain(..).collect();
+        for mut task in tasks {
+            if runtime::timeout_at(deadline, &mut task).await.is_err() {
+                warn!("background task didn't stop before shutdown deadline, aborting");
+                task.abort();
+            }
+        }
+        self.remote_client.shutdown();
+        self.name_server.shutdown();
+    }
+
+    /// Unregister the groups of a producer or consumer going away on brokers so remaining
+    /// consumers are rebalanced right away, and release its reference of the client. The
+    /// client is shut down once released by all of its users. Outside of a tokio runtime,
+    /// e.g. when dropped, groups aren't unregistered and brokers expire them after missing
+    /// heartbeats instead
+    pub(crate) fn release(&self, producer_group: &str, consumer_group: &str) {
+        let last = match self.release_ref() {
+            Some(last) => last,
+            None => return,
+        };
+        if runtime::can_spawn() {
+            let client = self.clone();
+            let producer_group = producer_group.to_string();
+            let consumer_group = consumer_group.to_string();
+            runtime::spawn(async move {
+                let deadline = Instant::now() + RELEASE_SHUTDOWN_TIMEOUT;
+                client
+                    .unregister_and_close(&producer_group, &consumer_group, last, deadline)
+                    .await;
+            });
+        } else if last {
+            self.remote_client.shutdown();
+            self.name_server.shutdown();
+        }
+    }
+
+    /// Release the client like `release`, waiting at most `timeout` for the groups to be
+    /// unregistered and, if released by all of its users, for consumer offsets to be
+    /// persisted and background tasks to finish. Tasks still running after it are aborted
+    pub(crate) async fn shutdown(
+        &self,
+        producer_group: &str,
+        consumer_group: &str,
+        timeout: Duration,
+    ) {
+        let deadline = Instant::now() + timeout;
+        if let Some(last) = self.release_ref() {
+            self.unregister_and_close(producer_group, consumer_group, last, deadline)
+                .await;
+        }
+    }
+
+    /// Release a reference of the client, returns whether it was the last one, which stops
+    /// the client, or `None` if the client was shut down already
+    fn release_ref(&self) -> Option<bool> {
+        let last = self
+            .refs
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |refs| {
+                refs.checked_sub(1)
+            })
+            .map_or(true, |refs| refs == 1);
+        if last && !self.stop() {
+            return None;
+        }
+        Some(last)
+    }
+
+    async fn unregister_and_close(
+        &self,
+        producer_group: &str,
+        consumer_group: &str,
+        last: bool,
+        deadline: Instant,
+    ) {
+        if runtime::timeout_at(
+            deadline,
+            self.unregister_client(producer_group, consumer_group),
+        )
+        .await
+        .is_err()
+        {
+            warn!("unregister client timed out on shutdown");
+        }
+        if last {
+            self.close(deadline).await;
+        }
+    }
+
+    /// Mark the client shut down and stop background tasks, returns false if it was
+    /// shut down already
+    fn stop(&self) -> bool {
         match ClientState::try_from(
             self.state
                 .swap(ClientState::Shutdown.into(), Ordering::Relaxed),
         )
         .unwrap()
         {
-            ClientState::Shutdown => {} // shutdown already
+            ClientState::Shutdown => false,
             _ => {
                 if let Some(tx) = &*self.shutdown_tx.lock() {
-                    tx.send(()).unwrap();
+                    let _ = tx.send(());
                 }
-                self.remote_client.shutdown();
+                true
             }
         }
     }
@@ -284,26 +894,46 @@ where
             .unwrap_or(ClientState::StartFailed)
     }
 
+    /// Send a request and wait for its response without a deadline, for requests the
+    /// broker may hold like long polling pulls
     #[inline]
     pub async fn invoke(&self, addr: &str, cmd: RemotingCommand) -> Result<RemotingCommand, Error> {
-        Ok(self.remote_client.invoke(addr, cmd).await?)
+        self.remote_client.invoke(addr, cmd).await
     }
 
+    /// Send a request and wait for its response at most `timeout`
     #[inline]
-    pub async fn invoke_timeout(
+    pub async fn invoke_sync(
         &self,
         addr: &str,
         cmd: RemotingCommand,
-        timeout: time::Duration,
+        timeout: Duration,
     ) -> Result<RemotingCommand, Error> {
-        time::timeout(timeout, self.remote_client.invoke(addr, cmd))
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e))?
+        self.remote_client.invoke_timeout(addr, cmd, timeout).await
+    }
+
+    /// Send a request in the background, `callback` is called with its response or error
+    pub fn invoke_async<F>(
+        &self,
+        addr: &str,
+        cmd: RemotingCommand,
+        timeout: Duration,
+        callback: F,
+    ) -> JoinHandle<()>
+    where
+        F: FnOnce(Result<RemotingCommand, Error>) + Send + 'static,
+    {
+        let remote_client = self.remote_client.clone();
+        let addr = addr.to_string();
+        runtime::spawn(async move {
+            callback(remote_client.invoke_timeout(&addr, cmd, timeout).await);
+        })
     }
 
+    /// Send a request without waiting for any response
     #[inline]
     pub async fn invoke_oneway(&self, addr: &str, cmd: RemotingCommand) -> Result<(), Error> {
-        Ok(self.remote_client.invoke_oneway(addr, cmd).await?)
+        self.remote_client.invoke_oneway(addr, cmd).await
     }
 
     pub async fn pull_message(
@@ -312,52 +942,237 @@ where
         request: PullMessageRequestHeader,
     ) -> Result<PullResult, Error> {
         let cmd = RemotingCommand::with_header(RequestCode::PullMessage, request, Vec::new());
-        let res = self.remote_client.invoke(addr, cmd).await?;
-        let status = match ResponseCode::from_code(res.code())? {
-            ResponseCode::Success => PullStatus::Found,
-            ResponseCode::PullNotFound => PullStatus::NoNewMsg,
-            ResponseCode::PullRetryImmediately => PullStatus::NoMsgMatched,
-            ResponseCode::PullOffsetMoved => PullStatus::OffsetIllegal,
-            _ => {
-                return Err(Error::ResponseError {
-                    code: res.code(),
-                    message: format!(
-                        "unknown response code: {}, remark: {}",
-                        res.code(),
-                        res.header.remark
-                    ),
-                });
-            }
+        let res = self.invoke(addr, cmd).await?;
+        let status = match ResponseCode::try_from(res.code()) {
+            Ok(ResponseCode::Success) => PullStatus::Found,
+            Ok(ResponseCode::PullNotFound) => PullStatus::NoNewMsg,
+            Ok(ResponseCode::PullRetryImmediately) => PullStatus::NoMsgMatched,
+            Ok(ResponseCode::PullOffsetMoved) => PullStatus::OffsetIllegal,
+            _ => return Err(res.into_broker_error(addr)),
         };
-        let ext_fields = &res.header.ext_fields;
-        let max_offset = ext_fields
-            .get("maxOffset")
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or_default();
-        let min_offset = ext_fields
-            .get("minOffset")
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or_default();
-        let next_begin_offset = ext_fields
-            .get("nextBeginOffset")
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or_default();
-        let suggest_which_broker_id = ext_fields
-            .get("suggestWhichBrokerId")
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or_default();
+        let header: PullMessageResponseHeader = res.decode_header()?;
         Ok(PullResult {
-            next_begin_offset,
-            min_offset,
-            max_offset,
-            suggest_which_broker_id,
+            next_begin_offset: header.next_begin_offset,
+            min_offset: header.min_offset,
+            max_offset: header.max_offset,
+            suggest_which_broker_id: header.suggest_which_broker_id,
             status,
-            message_exts: Vec::new(),
             body: res.body,
         })
     }
 
-    pub(crate) fn register_consumer(&self, group: &str, consumer: Arc<Mutex<ConsumerInner>>) {
+    async fn process_request(&self, addr: &str, request: RemotingCommand) {
+        let opaque = request.opaque();
+        let response = match self.options.processors.get(request.code()) {
+            Some(processor) => processor.process_request(addr, &request).await,
+            None => self.process_builtin_request(addr, &request).await,
+        };
+        if request.is_oneway() {
+            return;
+        }
+        if let Some(mut response) = response {
+            response.set_opaque(opaque);
+            if let Err(err) = self.remote_client.send_response(addr, response).await {
+                warn!(broker = %addr, "send response to broker failed: {:?}", err);
+            }
+        }
+    }
+
+    async fn process_builtin_request(
+        &self,
+        addr: &str,
+        request: &RemotingCommand,
+    ) -> Option<RemotingCommand> {
+        match RequestCode::try_from(request.code()) {
+            Ok(RequestCode::NotifyConsumerIdsChanged) => {
+                info!(broker = %addr, "receive consumer ids changed notification, rebalance immediately");
+                self.rebalance_immediately().await;
+                None
+            }
+            Ok(RequestCode::ResetConsumerClientOffset) => {
+                self.reset_consumer_offset(request).await;
+                None
+            }
+            Ok(RequestCode::ConsumeMessageDirectly) => {
+                Some(self.consume_message_directly(request).await)
+            }
+            Ok(RequestCode::GetConsumerRunningInfo) => {
+                Some(self.get_consumer_running_info(request).await)
+            }
+            Ok(RequestCode::CheckTransactionState) => {
+                self.check_transaction_state(addr, request).await;
+                None
+            }
+            _ => {
+                warn!(code = request.code(), broker = %addr, "unsupported request code from broker");
+                Some(RemotingCommand::response(
+                    ResponseCode::RequestCodeNotSupported,
+                    format!("request code {} not supported", request.code()),
+                    Vec::new(),
+                ))
+            }
+        }
+    }
+
+    async fn consume_message_directly(&self, request: &RemotingCommand) -> RemotingCommand {
+        let error_response = |remark: String| {
+            RemotingCommand::response(ResponseCode::SystemError, remark, Vec::new())
+        };
+        let header = match request.decode_header::<ConsumeMessageDirectlyRequestHeader>() {
+            Ok(header) => header,
+            Err(err) => return error_response(err.to_string()),
+        };
+        let msg = match MessageExt::decode(&request.body).map(|mut msgs| msgs.pop()) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return error_response("no message found in request body".to_string()),
+            Err(err) => return error_response(err.to_string()),
+        };
+        info!(consumer_group = %header.consumer_group, client_id = %header.client_id, broker = %header.broker_name, msg_id = %header.msg_id, "receive consume message directly request");
+        let consumer = self.consumers.lock().get(&header.consumer_group).cloned();
+        let consumer = match consumer {
+            Some(consumer) => consumer,
+            None => {
+                return error_response(format!(
+                    "the consumer group {} not exist in this consumer",
+                    header.consumer_group
+                ))
+            }
+        };
+        let result = consumer.consume_message_directly(msg).await;
+        match serde_json::to_vec(&result) {
+            Ok(body) => RemotingCommand::response(ResponseCode::Success, String::new(), body),
+            Err(err) => error_response(err.to_string()),
+        }
+    }
+
+    async fn get_consumer_running_info(&self, request: &RemotingCommand) -> RemotingCommand {
+        let error_response = |remark: String| {
+            RemotingCommand::response(ResponseCode::SystemError, remark, Vec::new())
+        };
+        let header = match request.decode_header::<GetConsumerRunningInfoRequestHeader>() {
+            Ok(header) => header,
+            Err(err) => return error_response(err.to_string()),
+        };
+        debug!(consumer_group = %header.consumer_group, client_id = %header.client_id, jstack_enable = header.jstack_enable, "receive get consumer running info request");
+        let consumer = self.consumers.lock().get(&header.consumer_group).cloned();
+        let consumer = match consumer {
+            Some(consumer) => consumer,
+            None => {
+                return error_response(format!(
+                    "the consumer group {} not exist in this consumer",
+                    header.consumer_group
+                ))
+            }
+        };
+        match consumer.running_info().await.encode() {
+            Ok(body) => RemotingCommand::response(ResponseCode::Success, String::new(), body),
+            Err(err) => error_response(err.to_string()),
+        }
+    }
+
+    /// Ask the producer's transaction checker for the state of a half message and end its
+    /// transaction accordingly. Nothing is sent back if the check fails, broker checks the
+    /// message again later until its check times run out
+    async fn check_transaction_state(&self, addr: &str, request: &RemotingCommand) {
+        let header = match request.decode_header::<CheckTransactionStateRequestHeader>() {
+            Ok(header) => header,
+            Err(err) => {
+                warn!(broker = %addr, "invalid check transaction state request: {:?}", err);
+                return;
+            }
+        };
+        let mut msg = match MessageExt::decode(&request.body).map(|mut msgs| msgs.pop()) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => {
+                warn!(broker = %addr, "no message found in check transaction state request");
+                return;
+            }
+            Err(err) => {
+                warn!(broker = %addr, "invalid check transaction state request body: {:?}", err);
+                return;
+            }
+        };
+        let group = match msg.message.get_property(Property::PRODUCER_GROUP) {
+            Some(group) => group.clone(),
+            None => {
+                warn!(broker = %addr, msg_id = %header.msg_id, "check transaction state, but producer group of message not found");
+                return;
+            }
+        };
+        let producer = self.producers.lock().get(&group).cloned();
+        let checker = match producer.and_then(|producer| producer.lock().transaction_checker()) {
+            Some(checker) => checker,
+            None => {
+                warn!(producer_group = %group, msg_id = %header.msg_id, "check transaction state, but no transaction checker registered");
+                return;
+            }
+        };
+        let msg_id = msg
+            .message
+            .unique_key()
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| msg.msg_id.clone());
+        if !header.transaction_id.is_empty() {
+            msg.message
+                .set_transaction_id(header.transaction_id.clone());
+        }
+        // checkers see topics without the namespace
+        msg.message.topic = self.unwrap_namespace(&msg.message.topic);
+        let (state, remark) = match runtime::spawn_blocking(move || checker(&msg)).await {
+            Ok(state) => (state, String::new()),
+            Err(err) => (
+                LocalTransactionState::Unknown,
+                format!("check local transaction state failed: {}", err),
+            ),
+        };
+        debug!(producer_group = %group, msg_id = %msg_id, state = ?state, "end transaction of check back");
+        let end_header = EndTransactionRequestHeader {
+            producer_group: group,
+            tran_state_table_offset: header.tran_state_table_offset,
+            commit_log_offset: header.commit_log_offset,
+            commit_or_rollback: state.sys_flag(),
+            from_transaction_check: true,
+            msg_id,
+            transaction_id: header.transaction_id,
+        };
+        let mut cmd =
+            RemotingCommand::with_header(RequestCode::EndTransaction, end_header, Vec::new());
+        cmd.header.remark = remark;
+        if let Err(err) = self.invoke_oneway(addr, cmd).await {
+            warn!(broker = %addr, "end transaction of check back failed: {:?}", err);
+        }
+    }
+
+    async fn reset_consumer_offset(&self, request: &RemotingCommand) {
+        let header = match request.decode_header::<ResetOffsetRequestHeader>() {
+            Ok(header) => header,
+            Err(err) => {
+                warn!("invalid reset consumer offset request: {:?}", err);
+                return;
+            }
+        };
+        let body = match ResetOffsetBody::decode(&request.body) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!("invalid reset consumer offset request body: {:?}", err);
+                return;
+            }
+        };
+        info!(consumer_group = %header.group, topic = %header.topic, timestamp = header.timestamp, is_force = header.is_force, "receive reset consumer offset request");
+        let consumer = self.consumers.lock().get(&header.group).cloned();
+        match consumer {
+            Some(consumer) => {
+                consumer
+                    .reset_offset(&header.topic, &body.offset_table)
+                    .await
+            }
+            None => {
+                warn!(consumer_group = %header.group, "reset offset, but consumer group not found")
+            }
+        }
+    }
+
+    pub(crate) fn register_consumer(&self, group: &str, consumer: Arc<ConsumerInner>) {
         let mut consumers = self.consumers.lock();
         consumers.entry(group.to_string()).or_insert(consumer);
     }
@@ -377,15 +1192,22 @@ where
         producers.remove(group);
     }
 
-    fn rebalance_immediately(&self) {
-        let consumers = self.consumers.lock();
-        for consumer in consumers.values() {
-            consumer.lock().rebalance();
+    pub(crate) async fn rebalance_immediately(&self) {
+        let consumers: Vec<Arc<ConsumerInner>> = self.consumers.lock().values().cloned().collect();
+        for consumer in consumers {
+            consumer.rebalance().await;
+        }
+    }
+
+    async fn persist_consumer_offset(&self) {
+        let consumers: Vec<Arc<ConsumerInner>> = self.consumers.lock().values().cloned().collect();
+        for consumer in consumers {
+            consumer.persist_consumer_offset().await;
         }
     }
 
-    async fn send_heartbeat_to_all_brokers(&self) {
-        use model::{HeartbeatData, ProducerData};
+    pub(crate) async fn send_heartbeat_to_all_brokers(&self) {
+        use model::{ConsumerData, HeartbeatData, ProducerData};
 
         let producer_data_set: Vec<ProducerData> = self
             .producers
@@ -395,8 +1217,12 @@ where
                 group_name: group_name.clone(),
             })
             .collect();
-        // FIXME
-        let consumer_data_set = Vec::new();
+        let consumer_data_set: Vec<ConsumerData> = self
+            .consumers
+            .lock()
+            .values()
+            .map(|consumer| consumer.consumer_data())
+            .collect();
         if producer_data_set.is_empty() && consumer_data_set.is_empty() {
             debug!("sending heartbeat, but no producer and no consumer found");
             return;
@@ -412,59 +1238,60 @@ where
             consumer_data_set,
         };
         let hb_bytes = serde_json::to_vec(&heartbeat_data).unwrap();
-        for (broker_name, broker_data) in broker_address_map {
+        let mut heartbeats = Vec::new();
+        for (broker_name, broker_data) in &broker_address_map {
             for (id, addr) in &broker_data.broker_addrs {
-                if heartbeat_data.consumer_data_set.is_empty() && *id != 0 {
+                // slaves only serve consumers
+                if heartbeat_data.consumer_data_set.is_empty() && *id != MASTER_ID {
                     continue;
                 }
-                debug!(
-                    broker_name = %broker_name,
-                    broker_id = id,
-                    broker_addr = %addr,
-                    "try to send heart beat to broker",
-                );
-                let cmd = RemotingCommand::new(
-                    RequestCode::Heartbeat.into(),
-                    0,
-                    String::new(),
+                let cmd = RemotingCommand::with_header(
+                    RequestCode::Heartbeat,
                     HashMap::new(),
                     hb_bytes.clone(),
                 );
-                match time::timeout(
-                    time::Duration::from_secs(3),
-                    self.remote_client.invoke(addr, cmd),
-                )
-                .await
-                {
-                    Ok(Ok(res)) => match ResponseCode::try_from(res.code()) {
-                        Ok(ResponseCode::Success) => {
-                            self.name_server.add_broker_version(
-                                &broker_name,
-                                addr,
-                                res.header.version as i32,
-                            );
-                            info!(
-                                broker_name = %broker_name,
-                                broker_id = id,
-                                broker_addr = %addr,
-                                "send heart beat to broker success",
-                            );
-                        }
-                        _ => {
-                            warn!(
-                                broker_name = %broker_name,
-                                broker_id = id,
-                                broker_addr = %addr,
-                                code = res.code(),
-                                "send heart beat to broker failed",
-                            );
-                        }
-                    },
-                    Ok(Err(err)) => warn!("send heart beat to broker {} error {:?}", id, err),
-                    Err(_) => warn!("send heart beat to broker {} timed out", id),
-                }
+                heartbeats.push(self.send_heartbeat(broker_name, *id, addr, cmd));
             }
         }
+        // a slow broker doesn't delay heartbeats to the others
+        futures::future::join_all(heartbeats).await;
+    }
+
+    async fn send_heartbeat(&self, broker_name: &str, id: i64, addr: &str, cmd: RemotingCommand) {
+        debug!(
+            broker_name = %broker_name,
+            broker_id = id,
+            broker_addr = %addr,
+            "try to send heart beat to broker",
+        );
+        match runtime::timeout(Duration::from_secs(3), self.invoke(addr, cmd)).await {
+            Ok(Ok(res)) => match ResponseCode::try_from(res.code()) {
+                Ok(ResponseCode::Success) => {
+                    self.name_server.add_broker_version(
+                        broker_name,
+                        addr,
+                        res.header.version as i32,
+                    );
+                    info!(
+                        broker_name = %broker_name,
+                        broker_id = id,
+                        broker_addr = %addr,
+                        "send heart beat to broker success",
+                    );
+                }
+                _ => {
+                    warn!(
+                        broker_name = %broker_name,
+                        broker_id = id,
+                        broker_addr = %addr,
+                        code = res.code(),
+                        "send heart beat to broker failed",
+                    );
+                }
+            },
+            Ok(Err(err)) => warn!("send heart beat to broker {} error {:?}", addr, err),
+            Err(_) => warn!("send heart beat to broker {} timed out", addr),
+        }
     }
 
     pub fn update_publish_info(&self, topic: &str, data: TopicRouteData, changed: bool) {
@@ -477,11 +1304,9 @@ where
         let producers = self.producers.lock();
         for producer in producers.values() {
             let mut producer = producer.lock();
-            let updated = if changed {
-                true
-            } else {
-                producer.is_publish_topic_need_update(topic)
-            };
+            let updated = changed
+                || producer.is_publish_topic_need_update(topic)
+                || producer.is_publish_route_changed(topic, &data);
             if updated {
                 let mut publish_info = data.to_publish_info(topic);
                 publish_info.have_topic_router_info = true;
@@ -490,32 +1315,74 @@ where
         }
     }
 
-    async fn update_topic_route_info(&self) {
+    /// Update readable queues of consumers subscribing `topic`, returns whether those of any
+    /// consumer changed
+    pub fn update_subscribe_info(&self, topic: &str, data: &TopicRouteData) -> bool {
+        let consumers = self.consumers.lock();
+        let mut changed = false;
+        for consumer in consumers.values() {
+            changed |= consumer.update_topic_subscribe_info(topic, data);
+        }
+        changed
+    }
+
+    /// Switch to name servers `addrs`, then refresh routes of topics against them and send
+    /// heartbeats to brokers they route to
+    pub(crate) async fn update_name_server_addrs(&self, addrs: Vec<String>) -> Result<(), Error> {
+        self.name_server.update_addrs(addrs)?;
+        self.update_topic_route_info().await;
+        self.send_heartbeat_to_all_brokers().await;
+        Ok(())
+    }
+
+    pub(crate) async fn update_topic_route_info(&self) {
         let mut topics = HashSet::new();
         {
             let producers = self.producers.lock();
             for producer in producers.values() {
                 topics.extend(producer.lock().publish_topic_list());
             }
+            let consumers = self.consumers.lock();
+            for consumer in consumers.values() {
+                topics.extend(consumer.subscribed_topics());
+            }
         }
         if topics.is_empty() {
             debug!("updating topic route info, but no topics found");
             return;
         }
         info!("update route info for topics: {:?}", topics);
+        let mut subscribe_info_changed = false;
         for topic in &topics {
+            let old_route_data = self.name_server.topic_route_data(topic);
             match self.name_server.update_topic_route_info(topic).await {
                 Ok((route_data, changed)) => {
                     info!(route_data = ?route_data, changed = changed, "topic route info updated");
+                    let diff = route_data.diff(topic, old_route_data.as_ref());
+                    if !diff.is_empty() {
+                        info!(topic = %topic, diff = ?diff, "topic route changed");
+                    }
+                    subscribe_info_changed |= self.update_subscribe_info(topic, &route_data);
                     self.update_publish_info(topic, route_data, changed);
                 }
                 Err(err) => error!("update topic {} route info failed: {:?}", topic, err),
             }
         }
+        // rather than waiting for the next scheduled rebalance
+        if subscribe_info_changed {
+            info!("subscribed queues changed, rebalance immediately");
+            self.rebalance_immediately().await;
+        }
     }
 
     async fn unregister_client(&self, producer_group: &str, consumer_group: &str) {
-        for broker_data in self.name_server.broker_address_map().values() {
+        // released by an admin, which registers no group
+        if producer_group.is_empty() && consumer_group.is_empty() {
+            return;
+        }
+        let broker_address_map = self.name_server.broker_address_map();
+        let mut requests = Vec::new();
+        for broker_data in broker_address_map.values() {
             for broker_addr in broker_data.broker_addrs.values() {
                 let header = UnregisterClientRequestHeader {
                     client_id: self.id(),
@@ -524,16 +1391,24 @@ where
                 };
                 let cmd =
                     RemotingCommand::with_header(RequestCode::UnregisterClient, header, Vec::new());
-                match self.remote_client.invoke(broker_addr, cmd).await {
-                    Ok(res) => {
-                        if res.code() != ResponseCode::Success {
-                            warn!(code = res.code(), remark = %res.header.remark, "unregister client failed");
+                requests.push(async move {
+                    match self
+                        .invoke_sync(broker_addr, cmd, Duration::from_secs(3))
+                        .await
+                    {
+                        Ok(res) => {
+                            if res.code() != ResponseCode::Success {
+                                warn!(broker = %broker_addr, code = res.code(), remark = %res.header.remark, "unregister client failed");
+                            }
+                        }
+                        Err(err) => {
+                            warn!(broker = %broker_addr, "unregister client failed: {:?}", err)
                         }
                     }
-                    Err(err) => warn!("unregister client failed: {:?}", err),
-                }
+                });
             }
         }
+        futures::future::join_all(requests).await;
     }
 
     pub async fn create_topic(
@@ -551,27 +1426,10 @@ where
         for broker_data in broker_datas {
             if let Some(addr) = broker_data.broker_addrs.get(&MASTER_ID) {
                 for _ in 0..5usize {
-                    let header = CreateTopicRequestHeader {
-                        topic: new_topic.topic_name.clone(),
-                        default_topic: "TBW102".to_string(), // FIXME
-                        read_queue_nums: new_topic.read_queue_nums,
-                        write_queue_nums: new_topic.write_queue_nums,
-                        permission: new_topic.permission.bits(),
-                        topic_filter_type: new_topic.topic_filter_type.to_string(),
-                        topic_sys_flag: new_topic.topic_sys_flag,
-                        order: new_topic.order,
-                    };
-                    let cmd = RemotingCommand::with_header(
-                        RequestCode::UpdateAndCreateTopic,
-                        header,
-                        Vec::new(),
-                    );
-                    match self.remote_client.invoke(addr, cmd).await {
-                        Ok(res) => {
-                            if res.code() == ResponseCode::Success {
-                                create_ok_at_least_once = true;
-                                break;
-                            }
+                    match self.update_and_create_topic(addr, new_topic).await {
+                        Ok(()) => {
+                            create_ok_at_least_once = true;
+                            break;
                         }
                         Err(err) => last_error = Some(err),
                     }
@@ -586,44 +1444,116 @@ where
         Ok(())
     }
 
+    /// Create `config` on the broker at `addr` with `UPDATE_AND_CREATE_TOPIC`, or update
+    /// it if the topic exists
+    pub async fn update_and_create_topic(
+        &self,
+        addr: &str,
+        config: &model::TopicConfig,
+    ) -> Result<(), Error> {
+        let header = CreateTopicRequestHeader {
+            topic: config.topic_name.clone(),
+            default_topic: AUTO_CREATE_TOPIC_KEY.to_string(),
+            read_queue_nums: config.read_queue_nums,
+            write_queue_nums: config.write_queue_nums,
+            permission: config.permission.bits(),
+            topic_filter_type: config.topic_filter_type.to_string(),
+            topic_sys_flag: config.topic_sys_flag,
+            order: config.order,
+        };
+        let cmd =
+            RemotingCommand::with_header(RequestCode::UpdateAndCreateTopic, header, Vec::new());
+        let res = self.invoke(addr, cmd).await?;
+        if res.code() != ResponseCode::Success {
+            return Err(res.into_broker_error(addr));
+        }
+        Ok(())
+    }
+
+    /// Messages of the index of the broker at `addr` matching `header` with `QUERY_MESSAGE`,
+    /// empty if none was found
+    pub async fn query_message(
+        &self,
+        addr: &str,
+        header: QueryMessageRequestHeader,
+        timeout: Duration,
+    ) -> Result<Vec<MessageExt>, Error> {
+        let cmd = RemotingCommand::with_header(RequestCode::QueryMessage, header, Vec::new());
+        let res = self.invoke_sync(addr, cmd, timeout).await?;
+        match ResponseCode::try_from(res.code()) {
+            Ok(ResponseCode::Success) => MessageExt::decode(res.body()),
+            Ok(ResponseCode::QueryNotFound) => Ok(Vec::new()),
+            _ => Err(res.into_broker_error(addr)),
+        }
+    }
+
+    /// Delete `topic` from the broker at `addr` with `DELETE_TOPIC_IN_BROKER`
+    pub async fn delete_topic_in_broker(&self, addr: &str, topic: &str) -> Result<(), Error> {
+        let header = DeleteTopicRequestHeader {
+            topic: topic.to_string(),
+        };
+        let cmd =
+            RemotingCommand::with_header(RequestCode::DeleteTopicInBroker, header, Vec::new());
+        let res = self.invoke(addr, cmd).await?;
+        if res.code() != ResponseCode::Success {
+            return Err(res.into_broker_error(addr));
+        }
+        Ok(())
+    }
+
     pub async fn send_message_back(
         &self,
         broker_addr: &str,
+        group: &str,
         msg: &MessageExt,
         delay_level: i32,
         max_reconsume_times: i32,
     ) -> Result<(), Error> {
         let header = ConsumerSendMsgBackRequestHeader {
             offset: msg.commit_log_offset,
-            group: self.options.group_name.clone(),
+            group: group.to_string(),
             delay_level,
-            origin_msg_id: msg.msg_id.clone(),
+            // retried messages keep the ID of the message first sent
+            origin_msg_id: msg.origin_message_id().to_string(),
             origin_topic: msg.message.topic.clone(),
             unit_mode: self.options.unit_mode,
             max_reconsume_times,
         };
         let cmd =
             RemotingCommand::with_header(RequestCode::ConsumerSendMsgBack, header, Vec::new());
-        let res = self.remote_client.invoke(broker_addr, cmd).await?;
+        let res = self.invoke(broker_addr, cmd).await?;
         if res.code() == ResponseCode::Success {
             Ok(())
         } else {
-            Err(Error::ResponseError {
-                code: res.code(),
-                message: res.header.remark,
-            })
+            Err(res.into_broker_error(broker_addr))
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{model::TopicConfig, Client, ClientOptions};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::{mpsc, oneshot};
+    use tokio_util::codec::Framed;
+
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use super::{model::TopicConfig, Client, ClientOptions, ClientState, RequestProcessor};
     use crate::namesrv::NameServer;
+    use crate::protocol::{MqCodec, RemotingCommand, RequestCode, ResponseCode};
     use crate::resolver::{Resolver, StaticResolver};
 
     fn new_client() -> Client<Resolver> {
-        let options = ClientOptions::default();
+        new_client_with_options(ClientOptions::default())
+    }
+
+    fn new_client_with_options(options: ClientOptions) -> Client<Resolver> {
         let name_server = NameServer::new(
             Resolver::Static(StaticResolver::new(vec!["localhost:9876".to_string()])),
             options.credentials.clone(),
@@ -640,4 +1570,188 @@ mod test {
             .await
             .unwrap();
     }
+
+    /// Broker answering requests with their code as remark, oneway requests are forwarded
+    async fn echo_broker() -> (String, mpsc::UnboundedReceiver<RemotingCommand>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (oneway_tx, oneway_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, MqCodec::default());
+            while let Some(Ok(req)) = framed.next().await {
+                if req.is_oneway() {
+                    let _ = oneway_tx.send(req);
+                    continue;
+                }
+                let mut res = RemotingCommand::response(
+                    ResponseCode::Success,
+                    req.code().to_string(),
+                    Vec::new(),
+                );
+                res.set_opaque(req.opaque());
+                framed.send(res).await.unwrap();
+            }
+        });
+        (addr, oneway_rx)
+    }
+
+    #[tokio::test]
+    async fn test_client_invoke() {
+        let (addr, mut oneway_rx) = echo_broker().await;
+        let client = new_client();
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        let res = client
+            .invoke_sync(&addr, cmd, Duration::from_secs(3))
+            .await
+            .unwrap();
+        assert_eq!("10", res.header.remark);
+
+        let (tx, rx) = oneshot::channel();
+        let cmd = RemotingCommand::new(11, 0, String::new(), HashMap::new(), Vec::new());
+        client.invoke_async(&addr, cmd, Duration::from_secs(3), move |res| {
+            let _ = tx.send(res.map(|res| res.header.remark));
+        });
+        assert_eq!("11", rx.await.unwrap().unwrap());
+
+        let cmd = RemotingCommand::new(12, 0, String::new(), HashMap::new(), Vec::new());
+        client.invoke_oneway(&addr, cmd).await.unwrap();
+        assert_eq!(12, oneway_rx.recv().await.unwrap().code());
+    }
+
+    struct CheckTransactionProcessor;
+
+    #[async_trait]
+    impl RequestProcessor for CheckTransactionProcessor {
+        async fn process_request(
+            &self,
+            _addr: &str,
+            _request: &RemotingCommand,
+        ) -> Option<RemotingCommand> {
+            Some(RemotingCommand::response(
+                ResponseCode::Success,
+                "processed".to_string(),
+                Vec::new(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_dispatch_broker_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (responses_tx, mut responses_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, MqCodec::default());
+            // wait for the client to connect
+            framed.next().await.unwrap().unwrap();
+            for (opaque, code) in [
+                (100, RequestCode::CheckTransactionState.into()),
+                (101, 9999),
+            ] {
+                let mut req =
+                    RemotingCommand::new(code, 0, String::new(), HashMap::new(), Vec::new());
+                req.set_opaque(opaque);
+                framed.send(req).await.unwrap();
+                let res = framed.next().await.unwrap().unwrap();
+                let _ = responses_tx.send(res);
+            }
+        });
+
+        let mut options = ClientOptions::default();
+        options.register_processor(
+            RequestCode::CheckTransactionState,
+            Arc::new(CheckTransactionProcessor),
+        );
+        let client = new_client_with_options(options);
+        client.start();
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        client.invoke_oneway(&addr, cmd).await.unwrap();
+
+        let res = responses_rx.recv().await.unwrap();
+        assert!(res.is_response());
+        assert_eq!(100, res.opaque());
+        assert_eq!("processed", res.header.remark);
+
+        let res = responses_rx.recv().await.unwrap();
+        assert_eq!(101, res.opaque());
+        assert!(res.code() == ResponseCode::RequestCodeNotSupported);
+        client.shutdown("", "", Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_shutdown_stops_background_tasks() {
+        let client = new_client();
+        client.start();
+        assert_eq!(8, client.tasks.lock().len());
+        let handles: Vec<_> = client
+            .tasks
+            .lock()
+            .iter()
+            .map(|task| task.abort_handle())
+            .collect();
+
+        let start = tokio::time::Instant::now();
+        client.shutdown("", "", Duration::from_secs(5)).await;
+        // tasks stop on the shutdown signal rather than being aborted at the deadline
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(handles.iter().all(|handle| !handle.is_aborted()));
+        assert!(client.tasks.lock().is_empty());
+        assert!(matches!(client.state(), ClientState::Shutdown));
+    }
+
+    #[tokio::test]
+    async fn test_client_unregister_on_shutdown() {
+        // serves as both name server and broker
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let route = format!(
+            r#"{{"queueDatas":[],"brokerDatas":[{{"cluster":"DefaultCluster","brokerName":"broker-a","brokerAddrs":{{"0":"{}"}}}}],"filterServerTable":{{}}}}"#,
+            addr
+        );
+        let (unregister_tx, mut unregister_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let route = route.clone();
+                let unregister_tx = unregister_tx.clone();
+                tokio::spawn(async move {
+                    let mut framed = Framed::new(socket, MqCodec::default());
+                    while let Some(Ok(req)) = framed.next().await {
+                        let body = if req.code() == RequestCode::GetRouteInfoByTopic {
+                            route.as_bytes().to_vec()
+                        } else {
+                            Vec::new()
+                        };
+                        if req.code() == RequestCode::UnregisterClient {
+                            let _ = unregister_tx.send(req.header.ext_fields.clone());
+                        }
+                        let mut res =
+                            RemotingCommand::response(ResponseCode::Success, String::new(), body);
+                        res.set_opaque(req.opaque());
+                        framed.send(res).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let options = ClientOptions::default();
+        let name_server =
+            NameServer::new(Resolver::Static(StaticResolver::new(vec![addr])), None).unwrap();
+        let client = Client::new(options, name_server);
+        client
+            .name_server
+            .update_topic_route_info("TopicTest")
+            .await
+            .unwrap();
+        client.release("ProducerGroup", "");
+        let fields = unregister_rx.recv().await.unwrap();
+        assert_eq!("ProducerGroup", fields["producerGroup"]);
+        assert_eq!(client.id(), fields["clientID"]);
+
+        // shut down already, not unregistered again
+        client.release("ProducerGroup", "");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(unregister_rx.try_recv().is_err());
+    }
 }
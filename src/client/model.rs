@@ -1,9 +1,12 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 
+use crate::message::MessageQueue;
 use crate::permission::Permission;
+use crate::route::MASTER_ID;
+use crate::Error;
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct ProducerData {
@@ -92,4 +95,344 @@ impl TopicConfig {
             order: false,
         }
     }
+
+    pub fn topic_name(&self) -> &str {
+        &self.topic_name
+    }
+
+    pub fn set_read_queue_nums(&mut self, queue_nums: u32) -> &mut Self {
+        self.read_queue_nums = queue_nums;
+        self
+    }
+
+    pub fn set_write_queue_nums(&mut self, queue_nums: u32) -> &mut Self {
+        self.write_queue_nums = queue_nums;
+        self
+    }
+
+    /// Read and write by default
+    pub fn set_permission(&mut self, permission: Permission) -> &mut Self {
+        self.permission = permission;
+        self
+    }
+
+    /// Whether messages of the topic are ordered within each queue
+    pub fn set_order(&mut self, order: bool) -> &mut Self {
+        self.order = order;
+        self
+    }
+}
+
+/// Config of a consumer group on brokers, body of `UpdateAndCreateSubscriptionGroup` request
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SubscriptionGroupConfig {
+    #[serde(rename = "groupName")]
+    pub(crate) group_name: String,
+    #[serde(rename = "consumeEnable")]
+    pub(crate) consume_enable: bool,
+    #[serde(rename = "consumeFromMinEnable")]
+    pub(crate) consume_from_min_enable: bool,
+    #[serde(rename = "consumeBroadcastEnable")]
+    pub(crate) consume_broadcast_enable: bool,
+    #[serde(rename = "retryQueueNums")]
+    pub(crate) retry_queue_nums: i32,
+    #[serde(rename = "retryMaxTimes")]
+    pub(crate) retry_max_times: i32,
+    #[serde(rename = "brokerId")]
+    pub(crate) broker_id: i64,
+    #[serde(rename = "whichBrokerWhenConsumeSlowly")]
+    pub(crate) which_broker_when_consume_slowly: i64,
+    #[serde(rename = "notifyConsumerIdsChangedEnable")]
+    pub(crate) notify_consumer_ids_changed_enable: bool,
+}
+
+impl SubscriptionGroupConfig {
+    /// Defaults of brokers, 1 retry queue and 16 retries, consuming from the master
+    pub fn new<S: Into<String>>(group_name: S) -> Self {
+        Self {
+            group_name: group_name.into(),
+            consume_enable: true,
+            consume_from_min_enable: true,
+            consume_broadcast_enable: true,
+            retry_queue_nums: 1,
+            retry_max_times: 16,
+            broker_id: MASTER_ID,
+            which_broker_when_consume_slowly: 1,
+            notify_consumer_ids_changed_enable: true,
+        }
+    }
+
+    pub fn group_name(&self) -> &str {
+        &self.group_name
+    }
+
+    /// Whether consumers of the group may pull messages
+    pub fn set_consume_enable(&mut self, enable: bool) -> &mut Self {
+        self.consume_enable = enable;
+        self
+    }
+
+    pub fn set_consume_broadcast_enable(&mut self, enable: bool) -> &mut Self {
+        self.consume_broadcast_enable = enable;
+        self
+    }
+
+    pub fn set_retry_queue_nums(&mut self, queue_nums: i32) -> &mut Self {
+        self.retry_queue_nums = queue_nums;
+        self
+    }
+
+    /// Times messages are redelivered before they go to the dead letter queue
+    pub fn set_retry_max_times(&mut self, times: i32) -> &mut Self {
+        self.retry_max_times = times;
+        self
+    }
+
+    /// Broker consumers pull from
+    pub fn set_broker_id(&mut self, broker_id: i64) -> &mut Self {
+        self.broker_id = broker_id;
+        self
+    }
+
+    /// Broker consumers pull from when they lag behind too much for the master
+    pub fn set_which_broker_when_consume_slowly(&mut self, broker_id: i64) -> &mut Self {
+        self.which_broker_when_consume_slowly = broker_id;
+        self
+    }
+
+    /// Whether consumers are told to rebalance at once when others join or leave the group
+    pub fn set_notify_consumer_ids_changed_enable(&mut self, enable: bool) -> &mut Self {
+        self.notify_consumer_ids_changed_enable = enable;
+        self
+    }
+}
+
+/// Body of `ResetConsumerClientOffset` request
+///
+/// Broker serializes the offset table with fastjson which uses JSON objects as map keys,
+/// like `{"offsetTable":{{"brokerName":"a","queueId":0,"topic":"t"}:100}}`,
+/// so it can't be deserialized by serde_json directly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResetOffsetBody {
+    pub offset_table: HashMap<MessageQueue, i64>,
+}
+
+impl ResetOffsetBody {
+    pub fn decode(body: &[u8]) -> Result<Self, Error> {
+        let s = String::from_utf8(body.to_vec())?;
+        let offset_table = decode_queue_table(&s, "offsetTable")?;
+        Ok(Self { offset_table })
+    }
+}
+
+/// Decode the fastjson map of `field` in `s` keyed by message queue objects, empty if `s`
+/// has no such field
+pub(crate) fn decode_queue_table<V: DeserializeOwned>(
+    s: &str,
+    field: &str,
+) -> Result<HashMap<MessageQueue, V>, Error> {
+    let invalid = || Error::InvalidHeader(format!("invalid {}: {}", field, s));
+    let mut table = HashMap::new();
+    let key = format!("\"{}\"", field);
+    let start = match s.find(&key) {
+        Some(pos) => pos + key.len(),
+        None => return Ok(table),
+    };
+    let rest = s[start..].trim_start();
+    let rest = rest.strip_prefix(':').ok_or_else(invalid)?.trim_start();
+    let mut rest = rest.strip_prefix('{').ok_or_else(invalid)?;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.is_empty() || rest.starts_with('}') {
+            break;
+        }
+        let key_end = rest.find('}').ok_or_else(invalid)? + 1;
+        let mq: MessageQueue = serde_json::from_str(&rest[..key_end])?;
+        rest = rest[key_end..].trim_start();
+        rest = rest.strip_prefix(':').ok_or_else(invalid)?;
+        // values are read up to their ends, objects and numbers alike
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter::<V>();
+        let value = values.next().ok_or_else(invalid)??;
+        rest = &rest[values.byte_offset()..];
+        table.insert(mq, value);
+    }
+    Ok(table)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ConsumeMessageDirectlyResultType {
+    #[serde(rename = "CR_SUCCESS")]
+    Success,
+    #[serde(rename = "CR_LATER")]
+    Later,
+    #[serde(rename = "CR_ROLLBACK")]
+    Rollback,
+    #[serde(rename = "CR_COMMIT")]
+    Commit,
+    #[serde(rename = "CR_THROW_EXCEPTION")]
+    ThrowException,
+}
+
+/// Response body of `ConsumeMessageDirectly` request
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsumeMessageDirectlyResult {
+    pub order: bool,
+    #[serde(rename = "autoCommit")]
+    pub auto_commit: bool,
+    #[serde(rename = "consumeResult")]
+    pub consume_result: ConsumeMessageDirectlyResultType,
+    pub remark: String,
+    #[serde(rename = "spentTimeMills")]
+    pub spent_time_millis: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessQueueInfo {
+    #[serde(rename = "commitOffset")]
+    pub commit_offset: i64,
+    #[serde(rename = "cachedMsgMinOffset")]
+    pub cached_msg_min_offset: i64,
+    #[serde(rename = "cachedMsgMaxOffset")]
+    pub cached_msg_max_offset: i64,
+    #[serde(rename = "cachedMsgCount")]
+    pub cached_msg_count: usize,
+    #[serde(rename = "cachedMsgSizeInMiB")]
+    pub cached_msg_size_in_mib: usize,
+    #[serde(rename = "transactionMsgMinOffset")]
+    pub transaction_msg_min_offset: i64,
+    #[serde(rename = "transactionMsgMaxOffset")]
+    pub transaction_msg_max_offset: i64,
+    #[serde(rename = "transactionMsgCount")]
+    pub transaction_msg_count: usize,
+    pub locked: bool,
+    #[serde(rename = "tryUnlockTimes")]
+    pub try_unlock_times: i64,
+    #[serde(rename = "lastLockTimestamp")]
+    pub last_lock_timestamp: i64,
+    // the misspelling comes from Java client
+    #[serde(rename = "droped")]
+    pub dropped: bool,
+    #[serde(rename = "lastPullTimestamp")]
+    pub last_pull_timestamp: i64,
+    #[serde(rename = "lastConsumeTimestamp")]
+    pub last_consume_timestamp: i64,
+}
+
+/// Response body of `GetConsumerRunningInfo` request
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerRunningInfo {
+    pub properties: HashMap<String, String>,
+    pub subscription_set: Vec<SubscriptionData>,
+    pub mq_table: BTreeMap<MessageQueue, ProcessQueueInfo>,
+}
+
+impl ConsumerRunningInfo {
+    /// Encode in fastjson style, `mqTable` uses message queue objects as map keys
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"{\"properties\":");
+        serde_json::to_writer(&mut buf, &self.properties)?;
+        buf.extend_from_slice(b",\"statusTable\":{},\"subscriptionSet\":");
+        serde_json::to_writer(&mut buf, &self.subscription_set)?;
+        buf.extend_from_slice(b",\"mqTable\":{");
+        for (i, (mq, info)) in self.mq_table.iter().enumerate() {
+            if i > 0 {
+                buf.push(b',');
+            }
+            serde_json::to_writer(&mut buf, mq)?;
+            buf.push(b':');
+            serde_json::to_writer(&mut buf, info)?;
+        }
+        buf.extend_from_slice(b"}}");
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ConsumeMessageDirectlyResult, ConsumeMessageDirectlyResultType, ConsumerRunningInfo,
+        ProcessQueueInfo, ResetOffsetBody, SubscriptionGroupConfig,
+    };
+    use crate::message::MessageQueue;
+
+    #[test]
+    fn test_encode_subscription_group_config() {
+        let mut config = SubscriptionGroupConfig::new("GroupA");
+        config.set_retry_queue_nums(2).set_broker_id(1);
+        assert_eq!(
+            r#"{"groupName":"GroupA","consumeEnable":true,"consumeFromMinEnable":true,"consumeBroadcastEnable":true,"retryQueueNums":2,"retryMaxTimes":16,"brokerId":1,"whichBrokerWhenConsumeSlowly":1,"notifyConsumerIdsChangedEnable":true}"#,
+            serde_json::to_string(&config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_reset_offset_body() {
+        let body = br#"{"offsetTable":{{"brokerName":"broker-a","queueId":0,"topic":"test"}:100,{"brokerName":"broker-a","queueId":1,"topic":"test"}:200}}"#;
+        let body = ResetOffsetBody::decode(body).unwrap();
+        assert_eq!(2, body.offset_table.len());
+        let mq = MessageQueue {
+            topic: "test".to_string(),
+            broker_name: "broker-a".to_string(),
+            queue_id: 1,
+        };
+        assert_eq!(Some(&200), body.offset_table.get(&mq));
+
+        let body = ResetOffsetBody::decode(br#"{"offsetTable":{}}"#).unwrap();
+        assert!(body.offset_table.is_empty());
+        assert!(ResetOffsetBody::decode(br#"{"offsetTable":{{"topic":1}}}"#).is_err());
+    }
+
+    #[test]
+    fn test_encode_consume_message_directly_result() {
+        let result = ConsumeMessageDirectlyResult {
+            order: false,
+            auto_commit: true,
+            consume_result: ConsumeMessageDirectlyResultType::Success,
+            remark: String::new(),
+            spent_time_millis: 10,
+        };
+        assert_eq!(
+            r#"{"order":false,"autoCommit":true,"consumeResult":"CR_SUCCESS","remark":"","spentTimeMills":10}"#,
+            serde_json::to_string(&result).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_consumer_running_info() {
+        let mut info = ConsumerRunningInfo::default();
+        info.properties.insert(
+            "PROP_CONSUME_TYPE".to_string(),
+            "CONSUME_PASSIVELY".to_string(),
+        );
+        let mq = MessageQueue {
+            topic: "test".to_string(),
+            broker_name: "broker-a".to_string(),
+            queue_id: 0,
+        };
+        info.mq_table.insert(
+            mq,
+            ProcessQueueInfo {
+                commit_offset: 10,
+                cached_msg_min_offset: 0,
+                cached_msg_max_offset: 0,
+                cached_msg_count: 0,
+                cached_msg_size_in_mib: 0,
+                transaction_msg_min_offset: 0,
+                transaction_msg_max_offset: 0,
+                transaction_msg_count: 0,
+                locked: false,
+                try_unlock_times: 0,
+                last_lock_timestamp: 0,
+                dropped: false,
+                last_pull_timestamp: 0,
+                last_consume_timestamp: 0,
+            },
+        );
+        let encoded = String::from_utf8(info.encode().unwrap()).unwrap();
+        assert!(encoded.starts_with(
+            r#"{"properties":{"PROP_CONSUME_TYPE":"CONSUME_PASSIVELY"},"statusTable":{},"subscriptionSet":[],"mqTable":{{"topic":"test","brokerName":"broker-a","queueId":0}:{"commitOffset":10,"#
+        ));
+        assert!(encoded.ends_with("}}}"));
+    }
 }
@@ -0,0 +1,117 @@
+use crate::topic::{is_system_resource, DLQ_GROUP_TOPIC_PREFIX, RETRY_GROUP_TOPIC_PREFIX};
+
+const NAMESPACE_SEPARATOR: char = '%';
+
+// host names of cloud instance endpoints start with the instance ID, `MQ_INST_xxx_yyy`
+const INSTANCE_ID_PREFIX: &str = "MQ_INST_";
+
+/// Split the retry or dead letter queue topic prefix off a resource
+fn split_prefix(resource: &str) -> (&str, &str) {
+    for prefix in [RETRY_GROUP_TOPIC_PREFIX, DLQ_GROUP_TOPIC_PREFIX] {
+        if let Some(name) = resource.strip_prefix(prefix) {
+            return (prefix, name);
+        }
+    }
+    ("", resource)
+}
+
+fn strip_namespace<'a>(namespace: &str, name: &'a str) -> Option<&'a str> {
+    name.strip_prefix(namespace)
+        .and_then(|name| name.strip_prefix(NAMESPACE_SEPARATOR))
+}
+
+/// Prefix a topic or group with the namespace as `namespace%resource`, retry and dead
+/// letter queue topics keep their prefix in front. System resources and those wrapped
+/// already are returned as is
+pub(crate) fn wrap(namespace: &str, resource: &str) -> String {
+    if namespace.is_empty() || resource.is_empty() {
+        return resource.to_string();
+    }
+    let (prefix, name) = split_prefix(resource);
+    if is_system_resource(name) || strip_namespace(namespace, name).is_some() {
+        return resource.to_string();
+    }
+    format!("{}{}{}{}", prefix, namespace, NAMESPACE_SEPARATOR, name)
+}
+
+/// Instance ID of a cloud instance endpoint like `http://MQ_INST_xxx_yyy.region.mq.aliyuncs.com:80`,
+/// used as the namespace of cloud clients
+pub(crate) fn from_endpoint(endpoint: &str) -> Option<String> {
+    let host = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, host)| host);
+    let (instance_id, _) = host.split_once('.')?;
+    let name = instance_id.strip_prefix(INSTANCE_ID_PREFIX)?;
+    let valid = name.contains('_')
+        && !name.starts_with('_')
+        && !name.ends_with('_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Some(instance_id.to_string())
+    } else {
+        None
+    }
+}
+
+/// Remove the namespace prefix added by `wrap`
+pub(crate) fn unwrap(namespace: &str, resource: &str) -> String {
+    if namespace.is_empty() {
+        return resource.to_string();
+    }
+    let (prefix, name) = split_prefix(resource);
+    match strip_namespace(namespace, name) {
+        Some(name) => format!("{}{}", prefix, name),
+        None => resource.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_endpoint, unwrap, wrap};
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!("TopicTest", wrap("", "TopicTest"));
+        assert_eq!("ns%TopicTest", wrap("ns", "TopicTest"));
+        // wrapped already
+        assert_eq!("ns%TopicTest", wrap("ns", "ns%TopicTest"));
+        assert_eq!("%RETRY%ns%GroupA", wrap("ns", "%RETRY%GroupA"));
+        assert_eq!("%DLQ%ns%GroupA", wrap("ns", "%DLQ%GroupA"));
+        assert_eq!("%RETRY%ns%GroupA", wrap("ns", "%RETRY%ns%GroupA"));
+        // system resources
+        assert_eq!("TBW102", wrap("ns", "TBW102"));
+        assert_eq!("RMQ_SYS_TRACE_TOPIC", wrap("ns", "RMQ_SYS_TRACE_TOPIC"));
+        assert_eq!("DEFAULT_CONSUMER", wrap("ns", "DEFAULT_CONSUMER"));
+    }
+
+    #[test]
+    fn test_unwrap() {
+        assert_eq!("ns%TopicTest", unwrap("", "ns%TopicTest"));
+        assert_eq!("TopicTest", unwrap("ns", "ns%TopicTest"));
+        assert_eq!("TopicTest", unwrap("ns", "TopicTest"));
+        assert_eq!("other%TopicTest", unwrap("ns", "other%TopicTest"));
+        // namespace is a prefix of another one
+        assert_eq!("nsx%TopicTest", unwrap("ns", "nsx%TopicTest"));
+        assert_eq!("%RETRY%GroupA", unwrap("ns", "%RETRY%ns%GroupA"));
+        assert_eq!("%DLQ%GroupA", unwrap("ns", "%DLQ%ns%GroupA"));
+    }
+
+    #[test]
+    fn test_from_endpoint() {
+        assert_eq!(
+            Some("MQ_INST_123_abc".to_string()),
+            from_endpoint("http://MQ_INST_123_abc.cn-hangzhou.mq.aliyuncs.com:80")
+        );
+        assert_eq!(
+            Some("MQ_INST_123_abc".to_string()),
+            from_endpoint("MQ_INST_123_abc.cn-hangzhou.mq.aliyuncs.com:80")
+        );
+        assert_eq!(None, from_endpoint("127.0.0.1:9876"));
+        assert_eq!(None, from_endpoint("http://nameserver.internal:9876"));
+        assert_eq!(
+            None,
+            from_endpoint("MQ_INST_abc.cn-hangzhou.mq.aliyuncs.com:80")
+        );
+        assert_eq!(None, from_endpoint("MQ_INST_123_abc:80"));
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::protocol::{RemotingCommand, RequestCode};
+
+/// Handler of requests initiated by brokers, registered with
+/// `ClientOptions::register_processor` and implemented with `#[async_trait]`
+#[async_trait]
+pub trait RequestProcessor: Send + Sync {
+    /// Process a request sent by broker `addr`, the returned response is sent back unless
+    /// the request is oneway
+    async fn process_request(
+        &self,
+        addr: &str,
+        request: &RemotingCommand,
+    ) -> Option<RemotingCommand>;
+}
+
+/// Request processors registered by request code
+#[derive(Clone, Default)]
+pub(crate) struct ProcessorTable {
+    processors: HashMap<i16, Arc<dyn RequestProcessor>>,
+}
+
+impl fmt::Debug for ProcessorTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codes: Vec<i16> = self.processors.keys().copied().collect();
+        f.debug_struct("ProcessorTable")
+            .field("codes", &codes)
+            .finish()
+    }
+}
+
+impl ProcessorTable {
+    /// Register the processor of a request code, replacing the previous one
+    pub fn register(&mut self, code: RequestCode, processor: Arc<dyn RequestProcessor>) {
+        self.processors.insert(code.into(), processor);
+    }
+
+    pub fn get(&self, code: i16) -> Option<Arc<dyn RequestProcessor>> {
+        self.processors.get(&code).cloned()
+    }
+}
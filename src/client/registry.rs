@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::{namespace, AccessChannel, Client, ClientOptions, ClientState};
+use crate::namesrv::NameServer;
+use crate::resolver::Resolver;
+use crate::Error;
+
+/// Clients shared by producers and consumers of the process, keyed by client ID and name
+/// servers
+static CLIENTS: Lazy<Mutex<HashMap<String, Client<Resolver>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl Client<Resolver> {
+    /// Get the client shared by producers and consumers with the same client ID, namespace
+    /// and name servers, like `MQClientInstance` of the Java client, creating one if there's none or
+    /// the previous one was shut down. A shared client keeps the options it was created
+    /// with, set a different instance name to get a client of its own.
+    ///
+    /// Every client got this way must be released once.
+    pub(crate) fn get_or_create(
+        options: &ClientOptions,
+        resolver: &Resolver,
+    ) -> Result<Self, Error> {
+        let mut options = options.clone();
+        let mut resolver = resolver.clone();
+        if options.access_channel == AccessChannel::Cloud {
+            if options.namespace.is_empty() {
+                if let Some(instance_id) = resolver
+                    .static_addrs()
+                    .iter()
+                    .find_map(|addr| namespace::from_endpoint(addr))
+                {
+                    options.namespace = instance_id;
+                }
+            }
+            resolver.strip_endpoint_scheme();
+        }
+        let instance_id = match options.access_channel {
+            AccessChannel::Cloud => options.namespace.as_str(),
+            AccessChannel::Local => "",
+        };
+        resolver.set_http_tenant(&options.unit_name, instance_id);
+        let key = format!(
+            "{}#{}#{}",
+            options.client_id(),
+            options.namespace,
+            resolver.cache_key()
+        );
+        let mut clients = CLIENTS.lock();
+        // drop clients released by all of their users
+        clients.retain(|_, client| !matches!(client.state(), ClientState::Shutdown));
+        if let Some(client) = clients.get(&key) {
+            if client.retain() {
+                return Ok(client.clone());
+            }
+        }
+        if let Some(proxy) = &options.proxy {
+            resolver.set_proxy(proxy)?;
+        }
+        let name_server = NameServer::with_options(
+            resolver,
+            options.credentials.clone(),
+            options.remoting_options(),
+        )?;
+        let client = Client::new(options, name_server);
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Client;
+    use crate::client::{AccessChannel, ClientOptions, ClientState};
+    use crate::resolver::{Resolver, StaticResolver};
+
+    fn resolver(addr: &str) -> Resolver {
+        Resolver::Static(StaticResolver::new(vec![addr.to_string()]))
+    }
+
+    #[tokio::test]
+    async fn test_share_client() {
+        let mut options = ClientOptions::default();
+        options.set_instance_name("test_share_client");
+        let client1 = Client::get_or_create(&options, &resolver("127.0.0.1:9876")).unwrap();
+        let client2 = Client::get_or_create(&options, &resolver("127.0.0.1:9876")).unwrap();
+        assert!(client1.is_same_instance(&client2));
+
+        // other name servers or client ID get a client of their own
+        let other = Client::get_or_create(&options, &resolver("127.0.0.2:9876")).unwrap();
+        assert!(!client1.is_same_instance(&other));
+        let mut other_options = options.clone();
+        other_options.set_instance_name("test_share_client_other");
+        let other = Client::get_or_create(&other_options, &resolver("127.0.0.1:9876")).unwrap();
+        assert!(!client1.is_same_instance(&other));
+
+        // shut down once released by all of its users
+        client1.release("", "");
+        assert!(!matches!(client2.state(), ClientState::Shutdown));
+        client2.release("", "");
+        assert!(matches!(client2.state(), ClientState::Shutdown));
+
+        let client3 = Client::get_or_create(&options, &resolver("127.0.0.1:9876")).unwrap();
+        assert!(!client3.is_same_instance(&client1));
+        assert!(matches!(client3.state(), ClientState::Created));
+    }
+
+    #[tokio::test]
+    async fn test_cloud_access_channel() {
+        let endpoint = "http://MQ_INST_123_abc.cn-hangzhou.mq.aliyuncs.com:80";
+        let mut options = ClientOptions::default();
+        options
+            .set_instance_name("test_cloud_access_channel")
+            .set_access_channel(AccessChannel::Cloud);
+        // the instance ID is the namespace
+        let client = Client::get_or_create(&options, &resolver(endpoint)).unwrap();
+        assert_eq!("MQ_INST_123_abc%TopicA", client.wrap_namespace("TopicA"));
+        assert_eq!(
+            "%RETRY%MQ_INST_123_abc%GroupA",
+            client.wrap_namespace("%RETRY%GroupA")
+        );
+        client.release("", "");
+
+        let mut cloud_resolver = resolver(endpoint);
+        cloud_resolver.strip_endpoint_scheme();
+        assert_eq!(
+            ["MQ_INST_123_abc.cn-hangzhou.mq.aliyuncs.com:80"],
+            cloud_resolver.static_addrs()
+        );
+
+        options.set_access_channel(AccessChannel::Local);
+        let client = Client::get_or_create(&options, &resolver(endpoint)).unwrap();
+        assert_eq!("TopicA", client.wrap_namespace("TopicA"));
+        client.release("", "");
+
+        assert_eq!(
+            "RMQ_SYS_TRACE_TOPIC",
+            AccessChannel::Local.trace_topic("cn-hangzhou")
+        );
+        assert_eq!(
+            "TRACE_DATA_cn-hangzhou",
+            AccessChannel::Cloud.trace_topic("cn-hangzhou")
+        );
+    }
+}
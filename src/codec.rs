@@ -0,0 +1,57 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Error;
+
+/// Encodes values of `T` into message bodies and decodes them back, see
+/// [`Producer::send_with_codec`](crate::Producer::send_with_codec) and
+/// [`MessageExt::decode_body_with`](crate::MessageExt::decode_body_with)
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error>;
+
+    fn decode(&self, body: &[u8]) -> Result<T, Error>;
+}
+
+/// Bodies as JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl<T> Codec<T> for JsonCodec
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, body: &[u8]) -> Result<T, Error> {
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::{Codec, JsonCodec};
+    use crate::Error;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        item: String,
+    }
+
+    #[test]
+    fn test_json_codec() {
+        let order = Order {
+            id: 1,
+            item: "book".to_string(),
+        };
+        let body = JsonCodec.encode(&order).unwrap();
+        assert_eq!(br#"{"id":1,"item":"book"}"#.to_vec(), body);
+        assert_eq!(order, JsonCodec.decode(&body).unwrap());
+        let res: Result<Order, Error> = JsonCodec.decode(b"{\"id\":1}");
+        assert!(matches!(res, Err(Error::Json(_))));
+    }
+}
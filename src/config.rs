@@ -0,0 +1,368 @@
+//! Options loaded from TOML files and `ROCKETMQ_*` environment variables
+//!
+//! ```toml
+//! [client]
+//! name_servers = ["127.0.0.1:9876"]
+//! namespace = "test"
+//! access_key = "ak"
+//! secret_key = "sk"
+//! connect_timeout_ms = 3000
+//!
+//! [producer]
+//! group = "producer_group"
+//! send_msg_timeout_ms = 3000
+//!
+//! [consumer]
+//! group = "consumer_group"
+//! consume_from = "CONSUME_FROM_FIRST_OFFSET"
+//! pull_batch_size = 32
+//! ```
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::client::{AccessChannel, ClientOptions, Credentials};
+use crate::consumer::{ConsumeFrom, ConsumerOptions, MessageModel};
+use crate::producer::ProducerOptions;
+use crate::Error;
+
+const ENV_PREFIX: &str = "ROCKETMQ_";
+
+/// Options shared by producers and consumers, the `[client]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ClientConfig {
+    name_servers: Option<Vec<String>>,
+    name_server_domain: Option<String>,
+    name_server_srv: Option<String>,
+    instance_name: Option<String>,
+    unit_name: Option<String>,
+    namespace: Option<String>,
+    access_channel: Option<AccessChannel>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    security_token: Option<String>,
+    connect_timeout_ms: Option<u64>,
+    connection_idle_timeout_ms: Option<u64>,
+    max_frame_length: Option<usize>,
+    max_in_flight_requests: Option<usize>,
+    tcp_nodelay: Option<bool>,
+    tcp_keepalive_ms: Option<u64>,
+}
+
+/// The `[producer]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProducerConfig {
+    group: Option<String>,
+    send_msg_timeout_ms: Option<u64>,
+    default_topic_queue_nums: Option<i32>,
+    create_topic_key: Option<String>,
+}
+
+/// The `[consumer]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ConsumerConfig {
+    group: Option<String>,
+    message_model: Option<MessageModel>,
+    consume_from: Option<ConsumeFrom>,
+    consume_timestamp: Option<i64>,
+    max_reconsume_times: Option<i32>,
+    max_reconsume_times_orderly: Option<i32>,
+    pull_batch_size: Option<i32>,
+    pull_interval_ms: Option<u64>,
+    consume_message_batch_max_size: Option<usize>,
+    suspend_current_queue_time_millis: Option<u64>,
+    check_crc_on_recv: Option<bool>,
+}
+
+/// Config file, tables other than `client`, `producer` and `consumer` are left to the
+/// application
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct FileConfig {
+    client: ClientConfig,
+    producer: ProducerConfig,
+    consumer: ConsumerConfig,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config {}: {}", path.display(), err),
+            )
+            .into()
+        })
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|err| err.to_string())
+    }
+
+    pub fn producer_options(&self) -> ProducerOptions {
+        let producer = &self.producer;
+        let mut options = ProducerOptions::with_client_options(
+            self.client.client_options(producer.group.as_deref()),
+        );
+        if let Some(addrs) = &self.client.name_servers {
+            options.set_name_server(addrs.clone());
+        } else if let Some(url) = &self.client.name_server_domain {
+            options.set_name_server_domain(url);
+        } else if let Some(name) = &self.client.name_server_srv {
+            options.set_name_server_srv(name);
+        }
+        if let Some(timeout) = producer.send_msg_timeout_ms {
+            options.set_send_msg_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(queue_nums) = producer.default_topic_queue_nums {
+            options.set_default_topic_queue_nums(queue_nums);
+        }
+        if let Some(key) = &producer.create_topic_key {
+            options.set_create_topic_key(key);
+        }
+        options
+    }
+
+    pub fn consumer_options(&self) -> ConsumerOptions {
+        let consumer = &self.consumer;
+        let mut options = ConsumerOptions::with_client_options(
+            self.client.client_options(consumer.group.as_deref()),
+        );
+        if let Some(addrs) = &self.client.name_servers {
+            options.set_name_server(addrs.clone());
+        } else if let Some(url) = &self.client.name_server_domain {
+            options.set_name_server_domain(url);
+        } else if let Some(name) = &self.client.name_server_srv {
+            options.set_name_server_srv(name);
+        }
+        if let Some(model) = consumer.message_model {
+            options.set_message_model(model);
+        }
+        if let Some(consume_from) = consumer.consume_from {
+            options.set_consume_from(consume_from);
+        }
+        if let Some(timestamp) = consumer.consume_timestamp {
+            options.set_consume_timestamp(timestamp);
+        }
+        if let Some(times) = consumer.max_reconsume_times {
+            options.set_max_reconsume_times(times);
+        }
+        if let Some(times) = consumer.max_reconsume_times_orderly {
+            options.set_max_reconsume_times_orderly(times);
+        }
+        if let Some(size) = consumer.pull_batch_size {
+            options.set_pull_batch_size(size);
+        }
+        if let Some(interval) = consumer.pull_interval_ms {
+            options.set_pull_interval(Duration::from_millis(interval));
+        }
+        if let Some(size) = consumer.consume_message_batch_max_size {
+            options.set_consume_message_batch_max_size(size);
+        }
+        if let Some(millis) = consumer.suspend_current_queue_time_millis {
+            options.set_suspend_current_queue_time_millis(millis);
+        }
+        if let Some(check) = consumer.check_crc_on_recv {
+            options.set_check_crc_on_recv(check);
+        }
+        options
+    }
+}
+
+impl ClientConfig {
+    /// Read `ROCKETMQ_<FIELD>` variables, e.g. `ROCKETMQ_ACCESS_KEY`, name servers are left
+    /// to the `NAMESRV_ADDR` variable read by resolvers
+    pub fn from_env() -> Result<Self, Error> {
+        Ok(Self {
+            name_servers: None,
+            name_server_domain: None,
+            name_server_srv: None,
+            instance_name: env_var("INSTANCE_NAME")?,
+            unit_name: env_var("UNIT_NAME")?,
+            namespace: env_var("NAMESPACE")?,
+            access_channel: env_var::<String>("ACCESS_CHANNEL")?
+                .map(|channel| match channel.to_ascii_lowercase().as_str() {
+                    "local" => Ok(AccessChannel::Local),
+                    "cloud" => Ok(AccessChannel::Cloud),
+                    _ => Err(invalid_env("ACCESS_CHANNEL", &channel)),
+                })
+                .transpose()?,
+            access_key: env_var("ACCESS_KEY")?,
+            secret_key: env_var("SECRET_KEY")?,
+            security_token: env_var("SECURITY_TOKEN")?,
+            connect_timeout_ms: env_var("CONNECT_TIMEOUT_MS")?,
+            connection_idle_timeout_ms: env_var("CONNECTION_IDLE_TIMEOUT_MS")?,
+            max_frame_length: env_var("MAX_FRAME_LENGTH")?,
+            max_in_flight_requests: env_var("MAX_IN_FLIGHT_REQUESTS")?,
+            tcp_nodelay: env_var("TCP_NODELAY")?,
+            tcp_keepalive_ms: env_var("TCP_KEEPALIVE_MS")?,
+        })
+    }
+
+    /// Client options of `group`, the default group if `None`
+    pub fn client_options(&self, group: Option<&str>) -> ClientOptions {
+        let mut options = match group {
+            Some(group) => ClientOptions::new(group),
+            None => ClientOptions::default(),
+        };
+        if let Some(name) = &self.instance_name {
+            options.set_instance_name(name);
+        }
+        if let Some(name) = &self.unit_name {
+            options.set_unit_name(name);
+        }
+        if let Some(namespace) = &self.namespace {
+            options.set_namespace(namespace);
+        }
+        if let Some(channel) = self.access_channel {
+            options.set_access_channel(channel);
+        }
+        if let (Some(access_key), Some(secret_key)) = (&self.access_key, &self.secret_key) {
+            let mut credentials = Credentials::new(access_key.as_str(), secret_key.as_str());
+            credentials.security_token = self.security_token.clone();
+            options.set_credentials(credentials);
+        }
+        if let Some(timeout) = self.connect_timeout_ms {
+            options.set_connect_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(timeout) = self.connection_idle_timeout_ms {
+            options.set_connection_idle_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(length) = self.max_frame_length {
+            options.set_max_frame_length(length);
+        }
+        if let Some(requests) = self.max_in_flight_requests {
+            options.set_max_in_flight_requests(requests);
+        }
+        if let Some(nodelay) = self.tcp_nodelay {
+            options.set_tcp_nodelay(nodelay);
+        }
+        if let Some(interval) = self.tcp_keepalive_ms {
+            options.set_tcp_keepalive(Duration::from_millis(interval));
+        }
+        options
+    }
+}
+
+/// Value of the `ROCKETMQ_<name>` variable, `None` if unset or empty
+pub(crate) fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, Error> {
+    match env::var(format!("{}{}", ENV_PREFIX, name)) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid_env(name, &value)),
+        _ => Ok(None),
+    }
+}
+
+fn invalid_env(name: &str, value: &str) -> Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid {}{}: {:?}", ENV_PREFIX, name, value),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::time::Duration;
+
+    use super::{ClientConfig, FileConfig};
+    use crate::client::AccessChannel;
+
+    #[test]
+    fn test_parse_file() {
+        let config = FileConfig::parse(
+            r#"
+            [client]
+            name_servers = ["127.0.0.1:9876", "127.0.0.2:9876"]
+            namespace = "test"
+            access_channel = "cloud"
+            access_key = "ak"
+            secret_key = "sk"
+            connect_timeout_ms = 1500
+            tcp_nodelay = false
+
+            [producer]
+            group = "producer_group"
+            send_msg_timeout_ms = 5000
+
+            [consumer]
+            group = "consumer_group"
+            message_model = "BROADCASTING"
+            consume_from = "CONSUME_FROM_FIRST_OFFSET"
+            pull_batch_size = 16
+
+            [app]
+            port = 8080
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(vec![
+                "127.0.0.1:9876".to_string(),
+                "127.0.0.2:9876".to_string()
+            ]),
+            config.client.name_servers
+        );
+        assert_eq!(Some(5000), config.producer.send_msg_timeout_ms);
+        assert_eq!(Some(16), config.consumer.pull_batch_size);
+
+        let producer = config.producer_options();
+        assert_eq!("producer_group", producer.group_name());
+        let consumer = config.consumer_options();
+        assert_eq!("consumer_group", consumer.group_name());
+
+        let options = config.client.client_options(Some("group"));
+        assert_eq!("test", options.namespace);
+        assert_eq!(AccessChannel::Cloud, options.access_channel);
+        assert_eq!("ak", options.credentials.unwrap().access_key);
+        assert_eq!(Duration::from_millis(1500), options.connect_timeout);
+        assert!(!options.tcp_nodelay);
+
+        assert!(FileConfig::parse("[client]\nname_server = \"127.0.0.1:9876\"").is_err());
+        assert!(FileConfig::parse("[consumer]\npull_batch_size = \"16\"").is_err());
+        assert!(FileConfig::parse("[client").is_err());
+    }
+
+    #[test]
+    fn test_from_env() {
+        env::set_var("ROCKETMQ_NAMESPACE", "env");
+        env::set_var("ROCKETMQ_ACCESS_KEY", "ak");
+        env::set_var("ROCKETMQ_SECRET_KEY", "sk");
+        env::set_var("ROCKETMQ_SECURITY_TOKEN", "token");
+        env::set_var("ROCKETMQ_MAX_IN_FLIGHT_REQUESTS", "64");
+        let options = ClientConfig::from_env()
+            .unwrap()
+            .client_options(Some("group"));
+        assert_eq!("env", options.namespace);
+        assert_eq!(64, options.max_in_flight_requests);
+        let credentials = options.credentials.unwrap();
+        assert_eq!("sk", credentials.secret_key);
+        assert_eq!(Some("token".to_string()), credentials.security_token);
+
+        env::set_var("ROCKETMQ_MAX_IN_FLIGHT_REQUESTS", "many");
+        assert!(ClientConfig::from_env().is_err());
+        for name in &[
+            "NAMESPACE",
+            "ACCESS_KEY",
+            "SECRET_KEY",
+            "SECURITY_TOKEN",
+            "MAX_IN_FLIGHT_REQUESTS",
+        ] {
+            env::remove_var(format!("ROCKETMQ_{}", name));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Push consumption from 5.x proxies: queues assigned to the consumer are received from
+//! with long polling, messages consumed are acknowledged and the others are redelivered
+//! once they become visible again
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use tracing::{info, warn};
+use tracing_futures::Instrument;
+
+use super::{ConsumeResult, ConsumerInner};
+use crate::client::model::SubscriptionData;
+use crate::grpc::{pb, GrpcClient};
+use crate::instrument;
+use crate::message::{MessageExt, MessageQueue};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::runtime::{self, JoinHandle};
+
+const ASSIGNMENT_INTERVAL: Duration = Duration::from_secs(5);
+const LONG_POLLING_TIMEOUT: Duration = Duration::from_secs(20);
+// messages not acknowledged in time are redelivered to other consumers
+const INVISIBLE_DURATION: Duration = Duration::from_secs(30);
+// messages failed to consume are redelivered after the delay
+const CONSUME_RETRY_DELAY: Duration = Duration::from_secs(5);
+const RECEIVE_DELAY_WHEN_ERROR: Duration = Duration::from_secs(3);
+
+/// Task receiving messages of a queue, aborted once the queue is no longer assigned
+struct Receiver(JoinHandle<()>);
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Report subscriptions to the proxy and keep receiving messages of every queue assigned to
+/// the consumer, until the client shuts down
+pub(crate) async fn assign(inner: Arc<ConsumerInner>, grpc: GrpcClient) {
+    // (topic, broker, queue ID) -> receiver
+    let mut receivers: HashMap<(String, String, i32), Receiver> = HashMap::new();
+    let mut interval = runtime::interval(ASSIGNMENT_INTERVAL);
+    loop {
+        interval.tick().await;
+        // proxies take topics apart from the namespace
+        let subscriptions: Vec<SubscriptionData> = inner
+            .subscriptions
+            .lock()
+            .values()
+            .map(|sub_data| SubscriptionData {
+                topic: inner.client.unwrap_namespace(&sub_data.topic),
+                ..sub_data.clone()
+            })
+            .collect();
+        grpc.update_subscriptions(
+            &subscriptions,
+            inner.options.pull_batch_size,
+            LONG_POLLING_TIMEOUT,
+        );
+        let mut assigned = HashMap::new();
+        // queues of topics failed to query keep being received from
+        let mut failed = HashSet::new();
+        for sub_data in &subscriptions {
+            match grpc.query_assignment(&sub_data.topic).await {
+                Ok(mqs) => {
+                    for mq in mqs {
+                        assigned.insert(queue_key(&mq), mq);
+                    }
+                }
+                Err(err) => {
+                    warn!(consumer_group = %inner.group, topic = %sub_data.topic, "query assignment error: {:?}", err);
+                    failed.insert(sub_data.topic.clone());
+                }
+            }
+        }
+        receivers.retain(|key, _| assigned.contains_key(key) || failed.contains(&key.0));
+        for (key, mq) in assigned {
+            if let Entry::Vacant(entry) = receivers.entry(key) {
+                info!(consumer_group = %inner.group, message_queue = ?entry.key(), "start receiving messages of assigned queue");
+                let task = runtime::spawn(receive(Arc::clone(&inner), grpc.clone(), mq));
+                entry.insert(Receiver(task));
+            }
+        }
+    }
+}
+
+fn queue_key(mq: &pb::MessageQueue) -> (String, String, i32) {
+    (
+        mq.topic
+            .as_ref()
+            .map(|topic| topic.name.clone())
+            .unwrap_or_default(),
+        mq.broker
+            .as_ref()
+            .map(|broker| broker.name.clone())
+            .unwrap_or_default(),
+        mq.id,
+    )
+}
+
+async fn receive(inner: Arc<ConsumerInner>, grpc: GrpcClient, pb_mq: pb::MessageQueue) {
+    let (topic, broker_name, queue_id) = queue_key(&pb_mq);
+    // subscriptions and listeners are keyed by topics wrapped with the namespace
+    let mq = MessageQueue {
+        topic: inner.client.wrap_namespace(&topic),
+        broker_name,
+        queue_id: queue_id as u32,
+    };
+    loop {
+        let sub_data = match inner.subscription_data(&mq.topic) {
+            Some(sub_data) => sub_data,
+            None => {
+                info!(consumer_group = %inner.group, message_queue = ?mq, "topic unsubscribed, stop receiving");
+                return;
+            }
+        };
+        let invisible_duration = inner.options.consume_timeout.max(INVISIBLE_DURATION);
+        let result = grpc
+            .receive_message(
+                &pb_mq,
+                &sub_data,
+                &mq.topic,
+                inner.options.pull_batch_size,
+                invisible_duration,
+                LONG_POLLING_TIMEOUT,
+            )
+            .await;
+        match result {
+            Ok(msgs) => {
+                if !msgs.is_empty() {
+                    consume_messages(&inner, &grpc, &mq, &topic, msgs).await;
+                }
+            }
+            Err(err) => {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, "receive message error: {:?}", err);
+                runtime::sleep(RECEIVE_DELAY_WHEN_ERROR).await;
+            }
+        }
+    }
+}
+
+async fn consume_messages(
+    inner: &ConsumerInner,
+    grpc: &GrpcClient,
+    mq: &MessageQueue,
+    topic: &str,
+    mut msgs: Vec<(MessageExt, String)>,
+) {
+    let batch_size = inner.options.consume_message_batch_max_size.max(1);
+    while !msgs.is_empty() {
+        let (batch, receipt_handles): (Vec<MessageExt>, Vec<String>) =
+            msgs.drain(..batch_size.min(msgs.len())).unzip();
+        let msg_ids: Vec<String> = batch.iter().map(|msg| msg.msg_id.clone()).collect();
+        let span = instrument::dispatch_span(mq, &msg_ids[0], batch.len());
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let result = inner.call_listener(batch).instrument(span.clone()).await;
+        instrument::record_result(&span, &result);
+        let consumed = matches!(result, Ok(ConsumeResult::Success | ConsumeResult::Commit));
+        #[cfg(feature = "metrics")]
+        metrics::record_consume(&inner.group, &mq.topic, start.elapsed(), consumed);
+        if let Err(err) = &result {
+            warn!(consumer_group = %inner.group, message_queue = ?mq, "consume message error: {}", err);
+        }
+        let entries: Vec<(String, String)> = msg_ids.into_iter().zip(receipt_handles).collect();
+        if consumed {
+            if let Err(err) = grpc.ack_message(topic, entries).await {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, "ack message error: {:?}", err);
+            }
+            continue;
+        }
+        // redelivered after the retry delay rather than the rest of the invisible duration,
+        // the proxy sends messages retried too many times to the dead letter queue
+        for (msg_id, receipt_handle) in entries {
+            if let Err(err) = grpc
+                .change_invisible_duration(
+                    topic,
+                    msg_id.clone(),
+                    receipt_handle,
+                    CONSUME_RETRY_DELAY,
+                )
+                .await
+            {
+                warn!(consumer_group = %inner.group, msg_id = %msg_id, "change invisible duration error: {:?}", err);
+            }
+        }
+    }
+}
@@ -1,14 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use bitflags::bitflags;
 use parking_lot::Mutex;
-use tracing::error;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
-use crate::client::{Client, ClientOptions};
-use crate::message::MessageQueue;
-use crate::namesrv::NameServer;
+use crate::client::model::{
+    ConsumeMessageDirectlyResult, ConsumeMessageDirectlyResultType, ConsumerData,
+    ConsumerRunningInfo, SubscriptionData,
+};
+use crate::client::{Client, ClientOptions, RELEASE_SHUTDOWN_TIMEOUT};
+use crate::config::FileConfig;
+#[cfg(feature = "grpc")]
+use crate::grpc::GrpcClient;
+use crate::message::{MessageExt, MessageQueue};
 use crate::protocol::{
     request::{
         GetConsumerListRequestHeader, GetMaxOffsetRequestHeader,
@@ -16,23 +28,40 @@ use crate::protocol::{
     },
     RemotingCommand, RequestCode, ResponseCode,
 };
-use crate::resolver::{HttpResolver, PassthroughResolver, Resolver};
+use crate::resolver::{HttpResolver, PassthroughResolver, Resolver, SrvResolver};
+use crate::route::TopicRouteData;
+use crate::runtime;
+use crate::topic;
 use crate::Error;
 
+#[cfg(feature = "grpc")]
+mod grpc;
 mod offset_store;
 mod process_queue;
 mod push;
 /// Message queue allocation strategy
 pub mod strategy;
 
-use offset_store::{LocalFileOffsetStore, OffsetStorage, RemoteBrokerOffsetStore};
+use offset_store::{
+    LocalFileOffsetStore, OffsetStorage, OffsetStore, ReadType, RemoteBrokerOffsetStore,
+};
 use process_queue::ProcessQueue;
 pub use push::PushConsumer;
 use strategy::{AllocateAveragely, AllocateStrategy};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+bitflags! {
+    struct PullSysFlag: i32 {
+        const COMMIT_OFFSET = 0x1;
+        const SUSPEND = 0x1 << 1;
+        const SUBSCRIPTION = 0x1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum MessageModel {
+    #[serde(rename = "BROADCASTING")]
     BroadCasting,
+    #[serde(rename = "CLUSTERING")]
     Clustering,
 }
 
@@ -46,13 +75,26 @@ impl fmt::Display for MessageModel {
 }
 
 /// Consume from where
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum ConsumeFrom {
+    #[serde(rename = "CONSUME_FROM_LAST_OFFSET")]
     LastOffset,
+    #[serde(rename = "CONSUME_FROM_FIRST_OFFSET")]
     FirstOffset,
+    #[serde(rename = "CONSUME_FROM_TIMESTAMP")]
     Timestamp,
 }
 
+impl fmt::Display for ConsumeFrom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsumeFrom::LastOffset => write!(f, "CONSUME_FROM_LAST_OFFSET"),
+            ConsumeFrom::FirstOffset => write!(f, "CONSUME_FROM_FIRST_OFFSET"),
+            ConsumeFrom::Timestamp => write!(f, "CONSUME_FROM_TIMESTAMP"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ExpressionType {
     Sql92,
@@ -68,6 +110,37 @@ impl fmt::Display for ExpressionType {
     }
 }
 
+/// Message selector of a topic subscription
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageSelector {
+    expression_type: ExpressionType,
+    expression: String,
+}
+
+impl MessageSelector {
+    /// Select messages by tags, for example `TagA || TagB`, `*` selects all messages
+    pub fn tag<S: Into<String>>(expression: S) -> Self {
+        Self {
+            expression_type: ExpressionType::Tag,
+            expression: expression.into(),
+        }
+    }
+
+    /// Select messages by SQL92 expression on message properties
+    pub fn sql92<S: Into<String>>(expression: S) -> Self {
+        Self {
+            expression_type: ExpressionType::Sql92,
+            expression: expression.into(),
+        }
+    }
+}
+
+impl Default for MessageSelector {
+    fn default() -> Self {
+        Self::tag("*")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ConsumerOptions {
     client_options: ClientOptions,
@@ -76,24 +149,64 @@ pub struct ConsumerOptions {
     consume_timeout: Duration,
     message_model: MessageModel,
     consume_from: ConsumeFrom,
+    // timestamp in milliseconds used by `ConsumeFrom::Timestamp`
+    consume_timestamp: i64,
     auto_commit: bool,
+    pull_batch_size: i32,
+    pull_interval: Duration,
+    consume_message_batch_max_size: usize,
+    // pause before consuming again when listener returns `SuspendCurrentQueueAMoment`
+    suspend_current_queue_time_millis: u64,
+    max_reconsume_times_orderly: i32,
+    check_crc_on_recv: bool,
 }
 
 impl Default for ConsumerOptions {
     fn default() -> Self {
+        let half_an_hour_ago = OffsetDateTime::now_utc() - time::Duration::minutes(30);
         Self {
             client_options: ClientOptions::default(),
-            resolver: Resolver::Http(HttpResolver::new("DEFAULT".to_string())),
+            resolver: Resolver::default(),
             max_reconsume_times: -1,
             consume_timeout: Duration::from_secs(0),
             message_model: MessageModel::Clustering,
             consume_from: ConsumeFrom::LastOffset,
+            consume_timestamp: (half_an_hour_ago - OffsetDateTime::unix_epoch())
+                .whole_milliseconds() as i64,
             auto_commit: true,
+            pull_batch_size: 32,
+            pull_interval: Duration::from_secs(0),
+            consume_message_batch_max_size: 1,
+            suspend_current_queue_time_millis: 1000,
+            max_reconsume_times_orderly: i32::MAX,
+            check_crc_on_recv: false,
         }
     }
 }
 
 impl ConsumerOptions {
+    pub fn new() -> Self {
+        ConsumerOptions::default()
+    }
+
+    pub fn with_client_options(client_options: ClientOptions) -> Self {
+        Self {
+            client_options,
+            ..Default::default()
+        }
+    }
+
+    /// Options of the `[client]` and `[consumer]` tables of the TOML file at `path`,
+    /// e.g. `name_servers`, `access_key`, `group`, `consume_from` or `pull_batch_size`.
+    /// Durations are in milliseconds, like `pull_interval_ms`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(FileConfig::load(path.as_ref())?.consumer_options())
+    }
+
+    pub fn group_name(&self) -> &str {
+        &self.client_options.group_name
+    }
+
     pub fn set_resolver(&mut self, resolver: Resolver) -> &mut Self {
         self.resolver = resolver;
         self
@@ -114,6 +227,67 @@ impl ConsumerOptions {
         ));
         self
     }
+
+    /// Resolve name servers from DNS SRV records of `name`, e.g. `_rocketmq._tcp.cluster.internal`
+    pub fn set_name_server_srv(&mut self, name: &str) -> &mut Self {
+        self.resolver = Resolver::Srv(SrvResolver::new(name.to_string()));
+        self
+    }
+
+    pub fn set_message_model(&mut self, model: MessageModel) -> &mut Self {
+        self.message_model = model;
+        self
+    }
+
+    pub fn set_consume_from(&mut self, consume_from: ConsumeFrom) -> &mut Self {
+        self.consume_from = consume_from;
+        self
+    }
+
+    /// Set the timestamp in milliseconds to consume from when using `ConsumeFrom::Timestamp`
+    pub fn set_consume_timestamp(&mut self, timestamp: i64) -> &mut Self {
+        self.consume_timestamp = timestamp;
+        self
+    }
+
+    pub fn set_max_reconsume_times(&mut self, times: i32) -> &mut Self {
+        self.max_reconsume_times = times;
+        self
+    }
+
+    pub fn set_pull_batch_size(&mut self, size: i32) -> &mut Self {
+        self.pull_batch_size = size;
+        self
+    }
+
+    pub fn set_pull_interval(&mut self, interval: Duration) -> &mut Self {
+        self.pull_interval = interval;
+        self
+    }
+
+    pub fn set_consume_message_batch_max_size(&mut self, size: usize) -> &mut Self {
+        self.consume_message_batch_max_size = size;
+        self
+    }
+
+    /// Set how long the queue is blocked when listener returns `SuspendCurrentQueueAMoment`
+    pub fn set_suspend_current_queue_time_millis(&mut self, millis: u64) -> &mut Self {
+        self.suspend_current_queue_time_millis = millis;
+        self
+    }
+
+    /// Set max reconsume times of messages suspending the queue, messages exceeding it are
+    /// sent to dead letter queue, unlimited by default
+    pub fn set_max_reconsume_times_orderly(&mut self, times: i32) -> &mut Self {
+        self.max_reconsume_times_orderly = times;
+        self
+    }
+
+    /// Verify the CRC32 of pulled message bodies, pulls containing corrupted messages are retried
+    pub fn set_check_crc_on_recv(&mut self, check: bool) -> &mut Self {
+        self.check_crc_on_recv = check;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -134,9 +308,11 @@ pub enum ConsumerReturn {
     Failed,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum ConsumeType {
+    #[serde(rename = "CONSUME_ACTIVELY")]
     Actively,
+    #[serde(rename = "CONSUME_PASSIVELY")]
     Passively,
 }
 
@@ -149,64 +325,500 @@ impl fmt::Display for ConsumeType {
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct ConsumerInner {}
+/// Java `String.hashCode` of tag, the broker filters messages in consume queue by it
+fn tag_hash_code(tag: &str) -> i32 {
+    tag.encode_utf16()
+        .fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32))
+}
 
-impl ConsumerInner {
-    pub fn rebalance(&self) {
-        todo!()
+fn build_subscription_data(topic: &str, selector: &MessageSelector) -> SubscriptionData {
+    let mut sub_data = SubscriptionData {
+        class_filter_mode: false,
+        topic: topic.to_string(),
+        sub_string: selector.expression.trim().to_string(),
+        tags_set: HashSet::new(),
+        code_set: HashSet::new(),
+        sub_version: (OffsetDateTime::now_utc() - OffsetDateTime::unix_epoch()).whole_milliseconds()
+            as i64,
+        expression_type: selector.expression_type.to_string(),
+    };
+    if selector.expression_type == ExpressionType::Tag {
+        if sub_data.sub_string.is_empty() || sub_data.sub_string == "*" {
+            sub_data.sub_string = "*".to_string();
+        } else {
+            for tag in sub_data.sub_string.split("||") {
+                let tag = tag.trim();
+                if !tag.is_empty() {
+                    sub_data.tags_set.insert(tag.to_string());
+                    sub_data.code_set.insert(tag_hash_code(tag).to_string());
+                }
+            }
+        }
     }
+    sub_data
 }
 
+/// Client IDs of the `consumerIdList` of a `GET_CONSUMER_LIST_BY_GROUP` response body
+fn decode_consumer_list(body: &[u8]) -> Result<Vec<String>, Error> {
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+    let result: serde_json::Value = serde_json::from_slice(body)?;
+    let list = match result.get("consumerIdList") {
+        Some(serde_json::Value::Array(list)) => list,
+        None | Some(serde_json::Value::Null) => return Ok(Vec::new()),
+        Some(list) => {
+            return Err(Error::InvalidMessage(format!(
+                "invalid consumerIdList {}",
+                list
+            )))
+        }
+    };
+    list.iter()
+        .map(|id| {
+            id.as_str()
+                .map(ToString::to_string)
+                .ok_or_else(|| Error::InvalidMessage(format!("invalid consumer ID {}", id)))
+        })
+        .collect()
+}
+
+/// `offset` field of a successful offset query response
+fn decode_offset(res: &RemotingCommand) -> Result<i64, Error> {
+    let offset = res.header.ext_fields.get("offset");
+    offset
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Error::InvalidHeader(format!("invalid offset {:?} in response", offset)))
+}
+
+type MessageListener = Arc<dyn Fn(&[MessageExt]) -> ConsumeResult + Send + Sync>;
+
 #[derive(Debug)]
-pub struct Consumer {
-    consumer_group: String,
-    inner: Arc<Mutex<ConsumerInner>>,
+pub(crate) struct PullRequest {
+    pub(crate) mq: MessageQueue,
+    pub(crate) pq: Arc<ProcessQueue>,
+    pub(crate) next_offset: i64,
+}
+
+pub(crate) struct ConsumerInner {
+    group: String,
     options: ConsumerOptions,
     client: Client<Resolver>,
     storage: OffsetStorage,
     allocate: AllocateStrategy,
-    process_queue_map: HashMap<MessageQueue, ProcessQueue>,
+    // topic -> SubscriptionData
+    subscriptions: Mutex<HashMap<String, SubscriptionData>>,
+    // topic -> listener
+    listeners: Mutex<HashMap<String, MessageListener>>,
+    // topic -> readable message queues of topic
+    topic_subscribe_info: Mutex<HashMap<String, Vec<MessageQueue>>>,
+    process_queue_table: Mutex<HashMap<MessageQueue, Arc<ProcessQueue>>>,
+    pull_request_tx: Mutex<Option<mpsc::UnboundedSender<PullRequest>>>,
+    pull_request_rx: Mutex<Option<mpsc::UnboundedReceiver<PullRequest>>>,
+    start_timestamp: i64,
+    // receives messages from 5.x proxies instead of pulling them from brokers
+    #[cfg(feature = "grpc")]
+    grpc: Option<GrpcClient>,
 }
 
-impl Consumer {
-    pub fn new() -> Result<Self, Error> {
-        Self::with_options(ConsumerOptions::default())
+impl fmt::Debug for ConsumerInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConsumerInner")
+            .field("group", &self.group)
+            .field("subscriptions", &self.subscriptions)
+            .finish()
     }
+}
 
-    pub fn with_options(options: ConsumerOptions) -> Result<Self, Error> {
-        let client_options = options.client_options.clone();
-        let inner = Arc::new(Mutex::new(ConsumerInner {}));
-        let name_server =
-            NameServer::new(options.resolver.clone(), client_options.credentials.clone())?;
-        let client = Client::new(client_options, name_server);
-        let consumer_group = &options.client_options.group_name;
-        let offset_store = match options.message_model {
-            MessageModel::Clustering => OffsetStorage::RemoteBroker(RemoteBrokerOffsetStore::new(
-                consumer_group,
-                client.clone(),
-            )),
-            MessageModel::BroadCasting => {
-                OffsetStorage::LocalFile(LocalFileOffsetStore::new(consumer_group, &client.id()))
+impl ConsumerInner {
+    pub(crate) fn subscribe(&self, topic: &str, selector: &MessageSelector) {
+        self.subscriptions
+            .lock()
+            .insert(topic.to_string(), build_subscription_data(topic, selector));
+    }
+
+    pub(crate) fn unsubscribe(&self, topic: &str) {
+        self.subscriptions.lock().remove(topic);
+    }
+
+    pub(crate) fn subscription_data(&self, topic: &str) -> Option<SubscriptionData> {
+        self.subscriptions.lock().get(topic).cloned()
+    }
+
+    pub(crate) fn subscribed_topics(&self) -> Vec<String> {
+        self.subscriptions.lock().keys().cloned().collect()
+    }
+
+    pub(crate) fn consumer_data(&self) -> ConsumerData {
+        ConsumerData {
+            group_name: self.group.clone(),
+            consumer_type: ConsumeType::Passively.to_string(),
+            message_model: self.options.message_model.to_string().to_uppercase(),
+            consume_from_where: self.options.consume_from.to_string(),
+            subscription_data_set: self.subscriptions.lock().values().cloned().collect(),
+            unit_mode: self.options.client_options.unit_mode,
+        }
+    }
+
+    /// Update readable queues of a subscribed topic, returns whether they changed so the
+    /// consumer should rebalance
+    pub(crate) fn update_topic_subscribe_info(&self, topic: &str, data: &TopicRouteData) -> bool {
+        if !self.subscriptions.lock().contains_key(topic) {
+            return false;
+        }
+        let mut mqs = data.to_subscribe_info(topic);
+        mqs.sort();
+        let old = self
+            .topic_subscribe_info
+            .lock()
+            .insert(topic.to_string(), mqs.clone());
+        old.map(|mut old| {
+            old.sort();
+            old != mqs
+        })
+        .unwrap_or(true)
+    }
+
+    fn take_pull_request_receiver(&self) -> Option<mpsc::UnboundedReceiver<PullRequest>> {
+        self.pull_request_rx.lock().take()
+    }
+
+    fn retry_topic(&self) -> String {
+        topic::retry_topic(&self.group)
+    }
+
+    /// Messages from retry topic carry their original topic in `RETRY_TOPIC` property
+    fn reset_retry_topic(&self, msg: &mut MessageExt) {
+        if msg.message.topic == self.retry_topic() {
+            if let Some(topic) = msg.retry_topic().map(str::to_string) {
+                msg.message.topic = topic;
             }
+        }
+    }
+
+    /// Invoke the listener of the topic messages belong to in a blocking thread
+    async fn call_listener(&self, msgs: Vec<MessageExt>) -> Result<ConsumeResult, String> {
+        let topic = match msgs.first() {
+            Some(msg) => msg.message.topic.clone(),
+            None => return Ok(ConsumeResult::Success),
         };
-        Ok(Self {
-            consumer_group: consumer_group.clone(),
-            inner,
-            options,
-            client,
-            storage: offset_store,
-            allocate: AllocateStrategy::Averagely(AllocateAveragely),
-            process_queue_map: HashMap::new(),
+        let listener = self.listeners.lock().get(&topic).cloned();
+        // listeners see topics without the namespace
+        let msgs: Vec<MessageExt> = msgs
+            .into_iter()
+            .map(|mut msg| {
+                msg.message.topic = self.client.unwrap_namespace(&msg.message.topic);
+                msg
+            })
+            .collect();
+        #[cfg(feature = "trace-context")]
+        let listener = listener.map(|listener| {
+            // batches are consumed in the context of their first message
+            let span = self
+                .options
+                .client_options
+                .trace_propagation
+                .consume_span(&msgs[0]);
+            move |msgs: &[MessageExt]| span.in_scope(|| listener(msgs))
+        });
+        match listener {
+            Some(listener) => runtime::spawn_blocking(move || listener(&msgs))
+                .await
+                .map_err(|err| format!("listener of topic {} panicked: {}", topic, err)),
+            None => Err(format!("no listener found for topic {}", topic)),
+        }
+    }
+
+    /// Consume a message pushed by broker without pulling, used by "consume directly" of console
+    pub(crate) async fn consume_message_directly(
+        &self,
+        mut msg: MessageExt,
+    ) -> ConsumeMessageDirectlyResult {
+        let start = Instant::now();
+        self.reset_retry_topic(&mut msg);
+        let (consume_result, remark) = match self.call_listener(vec![msg]).await {
+            Ok(ConsumeResult::Success) => {
+                (ConsumeMessageDirectlyResultType::Success, String::new())
+            }
+            Ok(ConsumeResult::Commit) => (ConsumeMessageDirectlyResultType::Commit, String::new()),
+            Ok(ConsumeResult::Rollback) => {
+                (ConsumeMessageDirectlyResultType::Rollback, String::new())
+            }
+            Ok(ConsumeResult::RetryLater) | Ok(ConsumeResult::SuspendCurrentQueueAMoment) => {
+                (ConsumeMessageDirectlyResultType::Later, String::new())
+            }
+            Err(err) => (ConsumeMessageDirectlyResultType::ThrowException, err),
+        };
+        ConsumeMessageDirectlyResult {
+            order: false,
+            auto_commit: true,
+            consume_result,
+            remark,
+            spent_time_millis: start.elapsed().as_millis() as u64,
+        }
+    }
+
+    pub(crate) async fn running_info(&self) -> ConsumerRunningInfo {
+        let mut info = ConsumerRunningInfo::default();
+        let options = &self.options;
+        let properties = &mut info.properties;
+        properties.insert(
+            "PROP_NAMESERVER_ADDR".to_string(),
+            self.client.name_server.address(),
+        );
+        properties.insert(
+            "PROP_CONSUME_TYPE".to_string(),
+            ConsumeType::Passively.to_string(),
+        );
+        properties.insert("PROP_CONSUME_ORDERLY".to_string(), "false".to_string());
+        properties.insert("PROP_THREADPOOL_CORE_SIZE".to_string(), "-1".to_string());
+        properties.insert(
+            "PROP_CONSUMER_START_TIMESTAMP".to_string(),
+            self.start_timestamp.to_string(),
+        );
+        properties.insert(
+            "PROP_CLIENT_VERSION".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        );
+        properties.insert("consumerGroup".to_string(), self.group.clone());
+        properties.insert(
+            "messageModel".to_string(),
+            options.message_model.to_string().to_uppercase(),
+        );
+        properties.insert(
+            "consumeFromWhere".to_string(),
+            options.consume_from.to_string(),
+        );
+        properties.insert(
+            "consumeTimestamp".to_string(),
+            options.consume_timestamp.to_string(),
+        );
+        properties.insert(
+            "maxReconsumeTimes".to_string(),
+            options.max_reconsume_times.to_string(),
+        );
+        properties.insert(
+            "pullBatchSize".to_string(),
+            options.pull_batch_size.to_string(),
+        );
+        properties.insert(
+            "pullInterval".to_string(),
+            options.pull_interval.as_millis().to_string(),
+        );
+        properties.insert(
+            "consumeMessageBatchMaxSize".to_string(),
+            options.consume_message_batch_max_size.to_string(),
+        );
+        properties.insert(
+            "suspendCurrentQueueTimeMillis".to_string(),
+            options.suspend_current_queue_time_millis.to_string(),
+        );
+        properties.insert(
+            "unitMode".to_string(),
+            options.client_options.unit_mode.to_string(),
+        );
+        info.subscription_set = self.subscriptions.lock().values().cloned().collect();
+        let process_queues: Vec<(MessageQueue, Arc<ProcessQueue>)> = self
+            .process_queue_table
+            .lock()
+            .iter()
+            .map(|(mq, pq)| (mq.clone(), Arc::clone(pq)))
+            .collect();
+        for (mq, pq) in process_queues {
+            let commit_offset = self.storage.read(&mq, ReadType::Memory).await;
+            info.mq_table.insert(mq, pq.info(commit_offset));
+        }
+        info
+    }
+
+    pub(crate) async fn rebalance(&self) {
+        let topics = self.subscribed_topics();
+        for topic in &topics {
+            let mqs = match self.topic_subscribe_info.lock().get(topic).cloned() {
+                Some(mqs) => mqs,
+                None => {
+                    if !topic::is_retry_topic(topic) {
+                        warn!(consumer_group = %self.group, topic = %topic, "do balance, but topic subscribe info not found");
+                    }
+                    continue;
+                }
+            };
+            let allocated = match self.options.message_model {
+                MessageModel::BroadCasting => mqs,
+                MessageModel::Clustering => {
+                    let mut cids = match self.get_consumer_list(topic).await {
+                        Ok(cids) => cids,
+                        Err(_) => continue,
+                    };
+                    if cids.is_empty() {
+                        warn!(consumer_group = %self.group, topic = %topic, "do balance, but consumer id list is empty");
+                        continue;
+                    }
+                    let mut mqs = mqs;
+                    mqs.sort();
+                    cids.sort();
+                    let cid_all: Vec<&str> = cids.iter().map(String::as_str).collect();
+                    self.allocate
+                        .allocate(&self.group, &self.client.id(), &mqs, &cid_all)
+                }
+            };
+            if self.update_process_queue_table(topic, &allocated).await {
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_rebalance(&self.group);
+                info!(consumer_group = %self.group, topic = %topic, allocated = ?allocated, "rebalance result changed");
+            }
+        }
+    }
+
+    async fn update_process_queue_table(&self, topic: &str, mqs: &[MessageQueue]) -> bool {
+        let mut changed = false;
+        let (removed, current): (Vec<MessageQueue>, Vec<MessageQueue>) = {
+            let table = self.process_queue_table.lock();
+            let removed = table
+                .keys()
+                .filter(|mq| mq.topic == topic && !mqs.contains(mq))
+                .cloned()
+                .collect();
+            (removed, table.keys().cloned().collect())
+        };
+        if !removed.is_empty() {
+            // Persist before the queues leave the process queue table
+            self.storage.persist(&current).await;
+            let mut table = self.process_queue_table.lock();
+            for mq in &removed {
+                if let Some(pq) = table.remove(mq) {
+                    pq.set_dropped(true);
+                }
+                self.storage.remove(mq);
+                info!(consumer_group = %self.group, message_queue = ?mq, "remove unnecessary message queue");
+            }
+            changed = true;
+        }
+        let added: Vec<MessageQueue> = {
+            let table = self.process_queue_table.lock();
+            mqs.iter()
+                .filter(|mq| !table.contains_key(mq))
+                .cloned()
+                .collect()
+        };
+        for mq in added {
+            self.storage.remove(&mq);
+            let next_offset = self.compute_pull_from_where(&mq).await;
+            if next_offset < 0 {
+                warn!(consumer_group = %self.group, message_queue = ?mq, "compute pull offset failed, skip adding message queue");
+                continue;
+            }
+            let pq = Arc::new(ProcessQueue::new());
+            self.process_queue_table
+                .lock()
+                .insert(mq.clone(), Arc::clone(&pq));
+            info!(consumer_group = %self.group, message_queue = ?mq, next_offset = next_offset, "add a new message queue");
+            if let Some(tx) = &*self.pull_request_tx.lock() {
+                let _ = tx.send(PullRequest {
+                    mq,
+                    pq,
+                    next_offset,
+                });
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    async fn compute_pull_from_where(&self, mq: &MessageQueue) -> i64 {
+        let last_offset = self.storage.read(mq, ReadType::Store).await;
+        if last_offset >= 0 {
+            return last_offset;
+        }
+        let is_retry_topic = topic::is_retry_topic(&mq.topic);
+        let offset = match self.options.consume_from {
+            ConsumeFrom::FirstOffset => return 0,
+            ConsumeFrom::LastOffset if is_retry_topic => return 0,
+            ConsumeFrom::LastOffset => self.get_max_offset(mq).await,
+            ConsumeFrom::Timestamp if is_retry_topic => self.get_max_offset(mq).await,
+            ConsumeFrom::Timestamp => {
+                self.search_offset_by_timestamp(mq, self.options.consume_timestamp)
+                    .await
+            }
+        };
+        offset.unwrap_or_else(|err| {
+            error!(consumer_group = %self.group, message_queue = ?mq, "compute pull offset error: {:?}", err);
+            -1
         })
     }
 
-    pub fn start(&self) {
-        self.client.start();
+    /// Reset offsets of the topic as requested by broker, queues being pulled are dropped
+    /// and pulling resumes from the new offsets.
+    pub(crate) async fn reset_offset(
+        &self,
+        topic: &str,
+        offset_table: &HashMap<MessageQueue, i64>,
+    ) {
+        let mut requests = Vec::new();
+        {
+            let mut table = self.process_queue_table.lock();
+            for (mq, offset) in offset_table {
+                if mq.topic != topic {
+                    continue;
+                }
+                if let Some(pq) = table.get(mq) {
+                    pq.set_dropped(true);
+                    let pq = Arc::new(ProcessQueue::new());
+                    table.insert(mq.clone(), Arc::clone(&pq));
+                    self.storage.update(mq, *offset, false);
+                    requests.push(PullRequest {
+                        mq: mq.clone(),
+                        pq,
+                        next_offset: *offset,
+                    });
+                }
+            }
+        }
+        info!(consumer_group = %self.group, topic = %topic, offset_table = ?offset_table, "reset consumer offset");
+        self.persist_consumer_offset().await;
+        if let Some(tx) = &*self.pull_request_tx.lock() {
+            for request in requests {
+                let _ = tx.send(request);
+            }
+        }
+    }
+
+    pub(crate) async fn persist_consumer_offset(&self) {
+        let mqs: Vec<MessageQueue> = self.process_queue_table.lock().keys().cloned().collect();
+        self.storage.persist(&mqs).await;
     }
 
-    pub fn shutdown(&self) {
-        self.client.shutdown();
+    /// Persist offsets of the queues consumed, waiting until `deadline` at most, then stop
+    /// the consumer and unregister it from the client
+    async fn stop(&self, deadline: Instant) {
+        if runtime::timeout_at(deadline, self.persist_consumer_offset())
+            .await
+            .is_err()
+        {
+            warn!(consumer_group = %self.group, "persist consumer offset timed out on shutdown");
+        }
+        self.unregister();
+    }
+
+    /// Stop the consumer and unregister it from the client, offsets not persisted yet are
+    /// lost
+    fn unregister(&self) {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            grpc.shutdown();
+        }
+        self.shutdown();
+        self.client.unregister_consumer(&self.group);
+    }
+
+    /// Stop pulling messages and drop all process queues
+    pub(crate) fn shutdown(&self) {
+        self.pull_request_tx.lock().take();
+        let mut table = self.process_queue_table.lock();
+        for pq in table.values() {
+            pq.set_dropped(true);
+        }
+        table.clear();
     }
 
     async fn get_broker_addr(&self, topic: &str) -> Result<String, Error> {
@@ -225,45 +837,56 @@ impl Consumer {
         }
     }
 
-    pub async fn get_consumer_list(&self, topic: &str) -> Result<Vec<String>, Error> {
+    async fn find_broker_addr(&self, mq: &MessageQueue) -> Result<String, Error> {
+        if mq.broker_name.is_empty() {
+            return self.get_broker_addr(&mq.topic).await;
+        }
+        match self
+            .client
+            .name_server
+            .find_broker_addr_by_name(&mq.broker_name)
+        {
+            Some(addr) => Ok(addr),
+            None => {
+                self.client
+                    .name_server
+                    .update_topic_route_info(&mq.topic)
+                    .await?;
+                self.client
+                    .name_server
+                    .find_broker_addr_by_name(&mq.broker_name)
+                    .ok_or(Error::EmptyRouteData)
+            }
+        }
+    }
+
+    async fn get_consumer_list(&self, topic: &str) -> Result<Vec<String>, Error> {
         let broker_addr = self.get_broker_addr(topic).await?;
         let header = GetConsumerListRequestHeader {
-            consumer_group: self.consumer_group.clone(),
+            consumer_group: self.group.clone(),
         };
         let cmd =
             RemotingCommand::with_header(RequestCode::GetConsumerListByGroup, header, Vec::new());
         match self
             .client
-            .invoke_timeout(&broker_addr, cmd, Duration::from_secs(3))
+            .invoke_sync(&broker_addr, cmd, Duration::from_secs(3))
             .await
         {
             Ok(res) => {
-                if res.body.is_empty() {
-                    return Ok(Vec::new());
-                }
-                let result: serde_json::Value = serde_json::from_slice(&res.body)?;
-                if let Some(list) = result
-                    .get("consumerIdList")
-                    .and_then(|list| list.as_array())
-                {
-                    let consumers: Vec<String> = list
-                        .iter()
-                        .map(|v| v.as_str().map(ToString::to_string).unwrap())
-                        .collect();
-                    Ok(consumers)
-                } else {
-                    Ok(Vec::new())
+                if res.code() != ResponseCode::Success {
+                    return Err(res.into_broker_error(&broker_addr));
                 }
+                decode_consumer_list(&res.body)
             }
             Err(err) => {
-                error!(consumer_group = %self.consumer_group, broker = %broker_addr, "get consumer list of group from broker error: {:?}", err);
+                error!(consumer_group = %self.group, broker = %broker_addr, "get consumer list of group from broker error: {:?}", err);
                 Err(err)
             }
         }
     }
 
-    pub async fn get_max_offset(&self, mq: &MessageQueue) -> Result<i64, Error> {
-        let broker_addr = self.get_broker_addr(&mq.topic).await?;
+    async fn get_max_offset(&self, mq: &MessageQueue) -> Result<i64, Error> {
+        let broker_addr = self.find_broker_addr(mq).await?;
         let header = GetMaxOffsetRequestHeader {
             topic: mq.topic.clone(),
             queue_id: mq.queue_id,
@@ -271,30 +894,21 @@ impl Consumer {
         let cmd = RemotingCommand::with_header(RequestCode::GetMaxOffset, header, Vec::new());
         let res = self
             .client
-            .invoke_timeout(&broker_addr, cmd, Duration::from_secs(3))
+            .invoke_sync(&broker_addr, cmd, Duration::from_secs(3))
             .await?;
         if res.code() == ResponseCode::Success {
-            let offset: i64 = res
-                .header
-                .ext_fields
-                .get("offset")
-                .and_then(|s| s.parse().ok())
-                .unwrap();
-            Ok(offset)
+            decode_offset(&res)
         } else {
-            Err(Error::ResponseError {
-                code: res.code(),
-                message: res.header.remark,
-            })
+            Err(res.into_broker_error(&broker_addr))
         }
     }
 
-    pub async fn search_offset_by_timestamp(
+    async fn search_offset_by_timestamp(
         &self,
         mq: &MessageQueue,
         timestamp: i64,
     ) -> Result<i64, Error> {
-        let broker_addr = self.get_broker_addr(&mq.topic).await?;
+        let broker_addr = self.find_broker_addr(mq).await?;
         let header = SearchOffsetByTimestampRequestHeader {
             topic: mq.topic.clone(),
             queue_id: mq.queue_id,
@@ -304,35 +918,269 @@ impl Consumer {
             RemotingCommand::with_header(RequestCode::SearchOffsetByTimestamp, header, Vec::new());
         let res = self
             .client
-            .invoke_timeout(&broker_addr, cmd, Duration::from_secs(3))
+            .invoke_sync(&broker_addr, cmd, Duration::from_secs(3))
             .await?;
         if res.code() == ResponseCode::Success {
-            let offset: i64 = res
-                .header
-                .ext_fields
-                .get("offset")
-                .and_then(|s| s.parse().ok())
-                .unwrap();
-            Ok(offset)
+            decode_offset(&res)
         } else {
-            Err(Error::ResponseError {
-                code: res.code(),
-                message: res.header.remark,
-            })
+            Err(res.into_broker_error(&broker_addr))
         }
     }
 }
 
-impl Drop for Consumer {
+/// Clones are handles of the same consumer, it's shut down once the last of them is dropped
+#[derive(Debug, Clone)]
+pub struct Consumer {
+    handle: Arc<ConsumerHandle>,
+}
+
+#[derive(Debug)]
+struct ConsumerHandle {
+    inner: Arc<ConsumerInner>,
+    // whether the shared client was released
+    released: AtomicBool,
+}
+
+impl ConsumerHandle {
+    /// Mark the consumer stopped, returns false if it was stopped already
+    fn mark_stopped(&self) -> bool {
+        !self.released.swap(true, Ordering::SeqCst)
+    }
+}
+
+impl Consumer {
+    pub fn new() -> Result<Self, Error> {
+        Self::with_options(ConsumerOptions::default())
+    }
+
+    pub fn with_options(options: ConsumerOptions) -> Result<Self, Error> {
+        topic::validate_group(&options.client_options.group_name)?;
+        #[cfg(feature = "grpc")]
+        let grpc = GrpcClient::push_consumer(&options.client_options);
+        let client = Client::get_or_create(&options.client_options, &options.resolver)?;
+        let consumer_group = client.wrap_namespace(&options.client_options.group_name);
+        let offset_store = match options.message_model {
+            MessageModel::Clustering => OffsetStorage::RemoteBroker(RemoteBrokerOffsetStore::new(
+                &consumer_group,
+                client.clone(),
+            )),
+            MessageModel::BroadCasting => {
+                OffsetStorage::LocalFile(LocalFileOffsetStore::new(&consumer_group, &client.id()))
+            }
+        };
+        let (pull_request_tx, pull_request_rx) = mpsc::unbounded_channel();
+        let inner = ConsumerInner {
+            group: consumer_group,
+            options,
+            client,
+            storage: offset_store,
+            allocate: AllocateStrategy::Averagely(AllocateAveragely),
+            subscriptions: Mutex::new(HashMap::new()),
+            listeners: Mutex::new(HashMap::new()),
+            topic_subscribe_info: Mutex::new(HashMap::new()),
+            process_queue_table: Mutex::new(HashMap::new()),
+            pull_request_tx: Mutex::new(Some(pull_request_tx)),
+            pull_request_rx: Mutex::new(Some(pull_request_rx)),
+            start_timestamp: (OffsetDateTime::now_utc() - OffsetDateTime::unix_epoch())
+                .whole_milliseconds() as i64,
+            #[cfg(feature = "grpc")]
+            grpc,
+        };
+        Ok(Self {
+            handle: Arc::new(ConsumerHandle {
+                inner: Arc::new(inner),
+                released: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    pub fn start(&self) {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.handle.inner.grpc {
+            grpc.start();
+            return;
+        }
+        self.handle
+            .inner
+            .client
+            .register_consumer(&self.handle.inner.group, Arc::clone(&self.handle.inner));
+        self.handle.inner.client.start();
+    }
+
+    /// Shut down the consumer, handles cloned from it included. Waits at most `timeout`
+    /// for offsets to be persisted, the group to be unregistered on brokers and, unless the
+    /// client is shared with other producers or consumers, the client to shut down
+    pub async fn shutdown(&self, timeout: Duration) {
+        if self.handle.mark_stopped() {
+            let deadline = Instant::now() + timeout;
+            let inner = &self.handle.inner;
+            inner.stop(deadline).await;
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            inner.client.shutdown("", &inner.group, timeout).await;
+        }
+    }
+
+    /// Switch to name servers `addrs` without restarting, e.g. while migrating name
+    /// servers, routes of topics are refreshed against them. Producers and consumers
+    /// sharing the client of this consumer switch too
+    pub async fn update_name_server_addrs(&self, addrs: Vec<String>) -> Result<(), Error> {
+        self.handle
+            .inner
+            .client
+            .update_name_server_addrs(addrs)
+            .await
+    }
+
+    pub async fn get_consumer_list(&self, topic: &str) -> Result<Vec<String>, Error> {
+        let topic = self.handle.inner.client.wrap_namespace(topic);
+        self.handle.inner.get_consumer_list(&topic).await
+    }
+
+    pub async fn get_max_offset(&self, mq: &MessageQueue) -> Result<i64, Error> {
+        let mq = self.handle.inner.client.wrap_queue(mq);
+        self.handle.inner.get_max_offset(&mq).await
+    }
+
+    pub async fn search_offset_by_timestamp(
+        &self,
+        mq: &MessageQueue,
+        timestamp: i64,
+    ) -> Result<i64, Error> {
+        let mq = self.handle.inner.client.wrap_queue(mq);
+        self.handle
+            .inner
+            .search_offset_by_timestamp(&mq, timestamp)
+            .await
+    }
+}
+
+impl Drop for ConsumerHandle {
     fn drop(&mut self) {
-        self.shutdown();
+        if !self.mark_stopped() {
+            return;
+        }
+        // offsets can only be persisted within a runtime
+        if runtime::can_spawn() {
+            let inner = Arc::clone(&self.inner);
+            runtime::spawn(async move {
+                let deadline = Instant::now() + RELEASE_SHUTDOWN_TIMEOUT;
+                inner.stop(deadline).await;
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                inner.client.shutdown("", &inner.group, timeout).await;
+            });
+        } else {
+            self.inner.unregister();
+            self.inner.client.release("", &self.inner.group);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Consumer, ConsumerOptions};
+    use std::collections::HashMap;
+
+    use super::{
+        build_subscription_data, decode_consumer_list, decode_offset, tag_hash_code, Consumer,
+        ConsumerOptions, MessageSelector,
+    };
     use crate::message::MessageQueue;
+    use crate::protocol::RemotingCommand;
+    use crate::route::TopicRouteData;
+    use crate::Error;
+
+    fn route_data(read_queue_nums: i32) -> TopicRouteData {
+        let json = format!(
+            r#"{{"queueDatas":[{{"brokerName":"broker-a","readQueueNums":{},"writeQueueNums":4,"perm":6}}],"brokerDatas":[],"filterServerTable":{{}}}}"#,
+            read_queue_nums
+        );
+        TopicRouteData::from_bytes(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_update_topic_subscribe_info_changed() {
+        let consumer = Consumer::new().unwrap();
+        let inner = &consumer.handle.inner;
+        // topics not subscribed are ignored
+        assert!(!inner.update_topic_subscribe_info("TopicA", &route_data(4)));
+        inner.subscribe("TopicA", &MessageSelector::default());
+        assert!(inner.update_topic_subscribe_info("TopicA", &route_data(4)));
+        assert!(!inner.update_topic_subscribe_info("TopicA", &route_data(4)));
+        // queues scaled out
+        assert!(inner.update_topic_subscribe_info("TopicA", &route_data(8)));
+        assert_eq!(8, inner.topic_subscribe_info.lock()["TopicA"].len());
+    }
+
+    #[test]
+    fn test_tag_hash_code() {
+        assert_eq!(0, tag_hash_code(""));
+        assert_eq!(2598919, tag_hash_code("TagA"));
+    }
+
+    #[test]
+    fn test_build_subscription_data() {
+        let sub_data = build_subscription_data("test", &MessageSelector::default());
+        assert_eq!("*", sub_data.sub_string);
+        assert!(sub_data.tags_set.is_empty());
+        assert_eq!("TAG", sub_data.expression_type);
+
+        let sub_data = build_subscription_data("test", &MessageSelector::tag("TagA || TagB"));
+        assert_eq!(2, sub_data.tags_set.len());
+        assert!(sub_data.tags_set.contains("TagA"));
+        assert!(sub_data.tags_set.contains("TagB"));
+        assert!(sub_data.code_set.contains("2598919"));
+
+        let sub_data = build_subscription_data("test", &MessageSelector::sql92("a > 5"));
+        assert_eq!("a > 5", sub_data.sub_string);
+        assert!(sub_data.tags_set.is_empty());
+        assert_eq!("SQL92", sub_data.expression_type);
+    }
+
+    #[test]
+    fn test_decode_consumer_list() {
+        assert_eq!(
+            vec!["127.0.0.1@1", "127.0.0.1@2"],
+            decode_consumer_list(br#"{"consumerIdList":["127.0.0.1@1","127.0.0.1@2"]}"#).unwrap()
+        );
+        assert!(decode_consumer_list(b"").unwrap().is_empty());
+        assert!(decode_consumer_list(b"{}").unwrap().is_empty());
+        assert!(decode_consumer_list(br#"{"consumerIdList":null}"#)
+            .unwrap()
+            .is_empty());
+        // corrupted
+        assert!(matches!(
+            decode_consumer_list(br#"{"consumerIdList":["127.0.0.1@1",1]}"#),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            decode_consumer_list(br#"{"consumerIdList":"127.0.0.1@1"}"#),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            decode_consumer_list(b"{consumerIdList"),
+            Err(Error::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_offset() {
+        let response = |offset: Option<&str>| {
+            let mut ext_fields = HashMap::new();
+            if let Some(offset) = offset {
+                ext_fields.insert("offset".to_string(), offset.to_string());
+            }
+            RemotingCommand::new(0, 0, String::new(), ext_fields, Vec::new())
+        };
+        assert_eq!(42, decode_offset(&response(Some("42"))).unwrap());
+        // corrupted
+        assert!(matches!(
+            decode_offset(&response(None)),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            decode_offset(&response(Some("4x"))),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
 
     #[tokio::test]
     async fn test_get_consumer_list() {
@@ -14,6 +14,7 @@ use crate::protocol::{
     RemotingCommand, RequestCode, ResponseCode,
 };
 use crate::resolver::Resolver;
+use crate::runtime;
 use crate::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -93,7 +94,7 @@ impl LocalFileOffsetStore {
     }
 
     async fn load(&self) {
-        let data = match tokio::fs::read(&self.path).await {
+        let data = match runtime::read_file(self.path.clone()).await {
             Ok(data) => data,
             Err(err) => {
                 warn!(
@@ -102,7 +103,7 @@ impl LocalFileOffsetStore {
                 );
                 let mut bak_path = self.path.clone();
                 bak_path.set_file_name("offset.json.bak");
-                match tokio::fs::read(&bak_path).await {
+                match runtime::read_file(bak_path).await {
                     Ok(data) => data,
                     Err(err) => {
                         warn!("read from local store bak file error: {:?}", err);
@@ -138,7 +139,7 @@ impl OffsetStore for LocalFileOffsetStore {
         };
         match serde_json::to_vec(&wrapper) {
             Ok(data) => {
-                if let Err(err) = tokio::fs::write(&self.path, data).await {
+                if let Err(err) = runtime::write_file(self.path.clone(), data).await {
                     error!(
                         "persist offset to {} failed: {:?}",
                         self.path.display(),
@@ -247,10 +248,7 @@ impl RemoteBrokerOffsetStore {
                 RemotingCommand::with_header(RequestCode::QueryConsumerOffset, header, Vec::new());
             let res = self.client.invoke(&addr, cmd).await?;
             if res.code() != ResponseCode::Success {
-                return Err(Error::ResponseError {
-                    code: res.code(),
-                    message: res.header.remark,
-                });
+                return Err(res.into_broker_error(&addr));
             }
             let offset: i64 = res.header.ext_fields["offset"].parse().unwrap_or(-1);
             return Ok(offset);
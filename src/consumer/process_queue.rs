@@ -1,9 +1,13 @@
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
 
-use parking_lot::RwLock;
 use time::OffsetDateTime;
 
+use crate::client::model::ProcessQueueInfo;
+
+fn now_millis() -> i64 {
+    (OffsetDateTime::now_utc() - OffsetDateTime::unix_epoch()).whole_milliseconds() as i64
+}
+
 #[derive(Debug)]
 pub struct ProcessQueue {
     msg_count: AtomicUsize,
@@ -20,7 +24,7 @@ pub struct ProcessQueue {
 
 impl ProcessQueue {
     pub fn new() -> Self {
-        let ts = OffsetDateTime::now_local().unix_timestamp();
+        let ts = now_millis();
         Self {
             msg_count: AtomicUsize::new(0),
             msg_size: AtomicUsize::new(0),
@@ -34,6 +38,45 @@ impl ProcessQueue {
             consuming: AtomicBool::new(false),
         }
     }
+
+    #[inline]
+    pub fn is_dropped(&self) -> bool {
+        self.dropped.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_dropped(&self, dropped: bool) {
+        self.dropped.store(dropped, Ordering::Release);
+    }
+
+    pub fn update_last_pull_timestamp(&self) {
+        self.last_pull_timestamp
+            .store(now_millis(), Ordering::Relaxed);
+    }
+
+    pub fn update_last_consume_timestamp(&self) {
+        self.last_consume_timestamp
+            .store(now_millis(), Ordering::Relaxed);
+    }
+
+    pub fn info(&self, commit_offset: i64) -> ProcessQueueInfo {
+        ProcessQueueInfo {
+            commit_offset,
+            cached_msg_min_offset: 0,
+            cached_msg_max_offset: self.queue_offset_max,
+            cached_msg_count: self.msg_count.load(Ordering::Relaxed),
+            cached_msg_size_in_mib: self.msg_size.load(Ordering::Relaxed) / (1024 * 1024),
+            transaction_msg_min_offset: 0,
+            transaction_msg_max_offset: 0,
+            transaction_msg_count: 0,
+            locked: self.locked.load(Ordering::Relaxed),
+            try_unlock_times: 0,
+            last_lock_timestamp: self.last_lock_timestamp.load(Ordering::Relaxed),
+            dropped: self.is_dropped(),
+            last_pull_timestamp: self.last_pull_timestamp.load(Ordering::Relaxed),
+            last_consume_timestamp: self.last_consume_timestamp.load(Ordering::Relaxed),
+        }
+    }
 }
 
 #[cfg(test)]
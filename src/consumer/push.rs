@@ -1,16 +1,41 @@
-use super::{Consumer, ConsumerOptions};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use tracing::{info, warn};
+use tracing_futures::Instrument;
+
+#[cfg(feature = "grpc")]
+use super::grpc;
+use super::{
+    ConsumeResult, Consumer, ConsumerInner, ConsumerOptions, MessageModel, MessageSelector,
+    OffsetStore, PullRequest, PullSysFlag, ReadType,
+};
+use crate::client::PullStatus;
+use crate::instrument;
+use crate::message::{MessageExt, MessageQueue};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::protocol::request::PullMessageRequestHeader;
+use crate::runtime;
 use crate::Error;
 
-#[derive(Debug)]
+const PULL_SUSPEND_TIMEOUT: Duration = Duration::from_secs(20);
+const PULL_DELAY_WHEN_ERROR: Duration = Duration::from_secs(3);
+const CONSUME_RETRY_DELAY: Duration = Duration::from_secs(5);
+// broker sends message back to dead letter queue directly with a negative delay level
+const DLQ_DELAY_LEVEL: i32 = -1;
+
+/// Push consumer, clones are handles of the same consumer like [`Consumer`]
+#[derive(Debug, Clone)]
 pub struct PushConsumer {
     consumer: Consumer,
 }
 
 impl PushConsumer {
     pub fn new() -> Result<Self, Error> {
-        Ok(Self {
-            consumer: Consumer::new()?,
-        })
+        Self::with_options(ConsumerOptions::default())
     }
 
     pub fn with_options(options: ConsumerOptions) -> Result<Self, Error> {
@@ -18,4 +43,382 @@ impl PushConsumer {
             consumer: Consumer::with_options(options)?,
         })
     }
+
+    fn wrap_namespace(&self, topic: &str) -> String {
+        self.consumer.handle.inner.client.wrap_namespace(topic)
+    }
+
+    /// Subscribe a topic with its own listener, a consumer can subscribe multiple topics,
+    /// messages are routed to the listener of the topic they belong to.
+    ///
+    /// Subscribing a topic again replaces its selector and listener.
+    pub fn subscribe<F>(&self, topic: &str, selector: MessageSelector, listener: F)
+    where
+        F: Fn(&[MessageExt]) -> ConsumeResult + Send + Sync + 'static,
+    {
+        let topic = self.wrap_namespace(topic);
+        self.consumer.handle.inner.subscribe(&topic, &selector);
+        self.consumer
+            .handle
+            .inner
+            .listeners
+            .lock()
+            .insert(topic, Arc::new(listener));
+    }
+
+    pub fn unsubscribe(&self, topic: &str) {
+        let topic = self.wrap_namespace(topic);
+        self.consumer.handle.inner.unsubscribe(&topic);
+        self.consumer.handle.inner.listeners.lock().remove(&topic);
+    }
+
+    pub fn start(&self) {
+        let inner = &self.consumer.handle.inner;
+        // proxies assign queues and redeliver messages failed to consume without retry topics
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &inner.grpc {
+            self.consumer.start();
+            grpc.spawn(grpc::assign(Arc::clone(inner), grpc.clone()));
+            return;
+        }
+        if inner.options.message_model == MessageModel::Clustering {
+            inner.subscribe(&inner.retry_topic(), &MessageSelector::default());
+        }
+        if let Some(mut pull_request_rx) = inner.take_pull_request_receiver() {
+            let inner = Arc::clone(inner);
+            runtime::spawn(async move {
+                while let Some(request) = pull_request_rx.recv().await {
+                    runtime::spawn(pull_message(Arc::clone(&inner), request));
+                }
+            });
+        }
+        self.consumer.start();
+        let client = inner.client.clone();
+        runtime::spawn(async move {
+            client.update_topic_route_info().await;
+            client.send_heartbeat_to_all_brokers().await;
+            client.rebalance_immediately().await;
+        });
+    }
+
+    /// See [`Consumer::shutdown`]
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.consumer.shutdown(timeout).await;
+    }
+
+    /// See [`Consumer::update_name_server_addrs`]
+    pub async fn update_name_server_addrs(&self, addrs: Vec<String>) -> Result<(), Error> {
+        self.consumer.update_name_server_addrs(addrs).await
+    }
+}
+
+async fn pull_message(inner: Arc<ConsumerInner>, mut request: PullRequest) {
+    let mq = request.mq.clone();
+    loop {
+        if request.pq.is_dropped() {
+            info!(consumer_group = %inner.group, message_queue = ?mq, "process queue dropped, stop pulling");
+            return;
+        }
+        let sub_data = match inner.subscription_data(&mq.topic) {
+            Some(sub_data) => sub_data,
+            None => {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, "subscription data not found, maybe unsubscribed");
+                runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                continue;
+            }
+        };
+        let broker_addr = match inner.find_broker_addr(&mq).await {
+            Ok(addr) => addr,
+            Err(err) => {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, "find broker address error: {:?}", err);
+                runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                continue;
+            }
+        };
+        let commit_offset = if inner.options.message_model == MessageModel::Clustering {
+            inner.storage.read(&mq, ReadType::Memory).await
+        } else {
+            0
+        };
+        let mut sys_flag = PullSysFlag::SUSPEND | PullSysFlag::SUBSCRIPTION;
+        if commit_offset > 0 {
+            sys_flag |= PullSysFlag::COMMIT_OFFSET;
+        }
+        let header = PullMessageRequestHeader {
+            consumer_group: inner.group.clone(),
+            topic: mq.topic.clone(),
+            queue_id: mq.queue_id as i32,
+            queue_offset: request.next_offset,
+            max_msg_nums: inner.options.pull_batch_size,
+            sys_flag: sys_flag.bits(),
+            commit_offset,
+            suspend_timeout_millis: PULL_SUSPEND_TIMEOUT,
+            sub_expression: sub_data.sub_string.clone(),
+            sub_version: sub_data.sub_version,
+            expression_type: sub_data.expression_type.clone(),
+        };
+        request.pq.update_last_pull_timestamp();
+        let span = instrument::pull_span(&mq, &broker_addr, request.next_offset);
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let result = inner
+            .client
+            .pull_message(&broker_addr, header)
+            .instrument(span.clone())
+            .await;
+        instrument::record_result(&span, &result.as_ref().map(|result| result.status));
+        #[cfg(feature = "metrics")]
+        metrics::record_pull(&inner.group, &mq.topic, start.elapsed(), result.is_ok());
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, "pull message error: {:?}", err);
+                runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                continue;
+            }
+        };
+        if request.pq.is_dropped() {
+            continue;
+        }
+        match result.status {
+            PullStatus::Found => {
+                // messages filtered out by tags are skipped without decoding them
+                let msgs: Result<Vec<MessageExt>, Error> = result
+                    .messages()
+                    .filter_tags(|tags| {
+                        sub_data.tags_set.is_empty()
+                            || tags
+                                .map(|tag| sub_data.tags_set.contains(tag))
+                                .unwrap_or(false)
+                    })
+                    .collect();
+                let msgs = match msgs {
+                    Ok(msgs) => msgs,
+                    Err(err) => {
+                        warn!(consumer_group = %inner.group, message_queue = ?mq, "decode pulled messages error: {:?}", err);
+                        runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                        continue;
+                    }
+                };
+                span.record("found", msgs.len());
+                if inner.options.check_crc_on_recv {
+                    if let Some(msg) = msgs.iter().find(|msg| !msg.check_crc()) {
+                        warn!(consumer_group = %inner.group, message_queue = ?mq, msg_id = %msg.msg_id, "message body crc check failed, pull again");
+                        runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                        continue;
+                    }
+                }
+                if !msgs.is_empty() {
+                    consume_messages(&inner, &request, msgs).await;
+                }
+                if request.pq.is_dropped() {
+                    continue;
+                }
+                request.next_offset = result.next_begin_offset;
+                inner.storage.update(&mq, request.next_offset, true);
+            }
+            PullStatus::NoNewMsg | PullStatus::NoMsgMatched => {
+                request.next_offset = result.next_begin_offset;
+                inner.storage.update(&mq, request.next_offset, true);
+            }
+            PullStatus::OffsetIllegal => {
+                warn!(consumer_group = %inner.group, message_queue = ?mq, offset = request.next_offset, next_begin_offset = result.next_begin_offset, "pull offset illegal");
+                request.next_offset = result.next_begin_offset;
+                inner.storage.update(&mq, request.next_offset, false);
+            }
+            PullStatus::BrokerTimeout => {
+                runtime::sleep(PULL_DELAY_WHEN_ERROR).await;
+                continue;
+            }
+        }
+        if inner.options.pull_interval > Duration::from_secs(0) {
+            runtime::sleep(inner.options.pull_interval).await;
+        }
+    }
+}
+
+async fn consume_messages(inner: &ConsumerInner, request: &PullRequest, msgs: Vec<MessageExt>) {
+    let batch_size = inner.options.consume_message_batch_max_size.max(1);
+    let mut batches: Vec<Vec<MessageExt>> = Vec::new();
+    for mut msg in msgs {
+        inner.reset_retry_topic(&mut msg);
+        match batches.last_mut() {
+            Some(batch)
+                if batch.len() < batch_size && batch[0].message.topic == msg.message.topic =>
+            {
+                batch.push(msg)
+            }
+            _ => batches.push(vec![msg]),
+        }
+    }
+    for mut batch in batches {
+        loop {
+            if request.pq.is_dropped() {
+                return;
+            }
+            request.pq.update_last_consume_timestamp();
+            let span = instrument::dispatch_span(&request.mq, &batch[0].msg_id, batch.len());
+            #[cfg(feature = "metrics")]
+            let start = Instant::now();
+            let result = inner
+                .call_listener(batch.clone())
+                .instrument(span.clone())
+                .await;
+            instrument::record_result(&span, &result);
+            #[cfg(feature = "metrics")]
+            metrics::record_consume(
+                &inner.group,
+                &request.mq.topic,
+                start.elapsed(),
+                matches!(result, Ok(ConsumeResult::Success | ConsumeResult::Commit)),
+            );
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    warn!(consumer_group = %inner.group, message_queue = ?request.mq, "consume message error: {}", err);
+                    ConsumeResult::RetryLater
+                }
+            };
+            match result {
+                ConsumeResult::Success | ConsumeResult::Commit => break,
+                ConsumeResult::SuspendCurrentQueueAMoment => {
+                    // Messages exceeding max reconsume times are sent to dead letter queue
+                    // instead of blocking the queue forever
+                    let max_reconsume_times = inner.options.max_reconsume_times_orderly;
+                    let (exceeded, mut remaining): (Vec<MessageExt>, Vec<MessageExt>) = batch
+                        .into_iter()
+                        .partition(|msg| msg.reconsume_times >= max_reconsume_times);
+                    if !exceeded.is_empty() {
+                        if inner.options.message_model == MessageModel::BroadCasting {
+                            for msg in &exceeded {
+                                warn!(consumer_group = %inner.group, msg_id = %msg.msg_id, "exceed max reconsume times, drop it");
+                            }
+                        } else {
+                            remaining.extend(
+                                send_message_back(inner, &request.mq, exceeded, DLQ_DELAY_LEVEL)
+                                    .await,
+                            );
+                            remaining.sort_by_key(|msg| msg.queue_offset);
+                        }
+                    }
+                    if remaining.is_empty() {
+                        break;
+                    }
+                    for msg in remaining.iter_mut() {
+                        msg.reconsume_times += 1;
+                    }
+                    batch = remaining;
+                    runtime::sleep(Duration::from_millis(
+                        inner.options.suspend_current_queue_time_millis,
+                    ))
+                    .await;
+                }
+                ConsumeResult::RetryLater | ConsumeResult::Rollback => {
+                    if inner.options.message_model == MessageModel::BroadCasting {
+                        for msg in &batch {
+                            warn!(consumer_group = %inner.group, msg_id = %msg.msg_id, "broadcasting consume failed, drop it");
+                        }
+                        break;
+                    }
+                    let failed = send_message_back(inner, &request.mq, batch, 0).await;
+                    if failed.is_empty() {
+                        break;
+                    }
+                    batch = failed;
+                    runtime::sleep(CONSUME_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+/// Send messages back to broker for retry, returns those failed to send back
+async fn send_message_back(
+    inner: &ConsumerInner,
+    mq: &MessageQueue,
+    msgs: Vec<MessageExt>,
+    delay_level: i32,
+) -> Vec<MessageExt> {
+    let broker_addr = match inner.find_broker_addr(mq).await {
+        Ok(addr) => addr,
+        Err(err) => {
+            warn!(consumer_group = %inner.group, message_queue = ?mq, "send message back error: {:?}", err);
+            return msgs
+                .into_iter()
+                .map(|mut msg| {
+                    msg.reconsume_times += 1;
+                    msg
+                })
+                .collect();
+        }
+    };
+    let mut failed = Vec::new();
+    for mut msg in msgs {
+        if let Err(err) = inner
+            .client
+            .send_message_back(
+                &broker_addr,
+                &inner.group,
+                &msg,
+                delay_level,
+                inner.options.max_reconsume_times,
+            )
+            .await
+        {
+            warn!(consumer_group = %inner.group, msg_id = %msg.msg_id, "send message back error: {:?}", err);
+            msg.reconsume_times += 1;
+            failed.push(msg);
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use super::{ConsumeResult, MessageSelector, PushConsumer};
+
+    #[test]
+    fn test_subscribe_multiple_topics() {
+        let consumer = PushConsumer::new().unwrap();
+        consumer.subscribe("TopicA", MessageSelector::default(), |_| {
+            ConsumeResult::Success
+        });
+        consumer.subscribe("TopicB", MessageSelector::tag("TagA"), |_| {
+            ConsumeResult::RetryLater
+        });
+        let mut topics = consumer.consumer.handle.inner.subscribed_topics();
+        topics.sort();
+        assert_eq!(vec!["TopicA", "TopicB"], topics);
+        assert_eq!(2, consumer.consumer.handle.inner.listeners.lock().len());
+
+        consumer.unsubscribe("TopicA");
+        assert_eq!(
+            vec!["TopicB"],
+            consumer.consumer.handle.inner.subscribed_topics()
+        );
+        assert!(!consumer
+            .consumer
+            .handle
+            .inner
+            .listeners
+            .lock()
+            .contains_key("TopicA"));
+    }
+
+    #[test]
+    fn test_clone_shares_consumer() {
+        let consumer = PushConsumer::new().unwrap();
+        let handle = consumer.clone();
+        handle.subscribe("TopicA", MessageSelector::default(), |_| {
+            ConsumeResult::Success
+        });
+        drop(handle);
+        assert_eq!(
+            vec!["TopicA"],
+            consumer.consumer.handle.inner.subscribed_topics()
+        );
+        assert!(!consumer.consumer.handle.released.load(Ordering::SeqCst));
+    }
 }
@@ -1,114 +1,146 @@
+use std::convert::TryFrom;
+use std::io;
 use std::string::FromUtf8Error;
-use std::{error, fmt, io};
 
-#[derive(Debug)]
+use thiserror::Error;
+
+use crate::protocol::response::ResponseCode;
+use crate::topic::NameError;
+
+#[derive(Debug, Error)]
 pub enum Error {
-    Connection(ConnectionError),
-    Client(ClientError),
-    Io(io::Error),
-    Json(serde_json::Error),
-    InvalidUtf8(FromUtf8Error),
+    #[error("{0}")]
+    Connection(#[from] ConnectionError),
+    #[error("{0}")]
+    Client(#[source] ClientError),
+    #[error("{0}")]
+    Frame(#[from] FrameError),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    InvalidUtf8(#[from] FromUtf8Error),
+    #[error("invalid header codec")]
     InvalidHeaderCodec,
+    #[error("invalid header: {0}")]
     InvalidHeader(String),
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+    /// Topic or group name breaking the naming rules
+    #[error("invalid name: {0}")]
+    InvalidName(#[from] NameError),
+    #[error("name server addresses are empty")]
     EmptyNameServers,
+    #[error("route data is empty")]
     EmptyRouteData,
+    #[error("batch message is empty")]
     EmptyBatchMessage,
+    #[error("topic {0} not exist")]
     TopicNotExist(String),
-    ResponseError { code: i16, message: String },
+    /// No response received before the request deadline
+    #[error("request timed out")]
+    Timeout,
+    /// Request rejected by the rate limit in non-blocking mode
+    #[error("request throttled by rate limit")]
+    Throttled,
+    /// SOCKS5 proxy rejected the connection or is misconfigured
+    #[error("proxy error: {0}")]
+    Proxy(String),
+    /// Invalid TLS configuration
+    #[cfg(feature = "tls")]
+    #[error("tls error: {0}")]
+    Tls(String),
+    /// gRPC request to a 5.x proxy failed
+    #[cfg(feature = "grpc")]
+    #[error("grpc error: {0}")]
+    Grpc(Box<tonic::Status>),
+    /// 5.x proxy responded with a non-OK code of apache/rocketmq-apis, like 40402 if the
+    /// topic is not found
+    #[cfg(feature = "grpc")]
+    #[error("proxy error, code: {code}, message: {message}")]
+    GrpcResponse { code: i32, message: String },
+    /// Broker or name server at `addr` responded with a non-success code, see
+    /// `ResponseCode`
+    #[error("broker error, code: {code}, remark: {remark}, addr: {addr}")]
+    Broker {
+        code: i16,
+        remark: String,
+        addr: String,
+    },
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Error {
+    /// Whether the error is caused by a broken or unavailable connection, retrying later
+    /// may succeed once the connection is re-established
+    pub fn is_retriable(&self) -> bool {
         match self {
-            Error::Connection(err) => err.fmt(f),
-            Error::Client(err) => err.fmt(f),
-            Error::Io(err) => err.fmt(f),
-            Error::Json(err) => err.fmt(f),
-            Error::InvalidUtf8(err) => err.fmt(f),
-            Error::InvalidHeaderCodec => write!(f, "invalid header codec"),
-            Error::InvalidHeader(ref err) => write!(f, "invalid header: {}", err),
-            Error::EmptyNameServers => write!(f, "name server addresses are empty"),
-            Error::EmptyRouteData => write!(f, "route data is empty"),
-            Error::EmptyBatchMessage => write!(f, "batch message is empty"),
-            Error::TopicNotExist(ref topic) => write!(f, "topic {} not exist", topic),
-            Error::ResponseError { code, message } => {
-                write!(f, "response error, code: {}, message: {}", code, message)
-            }
+            Error::Connection(err) => *err != ConnectionError::Shutdown,
+            Error::Io(_) | Error::Timeout => true,
+            #[cfg(feature = "grpc")]
+            Error::Grpc(status) => matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+            ),
+            // too many requests, request timeout and proxy timeout
+            #[cfg(feature = "grpc")]
+            Error::GrpcResponse { code, .. } => matches!(code, 42900 | 40800 | 50400),
+            _ => false,
         }
     }
-}
 
-impl error::Error for Error {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    /// Response code of a broker error, `None` for other errors or unknown codes
+    pub fn response_code(&self) -> Option<ResponseCode> {
         match self {
-            Error::Connection(err) => Some(err),
-            Error::Client(err) => Some(err),
-            Error::Io(err) => Some(err),
-            Error::Json(err) => Some(err),
-            Error::InvalidUtf8(err) => Some(err),
+            Error::Broker { code, .. } => ResponseCode::try_from(*code).ok(),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg(feature = "grpc")]
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Grpc(Box::new(status))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
 pub enum ConnectionError {
+    #[error("disconnected")]
     Disconnected,
+    #[error("canceled request")]
     Canceled,
+    #[error("the connection was shut down")]
     Shutdown,
+    /// the last connect attempt failed, waiting for the backoff delay to retry
+    #[error("waiting to reconnect")]
+    Reconnecting,
 }
 
-impl fmt::Display for ConnectionError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ConnectionError::Disconnected => write!(f, "disconnected"),
-            ConnectionError::Canceled => write!(f, "canceled request"),
-            ConnectionError::Shutdown => write!(f, "the connection was shut down"),
-        }
-    }
-}
-
-impl error::Error for ConnectionError {}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
 pub enum ClientError {
+    #[error("client is not started")]
     NotStarted,
+    #[error("client start failed")]
     StartFailed,
+    #[error("client was shut down")]
     Shutdown,
 }
 
-impl fmt::Display for ClientError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ClientError::NotStarted => write!(f, "client is not started"),
-            ClientError::StartFailed => write!(f, "client start failed"),
-            ClientError::Shutdown => write!(f, "client was shut down"),
-        }
-    }
-}
-
-impl error::Error for ClientError {}
-
-impl From<ConnectionError> for Error {
-    fn from(err: ConnectionError) -> Self {
-        Self::Connection(err)
-    }
-}
-
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Self::Io(err)
-    }
-}
-
-impl From<serde_json::Error> for Error {
-    fn from(err: serde_json::Error) -> Self {
-        Self::Json(err)
-    }
-}
-
-impl From<FromUtf8Error> for Error {
-    fn from(err: FromUtf8Error) -> Self {
-        Self::InvalidUtf8(err)
-    }
+/// Malformed remoting command frame
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum FrameError {
+    /// frame length is smaller than the fixed header length
+    #[error("frame too short, length: {0}")]
+    ShortFrame(i32),
+    /// frame length exceeds the max frame length
+    #[error("frame too long, length: {0}")]
+    TooLong(usize),
+    /// length of a field is larger than the remaining bytes
+    #[error("length mismatch, declared: {declared}, remaining: {remaining}")]
+    LengthMismatch { declared: usize, remaining: usize },
+    /// length of a field is negative
+    #[error("negative length: {0}")]
+    NegativeLength(i32),
 }
@@ -0,0 +1,708 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::Future;
+use hmac::{Hmac, Mac, NewMac};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use time::OffsetDateTime;
+use tonic::metadata::{AsciiMetadataValue, MetadataMap};
+use tonic::transport::{Channel, Endpoint};
+use tracing::{debug, error, info, info_span, warn};
+use tracing_futures::Instrument;
+
+use super::convert::{self, check_status};
+use super::pb::{
+    self, messaging_service_client::MessagingServiceClient, receive_message_response::Content,
+    settings::PubSub, telemetry_command::Command,
+};
+use super::Transport;
+use crate::client::model::SubscriptionData;
+use crate::client::{ClientOptions, ClientState, Credentials};
+use crate::error::{ClientError, Error};
+use crate::message::{Message, MessageExt};
+use crate::producer::SendResult;
+use crate::runtime::{self, JoinHandle};
+use crate::utils::client_ip_addr;
+
+type HmacSha1 = Hmac<sha1::Sha1>;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const TELEMETRY_RETRY_DELAY: Duration = Duration::from_secs(3);
+
+/// Client of the messaging service of 5.x proxies, clones share the channel, settings and
+/// background tasks. The channel is connected on start, as it's driven by tokio tasks
+#[derive(Debug, Clone)]
+pub(crate) struct GrpcClient {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    endpoints: Vec<String>,
+    client_id: String,
+    client_type: pb::ClientType,
+    // consumer group without the namespace, proxies take the namespace apart
+    group: Option<String>,
+    namespace: String,
+    credentials: Option<Credentials>,
+    born_host: String,
+    stub: OnceCell<MessagingServiceClient<Channel>>,
+    session: Mutex<Session>,
+    state: AtomicU8,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+/// Settings reported to the proxy, sent again whenever they change
+#[derive(Debug)]
+struct Session {
+    settings: pb::Settings,
+    telemetry_tx: Option<mpsc::UnboundedSender<pb::TelemetryCommand>>,
+}
+
+impl GrpcClient {
+    /// Client of a producer, if `options` speak to proxies over gRPC
+    pub fn producer(options: &ClientOptions) -> Option<Self> {
+        match &options.transport {
+            Transport::Grpc { endpoints } => Some(Self::new(
+                endpoints.clone(),
+                options,
+                pb::ClientType::Producer,
+                None,
+            )),
+            Transport::Remoting => None,
+        }
+    }
+
+    /// Client of a push consumer of the group of `options`, if they speak to proxies over
+    /// gRPC
+    pub fn push_consumer(options: &ClientOptions) -> Option<Self> {
+        match &options.transport {
+            Transport::Grpc { endpoints } => Some(Self::new(
+                endpoints.clone(),
+                options,
+                pb::ClientType::PushConsumer,
+                Some(options.group_name.clone()),
+            )),
+            Transport::Remoting => None,
+        }
+    }
+
+    /// Client of the proxies at `endpoints`, producers have no `group`
+    fn new(
+        endpoints: Vec<String>,
+        options: &ClientOptions,
+        client_type: pb::ClientType,
+        group: Option<String>,
+    ) -> Self {
+        let born_host = client_ip_addr()
+            .unwrap_or_else(|| IpAddr::from([127, 0, 0, 1]))
+            .to_string();
+        let settings = pb::Settings {
+            client_type: Some(client_type as i32),
+            access_point: Some(convert::endpoints(&endpoints)),
+            request_timeout: Some(convert::duration(REQUEST_TIMEOUT)),
+            user_agent: Some(pb::Ua {
+                language: pb::Language::Rust as i32,
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                platform: std::env::consts::OS.to_string(),
+                hostname: born_host.clone(),
+            }),
+            pub_sub: Some(PubSub::Publishing(pb::Publishing::default())),
+            ..pb::Settings::default()
+        };
+        Self {
+            inner: Arc::new(Inner {
+                endpoints,
+                client_id: options.client_id(),
+                client_type,
+                group,
+                namespace: options.namespace.clone(),
+                credentials: options.credentials.clone(),
+                born_host,
+                stub: OnceCell::new(),
+                session: Mutex::new(Session {
+                    settings,
+                    telemetry_tx: None,
+                }),
+                state: AtomicU8::new(ClientState::Created.into()),
+                tasks: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> ClientState {
+        ClientState::try_from(self.inner.state.load(Ordering::SeqCst)).unwrap()
+    }
+
+    /// Connect to the proxies, open the telemetry stream and send heartbeats
+    pub fn start(&self) {
+        if self
+            .inner
+            .state
+            .compare_exchange(
+                ClientState::Created.into(),
+                ClientState::StartFailed.into(),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_err()
+        {
+            return;
+        }
+        match self.connect() {
+            Ok(stub) => {
+                let _ = self.inner.stub.set(stub);
+            }
+            Err(err) => {
+                error!(endpoints = ?self.inner.endpoints, "connect to proxies error: {:?}", err);
+                return;
+            }
+        }
+        let client = self.clone();
+        self.spawn(
+            async move {
+                loop {
+                    if let Err(err) = client.telemetry().await {
+                        warn!("telemetry stream error: {:?}", err);
+                    }
+                    client.inner.session.lock().telemetry_tx.take();
+                    runtime::sleep(TELEMETRY_RETRY_DELAY).await;
+                }
+            }
+            .instrument(info_span!("grpc_telemetry")),
+        );
+        let client = self.clone();
+        self.spawn(
+            async move {
+                let mut interval = runtime::interval(HEARTBEAT_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = client.heartbeat().await {
+                        warn!("send heartbeat to proxies error: {:?}", err);
+                    }
+                }
+            }
+            .instrument(info_span!("grpc_heartbeat")),
+        );
+        self.inner
+            .state
+            .store(ClientState::Running.into(), Ordering::SeqCst);
+    }
+
+    /// Stop background tasks, those spawned by [`spawn`](Self::spawn) included, and tell
+    /// the proxies the client is gone
+    pub fn shutdown(&self) {
+        let state = self
+            .inner
+            .state
+            .swap(ClientState::Shutdown.into(), Ordering::SeqCst);
+        for task in self.inner.tasks.lock().drain(..) {
+            task.abort();
+        }
+        self.inner.session.lock().telemetry_tx.take();
+        if state == u8::from(ClientState::Running) && runtime::can_spawn() {
+            let client = self.clone();
+            runtime::spawn(async move {
+                if let Err(err) = client.notify_client_termination().await {
+                    warn!("notify proxies of client termination error: {:?}", err);
+                }
+            });
+        }
+    }
+
+    /// Spawn a task running until the client shuts down
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let task = runtime::spawn(future);
+        let mut tasks = self.inner.tasks.lock();
+        if matches!(self.state(), ClientState::Shutdown) {
+            task.abort();
+        } else {
+            tasks.push(task);
+        }
+    }
+
+    fn connect(&self) -> Result<MessagingServiceClient<Channel>, Error> {
+        let endpoints = self
+            .inner
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                let uri = if endpoint.contains("://") {
+                    endpoint.clone()
+                } else {
+                    format!("http://{}", endpoint)
+                };
+                Endpoint::from_shared(uri)
+                    .map(|endpoint| endpoint.connect_timeout(REQUEST_TIMEOUT).tcp_nodelay(true))
+                    .map_err(|err| {
+                        Error::Io(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid endpoint {}: {}", endpoint, err),
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let channel = match endpoints.len() {
+            0 => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "proxy endpoints are empty",
+                )))
+            }
+            1 => endpoints[0].connect_lazy(),
+            _ => Channel::balance_list(endpoints.into_iter()),
+        };
+        Ok(MessagingServiceClient::new(channel))
+    }
+
+    fn stub(&self) -> Result<MessagingServiceClient<Channel>, Error> {
+        match self.state() {
+            ClientState::Shutdown => Err(Error::Client(ClientError::Shutdown)),
+            _ => self
+                .inner
+                .stub
+                .get()
+                .cloned()
+                .ok_or(Error::Client(ClientError::NotStarted)),
+        }
+    }
+
+    fn resource(&self, name: &str) -> pb::Resource {
+        convert::resource(&self.inner.namespace, name)
+    }
+
+    fn group(&self) -> Option<pb::Resource> {
+        self.inner
+            .group
+            .as_deref()
+            .map(|group| self.resource(group))
+    }
+
+    fn request<T>(&self, message: T, timeout: Duration) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        self.sign(request.metadata_mut());
+        request.set_timeout(timeout);
+        request
+    }
+
+    /// Identify the client and sign requests with its credentials if any
+    fn sign(&self, metadata: &mut MetadataMap) {
+        let date_time = OffsetDateTime::now_utc().format("%Y%m%dT%H%M%SZ");
+        let headers = headers(
+            &self.inner.client_id,
+            &date_time,
+            self.inner.credentials.as_ref(),
+        );
+        for (key, value) in headers {
+            if let Ok(value) = value.parse::<AsciiMetadataValue>() {
+                metadata.insert(key, value);
+            }
+        }
+    }
+
+    /// Replace the publishing or subscription settings, reported to the proxy right away
+    /// once the telemetry stream is open
+    fn update_settings(&self, pub_sub: PubSub) {
+        let mut session = self.inner.session.lock();
+        if session.settings.pub_sub.as_ref() == Some(&pub_sub) {
+            return;
+        }
+        session.settings.pub_sub = Some(pub_sub);
+        let command = settings_command(session.settings.clone());
+        if let Some(tx) = &session.telemetry_tx {
+            let _ = tx.unbounded_send(command);
+        }
+    }
+
+    /// Report the subscriptions of a push consumer, messages are received in batches of
+    /// `batch_size` at most
+    pub fn update_subscriptions(
+        &self,
+        subscriptions: &[SubscriptionData],
+        batch_size: i32,
+        long_polling_timeout: Duration,
+    ) {
+        let subscriptions = subscriptions
+            .iter()
+            .map(|sub_data| pb::SubscriptionEntry {
+                topic: Some(self.resource(&sub_data.topic)),
+                expression: Some(convert::filter_expression(sub_data)),
+            })
+            .collect();
+        self.update_settings(PubSub::Subscription(pb::Subscription {
+            group: self.group(),
+            subscriptions,
+            fifo: Some(false),
+            receive_batch_size: Some(batch_size),
+            long_polling_timeout: Some(convert::duration(long_polling_timeout)),
+        }));
+    }
+
+    /// Open the telemetry stream with the current settings and answer commands of the
+    /// proxy until it's closed
+    async fn telemetry(&self) -> Result<(), Error> {
+        let (tx, rx) = mpsc::unbounded();
+        {
+            let mut session = self.inner.session.lock();
+            let _ = tx.unbounded_send(settings_command(session.settings.clone()));
+            session.telemetry_tx = Some(tx.clone());
+        }
+        let mut request = tonic::Request::new(rx);
+        self.sign(request.metadata_mut());
+        let mut stream = self
+            .stub()?
+            .telemetry(request)
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        while let Some(command) = stream.message().await.map_err(Error::from)? {
+            if let Some(reply) = handle_command(command) {
+                let _ = tx.unbounded_send(reply);
+            }
+        }
+        info!("telemetry stream closed by proxy");
+        Ok(())
+    }
+
+    async fn heartbeat(&self) -> Result<(), Error> {
+        let request = pb::HeartbeatRequest {
+            group: self.group(),
+            client_type: self.inner.client_type as i32,
+        };
+        let response = self
+            .stub()?
+            .heartbeat(self.request(request, REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        check_status(response.status.as_ref())
+    }
+
+    async fn notify_client_termination(&self) -> Result<(), Error> {
+        let stub = self
+            .inner
+            .stub
+            .get()
+            .cloned()
+            .ok_or(Error::Client(ClientError::NotStarted))?;
+        let request = pb::NotifyClientTerminationRequest {
+            group: self.group(),
+        };
+        let response = stub
+            .clone()
+            .notify_client_termination(self.request(request, REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        check_status(response.status.as_ref())
+    }
+
+    /// Send messages of the same topic together, whose topic isn't wrapped with the
+    /// namespace
+    pub async fn send_message(
+        &self,
+        msgs: Vec<Message>,
+        timeout: Duration,
+    ) -> Result<SendResult, Error> {
+        let topic = match msgs.first() {
+            Some(msg) => msg.topic.clone(),
+            None => return Err(Error::EmptyBatchMessage),
+        };
+        if msgs.iter().any(|msg| msg.topic != topic) {
+            return Err(Error::InvalidMessage(
+                "messages sent together must have the same topic".to_string(),
+            ));
+        }
+        let messages = msgs
+            .into_iter()
+            .map(|msg| convert::to_pb_message(msg, &self.inner.namespace, &self.inner.born_host))
+            .collect::<Result<Vec<_>, _>>()?;
+        let response = self
+            .stub()?
+            .send_message(self.request(pb::SendMessageRequest { messages }, timeout))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        // entries carry their own status when messages of a batch failed differently
+        if response.status.as_ref().map(|status| status.code)
+            != Some(pb::Code::MultipleResults as i32)
+        {
+            check_status(response.status.as_ref())?;
+        }
+        convert::to_send_result(&topic, response.entries)
+    }
+
+    /// Queues of `topic` assigned to the consumer group
+    pub async fn query_assignment(&self, topic: &str) -> Result<Vec<pb::MessageQueue>, Error> {
+        let request = pb::QueryAssignmentRequest {
+            topic: Some(self.resource(topic)),
+            group: self.group(),
+            endpoints: Some(convert::endpoints(&self.inner.endpoints)),
+        };
+        let response = self
+            .stub()?
+            .query_assignment(self.request(request, REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        check_status(response.status.as_ref())?;
+        Ok(response
+            .assignments
+            .into_iter()
+            .filter_map(|assignment| assignment.message_queue)
+            .collect())
+    }
+
+    /// Receive messages of `mq` matching `sub_data`, waiting for new messages up to
+    /// `long_polling_timeout`. Messages stay invisible to other consumers for
+    /// `invisible_duration` unless they are acknowledged, and are redelivered after it.
+    /// They are received with their receipt handles, in `topic` as seen by listeners
+    pub async fn receive_message(
+        &self,
+        mq: &pb::MessageQueue,
+        sub_data: &SubscriptionData,
+        topic: &str,
+        batch_size: i32,
+        invisible_duration: Duration,
+        long_polling_timeout: Duration,
+    ) -> Result<Vec<(MessageExt, String)>, Error> {
+        let request = pb::ReceiveMessageRequest {
+            group: self.group(),
+            message_queue: Some(mq.clone()),
+            filter_expression: Some(convert::filter_expression(sub_data)),
+            batch_size,
+            invisible_duration: Some(convert::duration(invisible_duration)),
+            auto_renew: false,
+            long_polling_timeout: Some(convert::duration(long_polling_timeout)),
+        };
+        let mut stream = self
+            .stub()?
+            .receive_message(self.request(request, long_polling_timeout + REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        let mut msgs = Vec::new();
+        while let Some(response) = stream.message().await.map_err(Error::from)? {
+            match response.content {
+                // no new messages before the long polling timeout
+                Some(Content::Status(status))
+                    if status.code != pb::Code::MessageNotFound as i32 =>
+                {
+                    check_status(Some(&status))?;
+                }
+                Some(Content::Message(msg)) => {
+                    match convert::to_message_ext(msg, topic.to_string()) {
+                        Ok(msg) => msgs.push(msg),
+                        Err(err) => {
+                            warn!(topic = %topic, "decode received message error: {:?}", err)
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Acknowledge messages of `topic` consumed, by their IDs and receipt handles
+    pub async fn ack_message(
+        &self,
+        topic: &str,
+        entries: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let request = pb::AckMessageRequest {
+            group: self.group(),
+            topic: Some(self.resource(topic)),
+            entries: entries
+                .into_iter()
+                .map(|(message_id, receipt_handle)| pb::AckMessageEntry {
+                    message_id,
+                    receipt_handle,
+                })
+                .collect(),
+        };
+        let response = self
+            .stub()?
+            .ack_message(self.request(request, REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        check_status(response.status.as_ref())?;
+        for entry in &response.entries {
+            check_status(entry.status.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Redeliver a message of `topic` after `invisible_duration` instead of its remaining
+    /// invisible duration
+    pub async fn change_invisible_duration(
+        &self,
+        topic: &str,
+        message_id: String,
+        receipt_handle: String,
+        invisible_duration: Duration,
+    ) -> Result<(), Error> {
+        let request = pb::ChangeInvisibleDurationRequest {
+            group: self.group(),
+            topic: Some(self.resource(topic)),
+            receipt_handle,
+            invisible_duration: Some(convert::duration(invisible_duration)),
+            message_id,
+        };
+        let response = self
+            .stub()?
+            .change_invisible_duration(self.request(request, REQUEST_TIMEOUT))
+            .await
+            .map_err(Error::from)?
+            .into_inner();
+        check_status(response.status.as_ref())
+    }
+}
+
+fn settings_command(settings: pb::Settings) -> pb::TelemetryCommand {
+    pb::TelemetryCommand {
+        status: None,
+        command: Some(Command::Settings(settings)),
+    }
+}
+
+/// Reply to a command of the proxy, if it expects one
+fn handle_command(command: pb::TelemetryCommand) -> Option<pb::TelemetryCommand> {
+    let unsupported = || pb::Status {
+        code: pb::Code::Unsupported as i32,
+        message: "not supported by the Rust client".to_string(),
+    };
+    match command.command? {
+        Command::Settings(settings) => {
+            debug!(settings = ?settings, "settings received from proxy");
+            None
+        }
+        Command::PrintThreadStackTraceCommand(command) => Some(pb::TelemetryCommand {
+            status: Some(unsupported()),
+            command: Some(Command::ThreadStackTrace(pb::ThreadStackTrace {
+                nonce: command.nonce,
+                thread_stack_trace: None,
+            })),
+        }),
+        Command::VerifyMessageCommand(command) => Some(pb::TelemetryCommand {
+            status: Some(unsupported()),
+            command: Some(Command::VerifyMessageResult(pb::VerifyMessageResult {
+                nonce: command.nonce,
+            })),
+        }),
+        Command::RecoverOrphanedTransactionCommand(command) => {
+            warn!(
+                transaction_id = %command.transaction_id,
+                "transactions aren't supported by the gRPC transport, orphaned transaction left to the proxy"
+            );
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Metadata of requests, `MQv2-HMAC-SHA1` signs the date time with the secret key
+fn headers(
+    client_id: &str,
+    date_time: &str,
+    credentials: Option<&Credentials>,
+) -> Vec<(&'static str, String)> {
+    let mut headers = vec![
+        ("x-mq-client-id", client_id.to_string()),
+        ("x-mq-language", "RUST".to_string()),
+        ("x-mq-client-version", env!("CARGO_PKG_VERSION").to_string()),
+        ("x-mq-protocol-version", "v2".to_string()),
+        ("x-mq-date-time", date_time.to_string()),
+    ];
+    if let Some(credentials) = credentials {
+        headers.push((
+            "authorization",
+            format!(
+                "MQv2-HMAC-SHA1 Credential={}, SignedHeaders=x-mq-date-time, Signature={}",
+                credentials.access_key,
+                signature(date_time.as_bytes(), credentials.secret_key.as_bytes())
+            ),
+        ));
+        if let Some(token) = &credentials.security_token {
+            headers.push(("x-mq-session-token", token.clone()));
+        }
+    }
+    headers
+}
+
+fn signature(data: &[u8], key: &[u8]) -> String {
+    let mut mac = HmacSha1::new_varkey(key).unwrap();
+    mac.update(data);
+    hex::encode_upper(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let unsigned = headers("127.0.0.1@1", "20230101T000000Z", None);
+        assert!(unsigned.contains(&("x-mq-client-id", "127.0.0.1@1".to_string())));
+        assert!(unsigned.contains(&("x-mq-date-time", "20230101T000000Z".to_string())));
+        assert!(unsigned.iter().all(|(key, _)| *key != "authorization"));
+
+        let credentials = Credentials::new("key", "key");
+        let signed = headers(
+            "127.0.0.1@1",
+            "The quick brown fox jumps over the lazy dog",
+            Some(&credentials),
+        );
+        assert!(signed.contains(&(
+            "authorization",
+            "MQv2-HMAC-SHA1 Credential=key, SignedHeaders=x-mq-date-time, Signature=DE7C9B85B8B78AA6BC8A7A36F70A90701C9DB4D9".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_handle_command() {
+        let reply = handle_command(pb::TelemetryCommand {
+            status: None,
+            command: Some(Command::PrintThreadStackTraceCommand(
+                pb::PrintThreadStackTraceCommand {
+                    nonce: "nonce".to_string(),
+                },
+            )),
+        })
+        .unwrap();
+        match reply.command {
+            Some(Command::ThreadStackTrace(trace)) => assert_eq!("nonce", trace.nonce),
+            command => panic!("unexpected reply {:?}", command),
+        }
+        assert!(handle_command(settings_command(pb::Settings::default())).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_before_start() {
+        let mut options = ClientOptions::default();
+        options.set_transport(Transport::Grpc {
+            endpoints: vec!["127.0.0.1:8081".to_string()],
+        });
+        let client = GrpcClient::producer(&options).unwrap();
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .body(b"hello".to_vec())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.send_message(vec![msg], REQUEST_TIMEOUT).await,
+            Err(Error::Client(ClientError::NotStarted))
+        ));
+    }
+}
@@ -0,0 +1,407 @@
+use std::io::Read;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+
+use super::pb;
+use crate::client::model::SubscriptionData;
+use crate::consumer::ExpressionType;
+use crate::error::Error;
+use crate::message::{body_crc, Message, MessageExt, MessageQueue, Property};
+use crate::producer::{SendResult, SendStatus};
+use crate::sys_flag::SysFlag;
+
+pub(crate) fn resource(namespace: &str, name: &str) -> pb::Resource {
+    pb::Resource {
+        resource_namespace: namespace.to_string(),
+        name: name.to_string(),
+    }
+}
+
+pub(crate) fn duration(duration: Duration) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: duration.as_secs() as i64,
+        nanos: duration.subsec_nanos() as i32,
+    }
+}
+
+fn timestamp(millis: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: millis.div_euclid(1000),
+        nanos: (millis.rem_euclid(1000) * 1_000_000) as i32,
+    }
+}
+
+fn millis(timestamp: &prost_types::Timestamp) -> i64 {
+    timestamp.seconds * 1000 + i64::from(timestamp.nanos) / 1_000_000
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Proxy endpoints `host:port` as reported in settings and assignment queries
+pub(crate) fn endpoints(endpoints: &[String]) -> pb::Endpoints {
+    let addresses: Vec<pb::Address> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let endpoint = endpoint
+                .trim_start_matches("http://")
+                .trim_start_matches("https://");
+            let (host, port) = match endpoint.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().unwrap_or(80)),
+                None => (endpoint, 80),
+            };
+            pb::Address {
+                host: host.to_string(),
+                port,
+            }
+        })
+        .collect();
+    let scheme = if addresses
+        .iter()
+        .all(|address| address.host.parse::<Ipv4Addr>().is_ok())
+    {
+        pb::AddressScheme::IPv4
+    } else if addresses.iter().all(|address| {
+        address
+            .host
+            .trim_matches(&['[', ']'][..])
+            .parse::<std::net::Ipv6Addr>()
+            .is_ok()
+    }) {
+        pb::AddressScheme::IPv6
+    } else {
+        pb::AddressScheme::DomainName
+    };
+    pb::Endpoints {
+        scheme: scheme as i32,
+        addresses,
+    }
+}
+
+/// Filter expression of a subscription, `*` when the expression is empty
+pub(crate) fn filter_expression(sub_data: &SubscriptionData) -> pb::FilterExpression {
+    let filter_type = if sub_data.expression_type == ExpressionType::Sql92.to_string() {
+        pb::FilterType::Sql
+    } else {
+        pb::FilterType::Tag
+    };
+    let expression = sub_data.sub_string.trim();
+    pb::FilterExpression {
+        r#type: filter_type as i32,
+        expression: if expression.is_empty() {
+            "*".to_string()
+        } else {
+            expression.to_string()
+        },
+    }
+}
+
+/// gRPC message of `msg`, whose topic is not wrapped with the namespace. Messages are
+/// scheduled by delivery time as 5.x proxies don't support delay levels
+pub(crate) fn to_pb_message(
+    mut msg: Message,
+    namespace: &str,
+    born_host: &str,
+) -> Result<pb::Message, Error> {
+    if msg.delay_time_level().is_some() {
+        return Err(Error::InvalidMessage(
+            "delay levels aren't supported by the gRPC transport, set a delivery time".to_string(),
+        ));
+    }
+    msg.check_delivery()?;
+    msg.set_default_unique_key();
+    let delivery_timestamp = msg
+        .deliver_time_ms()
+        .or_else(|| msg.start_deliver_time())
+        .map(timestamp);
+    let message_group = msg.sharding_key();
+    let message_type = if delivery_timestamp.is_some() {
+        pb::MessageType::Delay
+    } else if message_group.is_some() {
+        pb::MessageType::Fifo
+    } else {
+        pb::MessageType::Normal
+    };
+    let system_properties = pb::SystemProperties {
+        tag: msg.tags().map(str::to_string),
+        keys: msg.keys().into_iter().map(str::to_string).collect(),
+        message_id: msg.unique_key().unwrap_or_default().to_string(),
+        body_encoding: pb::Encoding::Identity as i32,
+        message_type: message_type as i32,
+        born_timestamp: Some(timestamp(now_millis())),
+        born_host: born_host.to_string(),
+        delivery_timestamp,
+        message_group,
+        ..pb::SystemProperties::default()
+    };
+    let user_properties = msg
+        .properties
+        .iter()
+        .filter(|(name, _)| !Property::SYSTEM.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    Ok(pb::Message {
+        topic: Some(resource(namespace, &msg.topic)),
+        user_properties,
+        system_properties: Some(system_properties),
+        body: msg.body.to_vec(),
+    })
+}
+
+/// Result of messages sent together, IDs of a batch are joined by commas like those of
+/// batches sent to brokers
+pub(crate) fn to_send_result(
+    topic: &str,
+    entries: Vec<pb::SendResultEntry>,
+) -> Result<SendResult, Error> {
+    for entry in &entries {
+        check_status(entry.status.as_ref())?;
+    }
+    let msg_id = entries
+        .iter()
+        .map(|entry| entry.message_id.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let transaction_id = entries
+        .first()
+        .map(|entry| entry.transaction_id.clone())
+        .filter(|id| !id.is_empty());
+    Ok(SendResult {
+        status: SendStatus::Ok,
+        msg_id,
+        message_queue: MessageQueue {
+            topic: topic.to_string(),
+            broker_name: String::new(),
+            queue_id: 0,
+        },
+        queue_offset: entries
+            .first()
+            .map(|entry| entry.offset)
+            .unwrap_or_default(),
+        transaction_id,
+        offset_msg_id: String::new(),
+        region_id: String::new(),
+        trace_on: false,
+    })
+}
+
+fn to_socket_addr(host: &str) -> SocketAddr {
+    host.parse()
+        .unwrap_or_else(|_| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+}
+
+/// Message received from a proxy with its receipt handle acknowledging it, in `topic`
+/// as seen by listeners
+pub(crate) fn to_message_ext(
+    msg: pb::Message,
+    topic: String,
+) -> Result<(MessageExt, String), Error> {
+    let props = msg.system_properties.unwrap_or_default();
+    let body = match pb::Encoding::from_i32(props.body_encoding) {
+        Some(pb::Encoding::Gzip) => {
+            let mut body = Vec::new();
+            GzDecoder::new(&msg.body[..]).read_to_end(&mut body)?;
+            body
+        }
+        _ => msg.body,
+    };
+    let mut message = Message::new(topic, String::new(), String::new(), 0, body, false);
+    message.properties = msg.user_properties;
+    if let Some(tag) = props.tag {
+        message.set_property(Property::TAGS.to_string(), tag);
+    }
+    if !props.keys.is_empty() {
+        message.set_keys(props.keys);
+    }
+    if let Some(group) = props.message_group {
+        message.set_property(Property::SHARDING_KEY.to_string(), group);
+    }
+    if let Some(delivery_timestamp) = &props.delivery_timestamp {
+        message.set_deliver_time_ms(millis(delivery_timestamp));
+    }
+    message.set_unique_key(props.message_id.clone());
+    let receipt_handle = match props.receipt_handle {
+        Some(receipt_handle) => receipt_handle,
+        None => {
+            return Err(Error::InvalidMessage(format!(
+                "message {} has no receipt handle",
+                props.message_id
+            )))
+        }
+    };
+    let reconsume_times = props.delivery_attempt.unwrap_or(1).max(1) - 1;
+    let msg = MessageExt {
+        queue_id: props.queue_id,
+        store_size: 0,
+        queue_offset: props.queue_offset.unwrap_or_default(),
+        sys_flag: SysFlag::empty(),
+        born_host: to_socket_addr(&props.born_host),
+        born_timestamp: props
+            .born_timestamp
+            .as_ref()
+            .map(millis)
+            .unwrap_or_default(),
+        store_host: to_socket_addr(&props.store_host),
+        store_timestamp: props
+            .store_timestamp
+            .as_ref()
+            .map(millis)
+            .unwrap_or_default(),
+        msg_id: props.message_id,
+        commit_log_offset: 0,
+        body_crc: body_crc(message.body()),
+        reconsume_times,
+        prepared_transaction_offset: 0,
+        raw_body: None,
+        message,
+    };
+    Ok((msg, receipt_handle))
+}
+
+/// Fails unless the status of a response is `OK`
+pub(crate) fn check_status(status: Option<&pb::Status>) -> Result<(), Error> {
+    match status {
+        Some(status) if status.code != pb::Code::Ok as i32 => Err(Error::GrpcResponse {
+            code: status.code,
+            message: status.message.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_pb_message() {
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .tag("TagA")
+            .keys(vec!["k1", "k2"])
+            .property("p", "v")
+            .body(b"hello".to_vec())
+            .build()
+            .unwrap();
+        let pb_msg = to_pb_message(msg, "ns", "10.0.0.1").unwrap();
+        assert_eq!(Some(resource("ns", "TopicTest")), pb_msg.topic);
+        assert_eq!(b"hello".to_vec(), pb_msg.body);
+        assert_eq!(Some(&"v".to_string()), pb_msg.user_properties.get("p"));
+        assert!(!pb_msg.user_properties.contains_key(Property::TAGS));
+        let props = pb_msg.system_properties.unwrap();
+        assert_eq!(Some("TagA".to_string()), props.tag);
+        assert_eq!(vec!["k1".to_string(), "k2".to_string()], props.keys);
+        assert!(!props.message_id.is_empty());
+        assert_eq!(pb::MessageType::Normal as i32, props.message_type);
+        assert_eq!("10.0.0.1", props.born_host);
+
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .sharding_key("order-1")
+            .body(b"hello".to_vec())
+            .build()
+            .unwrap();
+        let props = to_pb_message(msg, "", "10.0.0.1")
+            .unwrap()
+            .system_properties
+            .unwrap();
+        assert_eq!(pb::MessageType::Fifo as i32, props.message_type);
+        assert_eq!(Some("order-1".to_string()), props.message_group);
+
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .deliver_time_ms(1_600_000_000_123)
+            .body(b"hello".to_vec())
+            .build()
+            .unwrap();
+        let props = to_pb_message(msg, "", "10.0.0.1")
+            .unwrap()
+            .system_properties
+            .unwrap();
+        assert_eq!(pb::MessageType::Delay as i32, props.message_type);
+        assert_eq!(Some(timestamp(1_600_000_000_123)), props.delivery_timestamp);
+
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .delay_time_level(3)
+            .body(b"hello".to_vec())
+            .build()
+            .unwrap();
+        assert!(to_pb_message(msg, "", "10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_to_message_ext() {
+        let pb_msg = pb::Message {
+            topic: Some(resource("", "TopicTest")),
+            user_properties: vec![("p".to_string(), "v".to_string())]
+                .into_iter()
+                .collect(),
+            system_properties: Some(pb::SystemProperties {
+                tag: Some("TagA".to_string()),
+                keys: vec!["k1".to_string()],
+                message_id: "01ID".to_string(),
+                receipt_handle: Some("handle".to_string()),
+                queue_id: 2,
+                queue_offset: Some(42),
+                delivery_attempt: Some(3),
+                born_host: "10.0.0.1:1234".to_string(),
+                born_timestamp: Some(timestamp(1_600_000_000_123)),
+                ..pb::SystemProperties::default()
+            }),
+            body: b"hello".to_vec(),
+        };
+        let (msg, receipt_handle) = to_message_ext(pb_msg, "TopicTest".to_string()).unwrap();
+        assert_eq!("handle", receipt_handle);
+        assert_eq!("TopicTest", msg.topic());
+        assert_eq!(b"hello", msg.body());
+        assert_eq!(Some("TagA"), msg.tags());
+        assert_eq!(vec!["k1"], msg.keys());
+        assert_eq!(Some("v"), msg.get_property("p"));
+        assert_eq!("01ID", msg.msg_id());
+        assert_eq!(2, msg.queue_id());
+        assert_eq!(42, msg.queue_offset());
+        assert_eq!(2, msg.reconsume_times());
+        assert_eq!(
+            "10.0.0.1:1234".parse::<SocketAddr>().unwrap(),
+            msg.born_host()
+        );
+        assert_eq!(1_600_000_000_123, msg.born_timestamp());
+    }
+
+    #[test]
+    fn test_check_status() {
+        assert!(check_status(None).is_ok());
+        let ok = pb::Status {
+            code: pb::Code::Ok as i32,
+            message: "OK".to_string(),
+        };
+        assert!(check_status(Some(&ok)).is_ok());
+        let not_found = pb::Status {
+            code: pb::Code::TopicNotFound as i32,
+            message: "topic not found".to_string(),
+        };
+        match check_status(Some(&not_found)) {
+            Err(Error::GrpcResponse { code, .. }) => {
+                assert_eq!(pb::Code::TopicNotFound as i32, code)
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let ipv4 = endpoints(&["127.0.0.1:8081".to_string(), "10.0.0.1:8081".to_string()]);
+        assert_eq!(pb::AddressScheme::IPv4 as i32, ipv4.scheme);
+        assert_eq!(8081, ipv4.addresses[0].port);
+        let domain = endpoints(&["http://proxy.example.com:8081".to_string()]);
+        assert_eq!(pb::AddressScheme::DomainName as i32, domain.scheme);
+        assert_eq!("proxy.example.com", domain.addresses[0].host);
+    }
+}
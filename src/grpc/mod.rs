@@ -0,0 +1,25 @@
+//! Transport of RocketMQ 5.x proxies speaking the gRPC protocol of apache/rocketmq-apis.
+//! Producers send messages with `SendMessage`, push consumers receive them from the queues
+//! assigned by `QueryAssignment` with `ReceiveMessage` and acknowledge them with
+//! `AckMessage`, settings and commands of the proxy go through the `Telemetry` stream.
+
+#[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+compile_error!("feature `grpc` runs on the tokio runtime only");
+
+mod client;
+mod convert;
+#[allow(dead_code, missing_docs, clippy::all)]
+pub(crate) mod pb;
+
+pub(crate) use client::GrpcClient;
+
+/// Protocol producers and consumers speak to RocketMQ, see `ClientOptions::set_transport`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// Remoting protocol of name servers and brokers
+    #[default]
+    Remoting,
+    /// gRPC protocol of 5.x proxies at `endpoints`, like `127.0.0.1:8081`, name servers
+    /// aren't used
+    Grpc { endpoints: Vec<String> },
+}
@@ -0,0 +1,107 @@
+//! Spans of the send, pull and consume paths, attributing latency to brokers or listeners.
+//! Disabled spans are returned without the `instrument` feature. With the `otel` feature
+//! send spans also carry the fields of the OpenTelemetry messaging semantic conventions,
+//! which `tracing-opentelemetry` exports as producer spans
+
+use std::fmt;
+
+#[cfg(feature = "instrument")]
+use tracing::field::Empty;
+use tracing::Span;
+
+use crate::message::MessageQueue;
+
+/// Span of sending a message to `topic`, `queue`, `broker`, `msg_id` and `result` are
+/// recorded once known
+pub(crate) fn send_span(topic: &str, oneway: bool) -> Span {
+    #[cfg(feature = "otel")]
+    return tracing::info_span!(
+        "send",
+        otel.name = %format_args!("{} publish", topic),
+        otel.kind = "producer",
+        messaging.system = "rocketmq",
+        messaging.operation = "publish",
+        messaging.destination.name = %topic,
+        messaging.message.id = Empty,
+        topic = %topic,
+        oneway = oneway,
+        queue = Empty,
+        broker = Empty,
+        msg_id = Empty,
+        result = Empty,
+    );
+    #[cfg(all(feature = "instrument", not(feature = "otel")))]
+    return tracing::info_span!(
+        "send",
+        topic = %topic,
+        oneway = oneway,
+        queue = Empty,
+        broker = Empty,
+        msg_id = Empty,
+        result = Empty,
+    );
+    #[cfg(not(feature = "instrument"))]
+    {
+        let _ = (topic, oneway);
+        Span::none()
+    }
+}
+
+/// Span of a pull request of `mq` to `broker` from `offset`, `result` and `found` are
+/// recorded with the response
+pub(crate) fn pull_span(mq: &MessageQueue, broker: &str, offset: i64) -> Span {
+    #[cfg(feature = "instrument")]
+    return tracing::info_span!(
+        "pull",
+        topic = %mq.topic,
+        queue = mq.queue_id,
+        broker = %broker,
+        offset = offset,
+        result = Empty,
+        found = Empty,
+    );
+    #[cfg(not(feature = "instrument"))]
+    {
+        let _ = (mq, broker, offset);
+        Span::none()
+    }
+}
+
+/// Span of dispatching `count` messages of `mq` from `msg_id` on to the listener, `result`
+/// is recorded once it returns
+pub(crate) fn dispatch_span(mq: &MessageQueue, msg_id: &str, count: usize) -> Span {
+    #[cfg(feature = "instrument")]
+    return tracing::info_span!(
+        "dispatch",
+        topic = %mq.topic,
+        queue = mq.queue_id,
+        broker = %mq.broker_name,
+        msg_id = %msg_id,
+        count = count,
+        result = Empty,
+    );
+    #[cfg(not(feature = "instrument"))]
+    {
+        let _ = (mq, msg_id, count);
+        Span::none()
+    }
+}
+
+/// Record `msg_id` in the `msg_id` field of `span`, and the semantic convention one if any
+pub(crate) fn record_msg_id(span: &Span, msg_id: &str) {
+    span.record("msg_id", msg_id);
+    span.record("messaging.message.id", msg_id);
+}
+
+/// Record `result` in the `result` field of `span`, results in `Debug` form and errors in
+/// `Display` form
+pub(crate) fn record_result<T, E>(span: &Span, result: &Result<T, E>)
+where
+    T: fmt::Debug,
+    E: fmt::Display,
+{
+    match result {
+        Ok(value) => span.record("result", tracing::field::debug(value)),
+        Err(err) => span.record("result", tracing::field::display(err)),
+    };
+}
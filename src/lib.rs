@@ -1,8 +1,19 @@
+/// RocketMQ admin
+pub mod admin;
+mod buffer;
 mod client;
+mod codec;
+mod config;
 /// RocketMQ consumer
 pub mod consumer;
 mod error;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod instrument;
 mod message;
+/// RocketMQ client metrics
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod namesrv;
 mod permission;
 /// RocketMQ producer
@@ -12,8 +23,38 @@ mod remoting;
 /// RocketMQ name server resolver
 pub mod resolver;
 mod route;
+mod runtime;
+mod sys_flag;
+/// Mock broker for integration tests
+#[cfg(feature = "test-util")]
+pub mod test_util;
+/// Names of retry, dead letter queue and system topics, and naming rules of topics and groups
+pub mod topic;
+#[cfg(feature = "trace-context")]
+mod trace_context;
 mod utils;
 
-pub use consumer::{ConsumerOptions, PushConsumer};
+pub use admin::{Admin, AdminOptions};
+pub use client::model::{SubscriptionGroupConfig, TopicConfig};
+pub use client::{AccessChannel, ClientOptions, Credentials, RequestProcessor};
+pub use codec::{Codec, JsonCodec};
+pub use consumer::{ConsumeResult, ConsumerOptions, MessageSelector, PushConsumer};
 pub use error::Error;
-pub use producer::{Producer, ProducerOptions};
+#[cfg(feature = "grpc")]
+pub use grpc::Transport;
+pub use message::{Message, MessageBuilder, MessageExt, MessageExtIter, MessageId, MessageQueue};
+pub use permission::Permission;
+pub use producer::{LocalTransactionState, Producer, ProducerOptions};
+pub use protocol::compression::CompressionType;
+pub use protocol::{RemotingCommand, RequestCode, ResponseCode};
+#[cfg(feature = "tls")]
+pub use remoting::TlsConfig;
+pub use remoting::{ConnectionListener, LoggingRpcHook, ProxyConfig, RateLimit, RpcHook};
+#[cfg(feature = "fault-injection")]
+pub use remoting::{Fault, FaultInjector};
+pub use route::{BrokerData, QueueData, TopicRouteData};
+pub use sys_flag::SysFlag;
+#[cfg(feature = "otel")]
+pub use trace_context::OpenTelemetryPropagator;
+#[cfg(feature = "trace-context")]
+pub use trace_context::{TraceContext, TracePropagator};
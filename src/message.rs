@@ -1,20 +1,29 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{Cursor, Read, Write};
-use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::process;
 use std::time::SystemTime;
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use flate2::read::ZlibDecoder;
-use num_enum::{IntoPrimitive, TryFromPrimitive};
+use bytes::Bytes;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
 
+use crate::codec::Codec;
+use crate::error::FrameError;
+use crate::protocol::compression::{self, CompressionType};
+use crate::protocol::read_bytes;
+use crate::sys_flag::SysFlag;
+use crate::topic;
 use crate::utils::client_ip_addr;
 use crate::Error;
 
+pub(crate) const MESSAGE_MAGIC_CODE: i32 = -626843481;
+const MESSAGE_MAGIC_CODE_V2: i32 = -626843477;
 const NAME_VALUE_SEP: char = '\u{001}';
 const PROPERTY_SEP: char = '\u{002}';
 
@@ -26,7 +35,8 @@ static UNIQ_ID_GENERATOR: Lazy<Mutex<UniqueIdGenerator>> = Lazy::new(|| {
         IpAddr::V6(v6) => buf.extend_from_slice(&v6.octets()),
     };
     buf.write_i16::<BigEndian>(process::id() as i16).unwrap();
-    buf.write_i32::<BigEndian>(0).unwrap(); // classLoaderId
+    // hash of the class loader in Java clients, random to tell apart clients in the process
+    buf.write_i32::<BigEndian>(rand::random()).unwrap();
     let generator = UniqueIdGenerator {
         counter: 0,
         start_timestamp: 0,
@@ -65,20 +75,44 @@ impl Property {
     pub const CHECK_IMMUNITY_TIME_IN_SECONDS: &'static str = "CHECK_IMMUNITY_TIME_IN_SECONDS";
     pub const KEY_SEPARATOR: &'static str = " ";
     pub const SHARDING_KEY: &'static str = "SHARDING_KEY";
-}
+    /// Milliseconds since the epoch to deliver at, supported by Alibaba Cloud brokers
+    pub const START_DELIVER_TIME: &'static str = "__STARTDELIVERTIME";
+    /// Milliseconds since the epoch to deliver at, supported by 5.x brokers
+    pub const TIMER_DELIVER_MS: &'static str = "TIMER_DELIVER_MS";
 
-#[derive(Debug, Clone, Copy, PartialEq, IntoPrimitive, TryFromPrimitive)]
-#[repr(i32)]
-pub enum MessageSysFlag {
-    Compressed = 0x1,
-    MultiTags = 0x1 << 1,
-    TransactionNotType = 0,
-    TransactionPreparedType = 0x1 << 2,
-    TransactionCommitType = 0x2 << 2,
-    TransactionRollbackType = 0x3 << 2,
+    /// Properties set by the client or brokers, users can't set them as their own
+    pub(crate) const SYSTEM: &'static [&'static str] = &[
+        Self::KEYS,
+        Self::TAGS,
+        Self::WAIT_STORE_MSG_OK,
+        Self::DELAY_TIME_LEVEL,
+        Self::RETRY_TOPIC,
+        Self::REAL_TOPIC,
+        Self::REAL_QUEUE_ID,
+        Self::TRANSACTION_PREPARED,
+        Self::PRODUCER_GROUP,
+        Self::MIN_OFFSET,
+        Self::MAX_OFFSET,
+        Self::BUYER_ID,
+        Self::ORIGIN_MESSAGE_ID,
+        Self::TRANSFER_FLAG,
+        Self::CORRECTION_FLAG,
+        Self::MQ2_FLAG,
+        Self::RECONSUME_TIME,
+        Self::MSG_REGION,
+        Self::TRACE_SWITCH,
+        Self::UNIQ_CLIENT_MSG_ID_KEY,
+        Self::MAX_RECONSUME_TIMES,
+        Self::TRANSACTION_PREPARED_QUEUE_OFFSET,
+        Self::TRANSACTION_CHECK_TIMES,
+        Self::CHECK_IMMUNITY_TIME_IN_SECONDS,
+        Self::SHARDING_KEY,
+        Self::START_DELIVER_TIME,
+        Self::TIMER_DELIVER_MS,
+    ];
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct MessageQueue {
     pub topic: String,
     #[serde(rename = "brokerName")]
@@ -91,15 +125,22 @@ pub struct MessageQueue {
 pub struct Message {
     pub(crate) topic: String,
     pub(crate) flag: i32,
-    pub(crate) sys_flag: i32,
-    properties: HashMap<String, String>,
-    pub(crate) body: Vec<u8>,
+    pub(crate) sys_flag: SysFlag,
+    pub(crate) properties: HashMap<String, String>,
+    pub(crate) body: Bytes,
     transaction_id: String,
     pub(crate) batch: bool,
+    // unique keys of the messages encoded in the body of a batch
+    batch_unique_keys: Vec<String>,
     pub(crate) queue: Option<MessageQueue>,
 }
 
 impl Message {
+    /// Build a message field by field, validated on [`MessageBuilder::build`]
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+
     pub fn new(
         topic: String,
         tags: String,
@@ -121,11 +162,12 @@ impl Message {
         Message {
             topic,
             flag,
-            sys_flag: 0,
-            body,
+            sys_flag: SysFlag::empty(),
+            body: body.into(),
             properties: props,
             transaction_id: String::new(),
             batch: false,
+            batch_unique_keys: Vec::new(),
             queue: None,
         }
     }
@@ -147,6 +189,15 @@ impl Message {
             .or_insert_with(|| UNIQ_ID_GENERATOR.lock().generate());
     }
 
+    /// Unique keys of the message, or of every message of a batch
+    pub(crate) fn unique_keys(&self) -> Vec<&str> {
+        if self.batch {
+            self.batch_unique_keys.iter().map(String::as_str).collect()
+        } else {
+            self.unique_key().into_iter().collect()
+        }
+    }
+
     pub fn get_property(&self, property: &str) -> Option<&String> {
         self.properties.get(property)
     }
@@ -155,6 +206,34 @@ impl Message {
         self.properties.insert(property, value)
     }
 
+    /// Set a property of the application, failing if `name` is reserved for the client or
+    /// brokers, or if `name` or `value` contain the separators of encoded properties
+    pub fn put_user_property(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if Property::SYSTEM.contains(&name) {
+            return Err(Error::InvalidMessage(format!(
+                "property {} is reserved for the client and brokers",
+                name
+            )));
+        }
+        if name.trim().is_empty() {
+            return Err(Error::InvalidMessage("property name is empty".to_string()));
+        }
+        if name.contains([NAME_VALUE_SEP, PROPERTY_SEP])
+            || value.contains([NAME_VALUE_SEP, PROPERTY_SEP])
+        {
+            return Err(Error::InvalidMessage(format!(
+                "property {:?} contains reserved characters \\u{{1}} or \\u{{2}}",
+                name
+            )));
+        }
+        self.properties.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn remove_property(&mut self, property: &str) -> Option<String> {
+        self.properties.remove(property)
+    }
+
     pub fn sharding_key(&self) -> Option<String> {
         self.properties
             .get(Property::SHARDING_KEY)
@@ -162,12 +241,155 @@ impl Message {
             .and_then(|val| if val.is_empty() { None } else { Some(val) })
     }
 
+    /// Keys the message is indexed by on brokers
+    pub fn keys(&self) -> Vec<&str> {
+        self.properties
+            .get(Property::KEYS)
+            .map(|keys| {
+                keys.split(Property::KEY_SEPARATOR)
+                    .filter(|key| !key.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Replace the keys, joined by `Property::KEY_SEPARATOR` so a key containing it is
+    /// indexed as several keys
+    pub fn set_keys<I, S>(&mut self, keys: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let keys: Vec<S> = keys.into_iter().collect();
+        let keys: Vec<&str> = keys
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|key| !key.is_empty())
+            .collect();
+        if keys.is_empty() {
+            self.properties.remove(Property::KEYS);
+        } else {
+            self.properties.insert(
+                Property::KEYS.to_string(),
+                keys.join(Property::KEY_SEPARATOR),
+            );
+        }
+    }
+
+    /// Delay level the message is delivered after, `None` if it's not delayed
+    pub fn delay_time_level(&self) -> Option<i32> {
+        self.positive_property(Property::DELAY_TIME_LEVEL)
+    }
+
+    pub fn set_delay_time_level(&mut self, level: i32) {
+        self.properties
+            .insert(Property::DELAY_TIME_LEVEL.to_string(), level.to_string());
+    }
+
+    /// Milliseconds since the epoch the message is delivered at by Alibaba Cloud brokers
+    pub fn start_deliver_time(&self) -> Option<i64> {
+        self.positive_property(Property::START_DELIVER_TIME)
+    }
+
+    pub fn set_start_deliver_time(&mut self, timestamp_ms: i64) {
+        self.properties.insert(
+            Property::START_DELIVER_TIME.to_string(),
+            timestamp_ms.to_string(),
+        );
+    }
+
+    /// Milliseconds since the epoch the message is delivered at by 5.x brokers
+    pub fn deliver_time_ms(&self) -> Option<i64> {
+        self.positive_property(Property::TIMER_DELIVER_MS)
+    }
+
+    pub fn set_deliver_time_ms(&mut self, timestamp_ms: i64) {
+        self.properties.insert(
+            Property::TIMER_DELIVER_MS.to_string(),
+            timestamp_ms.to_string(),
+        );
+    }
+
+    fn positive_property<T>(&self, property: &str) -> Option<T>
+    where
+        T: std::str::FromStr + PartialOrd + Default,
+    {
+        self.properties
+            .get(property)
+            .and_then(|val| val.parse().ok())
+            .filter(|val| *val > T::default())
+    }
+
+    /// A message is scheduled by a delay level or a delivery time, but not by several of
+    /// them, which brokers would resolve differently. Like brokers, 0 means not scheduled
+    pub(crate) fn check_delivery(&self) -> Result<(), Error> {
+        let mut scheduled = Vec::new();
+        for property in &[
+            Property::DELAY_TIME_LEVEL,
+            Property::START_DELIVER_TIME,
+            Property::TIMER_DELIVER_MS,
+        ] {
+            if let Some(val) = self.properties.get(*property) {
+                match val.parse::<i64>() {
+                    Ok(0) => {}
+                    Ok(val) if val > 0 => scheduled.push(*property),
+                    _ => {
+                        return Err(Error::InvalidMessage(format!(
+                            "invalid {} {:?}",
+                            property, val
+                        )))
+                    }
+                }
+            }
+        }
+        if scheduled.len() > 1 {
+            return Err(Error::InvalidMessage(format!(
+                "conflicting delivery schedules {}",
+                scheduled.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn topic(&self) -> &str {
         &self.topic
     }
 
-    pub(crate) fn dump_properties(&self) -> String {
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    #[inline]
+    /// Id of the local transaction the message belongs to, empty if it's not transactional
+    pub fn transaction_id(&self) -> &str {
+        &self.transaction_id
+    }
+
+    pub(crate) fn set_transaction_id(&mut self, transaction_id: String) {
+        self.transaction_id = transaction_id;
+    }
+
+    pub fn tags(&self) -> Option<&str> {
+        self.properties.get(Property::TAGS).map(|s| &s[..])
+    }
+
+    #[inline]
+    pub fn sys_flag(&self) -> SysFlag {
+        self.sys_flag
+    }
+
+    /// Set `COMPRESSED` if the body is compressed already so producer sends it as is,
+    /// transaction types are set by producer
+    pub fn set_sys_flag(&mut self, sys_flag: SysFlag) {
+        self.sys_flag = sys_flag;
+    }
+
+    /// Properties as sent to and stored by broker, every property is its name and value
+    /// joined by `\u{1}` and followed by `\u{2}`, which can't be escaped
+    pub(crate) fn dump_properties(&self) -> Result<String, Error> {
+        self.check_properties()?;
         let mut s = String::new();
         for (k, v) in &self.properties {
             s.reserve(k.len() + v.len() + 2);
@@ -176,23 +398,45 @@ impl Message {
             s.push_str(v);
             s.push(PROPERTY_SEP);
         }
-        s
+        // broker reads the length as a short
+        if s.len() > i16::MAX as usize {
+            return Err(Error::InvalidMessage(format!(
+                "properties of {} bytes exceed the limit of {} bytes",
+                s.len(),
+                i16::MAX
+            )));
+        }
+        Ok(s)
     }
 
-    fn parse_properties(prop_str: &str) -> HashMap<String, String> {
-        let mut props = HashMap::new();
-        for item in prop_str.split(PROPERTY_SEP) {
-            let kv: Vec<&str> = item.split(NAME_VALUE_SEP).collect();
-            if kv.len() == 2 {
-                props.insert(kv[0].to_string(), kv[1].to_string());
-            }
+    /// Names must be non-empty, neither names nor values may contain the separators
+    fn check_properties(&self) -> Result<(), Error> {
+        let malformed = self.properties.iter().find(|(name, value)| {
+            name.is_empty()
+                || name.contains([NAME_VALUE_SEP, PROPERTY_SEP])
+                || value.contains([NAME_VALUE_SEP, PROPERTY_SEP])
+        });
+        match malformed {
+            Some((name, _)) => Err(Error::InvalidMessage(format!(
+                "property {:?} contains reserved characters",
+                name
+            ))),
+            None => Ok(()),
         }
-        props
+    }
+
+    fn parse_properties(prop_str: &str) -> HashMap<String, String> {
+        prop_str
+            .split(PROPERTY_SEP)
+            .filter_map(|item| item.split_once(NAME_VALUE_SEP))
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
     }
 
     fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
-        let properties = self.dump_properties();
-        let store_size = 4 + 4 + 4 + 4 + 4 + self.body.len() + properties.len();
+        let properties = self.dump_properties()?;
+        let store_size = 4 + 4 + 4 + 4 + 4 + self.body.len() + 2 + properties.len();
         // TotalSize Magic BodyCrc Flag BodySize Body PropertySize Property
         buf.reserve(store_size);
         buf.write_u32::<BigEndian>(store_size as u32)?; // 1. TotalSize
@@ -213,220 +457,819 @@ impl Message {
             Ok(msgs[0].clone())
         } else {
             let mut body = Vec::new();
+            let mut batch_unique_keys = Vec::with_capacity(msgs.len());
             for msg in msgs {
+                if msg.delay_time_level().is_some()
+                    || msg.start_deliver_time().is_some()
+                    || msg.deliver_time_ms().is_some()
+                {
+                    return Err(Error::InvalidMessage(
+                        "scheduled messages can't be sent in batch".to_string(),
+                    ));
+                }
+                let mut msg = msg.clone();
+                msg.set_default_unique_key();
                 msg.encode_into(&mut body)?;
+                batch_unique_keys.extend(msg.unique_key().map(str::to_string));
             }
             let msg = &msgs[0];
             Ok(Message {
                 topic: msg.topic.clone(),
                 queue: msg.queue.clone(),
                 flag: 0,
-                sys_flag: 0,
+                sys_flag: SysFlag::empty(),
                 properties: HashMap::new(),
-                body,
+                body: body.into(),
                 transaction_id: String::new(),
                 batch: true,
+                batch_unique_keys,
             })
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Builder of [`Message`], e.g.
+///
+/// ```
+/// use rocketmq::Message;
+///
+/// let msg = Message::builder()
+///     .topic("TopicTest")
+///     .tag("TagA")
+///     .keys(["order-1"])
+///     .property("region", "eu")
+///     .body(b"hello".to_vec())
+///     .build()
+///     .unwrap();
+/// assert_eq!(Some("TagA"), msg.tags());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MessageBuilder {
+    topic: String,
+    tag: Option<String>,
+    keys: Vec<String>,
+    flag: i32,
+    body: Vec<u8>,
+    wait_store_msg_ok: bool,
+    delay_time_level: Option<i32>,
+    deliver_time_ms: Option<i64>,
+    sharding_key: Option<String>,
+    properties: Vec<(String, String)>,
+}
+
+impl MessageBuilder {
+    pub fn topic(&mut self, topic: &str) -> &mut Self {
+        self.topic = topic.to_string();
+        self
+    }
+
+    /// Set the tag consumers filter messages by
+    pub fn tag(&mut self, tag: &str) -> &mut Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Set keys messages are indexed by on brokers
+    pub fn keys<I, S>(&mut self, keys: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set a user property, properties of the client and brokers are rejected on `build`
+    pub fn property(&mut self, name: &str, value: &str) -> &mut Self {
+        self.properties.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(&mut self, body: Vec<u8>) -> &mut Self {
+        self.body = body;
+        self
+    }
+
+    pub fn flag(&mut self, flag: i32) -> &mut Self {
+        self.flag = flag;
+        self
+    }
+
+    /// Wait for brokers to store the message before responding, false by default
+    pub fn wait_store_msg_ok(&mut self, wait: bool) -> &mut Self {
+        self.wait_store_msg_ok = wait;
+        self
+    }
+
+    /// Deliver the message after the delay of `level`, 1s 5s 10s 30s 1m ... 2h by default
+    /// brokers configuration
+    pub fn delay_time_level(&mut self, level: i32) -> &mut Self {
+        self.delay_time_level = Some(level);
+        self
+    }
+
+    /// Deliver the message at milliseconds since the epoch, needs 5.x brokers and can't be
+    /// set along with a delay level
+    pub fn deliver_time_ms(&mut self, timestamp_ms: i64) -> &mut Self {
+        self.deliver_time_ms = Some(timestamp_ms);
+        self
+    }
+
+    /// Key orderly messages are sent to the same queue by
+    pub fn sharding_key(&mut self, key: &str) -> &mut Self {
+        self.sharding_key = Some(key.to_string());
+        self
+    }
+
+    pub fn build(&self) -> Result<Message, Error> {
+        topic::validate_topic(&self.topic)
+            .map_err(|err| Error::InvalidMessage(format!("invalid topic, {}", err)))?;
+        if self.body.is_empty() {
+            return Err(Error::InvalidMessage("message body is empty".to_string()));
+        }
+        let mut msg = Message::new(
+            self.topic.clone(),
+            String::new(),
+            String::new(),
+            self.flag,
+            self.body.clone(),
+            self.wait_store_msg_ok,
+        );
+        for (name, value) in &self.properties {
+            msg.put_user_property(name, value)?;
+        }
+        if let Some(tag) = &self.tag {
+            msg.set_property(Property::TAGS.to_string(), tag.clone());
+        }
+        if let Some(key) = self
+            .keys
+            .iter()
+            .find(|key| key.is_empty() || key.contains(Property::KEY_SEPARATOR))
+        {
+            return Err(Error::InvalidMessage(format!(
+                "key {:?} is empty or contains the key separator",
+                key
+            )));
+        }
+        msg.set_keys(&self.keys);
+        if let Some(level) = self.delay_time_level {
+            if level <= 0 {
+                return Err(Error::InvalidMessage(format!(
+                    "invalid delay time level {}",
+                    level
+                )));
+            }
+            msg.set_delay_time_level(level);
+        }
+        if let Some(timestamp_ms) = self.deliver_time_ms {
+            msg.set_deliver_time_ms(timestamp_ms);
+        }
+        msg.check_delivery()?;
+        if let Some(key) = &self.sharding_key {
+            msg.set_property(Property::SHARDING_KEY.to_string(), key.clone());
+        }
+        // fail on oversized properties now rather than on send
+        msg.dump_properties()?;
+        Ok(msg)
+    }
+}
+
+/// CRC32 of message body as `UtilAll.crc32` of Java client, which is always positive
+pub(crate) fn body_crc(body: &[u8]) -> i32 {
+    (crc32fast::hash(body) & 0x7fff_ffff) as i32
+}
+
+#[derive(Clone)]
 pub struct MessageExt {
     pub(crate) message: Message,
-    queue_id: i32,
-    store_size: i32,
-    queue_offset: i64,
-    sys_flag: i32,
-    born_host: SocketAddrV4,
-    born_timestamp: i64,
-    store_host: SocketAddrV4,
-    store_timestamp: i64,
+    pub(crate) queue_id: i32,
+    pub(crate) store_size: i32,
+    pub(crate) queue_offset: i64,
+    pub(crate) sys_flag: SysFlag,
+    pub(crate) born_host: SocketAddr,
+    pub(crate) born_timestamp: i64,
+    pub(crate) store_host: SocketAddr,
+    pub(crate) store_timestamp: i64,
     pub(crate) msg_id: String,
     pub(crate) commit_log_offset: i64,
-    body_crc: i32,
-    reconsume_times: i32,
-    prepared_transaction_offset: i64,
+    pub(crate) body_crc: i32,
+    pub(crate) reconsume_times: i32,
+    pub(crate) prepared_transaction_offset: i64,
+    // body as received when it's compressed
+    pub(crate) raw_body: Option<Vec<u8>>,
 }
 
 impl MessageExt {
-    pub fn decode(input: &[u8]) -> Vec<Self> {
-        let input_len = input.len() as u64;
+    #[inline]
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    #[inline]
+    pub fn topic(&self) -> &str {
+        self.message.topic()
+    }
+
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        self.message.body()
+    }
+
+    #[inline]
+    pub fn tags(&self) -> Option<&str> {
+        self.message.tags()
+    }
+
+    #[inline]
+    pub fn keys(&self) -> Vec<&str> {
+        self.message.keys()
+    }
+
+    #[inline]
+    pub fn get_property(&self, name: &str) -> Option<&str> {
+        self.message.get_property(name).map(String::as_str)
+    }
+
+    #[inline]
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    #[inline]
+    pub fn queue_id(&self) -> i32 {
+        self.queue_id
+    }
+
+    #[inline]
+    pub fn queue_offset(&self) -> i64 {
+        self.queue_offset
+    }
+
+    #[inline]
+    pub fn reconsume_times(&self) -> i32 {
+        self.reconsume_times
+    }
+
+    #[inline]
+    pub fn sys_flag(&self) -> SysFlag {
+        self.sys_flag
+    }
+
+    #[inline]
+    pub fn body_crc(&self) -> i32 {
+        self.body_crc
+    }
+
+    /// Size of the message in the commit log
+    #[inline]
+    pub fn store_size(&self) -> i32 {
+        self.store_size
+    }
+
+    /// Address of the producer which sent the message
+    #[inline]
+    pub fn born_host(&self) -> SocketAddr {
+        self.born_host
+    }
+
+    /// Milliseconds since the epoch the message was sent at
+    #[inline]
+    pub fn born_timestamp(&self) -> i64 {
+        self.born_timestamp
+    }
+
+    /// Address of the broker which stored the message
+    #[inline]
+    pub fn store_host(&self) -> SocketAddr {
+        self.store_host
+    }
+
+    /// Milliseconds since the epoch the message was stored at
+    #[inline]
+    pub fn store_timestamp(&self) -> i64 {
+        self.store_timestamp
+    }
+
+    /// Physical offset of the message in the commit log
+    #[inline]
+    pub fn commit_log_offset(&self) -> i64 {
+        self.commit_log_offset
+    }
+
+    #[inline]
+    pub fn prepared_transaction_offset(&self) -> i64 {
+        self.prepared_transaction_offset
+    }
+
+    /// Check the body against the CRC32 broker computed when storing it
+    pub fn check_crc(&self) -> bool {
+        body_crc(self.raw_body()) == self.body_crc
+    }
+
+    /// Body as stored in broker, which is compressed if the `Compressed` sys flag is set,
+    /// while `message().body()` is always decompressed
+    pub fn raw_body(&self) -> &[u8] {
+        self.raw_body.as_deref().unwrap_or(&self.message.body)
+    }
+
+    /// ID of the message as first sent, retried messages are new messages on the retry
+    /// topic carrying it in `ORIGIN_MESSAGE_ID`
+    pub fn origin_message_id(&self) -> &str {
+        self.message
+            .get_property(Property::ORIGIN_MESSAGE_ID)
+            .filter(|id| !id.is_empty())
+            .map(String::as_str)
+            .unwrap_or(&self.msg_id)
+    }
+
+    /// Topic the message was first sent to, if it's a retried message
+    pub fn retry_topic(&self) -> Option<&str> {
+        self.message
+            .get_property(Property::RETRY_TOPIC)
+            .filter(|topic| !topic.is_empty())
+            .map(String::as_str)
+    }
+
+    /// Times the message was consumed before, as recorded by broker when it was sent back
+    pub fn reconsume_time(&self) -> Option<i32> {
+        self.message
+            .get_property(Property::RECONSUME_TIME)
+            .and_then(|times| times.parse().ok())
+    }
+
+    /// Times the message is retried before it's sent to the dead letter queue, as sent back
+    /// by the consumer
+    pub fn max_reconsume_times(&self) -> Option<i32> {
+        self.message
+            .get_property(Property::MAX_RECONSUME_TIMES)
+            .and_then(|times| times.parse().ok())
+    }
+
+    /// Decode the JSON body into `T`
+    pub fn decode_body<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.message.body)?)
+    }
+
+    /// Decode the body into `T` with `codec`
+    pub fn decode_body_with<T, C: Codec<T>>(&self, codec: &C) -> Result<T, Error> {
+        codec.decode(&self.message.body)
+    }
+
+    /// Decode messages stored back to back, like the body of pull response
+    pub fn decode(input: &[u8]) -> Result<Vec<Self>, Error> {
+        MessageExtIter::new(input).collect()
+    }
+
+    fn decode_one(store_size: i32, input: &[u8]) -> Result<Self, Error> {
         let mut rdr = Cursor::new(input);
-        let mut msgs = Vec::new();
-        while rdr.position() < input_len {
-            let store_size = rdr.read_i32::<BigEndian>().unwrap();
-            let magic_code = rdr.read_i32::<BigEndian>().unwrap();
-            if magic_code != -626843481 {
-                // TODO: check
+        let magic_code = rdr.read_i32::<BigEndian>()?;
+        if magic_code != MESSAGE_MAGIC_CODE && magic_code != MESSAGE_MAGIC_CODE_V2 {
+            return Err(Error::InvalidMessage(format!(
+                "invalid magic code: {}",
+                magic_code
+            )));
+        }
+        let body_crc = rdr.read_i32::<BigEndian>()?;
+        let queue_id = rdr.read_i32::<BigEndian>()?;
+        let flag = rdr.read_i32::<BigEndian>()?;
+        let queue_offset = rdr.read_i64::<BigEndian>()?;
+        let physic_offset = rdr.read_i64::<BigEndian>()?;
+        let sys_flag = SysFlag::from_bits_truncate(rdr.read_i32::<BigEndian>()?);
+        let born_timestamp = rdr.read_i64::<BigEndian>()?;
+        let born_host = read_host(&mut rdr, sys_flag, SysFlag::BORNHOST_V6)?;
+        let store_timestamp = rdr.read_i64::<BigEndian>()?;
+        let store_host = read_host(&mut rdr, sys_flag, SysFlag::STOREHOST_V6)?;
+
+        let reconsume_times = rdr.read_i32::<BigEndian>()?;
+        let prepared_transaction_offset = rdr.read_i64::<BigEndian>()?;
+
+        // Body
+        let body_len = rdr.read_i32::<BigEndian>()?;
+        let body = read_bytes(&mut rdr, body_len)?;
+        let (body, raw_body) = if sys_flag.is_compressed() {
+            match compression::decompress(&body, CompressionType::from_sys_flag(sys_flag)) {
+                Ok(decompressed) => (decompressed, Some(body)),
+                // listeners must not get compressed bodies
+                Err(err) => {
+                    return Err(Error::InvalidMessage(format!(
+                        "decompress message body failed: {}",
+                        err
+                    )))
+                }
             }
-            let body_crc = rdr.read_i32::<BigEndian>().unwrap();
-            let queue_id = rdr.read_i32::<BigEndian>().unwrap();
-            let flag = rdr.read_i32::<BigEndian>().unwrap();
-            let queue_offset = rdr.read_i64::<BigEndian>().unwrap();
-            let physic_offset = rdr.read_i64::<BigEndian>().unwrap();
-            let sys_flag = rdr.read_i32::<BigEndian>().unwrap();
-            let born_timestamp = rdr.read_i64::<BigEndian>().unwrap();
-            let mut born_host_buf = [0u8; 4];
-            rdr.read_exact(&mut born_host_buf).unwrap();
-            let born_host_port = rdr.read_i32::<BigEndian>().unwrap();
-            let born_host = SocketAddrV4::new(
-                Ipv4Addr::new(
-                    born_host_buf[0],
-                    born_host_buf[1],
-                    born_host_buf[2],
-                    born_host_buf[3],
-                ),
-                born_host_port as u16,
-            );
-            let store_timestamp = rdr.read_i64::<BigEndian>().unwrap();
-            let mut store_host_buf = [0u8; 4];
-            rdr.read_exact(&mut store_host_buf).unwrap();
-            let store_host_port = rdr.read_i32::<BigEndian>().unwrap();
-            let store_host = SocketAddrV4::new(
-                Ipv4Addr::new(
-                    store_host_buf[0],
-                    store_host_buf[1],
-                    store_host_buf[2],
-                    store_host_buf[3],
-                ),
-                store_host_port as u16,
-            );
+        } else {
+            (body, None)
+        };
 
-            let reconsume_times = rdr.read_i32::<BigEndian>().unwrap();
-            let prepared_transaction_offset = rdr.read_i64::<BigEndian>().unwrap();
-
-            // Body
-            let body_len = rdr.read_i32::<BigEndian>().unwrap();
-            let body = {
-                if body_len > 0 {
-                    let mut body = vec![0; body_len as usize];
-                    rdr.read_exact(&mut body).unwrap();
-                    // decompress
-                    if false {
-                        let mut decoder = ZlibDecoder::new(&body[..]);
-                        let mut body_buf = Vec::new();
-                        decoder.read_to_end(&mut body_buf).unwrap();
-                        body_buf
-                    } else {
-                        body
-                    }
-                } else {
-                    Vec::new()
+        // topic length takes 2 bytes since V2
+        let topic_len = if magic_code == MESSAGE_MAGIC_CODE_V2 {
+            rdr.read_i16::<BigEndian>()? as i32
+        } else {
+            rdr.read_u8()? as i32
+        };
+        let topic = String::from_utf8(read_bytes(&mut rdr, topic_len)?)?;
+
+        let properties_len = rdr.read_i16::<BigEndian>()?;
+        let properties = if properties_len > 0 {
+            let properties_str = String::from_utf8(read_bytes(&mut rdr, properties_len as i32)?)?;
+            Message::parse_properties(&properties_str)
+        } else {
+            HashMap::new()
+        };
+
+        let message = Message {
+            topic,
+            flag,
+            sys_flag: SysFlag::empty(),
+            properties,
+            body: body.into(),
+            transaction_id: String::new(),
+            batch: false,
+            batch_unique_keys: Vec::new(),
+            queue: None,
+        };
+        let msg_id = message
+            .unique_key()
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| {
+                MessageId {
+                    address: store_host,
+                    offset: physic_offset,
                 }
-            };
+                .to_string()
+            });
+        Ok(MessageExt {
+            message,
+            queue_id,
+            store_size,
+            queue_offset,
+            sys_flag,
+            born_host,
+            born_timestamp,
+            store_host,
+            store_timestamp,
+            msg_id,
+            commit_log_offset: physic_offset,
+            body_crc,
+            reconsume_times,
+            prepared_transaction_offset,
+            raw_body,
+        })
+    }
+}
+
+/// Messages stored back to back, like the body of pull responses, decoded one at a time as
+/// they are iterated. Once a store size is invalid the error is returned and iteration ends,
+/// as the messages after it can't be told apart
+#[derive(Debug, Clone)]
+pub struct MessageExtIter<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> MessageExtIter<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
 
-            let topic_len = rdr.read_u8().unwrap();
-            let mut topic_buf = vec![0; topic_len as usize];
-            rdr.read_exact(&mut topic_buf).unwrap();
-            let topic = String::from_utf8(topic_buf).unwrap();
-
-            let properties_len = rdr.read_i16::<BigEndian>().unwrap();
-            let properties = {
-                if properties_len > 0 {
-                    let mut properties_buf = vec![0; properties_len as usize];
-                    rdr.read_exact(&mut properties_buf).unwrap();
-                    let properties_str = String::from_utf8(properties_buf).unwrap();
-                    Message::parse_properties(&properties_str)
-                } else {
-                    HashMap::new()
+    /// Store size and the rest of the next message
+    fn next_frame(&mut self) -> Option<Result<(i32, &'a [u8]), Error>> {
+        if self.input.is_empty() {
+            return None;
+        }
+        let mut rdr = Cursor::new(self.input);
+        let frame = rdr
+            .read_i32::<BigEndian>()
+            .map_err(Error::from)
+            .and_then(|store_size| {
+                if store_size < 4 {
+                    return Err(Error::InvalidMessage(format!(
+                        "invalid store size: {}",
+                        store_size
+                    )));
                 }
-            };
+                // every message is decoded within its own store size so a corrupted one can't
+                // read into the next
+                skip_bytes(&mut rdr, store_size - 4)?;
+                Ok(store_size)
+            });
+        match frame {
+            Ok(store_size) => {
+                let (frame, rest) = self.input.split_at(store_size as usize);
+                self.input = rest;
+                Some(Ok((store_size, &frame[4..])))
+            }
+            Err(err) => {
+                self.input = &[];
+                Some(Err(err))
+            }
+        }
+    }
 
-            let message = Message {
-                topic,
-                flag,
-                sys_flag: 0,
-                properties,
-                body,
-                transaction_id: String::new(),
-                batch: false,
-                queue: None,
-            };
-            let msg_id = message
-                .unique_key()
-                .map(|key| key.to_string())
-                .unwrap_or_else(|| {
-                    Self::get_message_offset_id(store_host_buf, store_host_port, physic_offset)
-                });
-            let msg_ex = MessageExt {
-                message,
-                queue_id,
-                store_size,
-                queue_offset,
-                sys_flag,
-                born_host,
-                born_timestamp,
-                store_host,
-                store_timestamp,
-                msg_id,
-                commit_log_offset: physic_offset,
-                body_crc,
-                reconsume_times,
-                prepared_transaction_offset,
+    /// Decode only messages whose tags `filter` accepts, the tags of others are read
+    /// without decoding their bodies and properties
+    pub fn filter_tags<F>(
+        mut self,
+        mut filter: F,
+    ) -> impl Iterator<Item = Result<MessageExt, Error>> + 'a
+    where
+        F: FnMut(Option<&str>) -> bool + 'a,
+    {
+        std::iter::from_fn(move || loop {
+            let (store_size, frame) = match self.next_frame()? {
+                Ok(frame) => frame,
+                Err(err) => return Some(Err(err)),
             };
-            msgs.push(msg_ex);
+            match read_tags(frame) {
+                Ok(tags) if filter(tags.as_deref()) => {
+                    return Some(MessageExt::decode_one(store_size, frame))
+                }
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        })
+    }
+}
+
+impl<'a> Iterator for MessageExtIter<'a> {
+    type Item = Result<MessageExt, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.next_frame()?
+                .and_then(|(store_size, frame)| MessageExt::decode_one(store_size, frame)),
+        )
+    }
+}
+
+/// `TAGS` property of a message laid out like [`MessageExt::decode_one`] reads it, skipping
+/// the fields before
+fn read_tags(input: &[u8]) -> Result<Option<String>, Error> {
+    let mut rdr = Cursor::new(input);
+    let magic_code = rdr.read_i32::<BigEndian>()?;
+    if magic_code != MESSAGE_MAGIC_CODE && magic_code != MESSAGE_MAGIC_CODE_V2 {
+        return Err(Error::InvalidMessage(format!(
+            "invalid magic code: {}",
+            magic_code
+        )));
+    }
+    // body crc, queue id, flag, queue offset and physical offset
+    skip_bytes(&mut rdr, 4 + 4 + 4 + 8 + 8)?;
+    let sys_flag = SysFlag::from_bits_truncate(rdr.read_i32::<BigEndian>()?);
+    let host_len = |v6_flag| {
+        if sys_flag.contains(v6_flag) {
+            16 + 4
+        } else {
+            4 + 4
+        }
+    };
+    // born timestamp and host, store timestamp and host, reconsume times and prepared
+    // transaction offset
+    skip_bytes(
+        &mut rdr,
+        8 + host_len(SysFlag::BORNHOST_V6) + 8 + host_len(SysFlag::STOREHOST_V6) + 4 + 8,
+    )?;
+    let body_len = rdr.read_i32::<BigEndian>()?;
+    skip_bytes(&mut rdr, body_len)?;
+    let topic_len = if magic_code == MESSAGE_MAGIC_CODE_V2 {
+        rdr.read_i16::<BigEndian>()? as i32
+    } else {
+        rdr.read_u8()? as i32
+    };
+    skip_bytes(&mut rdr, topic_len)?;
+    let properties_len = rdr.read_i16::<BigEndian>()?;
+    if properties_len <= 0 {
+        return Ok(None);
+    }
+    let properties = String::from_utf8(read_bytes(&mut rdr, properties_len as i32)?)?;
+    Ok(properties
+        .split(PROPERTY_SEP)
+        .filter_map(|item| item.split_once(NAME_VALUE_SEP))
+        .find(|(name, _)| *name == Property::TAGS)
+        .map(|(_, value)| value.to_string()))
+}
+
+/// Advance `rdr` by `len` bytes, checked like [`read_bytes`] without copying them
+fn skip_bytes(rdr: &mut Cursor<&[u8]>, len: i32) -> Result<(), Error> {
+    if len < 0 {
+        return Err(FrameError::NegativeLength(len).into());
+    }
+    let len = len as usize;
+    let remaining = rdr.get_ref().len().saturating_sub(rdr.position() as usize);
+    if len > remaining {
+        return Err(FrameError::LengthMismatch {
+            declared: len,
+            remaining,
         }
-        msgs
+        .into());
+    }
+    rdr.set_position(rdr.position() + len as u64);
+    Ok(())
+}
+
+impl fmt::Debug for MessageExt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // bodies are left out by their sizes only
+        f.debug_struct("MessageExt")
+            .field("topic", &self.message.topic)
+            .field("msg_id", &self.msg_id)
+            .field("tags", &self.message.tags())
+            .field("keys", &self.message.keys())
+            .field("queue_id", &self.queue_id)
+            .field("queue_offset", &self.queue_offset)
+            .field("commit_log_offset", &self.commit_log_offset)
+            .field("sys_flag", &self.sys_flag)
+            .field("born_host", &self.born_host)
+            .field("born_timestamp", &self.born_timestamp)
+            .field("store_host", &self.store_host)
+            .field("store_timestamp", &self.store_timestamp)
+            .field("reconsume_times", &self.reconsume_times)
+            .field("properties", &self.message.properties)
+            .field("body_size", &self.message.body.len())
+            .field("store_size", &self.store_size)
+            .finish()
+    }
+}
+
+impl fmt::Display for MessageExt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MessageExt [topic={}, msg_id={}, queue_id={}, queue_offset={}, commit_log_offset={}, \
+             sys_flag={:#x}, born_host={}, born_timestamp={}, store_host={}, store_timestamp={}, \
+             reconsume_times={}, body_size={}]",
+            self.message.topic,
+            self.msg_id,
+            self.queue_id,
+            self.queue_offset,
+            self.commit_log_offset,
+            self.sys_flag.bits(),
+            self.born_host,
+            self.born_timestamp,
+            self.store_host,
+            self.store_timestamp,
+            self.reconsume_times,
+            self.message.body.len()
+        )
+    }
+}
+
+/// Read a host stored as its IP followed by a 4 bytes port, the IP takes 16 bytes if
+/// `v6_flag` is set in `sys_flag`
+fn read_host(
+    rdr: &mut Cursor<&[u8]>,
+    sys_flag: SysFlag,
+    v6_flag: SysFlag,
+) -> Result<SocketAddr, Error> {
+    let ip = if sys_flag.contains(v6_flag) {
+        let mut buf = [0u8; 16];
+        rdr.read_exact(&mut buf)?;
+        IpAddr::V6(Ipv6Addr::from(buf))
+    } else {
+        let mut buf = [0u8; 4];
+        rdr.read_exact(&mut buf)?;
+        IpAddr::V4(Ipv4Addr::from(buf))
+    };
+    let port = rdr.read_i32::<BigEndian>()?;
+    Ok(SocketAddr::new(ip, port as u16))
+}
+
+/// Write a host the way [`read_host`] reads it
+fn write_host(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(v4) => buf.extend_from_slice(&v4.octets()),
+        IpAddr::V6(v6) => buf.extend_from_slice(&v6.octets()),
+    }
+    buf.extend_from_slice(&(addr.port() as i32).to_be_bytes());
+}
+
+/// Message ID assigned by broker, the address of the broker and the commit log offset
+/// of the message, hex encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageId {
+    pub address: SocketAddr,
+    pub offset: i64,
+}
+
+impl MessageId {
+    /// Decode the offset message ID of a message, like `SendResult::offset_msg_id`
+    pub fn decode(msg_id: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidMessage(format!("invalid message id: {}", msg_id));
+        let bytes = hex::decode(msg_id).map_err(|_| invalid())?;
+        let sys_flag = match bytes.len() {
+            16 => SysFlag::empty(),
+            28 => SysFlag::STOREHOST_V6,
+            _ => return Err(invalid()),
+        };
+        let mut rdr = Cursor::new(&bytes[..]);
+        let address = read_host(&mut rdr, sys_flag, SysFlag::STOREHOST_V6)?;
+        let offset = rdr.read_i64::<BigEndian>()?;
+        Ok(Self { address, offset })
     }
+}
 
-    fn get_message_offset_id(store_host: [u8; 4], port: i32, commit_offset: i64) -> String {
-        let mut wtr = Vec::new();
-        wtr.write_all(&store_host).unwrap();
-        wtr.write_i32::<BigEndian>(port).unwrap();
-        wtr.write_i64::<BigEndian>(commit_offset).unwrap();
-        hex::encode(wtr)
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = Vec::with_capacity(28);
+        write_host(&mut buf, self.address);
+        buf.extend_from_slice(&self.offset.to_be_bytes());
+        f.write_str(&hex::encode_upper(buf))
     }
 }
 
+/// Generates unique keys of messages: the client IP, pid and class loader hash, followed by
+/// milliseconds since the start of the month and a counter
 struct UniqueIdGenerator {
     counter: i16,
     prefix: String,
+    // milliseconds since the epoch of the start of this month and of the next one
     start_timestamp: i64,
     next_timestamp: i64,
 }
 
 impl UniqueIdGenerator {
     fn generate(&mut self) -> String {
-        if SystemTime::now()
+        let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
-            .as_secs() as i64
-            > self.next_timestamp
-        {
-            // update timestamp
-            let now = OffsetDateTime::now_local();
-            let year = now.year();
-            let month = now.month();
-            self.start_timestamp = PrimitiveDateTime::new(
-                Date::try_from_ymd(year, month, 1).unwrap(),
-                Time::try_from_hms(0, 0, 0).unwrap(),
-            )
-            .assume_offset(now.offset())
-            .unix_timestamp();
-            self.next_timestamp = (PrimitiveDateTime::new(
+            .as_millis() as i64;
+        if now >= self.next_timestamp {
+            self.update_month();
+        }
+        self.counter = self.counter.wrapping_add(1);
+        let mut buf = Vec::with_capacity(6);
+        buf.write_i32::<BigEndian>((now - self.start_timestamp) as i32)
+            .unwrap();
+        buf.write_i16::<BigEndian>(self.counter).unwrap();
+        self.prefix.clone() + &hex::encode_upper(buf)
+    }
+
+    fn update_month(&mut self) {
+        let now = OffsetDateTime::now_local();
+        let (year, month) = (now.year(), now.month());
+        let (next_year, next_month) = if month == 12 {
+            (year + 1, 1)
+        } else {
+            (year, month + 1)
+        };
+        let start_of = |year, month| {
+            PrimitiveDateTime::new(
                 Date::try_from_ymd(year, month, 1).unwrap(),
                 Time::try_from_hms(0, 0, 0).unwrap(),
             )
             .assume_offset(now.offset())
-                + time::Duration::days(30))
-            .unix_timestamp();
-        }
-        self.counter += self.counter.wrapping_add(1);
-        let mut buf = Vec::new();
-        buf.write_i32::<BigEndian>(
-            ((OffsetDateTime::now_local().unix_timestamp() - self.start_timestamp) * 1000) as i32,
-        )
-        .unwrap();
-        buf.write_i16::<BigEndian>(self.counter).unwrap();
-        self.prefix.clone() + &hex::encode(buf)
+            .unix_timestamp()
+                * 1000
+        };
+        self.start_timestamp = start_of(year, month);
+        self.next_timestamp = start_of(next_year, next_month);
     }
 }
 
 #[cfg(test)]
-mod test {
-    use super::MessageExt;
-    use std::net::{Ipv4Addr, SocketAddrV4};
+pub(crate) mod test {
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    use super::{
+        body_crc, Message, MessageExt, MessageExtIter, MessageId, Property, MESSAGE_MAGIC_CODE,
+    };
+    use crate::error::Error;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+
+    const CRC_FIXTURE: [u8; 97] = [
+        0, 0, 0, 97, 218, 163, 32, 167, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
+        104, 101, 108, 108, 111, 1, 97, 0, 0,
+    ];
+
+    /// Message of `topic` in the store format brokers respond with, `properties` encoded
+    pub(crate) fn encode_message_ext(
+        topic: &str,
+        properties: &str,
+        store_timestamp: i64,
+        body: &[u8],
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let store_size = 91 + body.len() + topic.len() + properties.len();
+        buf.write_i32::<BigEndian>(store_size as i32).unwrap();
+        buf.write_i32::<BigEndian>(MESSAGE_MAGIC_CODE).unwrap();
+        buf.write_i32::<BigEndian>(body_crc(body)).unwrap();
+        // queue ID, flag, queue and commit log offsets, sys flag, born timestamp and host
+        buf.extend_from_slice(&[0; 44]);
+        buf.write_i64::<BigEndian>(store_timestamp).unwrap();
+        // store host, reconsume times and prepared transaction offset
+        buf.extend_from_slice(&[127, 0, 0, 1, 0, 0, 42, 159]);
+        buf.extend_from_slice(&[0; 12]);
+        buf.write_i32::<BigEndian>(body.len() as i32).unwrap();
+        buf.extend_from_slice(body);
+        buf.push(topic.len() as u8);
+        buf.extend_from_slice(topic.as_bytes());
+        buf.write_i16::<BigEndian>(properties.len() as i16).unwrap();
+        buf.extend_from_slice(properties.as_bytes());
+        buf
+    }
 
     #[test]
     fn test_decode_message_ext() {
@@ -438,19 +1281,20 @@ mod test {
             99, 0, 21, 97, 1, 49, 50, 51, 2, 98, 1, 104, 101, 108, 108, 111, 2, 99, 1, 51, 46, 49,
             52, 2,
         ];
-        let msgs = MessageExt::decode(&bytes[..]);
+        let msgs = MessageExt::decode(&bytes[..]).unwrap();
         assert_eq!(1, msgs.len());
         let msg = &msgs[0];
         assert_eq!("abc", msg.message.topic);
         assert_eq!(b"hello!q!", &msg.message.body[..]);
         assert_eq!(
-            SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 0),
-            msg.born_host
+            SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 0)),
+            msg.born_host()
         );
         assert_eq!(
-            SocketAddrV4::new(Ipv4Addr::new(192, 168, 2, 248), 0),
-            msg.store_host
+            SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(192, 168, 2, 248), 0)),
+            msg.store_host()
         );
+        assert_eq!(1547976740495, msg.born_timestamp());
         assert_eq!(123456, msg.commit_log_offset);
         assert_eq!(0, msg.prepared_transaction_offset);
         assert_eq!(0, msg.queue_id);
@@ -459,5 +1303,450 @@ mod test {
         assert_eq!("123", &msg.message.properties["a"]);
         assert_eq!("hello", &msg.message.properties["b"]);
         assert_eq!("3.14", &msg.message.properties["c"]);
+        assert_eq!("abc", msg.topic());
+        assert_eq!(Some("hello"), msg.get_property("b"));
+        assert_eq!(None, msg.tags());
+        assert!(msg.keys().is_empty());
+        let debug = format!("{:?}", msg);
+        assert!(debug.contains("body_size: 8"));
+        assert!(!debug.contains("104, 101"));
+        assert!(msg.decode_body::<serde_json::Value>().is_err());
+    }
+
+    #[test]
+    fn test_message_builder() {
+        let msg = Message::builder()
+            .topic("TopicTest")
+            .tag("TagA")
+            .keys(["k1", "k2"])
+            .property("region", "eu")
+            .body(b"hello".to_vec())
+            .delay_time_level(3)
+            .build()
+            .unwrap();
+        assert_eq!("TopicTest", msg.topic());
+        assert_eq!(Some("TagA"), msg.tags());
+        assert_eq!("k1 k2", msg.get_property(Property::KEYS).unwrap());
+        assert_eq!(vec!["k1", "k2"], msg.keys());
+        assert_eq!("eu", msg.get_property("region").unwrap());
+        assert_eq!("3", msg.get_property(Property::DELAY_TIME_LEVEL).unwrap());
+        assert_eq!(b"hello", msg.body());
+        assert!(msg.get_property(Property::WAIT_STORE_MSG_OK).is_none());
+
+        let invalid = |builder: &mut super::MessageBuilder| {
+            matches!(builder.build(), Err(Error::InvalidMessage(_)))
+        };
+        assert!(invalid(Message::builder().body(b"hello".to_vec())));
+        assert!(invalid(
+            Message::builder()
+                .topic("Topic Test")
+                .body(b"hello".to_vec())
+        ));
+        assert!(invalid(Message::builder().topic("TopicTest")));
+        assert!(invalid(
+            Message::builder()
+                .topic("TopicTest")
+                .property(Property::TAGS, "TagA")
+                .body(b"hello".to_vec())
+        ));
+        assert!(invalid(
+            Message::builder()
+                .topic("TopicTest")
+                .property("k", "v\u{002}")
+                .body(b"hello".to_vec())
+        ));
+        assert!(invalid(
+            Message::builder()
+                .topic("TopicTest")
+                .keys(["k1", "k 2"])
+                .body(b"hello".to_vec())
+        ));
+        assert!(invalid(
+            Message::builder()
+                .topic("TopicTest")
+                .delay_time_level(3)
+                .deliver_time_ms(1_700_000_000_000)
+                .body(b"hello".to_vec())
+        ));
+    }
+
+    #[test]
+    fn test_decode_message_ext_v6_hosts() {
+        use byteorder::{BigEndian, WriteBytesExt};
+
+        let born_ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let store_ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        let mut buf = Vec::new();
+        buf.write_i32::<BigEndian>(super::MESSAGE_MAGIC_CODE_V2)
+            .unwrap();
+        buf.write_i32::<BigEndian>(body_crc(b"hi")).unwrap();
+        buf.write_i32::<BigEndian>(3).unwrap(); // queue id
+        buf.write_i32::<BigEndian>(0).unwrap(); // flag
+        buf.write_i64::<BigEndian>(42).unwrap(); // queue offset
+        buf.write_i64::<BigEndian>(4096).unwrap(); // commit log offset
+        buf.write_i32::<BigEndian>(0x30).unwrap(); // born and store host v6
+        buf.write_i64::<BigEndian>(1000).unwrap();
+        buf.extend_from_slice(&born_ip.octets());
+        buf.write_i32::<BigEndian>(50000).unwrap();
+        buf.write_i64::<BigEndian>(2000).unwrap();
+        buf.extend_from_slice(&store_ip.octets());
+        buf.write_i32::<BigEndian>(10911).unwrap();
+        buf.write_i32::<BigEndian>(1).unwrap(); // reconsume times
+        buf.write_i64::<BigEndian>(0).unwrap();
+        buf.write_i32::<BigEndian>(2).unwrap();
+        buf.extend_from_slice(b"hi");
+        buf.write_i16::<BigEndian>(4).unwrap();
+        buf.extend_from_slice(b"test");
+        buf.write_i16::<BigEndian>(0).unwrap();
+        let mut bytes = Vec::new();
+        bytes.write_i32::<BigEndian>(buf.len() as i32 + 4).unwrap();
+        bytes.extend_from_slice(&buf);
+
+        let msg = MessageExt::decode(&bytes).unwrap().pop().unwrap();
+        assert_eq!("test", msg.message().topic());
+        assert_eq!(b"hi", msg.message().body());
+        assert!(msg.check_crc());
+        assert_eq!(bytes.len() as i32, msg.store_size());
+        assert_eq!(3, msg.queue_id());
+        assert_eq!(42, msg.queue_offset());
+        assert_eq!(4096, msg.commit_log_offset());
+        assert_eq!(1, msg.reconsume_times());
+        assert_eq!(1000, msg.born_timestamp());
+        assert_eq!(2000, msg.store_timestamp());
+        assert_eq!(SocketAddr::new(born_ip.into(), 50000), msg.born_host());
+        assert_eq!(SocketAddr::new(store_ip.into(), 10911), msg.store_host());
+        let display = msg.to_string();
+        assert!(display.contains("born_host=[fe80::1]:50000"));
+        assert!(display.contains("store_host=[2001:db8::2]:10911"));
+        assert!(display.contains("sys_flag=0x30"));
+        // store host of 16 bytes, port and commit log offset
+        assert_eq!(56, msg.msg_id().len());
+    }
+
+    #[test]
+    fn test_message_id() {
+        let id = MessageId::decode("C0A802F800002A9F000000000001E240").unwrap();
+        assert_eq!(
+            "192.168.2.248:10911".parse::<SocketAddr>().unwrap(),
+            id.address
+        );
+        assert_eq!(123456, id.offset);
+        assert_eq!("C0A802F800002A9F000000000001E240", id.to_string());
+
+        let id = MessageId {
+            address: "[2001:db8::2]:10911".parse().unwrap(),
+            offset: 4096,
+        };
+        assert_eq!(id, MessageId::decode(&id.to_string()).unwrap());
+        assert!(MessageId::decode("C0A802F8").is_err());
+        assert!(MessageId::decode("not a message id").is_err());
+    }
+
+    #[test]
+    fn test_unique_keys() {
+        let mut msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        msg.set_default_unique_key();
+        let key = msg.unique_key().unwrap().to_string();
+        // ip, pid, class loader hash, timestamp and counter
+        assert!(key.len() == 32 || key.len() == 56);
+        assert_eq!(key, key.to_uppercase());
+        assert_eq!(vec![key.as_str()], msg.unique_keys());
+
+        let mut keys = std::collections::HashSet::new();
+        for _ in 0..100 {
+            let mut msg = msg.clone();
+            msg.properties.clear();
+            msg.set_default_unique_key();
+            assert!(keys.insert(msg.unique_key().unwrap().to_string()));
+        }
+
+        // keys already set are kept
+        let mut unkeyed = msg.clone();
+        unkeyed.properties.clear();
+        let batch = Message::encode_batch(&[msg, unkeyed]).unwrap();
+        let keys = batch.unique_keys();
+        assert_eq!(2, keys.len());
+        assert_eq!(key, keys[0]);
+        assert_ne!(keys[0], keys[1]);
+    }
+
+    #[test]
+    fn test_properties_encoding() {
+        let mut msg = Message::new(
+            "test".to_string(),
+            "TagA".to_string(),
+            "k1 k2".to_string(),
+            0,
+            b"hello".to_vec(),
+            true,
+        );
+        msg.set_property("empty".to_string(), String::new());
+        let encoded = msg.dump_properties().unwrap();
+        assert!(encoded.ends_with('\u{2}'));
+        assert_eq!(msg.properties, Message::parse_properties(&encoded));
+        // garbage between separators is skipped
+        let props = Message::parse_properties("a\u{1}1\u{2}b\u{2}\u{1}c\u{2}d\u{1}x\u{1}y\u{2}");
+        assert_eq!(2, props.len());
+        assert_eq!("1", props["a"]);
+        assert_eq!("x\u{1}y", props["d"]);
+
+        msg.set_property("k".to_string(), "v\u{1}".to_string());
+        assert!(matches!(
+            msg.dump_properties(),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert!(Message::encode_batch(&[msg.clone(), msg.clone()]).is_err());
+        msg.set_property("k".to_string(), "v".repeat(i16::MAX as usize));
+        assert!(matches!(
+            msg.dump_properties(),
+            Err(Error::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_batch_store_size() {
+        let msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        let batch = Message::encode_batch(&[msg.clone(), msg]).unwrap();
+        let first =
+            u32::from_be_bytes([batch.body[0], batch.body[1], batch.body[2], batch.body[3]]);
+        assert_eq!(batch.body.len(), 2 * first as usize);
+    }
+
+    #[test]
+    fn test_retry_properties() {
+        let mut msg = MessageExt::decode(&CRC_FIXTURE[..]).unwrap().pop().unwrap();
+        msg.msg_id = "C0A802F800002A9F000000000001E240".to_string();
+        assert_eq!("C0A802F800002A9F000000000001E240", msg.origin_message_id());
+        assert_eq!(None, msg.retry_topic());
+        assert_eq!(None, msg.max_reconsume_times());
+
+        let props = [
+            (
+                Property::ORIGIN_MESSAGE_ID,
+                "7F00000100002A9F0000000000000400",
+            ),
+            (Property::RETRY_TOPIC, "TopicTest"),
+            (Property::RECONSUME_TIME, "2"),
+            (Property::MAX_RECONSUME_TIMES, "16"),
+        ];
+        for (name, value) in &props {
+            msg.message
+                .set_property(name.to_string(), value.to_string());
+        }
+        assert_eq!("7F00000100002A9F0000000000000400", msg.origin_message_id());
+        assert_eq!(Some("TopicTest"), msg.retry_topic());
+        assert_eq!(Some(2), msg.reconsume_time());
+        assert_eq!(Some(16), msg.max_reconsume_times());
+    }
+
+    #[test]
+    fn test_put_user_property() {
+        let mut msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        msg.put_user_property("region", "eu").unwrap();
+        assert_eq!("eu", msg.get_property("region").unwrap());
+        for (name, value) in &[
+            (Property::TAGS, "TagA"),
+            (Property::UNIQ_CLIENT_MSG_ID_KEY, "id"),
+            (" ", "v"),
+            ("k\u{1}", "v"),
+            ("k", "v\u{2}"),
+        ] {
+            assert!(matches!(
+                msg.put_user_property(name, value),
+                Err(Error::InvalidMessage(_))
+            ));
+        }
+        assert_eq!(None, msg.tags());
+
+        let oversized = Message::builder()
+            .topic("TopicTest")
+            .property("k", &"v".repeat(i16::MAX as usize))
+            .body(b"hello".to_vec())
+            .build();
+        assert!(matches!(oversized, Err(Error::InvalidMessage(_))));
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            "k1  k2 ".to_string(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        assert_eq!(vec!["k1", "k2"], msg.keys());
+        msg.set_keys(vec![
+            "order-1".to_string(),
+            String::new(),
+            "user-2".to_string(),
+        ]);
+        assert_eq!("order-1 user-2", msg.get_property(Property::KEYS).unwrap());
+        assert_eq!(vec!["order-1", "user-2"], msg.keys());
+        msg.set_keys(Vec::<&str>::new());
+        assert!(msg.get_property(Property::KEYS).is_none());
+        assert!(msg.keys().is_empty());
+    }
+
+    #[test]
+    fn test_delivery_schedule() {
+        let mut msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        assert_eq!(None, msg.delay_time_level());
+        assert!(msg.check_delivery().is_ok());
+        msg.set_deliver_time_ms(1_700_000_000_000);
+        assert_eq!(Some(1_700_000_000_000), msg.deliver_time_ms());
+        assert!(msg.check_delivery().is_ok());
+        assert!(Message::encode_batch(&[msg.clone(), msg.clone()]).is_err());
+        msg.set_start_deliver_time(1_700_000_000_000);
+        assert!(matches!(
+            msg.check_delivery(),
+            Err(Error::InvalidMessage(_))
+        ));
+
+        let mut msg = Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        );
+        msg.set_delay_time_level(0);
+        assert_eq!(None, msg.delay_time_level());
+        assert!(msg.check_delivery().is_ok());
+        // level 0 doesn't conflict with delivery times
+        msg.set_deliver_time_ms(1_700_000_000_000);
+        assert!(msg.check_delivery().is_ok());
+        msg.set_delay_time_level(2);
+        assert_eq!(Some(2), msg.delay_time_level());
+        assert!(msg.check_delivery().is_err());
+        msg.remove_property(Property::TIMER_DELIVER_MS);
+        assert!(msg.check_delivery().is_ok());
+        msg.set_delay_time_level(-1);
+        assert!(msg.check_delivery().is_err());
+        msg.set_property(Property::DELAY_TIME_LEVEL.to_string(), "one".to_string());
+        assert!(msg.check_delivery().is_err());
+    }
+
+    #[test]
+    fn test_check_body_crc() {
+        assert_eq!(907060870, body_crc(b"hello"));
+        let mut msg = MessageExt::decode(&CRC_FIXTURE[..]).unwrap().pop().unwrap();
+        assert_eq!(b"hello", &msg.message.body[..]);
+        assert!(!msg.check_crc());
+        msg.body_crc = body_crc(b"hello");
+        assert!(msg.check_crc());
+    }
+
+    #[test]
+    fn test_message_ext_iter() {
+        let mut bytes = Vec::new();
+        for (tags, body) in &[("TagA", "a"), ("TagB", "b"), ("", "c"), ("TagA", "d")] {
+            let properties = if tags.is_empty() {
+                String::new()
+            } else {
+                format!("TAGS\u{1}{}\u{2}", tags)
+            };
+            bytes.extend(encode_message_ext("test", &properties, 0, body.as_bytes()));
+        }
+        let bodies: Vec<Vec<u8>> = MessageExtIter::new(&bytes)
+            .map(|msg| msg.unwrap().message.body.to_vec())
+            .collect();
+        assert_eq!(
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()],
+            bodies
+        );
+
+        let bodies: Vec<Vec<u8>> = MessageExtIter::new(&bytes)
+            .filter_tags(|tags| tags == Some("TagA"))
+            .map(|msg| msg.unwrap().message.body.to_vec())
+            .collect();
+        assert_eq!(vec![b"a".to_vec(), b"d".to_vec()], bodies);
+        let untagged = MessageExtIter::new(&bytes)
+            .filter_tags(|tags| tags.is_none())
+            .count();
+        assert_eq!(1, untagged);
+
+        // messages before an invalid store size are decoded, iteration ends with its error
+        let len = bytes.len();
+        bytes.extend_from_slice(&[0, 0, 0, 2]);
+        let msgs: Vec<_> = MessageExtIter::new(&bytes).collect();
+        assert_eq!(5, msgs.len());
+        assert!(msgs[4].is_err());
+        assert!(MessageExtIter::new(&bytes[..len - 1])
+            .last()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_message_ext_malformed() {
+        // bad magic code
+        let mut bytes = CRC_FIXTURE;
+        bytes[4] = 0;
+        assert!(matches!(
+            MessageExt::decode(&bytes[..]),
+            Err(Error::InvalidMessage(_))
+        ));
+        // store size larger than input
+        let mut bytes = CRC_FIXTURE;
+        bytes[3] = 98;
+        assert!(MessageExt::decode(&bytes[..]).is_err());
+        // body length larger than store size
+        let mut bytes = CRC_FIXTURE;
+        bytes[84] = 0x7f;
+        assert!(MessageExt::decode(&bytes[..]).is_err());
+        // truncated
+        assert!(MessageExt::decode(&CRC_FIXTURE[..50]).is_err());
+        // compressed sys flag set on a body that isn't compressed
+        let mut bytes = encode_message_ext("test", "", 0, b"hello");
+        bytes[39] |= 0x1;
+        assert!(matches!(
+            MessageExt::decode(&bytes[..]),
+            Err(Error::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_message_ext_corrupted_never_panics() {
+        let mut rng = StdRng::seed_from_u64(334);
+        for _ in 0..10000 {
+            let mut bytes = CRC_FIXTURE.to_vec();
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..bytes.len());
+                bytes[i] = rng.gen();
+            }
+            let len = rng.gen_range(0..=bytes.len());
+            let _ = MessageExt::decode(&bytes[..len]);
+        }
     }
 }
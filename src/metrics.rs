@@ -0,0 +1,173 @@
+//! Counters and histograms of sends, pulls, listener calls, rebalances and connections of
+//! all clients of the process, recorded through the `metrics` facade. They are exported by
+//! the recorder the application installs, e.g. the Prometheus exporter of
+//! `metrics-exporter-prometheus`, and dropped without one
+//!
+//! ```no_run
+//! // after installing the recorder of the application
+//! rocketmq::metrics::describe();
+//! ```
+
+use std::time::Duration;
+
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit,
+};
+
+const SEND_TOTAL: &str = "rocketmq_send_total";
+const SEND_DURATION: &str = "rocketmq_send_duration_seconds";
+const PULL_TOTAL: &str = "rocketmq_pull_total";
+const PULL_DURATION: &str = "rocketmq_pull_duration_seconds";
+const CONSUME_TOTAL: &str = "rocketmq_consume_total";
+const CONSUME_DURATION: &str = "rocketmq_consume_duration_seconds";
+const REBALANCE_TOTAL: &str = "rocketmq_rebalance_total";
+const CONNECTIONS: &str = "rocketmq_connections";
+const CONNECTION_ERRORS_TOTAL: &str = "rocketmq_connection_errors_total";
+
+/// Describe the metrics to the installed recorder, exporters like the Prometheus one render
+/// descriptions as the help of metrics
+pub fn describe() {
+    describe_counter!(SEND_TOTAL, "Messages sent by producers");
+    describe_histogram!(
+        SEND_DURATION,
+        Unit::Seconds,
+        "Time to send a message until the broker responded"
+    );
+    describe_counter!(PULL_TOTAL, "Pull requests of push consumers");
+    describe_histogram!(
+        PULL_DURATION,
+        Unit::Seconds,
+        "Time of a pull request including the time brokers suspend it"
+    );
+    describe_counter!(CONSUME_TOTAL, "Message batches passed to listeners");
+    describe_histogram!(
+        CONSUME_DURATION,
+        Unit::Seconds,
+        "Time listeners took to consume a message batch"
+    );
+    describe_counter!(
+        REBALANCE_TOTAL,
+        "Rebalances of consumer groups changing the allocated queues"
+    );
+    describe_gauge!(CONNECTIONS, "Open connections to brokers and name servers");
+    describe_counter!(
+        CONNECTION_ERRORS_TOTAL,
+        "Failures to connect to, read from or write to brokers and name servers"
+    );
+}
+
+fn result_label(ok: bool) -> &'static str {
+    if ok {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+pub(crate) fn record_send(topic: &str, elapsed: Duration, ok: bool) {
+    let topic = topic.to_string();
+    counter!(SEND_TOTAL, "topic" => topic.clone(), "result" => result_label(ok)).increment(1);
+    histogram!(SEND_DURATION, "topic" => topic).record(elapsed);
+}
+
+pub(crate) fn record_pull(group: &str, topic: &str, elapsed: Duration, ok: bool) {
+    let (group, topic) = (group.to_string(), topic.to_string());
+    counter!(
+        PULL_TOTAL,
+        "group" => group.clone(),
+        "topic" => topic.clone(),
+        "result" => result_label(ok)
+    )
+    .increment(1);
+    histogram!(PULL_DURATION, "group" => group, "topic" => topic).record(elapsed);
+}
+
+pub(crate) fn record_consume(group: &str, topic: &str, elapsed: Duration, ok: bool) {
+    let (group, topic) = (group.to_string(), topic.to_string());
+    counter!(
+        CONSUME_TOTAL,
+        "group" => group.clone(),
+        "topic" => topic.clone(),
+        "result" => result_label(ok)
+    )
+    .increment(1);
+    histogram!(CONSUME_DURATION, "group" => group, "topic" => topic).record(elapsed);
+}
+
+pub(crate) fn record_rebalance(group: &str) {
+    counter!(REBALANCE_TOTAL, "group" => group.to_string()).increment(1);
+}
+
+pub(crate) fn record_connected() {
+    gauge!(CONNECTIONS).increment(1.0);
+}
+
+pub(crate) fn record_closed() {
+    gauge!(CONNECTIONS).decrement(1.0);
+}
+
+pub(crate) fn record_connection_error() {
+    counter!(CONNECTION_ERRORS_TOTAL).increment(1);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use metrics::Label;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use super::{record_closed, record_connected, record_send};
+
+    #[test]
+    fn test_record() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            record_send("TopicTest", Duration::from_millis(20), true);
+            record_send("TopicTest", Duration::from_millis(30), false);
+            record_connected();
+            record_connected();
+            record_closed();
+        });
+        let mut snapshot = snapshotter.snapshot().into_vec();
+        let mut value = |kind: MetricKind, name: &'static str, labels: &[(&'static str, &str)]| {
+            let labels: Vec<Label> = labels
+                .iter()
+                .map(|(name, value)| Label::new(*name, value.to_string()))
+                .collect();
+            let index = snapshot.iter().position(|(key, _, _, _)| {
+                key.kind() == kind
+                    && key.key().name() == name
+                    && key.key().labels().cloned().collect::<Vec<_>>() == labels
+            })?;
+            Some(snapshot.swap_remove(index).3)
+        };
+        assert_eq!(
+            Some(DebugValue::Counter(1)),
+            value(
+                MetricKind::Counter,
+                "rocketmq_send_total",
+                &[("topic", "TopicTest"), ("result", "success")]
+            )
+        );
+        match value(
+            MetricKind::Histogram,
+            "rocketmq_send_duration_seconds",
+            &[("topic", "TopicTest")],
+        ) {
+            Some(DebugValue::Histogram(values)) => {
+                assert_eq!(
+                    vec![0.02, 0.03],
+                    values.iter().map(|v| v.0).collect::<Vec<_>>()
+                )
+            }
+            value => panic!("expected send duration histogram, got {:?}", value),
+        }
+        assert_eq!(
+            Some(DebugValue::Gauge(1.0.into())),
+            value(MetricKind::Gauge, "rocketmq_connections", &[])
+        );
+    }
+}
@@ -1,23 +1,52 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use parking_lot::Mutex;
 use rand::prelude::*;
+use serde::Deserialize;
+use tracing::{info, warn};
 
 use crate::client::Credentials;
 use crate::message::MessageQueue;
 use crate::protocol::{
-    request::GetRouteInfoRequestHeader, RemotingCommand, RequestCode, ResponseCode,
+    request::{
+        DeleteTopicFromNamesrvRequestHeader, GetRouteInfoRequestHeader,
+        GetTopicsByClusterRequestHeader,
+    },
+    RemotingCommand, RequestCode, ResponseCode,
 };
-use crate::remoting::RemotingClient;
-use crate::resolver::NsResolver;
-use crate::route::{BrokerData, TopicRouteData, MASTER_ID};
+use crate::remoting::{RemotingClient, RemotingOptions};
+use crate::resolver::{normalize_addrs, NsResolver};
+use crate::route::{BrokerData, ClusterInfo, TopicRouteData, MASTER_ID};
 use crate::Error;
 
+/// Name servers failing a request are tried after the others for this long
+const NAME_SERVER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Requests to name servers time out after this, same as the Java client
+const NAME_SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Body of topic list responses
+#[derive(Debug, Deserialize)]
+struct TopicList {
+    #[serde(default, rename = "topicList")]
+    topic_list: Vec<String>,
+}
+
 #[derive(Debug)]
 struct NameServerInner {
     servers: Vec<String>,
+    // servers were set by `update_addrs`, the resolver isn't used anymore
+    pinned: bool,
+    // name server requests start with when none succeeded yet, moved to the next one when
+    // it fails
     index: usize,
+    // name server the last request succeeded with, requests start with it until it fails
+    chosen: Option<String>,
+    // name server -> end of its cool-down after a failed request
+    cooldowns: HashMap<String, Instant>,
     // broker name -> BrokerData
     broker_address_map: HashMap<String, BrokerData>,
     broker_version_map: HashMap<String, HashMap<String, i32>>,
@@ -34,9 +63,21 @@ pub struct NameServer<NR: NsResolver + Clone> {
 
 impl<NR: NsResolver + Clone> NameServer<NR> {
     pub fn new<C: Into<Option<Credentials>>>(resolver: NR, credentials: C) -> Result<Self, Error> {
+        Self::with_options(resolver, credentials, RemotingOptions::default())
+    }
+
+    /// Create a name server client sharing the transport options of broker connections
+    pub fn with_options<C: Into<Option<Credentials>>>(
+        resolver: NR,
+        credentials: C,
+        options: RemotingOptions,
+    ) -> Result<Self, Error> {
         let inner = NameServerInner {
             servers: Vec::new(),
+            pinned: false,
             index: 0,
+            chosen: None,
+            cooldowns: HashMap::new(),
             broker_address_map: HashMap::new(),
             broker_version_map: HashMap::new(),
             route_data_map: HashMap::new(),
@@ -44,17 +85,60 @@ impl<NR: NsResolver + Clone> NameServer<NR> {
         Ok(Self {
             inner: Arc::new(Mutex::new(inner)),
             resolver,
-            remoting_client: RemotingClient::new(credentials),
+            remoting_client: RemotingClient::with_options(credentials, options),
         })
     }
 
+    /// Interval of resolving name server addresses again
+    pub fn refresh_interval(&self) -> Duration {
+        self.resolver.refresh_interval()
+    }
+
+    /// Name server addresses separated by `;`
     pub fn address(&self) -> String {
+        self.inner.lock().servers.join(";")
+    }
+
+    /// Name servers in the order a request tries them: starting at the one the last request
+    /// succeeded with, or the next one after failures, those cooling down after failures
+    /// come last in case all of them failed
+    fn servers_to_try(&self) -> Vec<String> {
+        let inner = self.inner.lock();
+        let len = inner.servers.len();
+        if len == 0 {
+            return Vec::new();
+        }
+        let start = inner
+            .chosen
+            .as_ref()
+            .and_then(|chosen| inner.servers.iter().position(|addr| addr == chosen))
+            .unwrap_or(inner.index % len);
+        let now = Instant::now();
+        let (healthy, cooling): (Vec<String>, Vec<String>) = inner.servers[start..]
+            .iter()
+            .chain(&inner.servers[..start])
+            .cloned()
+            .partition(|addr| inner.cooldowns.get(addr).is_none_or(|until| *until <= now));
+        healthy.into_iter().chain(cooling).collect()
+    }
+
+    fn mark_failed(&self, addr: &str) {
+        let mut inner = self.inner.lock();
+        inner
+            .cooldowns
+            .insert(addr.to_string(), Instant::now() + NAME_SERVER_COOLDOWN);
+        if let Some(pos) = inner.servers.iter().position(|a| a == addr) {
+            if inner.chosen.as_deref() == Some(addr) || inner.index % inner.servers.len() == pos {
+                inner.chosen = None;
+                inner.index = pos + 1;
+            }
+        }
+    }
+
+    fn mark_succeeded(&self, addr: &str) {
         let mut inner = self.inner.lock();
-        let addr = &inner.servers[inner.index].clone();
-        let mut index = inner.index + 1;
-        index %= inner.servers.len();
-        inner.index = index;
-        addr.trim_start_matches("http(s)://").to_string()
+        inner.cooldowns.remove(addr);
+        inner.chosen = Some(addr.to_string());
     }
 
     pub fn broker_address_map(&self) -> HashMap<String, BrokerData> {
@@ -69,54 +153,215 @@ impl<NR: NsResolver + Clone> NameServer<NR> {
         self.inner.lock().servers.is_empty()
     }
 
+    /// Close connections to name servers
+    pub(crate) fn shutdown(&self) {
+        self.remoting_client.shutdown();
+    }
+
+    /// Switch to name servers `addrs` at runtime, e.g. while migrating name servers. They
+    /// replace those of the resolver, which isn't used anymore, and connections to the old
+    /// ones are closed
+    pub fn update_addrs(&self, addrs: Vec<String>) -> Result<(), Error> {
+        let addrs = normalize_addrs(addrs);
+        if addrs.is_empty() {
+            return Err(Error::EmptyNameServers);
+        }
+        let old_servers = {
+            let mut inner = self.inner.lock();
+            inner.pinned = true;
+            inner.index = 0;
+            inner.chosen = None;
+            inner.cooldowns.clear();
+            std::mem::replace(&mut inner.servers, addrs.clone())
+        };
+        info!(old = ?old_servers, new = ?addrs, "name server addresses updated");
+        for addr in old_servers.iter().filter(|addr| !addrs.contains(addr)) {
+            self.remoting_client.close(addr);
+        }
+        Ok(())
+    }
+
     pub async fn update_name_server_address(&self) -> Result<(), Error> {
+        if self.inner.lock().pinned {
+            return Ok(());
+        }
         if let Ok(servers) = self.resolver.resolve().await {
-            self.inner.lock().servers = servers;
+            let servers = normalize_addrs(servers);
+            let mut inner = self.inner.lock();
+            inner.cooldowns.retain(|addr, _| servers.contains(addr));
+            inner.servers = servers;
         }
         Ok(())
     }
 
-    pub async fn query_topic_route_info(&self, topic: &str) -> Result<TopicRouteData, Error> {
-        let mut servers = self.inner.lock().servers.clone();
+    /// Send the request built by `request` to name servers, failing over to the next one
+    /// until one of them responds, returns the response with the address of its server
+    async fn invoke_any<F>(&self, request: F) -> Result<(String, RemotingCommand), Error>
+    where
+        F: Fn() -> RemotingCommand,
+    {
+        self.resolve_if_empty().await;
+        let servers = self.servers_to_try();
         if servers.is_empty() {
+            return Err(Error::EmptyNameServers);
+        }
+        let mut last_err = Error::EmptyNameServers;
+        for addr in &servers {
+            let cmd = request();
+            let code = cmd.code();
+            match self
+                .remoting_client
+                .invoke_timeout(addr, cmd, NAME_SERVER_TIMEOUT)
+                .await
+            {
+                Ok(res) => {
+                    self.mark_succeeded(addr);
+                    return Ok((addr.clone(), res));
+                }
+                Err(err) => {
+                    warn!(name_server = %addr, code = code, "name server request failed, try the next name server: {:?}", err);
+                    self.mark_failed(addr);
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn resolve_if_empty(&self) {
+        if self.is_empty() {
             // Try update name servers
             if let Ok(new_servers) = self.resolver.resolve().await {
-                servers = new_servers.clone();
-                self.inner.lock().servers = new_servers;
-            } else {
-                return Err(Error::EmptyNameServers);
+                self.inner.lock().servers = normalize_addrs(new_servers);
             }
         }
-        let header = GetRouteInfoRequestHeader {
-            topic: topic.to_string(),
-        };
+    }
+
+    /// Delete `topic` of `cluster` from every name server with `DELETE_TOPIC_IN_NAMESRV`,
+    /// unlike queries it has to reach all of them as they don't share their routes
+    pub async fn delete_topic(&self, topic: &str, cluster: &str) -> Result<(), Error> {
+        self.resolve_if_empty().await;
+        let servers = self.inner.lock().servers.clone();
+        if servers.is_empty() {
+            return Err(Error::EmptyNameServers);
+        }
+        let mut last_err = None;
         for addr in &servers {
+            let header = DeleteTopicFromNamesrvRequestHeader {
+                topic: topic.to_string(),
+                cluster_name: cluster.to_string(),
+            };
             let cmd = RemotingCommand::with_header(
-                RequestCode::GetRouteInfoByTopic,
-                header.clone(),
+                RequestCode::DeleteTopicInNameServer,
+                header,
                 Vec::new(),
             );
-            let res = self.remoting_client.invoke(addr, cmd).await;
-            if let Ok(res) = res {
-                return match ResponseCode::from_code(res.header.code)? {
-                    ResponseCode::Success => {
-                        if res.body.is_empty() {
-                            // FIXME: error
-                        }
-                        let route_data = TopicRouteData::from_bytes(&res.body)?;
-                        Ok(route_data)
-                    }
-                    ResponseCode::TopicNotExist => Err(Error::TopicNotExist(topic.to_string())),
-                    _ => Err(Error::ResponseError {
-                        code: res.header.code,
-                        message: res.header.remark.clone(),
-                    }),
-                };
-            } else {
-                println!("{:?}", res);
+            let res = self
+                .remoting_client
+                .invoke_timeout(addr, cmd, NAME_SERVER_TIMEOUT)
+                .await
+                .and_then(|res| match ResponseCode::try_from(res.header.code) {
+                    Ok(ResponseCode::Success) => Ok(()),
+                    _ => Err(res.into_broker_error(addr)),
+                });
+            if let Err(err) = res {
+                warn!(name_server = %addr, topic = topic, "delete topic failed: {:?}", err);
+                last_err = Some(err);
             }
         }
-        Err(Error::EmptyRouteData)
+        match last_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Query route info of `topic` with `GET_ROUTEINFO_BY_TOPIC`, failing over to the next
+    /// name server until one of them responds
+    pub async fn query_topic_route_info(&self, topic: &str) -> Result<TopicRouteData, Error> {
+        let header = GetRouteInfoRequestHeader {
+            topic: topic.to_string(),
+        };
+        let (addr, res) = self
+            .invoke_any(|| {
+                RemotingCommand::with_header(
+                    RequestCode::GetRouteInfoByTopic,
+                    header.clone(),
+                    Vec::new(),
+                )
+            })
+            .await?;
+        match ResponseCode::try_from(res.header.code) {
+            Ok(ResponseCode::Success) if res.body.is_empty() => Err(Error::EmptyRouteData),
+            Ok(ResponseCode::Success) => TopicRouteData::from_bytes(&res.body),
+            Ok(ResponseCode::TopicNotExist) => Err(Error::TopicNotExist(topic.to_string())),
+            _ => Err(res.into_broker_error(&addr)),
+        }
+    }
+
+    /// Brokers of every cluster with `GET_BROKER_CLUSTER_INFO`, cluster name -> broker
+    /// name -> broker data with its addresses by broker id
+    pub async fn cluster_info(
+        &self,
+    ) -> Result<HashMap<String, HashMap<String, BrokerData>>, Error> {
+        let (addr, res) = self
+            .invoke_any(|| {
+                RemotingCommand::new(
+                    RequestCode::GetBrokerClusterInfo.into(),
+                    0,
+                    String::new(),
+                    HashMap::new(),
+                    Vec::new(),
+                )
+            })
+            .await?;
+        match ResponseCode::try_from(res.header.code) {
+            Ok(ResponseCode::Success) => Ok(ClusterInfo::from_bytes(&res.body)?.clusters()),
+            _ => Err(res.into_broker_error(&addr)),
+        }
+    }
+
+    /// Topics of every cluster with `GET_ALL_TOPIC_LIST_FROM_NAMESERVER`, sorted
+    pub async fn topic_list(&self) -> Result<Vec<String>, Error> {
+        let (addr, res) = self
+            .invoke_any(|| {
+                RemotingCommand::new(
+                    RequestCode::GetAllTopicListFromNameServer.into(),
+                    0,
+                    String::new(),
+                    HashMap::new(),
+                    Vec::new(),
+                )
+            })
+            .await?;
+        Self::decode_topic_list(&addr, res)
+    }
+
+    /// Topics of `cluster` with `GET_TOPICS_BY_CLUSTER`, sorted
+    pub async fn topics_by_cluster(&self, cluster: &str) -> Result<Vec<String>, Error> {
+        let header = GetTopicsByClusterRequestHeader {
+            cluster: cluster.to_string(),
+        };
+        let (addr, res) = self
+            .invoke_any(|| {
+                RemotingCommand::with_header(
+                    RequestCode::QueryTopicsByCluster,
+                    header.clone(),
+                    Vec::new(),
+                )
+            })
+            .await?;
+        Self::decode_topic_list(&addr, res)
+    }
+
+    fn decode_topic_list(addr: &str, res: RemotingCommand) -> Result<Vec<String>, Error> {
+        match ResponseCode::try_from(res.header.code) {
+            Ok(ResponseCode::Success) => {
+                let mut topics = serde_json::from_slice::<TopicList>(&res.body)?.topic_list;
+                topics.sort();
+                Ok(topics)
+            }
+            _ => Err(res.into_broker_error(addr)),
+        }
     }
 
     pub async fn update_topic_route_info(
@@ -182,24 +427,12 @@ impl<NR: NsResolver + Clone> NameServer<NR> {
         topic: &str,
     ) -> Result<Vec<MessageQueue>, Error> {
         let route_data = self.query_topic_route_info(topic).await?;
-        let mqs: Vec<MessageQueue> = route_data
-            .queue_datas
-            .into_iter()
-            .flat_map(|queue_data| {
-                let mut mqs = Vec::new();
-                if queue_data.perm.is_readable() {
-                    for i in 0..queue_data.read_queue_nums {
-                        mqs.push(MessageQueue {
-                            topic: topic.to_string(),
-                            broker_name: queue_data.broker_name.clone(),
-                            queue_id: i as u32,
-                        })
-                    }
-                }
-                mqs
-            })
-            .collect();
-        Ok(mqs)
+        Ok(route_data.to_subscribe_info(topic))
+    }
+
+    /// Route of `topic` cached by the last update
+    pub fn topic_route_data(&self, topic: &str) -> Option<TopicRouteData> {
+        self.inner.lock().route_data_map.get(topic).cloned()
     }
 
     pub async fn fetch_publish_message_queues(
@@ -266,6 +499,15 @@ impl<NR: NsResolver + Clone> NameServer<NR> {
             .or_default()
             .insert(broker_addr.to_string(), version);
     }
+
+    /// Version reported by the broker in its last heartbeat response
+    pub fn find_broker_version(&self, broker_name: &str, broker_addr: &str) -> Option<i32> {
+        self.inner
+            .lock()
+            .broker_version_map
+            .get(broker_name)
+            .and_then(|versions| versions.get(broker_addr).copied())
+    }
 }
 
 #[cfg(test)]
@@ -275,6 +517,165 @@ mod test {
 
     const TOPIC: &'static str = "DefaultCluster";
 
+    /// Name server answering route queries with `body`, or `TOPIC_NOT_EXIST` when it's empty
+    async fn fake_name_server(body: &'static str) -> String {
+        fake_name_server_for(RequestCode::GetRouteInfoByTopic, body).await
+    }
+
+    /// Name server answering requests of `code` with `body`
+    async fn fake_name_server_for(code: RequestCode, body: &'static str) -> String {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::Framed;
+
+        use crate::protocol::MqCodec;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, MqCodec::default());
+            while let Some(Ok(req)) = framed.next().await {
+                assert!(req.code() == code);
+                if code == RequestCode::GetRouteInfoByTopic {
+                    assert_eq!(TOPIC, req.header.ext_fields["topic"]);
+                }
+                let code = if body.is_empty() {
+                    ResponseCode::TopicNotExist
+                } else {
+                    ResponseCode::Success
+                };
+                let mut res = RemotingCommand::response(code, String::new(), body.into());
+                res.set_opaque(req.opaque());
+                framed.send(res).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_servers_to_try() {
+        let namesrv = NameServer::new(StaticResolver::new(vec![]), None).unwrap();
+        assert!(namesrv.servers_to_try().is_empty());
+        namesrv.inner.lock().servers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        assert_eq!(vec!["a", "b", "c"], namesrv.servers_to_try());
+        // requests stick to the name server which succeeded last
+        namesrv.mark_succeeded("b");
+        assert_eq!(vec!["b", "c", "a"], namesrv.servers_to_try());
+        assert_eq!(vec!["b", "c", "a"], namesrv.servers_to_try());
+        // until it fails, failed ones come last until their cool-down ends
+        namesrv.mark_failed("b");
+        assert_eq!(vec!["c", "a", "b"], namesrv.servers_to_try());
+        namesrv.mark_failed("a");
+        assert_eq!(vec!["c", "a", "b"], namesrv.servers_to_try());
+        namesrv.mark_failed("c");
+        assert_eq!(vec!["a", "b", "c"], namesrv.servers_to_try());
+        namesrv.mark_succeeded("c");
+        assert_eq!(vec!["c", "a", "b"], namesrv.servers_to_try());
+        assert_eq!("a;b;c", namesrv.address());
+    }
+
+    #[tokio::test]
+    async fn test_query_topic_route_info_failover() {
+        let dead = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead_addr = dead.local_addr().unwrap().to_string();
+        drop(dead);
+        let addr = fake_name_server("").await;
+        let namesrv = NameServer::new(
+            StaticResolver::new(vec![dead_addr.clone(), addr.clone()]),
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            namesrv.query_topic_route_info(TOPIC).await,
+            Err(Error::TopicNotExist(_))
+        ));
+        assert!(namesrv.inner.lock().cooldowns.contains_key(&dead_addr));
+        assert_eq!(Some(&addr), namesrv.inner.lock().chosen.as_ref());
+        assert_eq!(vec![addr, dead_addr], namesrv.servers_to_try());
+    }
+
+    #[tokio::test]
+    async fn test_query_topic_route_info_from_fake_server() {
+        // fastjson writes integer map keys unquoted
+        let body = r#"{"brokerDatas":[{"brokerAddrs":{0:"127.0.0.1:10911",1:"127.0.0.1:10921"},"brokerName":"broker-a","cluster":"DefaultCluster"}],"filterServerTable":{},"queueDatas":[{"brokerName":"broker-a","perm":6,"readQueueNums":8,"topicSynFlag":0,"writeQueueNums":8}]}"#;
+        let addr = fake_name_server(body).await;
+        let namesrv = NameServer::new(StaticResolver::new(vec![addr]), None).unwrap();
+        let route_data = namesrv.query_topic_route_info(TOPIC).await.unwrap();
+        assert_eq!(1, route_data.broker_datas.len());
+        let broker_data = &route_data.broker_datas[0];
+        assert_eq!("broker-a", broker_data.broker_name);
+        assert_eq!("127.0.0.1:10911", broker_data.broker_addrs[&MASTER_ID]);
+        assert_eq!("127.0.0.1:10921", broker_data.broker_addrs[&1]);
+        assert_eq!(1, route_data.queue_datas.len());
+        assert_eq!(8, route_data.queue_datas[0].read_queue_nums);
+        assert!(route_data.queue_datas[0].perm.is_readable());
+
+        let addr = fake_name_server("").await;
+        let namesrv = NameServer::new(StaticResolver::new(vec![addr]), None).unwrap();
+        assert!(matches!(
+            namesrv.query_topic_route_info(TOPIC).await,
+            Err(Error::TopicNotExist(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_update_addrs() {
+        let body = r#"{"brokerDatas":[],"filterServerTable":{},"queueDatas":[]}"#;
+        let addr = fake_name_server(body).await;
+        let dead_addr = "127.0.0.1:1".to_string();
+        let namesrv = NameServer::new(StaticResolver::new(vec![dead_addr]), None).unwrap();
+        namesrv.update_name_server_address().await.unwrap();
+        assert!(matches!(
+            namesrv.update_addrs(vec![" ".to_string()]),
+            Err(Error::EmptyNameServers)
+        ));
+
+        namesrv
+            .update_addrs(vec![addr.clone(), format!(" {}", addr)])
+            .unwrap();
+        assert_eq!(addr, namesrv.address());
+        // the resolver doesn't override addresses updated at runtime
+        namesrv.update_name_server_address().await.unwrap();
+        assert_eq!(addr, namesrv.address());
+        assert!(namesrv.query_topic_route_info(TOPIC).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cluster_info() {
+        let body = r#"{"brokerAddrTable":{"broker-a":{"brokerAddrs":{0:"127.0.0.1:10911",1:"127.0.0.1:10921"},"brokerName":"broker-a","cluster":"DefaultCluster"},"broker-b":{"brokerAddrs":{0:"127.0.0.2:10911"},"brokerName":"broker-b","cluster":"DefaultCluster"}},"clusterAddrTable":{"DefaultCluster":["broker-a","broker-b"]}}"#;
+        let addr = fake_name_server_for(RequestCode::GetBrokerClusterInfo, body).await;
+        let namesrv = NameServer::new(StaticResolver::new(vec![addr]), None).unwrap();
+        let clusters = namesrv.cluster_info().await.unwrap();
+        assert_eq!(1, clusters.len());
+        let brokers = &clusters["DefaultCluster"];
+        assert_eq!(2, brokers.len());
+        assert_eq!("127.0.0.1:10921", brokers["broker-a"].broker_addrs[&1]);
+        assert_eq!(
+            "127.0.0.2:10911",
+            brokers["broker-b"].broker_addrs[&MASTER_ID]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_topic_list() {
+        let body = r#"{"topicList":["TopicB","TopicA","%RETRY%GroupA"],"brokerAddr":null}"#;
+        let addr = fake_name_server_for(RequestCode::GetAllTopicListFromNameServer, body).await;
+        let namesrv = NameServer::new(StaticResolver::new(vec![addr]), None).unwrap();
+        assert_eq!(
+            vec!["%RETRY%GroupA", "TopicA", "TopicB"],
+            namesrv.topic_list().await.unwrap()
+        );
+
+        let addr =
+            fake_name_server_for(RequestCode::QueryTopicsByCluster, r#"{"topicList":[]}"#).await;
+        let namesrv = NameServer::new(StaticResolver::new(vec![addr]), None).unwrap();
+        assert!(namesrv
+            .topics_by_cluster("DefaultCluster")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_query_topic_route_info_with_empty_namesrv() {
         let namesrv = NameServer::new(StaticResolver::new(vec![]), None).unwrap();
@@ -282,6 +683,29 @@ mod test {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_find_broker_version() {
+        let namesrv = NameServer::new(StaticResolver::new(vec![]), None).unwrap();
+        assert_eq!(
+            None,
+            namesrv.find_broker_version("broker-a", "127.0.0.1:10911")
+        );
+        namesrv.add_broker_version("broker-a", "127.0.0.1:10911", 395);
+        namesrv.add_broker_version("broker-a", "127.0.0.1:10921", 373);
+        assert_eq!(
+            Some(395),
+            namesrv.find_broker_version("broker-a", "127.0.0.1:10911")
+        );
+        assert_eq!(
+            Some(373),
+            namesrv.find_broker_version("broker-a", "127.0.0.1:10921")
+        );
+        assert_eq!(
+            None,
+            namesrv.find_broker_version("broker-b", "127.0.0.1:10911")
+        );
+    }
+
     #[tokio::test]
     async fn test_query_topic_route_info() {
         let namesrv = NameServer::new(
@@ -1,25 +1,42 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io::{self, Write};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
 
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
 use parking_lot::Mutex;
+use serde::Serialize;
 use time::OffsetDateTime;
+use tracing::Span;
+use tracing_futures::Instrument;
 
+use crate::buffer;
 use crate::client::{Client, ClientOptions, ClientState};
+use crate::codec::Codec;
+use crate::config::FileConfig;
 use crate::error::{ClientError, Error};
-use crate::message::{Message, MessageQueue, MessageSysFlag, Property};
-use crate::namesrv::NameServer;
+#[cfg(feature = "grpc")]
+use crate::grpc::GrpcClient;
+use crate::instrument;
+use crate::message::{Message, MessageExt, MessageQueue, Property};
+#[cfg(feature = "metrics")]
+use crate::metrics;
 use crate::producer::selector::QueueSelect;
 use crate::protocol::{
+    compression::{self, CompressionType},
     request::{SendMessageRequestHeader, SendMessageRequestV2Header},
+    response::SendMessageResponseHeader,
     RemotingCommand, RequestCode, ResponseCode,
 };
-use crate::resolver::{HttpResolver, PassthroughResolver, Resolver};
-use crate::route::TopicPublishInfo;
+use crate::resolver::{HttpResolver, PassthroughResolver, Resolver, SrvResolver};
+use crate::route::{TopicPublishInfo, TopicRouteData};
+use crate::sys_flag::SysFlag;
+use crate::topic;
 use selector::QueueSelector;
 
 /// Message queue selector
@@ -49,6 +66,30 @@ pub struct SendResult {
     pub trace_on: bool,
 }
 
+/// State of a local transaction, reported to broker to commit or rollback its half message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocalTransactionState {
+    CommitMessage,
+    RollbackMessage,
+    /// Not decided yet, broker checks back again later
+    Unknown,
+}
+
+impl LocalTransactionState {
+    /// Transaction type of the end transaction request
+    pub(crate) fn sys_flag(self) -> i32 {
+        match self {
+            LocalTransactionState::CommitMessage => SysFlag::TRANSACTION_COMMIT,
+            LocalTransactionState::RollbackMessage => SysFlag::TRANSACTION_ROLLBACK,
+            LocalTransactionState::Unknown => SysFlag::empty(),
+        }
+        .bits()
+    }
+}
+
+pub(crate) type TransactionChecker =
+    Arc<dyn Fn(&MessageExt) -> LocalTransactionState + Send + Sync>;
+
 /// RocketMQ producer options
 #[derive(Debug, Clone)]
 pub struct ProducerOptions {
@@ -60,6 +101,7 @@ pub struct ProducerOptions {
     create_topic_key: String,
     compress_msg_body_over_how_much: usize,
     compress_level: u32,
+    compression_type: CompressionType,
     max_message_size: usize,
     max_retries: usize,
 }
@@ -69,12 +111,13 @@ impl Default for ProducerOptions {
         Self {
             client_options: ClientOptions::default(),
             selector: QueueSelector::default(),
-            resolver: Resolver::Http(HttpResolver::new("DEFAULT".to_string())),
+            resolver: Resolver::default(),
             send_msg_timeout: Duration::from_secs(3),
             default_topic_queue_nums: 4,
-            create_topic_key: "TBW102".to_string(),
+            create_topic_key: topic::AUTO_CREATE_TOPIC_KEY.to_string(),
             compress_msg_body_over_how_much: 4 * 1024, // 4K
             compress_level: 5,
+            compression_type: CompressionType::Zlib,
             max_message_size: 4 * 1024 * 1024, // 4M
             max_retries: 2,
         }
@@ -93,6 +136,13 @@ impl ProducerOptions {
         }
     }
 
+    /// Options of the `[client]` and `[producer]` tables of the TOML file at `path`,
+    /// e.g. `name_servers`, `access_key`, `group` or `send_msg_timeout_ms`. Durations are
+    /// in milliseconds
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(FileConfig::load(path.as_ref())?.producer_options())
+    }
+
     pub fn group_name(&self) -> &str {
         &self.client_options.group_name
     }
@@ -102,6 +152,24 @@ impl ProducerOptions {
         self
     }
 
+    /// Codec of bodies compressed by the producer, zlib by default, lz4 and zstd need the
+    /// `lz4` and `zstd` features. Consumers of 4.x don't read the compression type and
+    /// decompress everything as zlib
+    pub fn set_compression_type(
+        &mut self,
+        compression_type: CompressionType,
+    ) -> Result<&mut Self, Error> {
+        if !compression_type.is_supported() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} compression is not supported", compression_type),
+            )
+            .into());
+        }
+        self.compression_type = compression_type;
+        Ok(self)
+    }
+
     pub fn set_default_topic_queue_nums(&mut self, queue_nums: i32) -> &mut Self {
         self.default_topic_queue_nums = queue_nums;
         self
@@ -132,20 +200,40 @@ impl ProducerOptions {
         ));
         self
     }
+
+    /// Resolve name servers from DNS SRV records of `name`, e.g. `_rocketmq._tcp.cluster.internal`
+    pub fn set_name_server_srv(&mut self, name: &str) -> &mut Self {
+        self.resolver = Resolver::Srv(SrvResolver::new(name.to_string()));
+        self
+    }
 }
 
-#[derive(Debug)]
 pub(crate) struct ProducerInner {
     publish_info: HashMap<String, TopicPublishInfo>,
+    transaction_checker: Option<TransactionChecker>,
+}
+
+impl fmt::Debug for ProducerInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProducerInner")
+            .field("publish_info", &self.publish_info)
+            .field("transaction_checker", &self.transaction_checker.is_some())
+            .finish()
+    }
 }
 
 impl ProducerInner {
     fn new() -> Self {
         Self {
             publish_info: HashMap::new(),
+            transaction_checker: None,
         }
     }
 
+    pub(crate) fn transaction_checker(&self) -> Option<TransactionChecker> {
+        self.transaction_checker.clone()
+    }
+
     pub(crate) fn publish_topic_list(&self) -> Vec<String> {
         self.publish_info.keys().cloned().collect()
     }
@@ -163,17 +251,53 @@ impl ProducerInner {
             .unwrap_or(true)
     }
 
+    /// Whether the publish info of `topic` was built from a route other than `data`, e.g.
+    /// the route cache was refreshed by a consumer before
+    pub(crate) fn is_publish_route_changed(&self, topic: &str, data: &TopicRouteData) -> bool {
+        self.publish_info
+            .get(topic)
+            .map(|info| info.route_data != *data)
+            .unwrap_or(true)
+    }
+
     pub(crate) fn is_unit_mode(&self) -> bool {
         false
     }
 }
 
-/// RocketMQ producer
-#[derive(Debug)]
+/// RocketMQ producer, clones are handles of the same producer sharing its state, it's
+/// shut down once the last of them is dropped
+#[derive(Debug, Clone)]
 pub struct Producer {
+    handle: Arc<ProducerHandle>,
+}
+
+#[derive(Debug)]
+struct ProducerHandle {
     inner: Arc<Mutex<ProducerInner>>,
     options: ProducerOptions,
+    // shared with other producers and consumers
     client: Client<Resolver>,
+    // whether the shared client was released
+    released: AtomicBool,
+    // sends messages to 5.x proxies instead of brokers
+    #[cfg(feature = "grpc")]
+    grpc: Option<GrpcClient>,
+}
+
+impl ProducerHandle {
+    /// Stop the producer, returns false if it was stopped already
+    fn stop(&self) -> bool {
+        if self.released.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            grpc.shutdown();
+        }
+        self.client.unregister_producer(self.options.group_name());
+        true
+    }
 }
 
 impl Producer {
@@ -181,30 +305,65 @@ impl Producer {
         Self::with_options(ProducerOptions::default())
     }
 
-    pub fn with_options(options: ProducerOptions) -> Result<Self, Error> {
-        let client_options = options.client_options.clone();
-        let name_server =
-            NameServer::new(options.resolver.clone(), client_options.credentials.clone())?;
+    pub fn with_options(mut options: ProducerOptions) -> Result<Self, Error> {
+        topic::validate_group(options.group_name())?;
+        #[cfg(feature = "grpc")]
+        let grpc = GrpcClient::producer(&options.client_options);
+        let client = Client::get_or_create(&options.client_options, &options.resolver)?;
+        options.client_options.group_name = client.wrap_namespace(options.group_name());
         Ok(Self {
-            inner: Arc::new(Mutex::new(ProducerInner::new())),
-            options,
-            client: Client::new(client_options, name_server),
+            handle: Arc::new(ProducerHandle {
+                inner: Arc::new(Mutex::new(ProducerInner::new())),
+                options,
+                client,
+                released: AtomicBool::new(false),
+                #[cfg(feature = "grpc")]
+                grpc,
+            }),
         })
     }
 
     pub fn start(&self) {
-        self.client
-            .register_producer(&self.options.group_name(), Arc::clone(&self.inner));
-        self.client.start();
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.handle.grpc {
+            grpc.start();
+            return;
+        }
+        self.handle.client.register_producer(
+            self.handle.options.group_name(),
+            Arc::clone(&self.handle.inner),
+        );
+        self.handle.client.start();
     }
 
-    pub fn shutdown(&self) {
-        self.client.unregister_producer(&self.options.group_name());
-        self.client.shutdown();
+    /// Shut down the producer, handles cloned from it included. Waits at most `timeout`
+    /// for the group to be unregistered on brokers and, unless the client is shared with
+    /// other producers or consumers, for the client to shut down
+    pub async fn shutdown(&self, timeout: Duration) {
+        if self.handle.stop() {
+            let group = self.handle.options.group_name();
+            self.handle.client.shutdown(group, "", timeout).await;
+        }
+    }
+
+    /// Set the checker broker asks, through the client, for the state of local transactions
+    /// whose half messages are neither committed nor rolled back in time
+    pub fn set_transaction_checker<F>(&self, checker: F)
+    where
+        F: Fn(&MessageExt) -> LocalTransactionState + Send + Sync + 'static,
+    {
+        self.handle.inner.lock().transaction_checker = Some(Arc::new(checker));
     }
 
     fn check_state(&self) -> Result<(), Error> {
-        match self.client.state() {
+        #[cfg(feature = "grpc")]
+        let state = match &self.handle.grpc {
+            Some(grpc) => grpc.state(),
+            None => self.handle.client.state(),
+        };
+        #[cfg(not(feature = "grpc"))]
+        let state = self.handle.client.state();
+        match state {
             ClientState::Created => Err(Error::Client(ClientError::NotStarted)),
             ClientState::StartFailed => Err(Error::Client(ClientError::StartFailed)),
             ClientState::Shutdown => Err(Error::Client(ClientError::Shutdown)),
@@ -212,58 +371,144 @@ impl Producer {
         }
     }
 
+    /// Switch to name servers `addrs` without restarting, e.g. while migrating name
+    /// servers, routes of topics are refreshed against them. Producers and consumers
+    /// sharing the client of this producer switch too
+    pub async fn update_name_server_addrs(&self, addrs: Vec<String>) -> Result<(), Error> {
+        self.handle.client.update_name_server_addrs(addrs).await
+    }
+
     pub async fn send(&self, msg: Message) -> Result<SendResult, Error> {
-        self.check_state()?;
-        let mut msg = msg;
-        let namespace = &self.options.client_options.namespace;
-        if !namespace.is_empty() {
-            msg.topic = format!("{}%{}", namespace, msg.topic);
+        let span = instrument::send_span(msg.topic(), false);
+        #[cfg(feature = "metrics")]
+        let (topic, start) = (msg.topic().to_string(), Instant::now());
+        let result = async {
+            self.check_state()?;
+            #[cfg(feature = "grpc")]
+            if let Some(grpc) = &self.handle.grpc {
+                let result = grpc
+                    .send_message(vec![msg], self.handle.options.send_msg_timeout)
+                    .await?;
+                instrument::record_msg_id(&Span::current(), &result.msg_id);
+                return Ok(result);
+            }
+            let mut msg = msg;
+            msg.topic = self.handle.client.wrap_namespace(&msg.topic);
+            let (mq, addr) = self.select_broker(&msg).await?;
+            let cmd = self.build_send_request(&mq, &mut msg)?;
+            let res = self
+                .handle
+                .client
+                .invoke_sync(&addr, cmd, self.handle.options.send_msg_timeout)
+                .await?;
+            let mut result = Self::process_send_response(&mq.broker_name, &addr, res, &[msg])?;
+            result.message_queue = self.handle.client.unwrap_queue(&result.message_queue);
+            instrument::record_msg_id(&Span::current(), &result.msg_id);
+            Ok(result)
         }
-        let mq = self
-            .select_message_queue(&msg)
-            .await?
-            .ok_or(Error::EmptyRouteData)?;
-        let addr = self
-            .client
-            .name_server
-            .find_broker_addr_by_name(&mq.broker_name)
-            .ok_or(Error::EmptyRouteData)?;
-        let cmd = self.build_send_request(&mq, &mut msg)?;
-        let res = tokio::time::timeout(
-            self.options.send_msg_timeout.clone(),
-            self.client.invoke(&addr, cmd),
-        )
-        .await
-        .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e))??;
-        Self::process_send_response(&mq.broker_name, res, &[msg])
+        .instrument(span.clone())
+        .await;
+        instrument::record_result(&span, &result.as_ref().map(|result| result.status));
+        #[cfg(feature = "metrics")]
+        metrics::record_send(&topic, start.elapsed(), result.is_ok());
+        result
     }
 
+    /// Send messages of the same topic together, proxies take them as they are over gRPC
     pub async fn send_batch(&self, msgs: &[Message]) -> Result<SendResult, Error> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.handle.grpc {
+            self.check_state()?;
+            return grpc
+                .send_message(msgs.to_vec(), self.handle.options.send_msg_timeout)
+                .await;
+        }
         let msg = Message::encode_batch(msgs)?;
         Ok(self.send(msg).await?)
     }
 
+    /// Send `value` to `topic` with a JSON body
+    pub async fn send_json<T>(&self, topic: &str, value: &T) -> Result<SendResult, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let msg = Message::builder()
+            .topic(topic)
+            .body(serde_json::to_vec(value)?)
+            .build()?;
+        self.send(msg).await
+    }
+
+    /// Send `value` to `topic` with a body encoded by `codec`
+    pub async fn send_with_codec<T, C>(
+        &self,
+        topic: &str,
+        codec: &C,
+        value: &T,
+    ) -> Result<SendResult, Error>
+    where
+        C: Codec<T>,
+    {
+        let msg = Message::builder()
+            .topic(topic)
+            .body(codec.encode(value)?)
+            .build()?;
+        self.send(msg).await
+    }
+
     pub async fn send_oneway(&self, msg: Message) -> Result<(), Error> {
-        self.check_state()?;
-        let mut msg = msg;
-        let namespace = &self.options.client_options.namespace;
-        if !namespace.is_empty() {
-            msg.topic = format!("{}%{}", namespace, msg.topic);
+        let span = instrument::send_span(msg.topic(), true);
+        #[cfg(feature = "metrics")]
+        let (topic, start) = (msg.topic().to_string(), Instant::now());
+        let result = async {
+            self.check_state()?;
+            // proxies acknowledge every message, oneway ones are sent like the others
+            #[cfg(feature = "grpc")]
+            if let Some(grpc) = &self.handle.grpc {
+                let result = grpc
+                    .send_message(vec![msg], self.handle.options.send_msg_timeout)
+                    .await?;
+                instrument::record_msg_id(&Span::current(), &result.msg_id);
+                return Ok(());
+            }
+            let mut msg = msg;
+            msg.topic = self.handle.client.wrap_namespace(&msg.topic);
+            let (mq, addr) = self.select_broker(&msg).await?;
+            let cmd = self.build_send_request(&mq, &mut msg)?;
+            instrument::record_msg_id(&Span::current(), &msg.unique_keys().join(","));
+            self.handle.client.invoke_oneway(&addr, cmd).await
         }
+        .instrument(span.clone())
+        .await;
+        instrument::record_result(&span, &result);
+        #[cfg(feature = "metrics")]
+        metrics::record_send(&topic, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Queue to send `msg` to and the address of its broker, recorded in the current span
+    async fn select_broker(&self, msg: &Message) -> Result<(MessageQueue, String), Error> {
         let mq = self
-            .select_message_queue(&msg)
+            .select_message_queue(msg)
             .await?
             .ok_or(Error::EmptyRouteData)?;
         let addr = self
+            .handle
             .client
             .name_server
             .find_broker_addr_by_name(&mq.broker_name)
             .ok_or(Error::EmptyRouteData)?;
-        let cmd = self.build_send_request(&mq, &mut msg)?;
-        Ok(self.client.invoke_oneway(&addr, cmd).await?)
+        let span = Span::current();
+        span.record("queue", mq.queue_id);
+        span.record("broker", mq.broker_name.as_str());
+        Ok((mq, addr))
     }
 
     pub async fn send_batch_oneway(&self, msgs: &[Message]) -> Result<(), Error> {
+        #[cfg(feature = "grpc")]
+        if self.handle.grpc.is_some() {
+            return self.send_batch(msgs).await.map(|_| ());
+        }
         let msg = Message::encode_batch(msgs)?;
         Ok(self.send_oneway(msg).await?)
     }
@@ -273,77 +518,92 @@ impl Producer {
         mq: &MessageQueue,
         msg: &mut Message,
     ) -> Result<RemotingCommand, Error> {
-        msg.set_default_unique_key();
-        let mut sys_flag = 0;
+        msg.check_delivery()?;
+        #[cfg(feature = "trace-context")]
+        self.handle
+            .options
+            .client_options
+            .trace_propagation
+            .inject(msg);
+        // messages of a batch got their keys when encoded
+        if !msg.batch {
+            msg.set_default_unique_key();
+        }
+        let mut sys_flag = SysFlag::empty();
         if let Some(tran_msg) = msg.get_property(Property::TRANSACTION_PREPARED) {
             let is_tran_msg: bool = tran_msg.parse().unwrap_or(false);
             if is_tran_msg {
-                let tran_prepared: i32 = MessageSysFlag::TransactionPreparedType.into();
-                sys_flag |= tran_prepared;
+                sys_flag.set_transaction_type(SysFlag::TRANSACTION_PREPARED);
             }
         }
-        let body = if !msg.batch {
-            let compressed_flag: i32 = MessageSysFlag::Compressed.into();
-            if msg.sys_flag & compressed_flag == compressed_flag {
-                // Already compressed
-                msg.body.clone()
-            } else {
-                if msg.body.len() >= self.options.compress_msg_body_over_how_much {
-                    let mut encoder =
-                        ZlibEncoder::new(Vec::new(), Compression::new(self.options.compress_level));
-                    encoder.write_all(&msg.body)?;
-                    let compressed = encoder.finish()?;
-                    msg.sys_flag |= compressed_flag;
-                    compressed
-                } else {
-                    msg.body.clone()
-                }
-            }
+        let body = if !msg.batch
+            && !msg.sys_flag.is_compressed()
+            && msg.body.len() >= self.handle.options.compress_msg_body_over_how_much
+        {
+            // compressed into a pooled buffer, which goes back to the pool once the request
+            // is written
+            let mut buf = buffer::get();
+            compression::compress_into(
+                &msg.body,
+                self.handle.options.compression_type,
+                self.handle.options.compress_level,
+                &mut buf,
+            )?;
+            msg.sys_flag
+                .insert(self.handle.options.compression_type.sys_flag());
+            buf.into_bytes()
         } else {
+            // batches and compressed messages are sent as is, sharing the body of the message
             msg.body.clone()
         };
-        let cmd = if msg.batch {
+        // let broker know the body is compressed and how
+        if msg.sys_flag.is_compressed() {
+            sys_flag |= SysFlag::COMPRESSED | msg.sys_flag.compression_type();
+        }
+        let mut cmd = if msg.batch {
             let header = SendMessageRequestV2Header {
-                producer_group: self.options.group_name().to_string(),
+                producer_group: self.handle.options.group_name().to_string(),
                 topic: mq.topic.clone(),
                 queue_id: mq.queue_id,
-                sys_flag,
+                sys_flag: sys_flag.bits(),
                 born_timestamp: (OffsetDateTime::now_utc() - OffsetDateTime::unix_epoch())
                     .whole_milliseconds() as i64,
                 flag: msg.flag,
-                properties: msg.dump_properties(),
+                properties: msg.dump_properties()?,
                 reconsume_times: 0,
-                unit_mode: self.options.client_options.unit_mode,
+                unit_mode: self.handle.options.client_options.unit_mode,
                 max_reconsume_times: 0,
                 batch: msg.batch,
-                default_topic: self.options.create_topic_key.clone(),
-                default_topic_queue_nums: self.options.default_topic_queue_nums,
+                default_topic: self.handle.options.create_topic_key.clone(),
+                default_topic_queue_nums: self.handle.options.default_topic_queue_nums,
             };
-            RemotingCommand::with_header(RequestCode::SendMessageV2, header, body)
+            RemotingCommand::with_header(RequestCode::SendMessageV2, header, Vec::new())
         } else {
             let header = SendMessageRequestHeader {
-                producer_group: self.options.group_name().to_string(),
+                producer_group: self.handle.options.group_name().to_string(),
                 topic: mq.topic.clone(),
                 queue_id: mq.queue_id,
-                sys_flag,
+                sys_flag: sys_flag.bits(),
                 born_timestamp: (OffsetDateTime::now_utc() - OffsetDateTime::unix_epoch())
                     .whole_milliseconds() as i64,
                 flag: msg.flag,
-                properties: msg.dump_properties(),
+                properties: msg.dump_properties()?,
                 reconsume_times: 0,
-                unit_mode: self.options.client_options.unit_mode,
+                unit_mode: self.handle.options.client_options.unit_mode,
                 max_reconsume_times: 0,
                 batch: msg.batch,
-                default_topic: self.options.create_topic_key.clone(),
-                default_topic_queue_nums: self.options.default_topic_queue_nums,
+                default_topic: self.handle.options.create_topic_key.clone(),
+                default_topic_queue_nums: self.handle.options.default_topic_queue_nums,
             };
-            RemotingCommand::with_header(RequestCode::SendMessage, header, body)
+            RemotingCommand::with_header(RequestCode::SendMessage, header, Vec::new())
         };
+        cmd.body = body;
         Ok(cmd)
     }
 
     fn process_send_response(
         broker_name: &str,
+        addr: &str,
         cmd: RemotingCommand,
         msgs: &[Message],
     ) -> Result<SendResult, Error> {
@@ -352,98 +612,126 @@ impl Producer {
             ResponseCode::FlushSlaveTimeout => SendStatus::FlushDiskTimeout,
             ResponseCode::SlaveNotAvailable => SendStatus::SlaveNotAvailable,
             ResponseCode::Success => SendStatus::Ok,
-            _ => {
-                return Err(Error::ResponseError {
-                    code: cmd.code(),
-                    message: cmd.header.remark,
-                })
-            }
+            _ => return Err(cmd.into_broker_error(addr)),
         };
         let uniq_msg_id = msgs
             .iter()
-            .filter_map(|msg| msg.unique_key())
+            .flat_map(|msg| msg.unique_keys())
             .collect::<Vec<&str>>()
             .join(",");
-        let region_id = cmd
-            .header
-            .ext_fields
-            .get(Property::MSG_REGION)
-            .cloned()
-            .unwrap_or_else(|| "DefaultRegion".to_string());
-        let trace_on = cmd
-            .header
-            .ext_fields
-            .get(Property::TRACE_SWITCH)
-            .map(|prop| !prop.is_empty() && prop != "false")
-            .unwrap_or(false);
-        let queue_id: u32 = cmd.header.ext_fields["queueId"].parse().unwrap();
-        let queue_offset: i64 = cmd.header.ext_fields["queueOffset"].parse().unwrap();
+        let header: SendMessageResponseHeader = cmd.decode_header()?;
         let result = SendResult {
             status,
             msg_id: uniq_msg_id,
             message_queue: MessageQueue {
                 topic: msgs[0].topic.clone(),
                 broker_name: broker_name.to_string(),
-                queue_id,
+                queue_id: header.queue_id,
             },
-            queue_offset,
-            transaction_id: cmd.header.ext_fields.get("transactionId").cloned(),
-            offset_msg_id: cmd.header.ext_fields["msgId"].clone(),
-            region_id,
-            trace_on,
+            queue_offset: header.queue_offset,
+            transaction_id: header.transaction_id,
+            offset_msg_id: header.msg_id,
+            region_id: header.msg_region,
+            trace_on: header.trace_on,
         };
         Ok(result)
     }
 
     async fn select_message_queue(&self, msg: &Message) -> Result<Option<MessageQueue>, Error> {
         let topic = msg.topic();
-        let info = self.inner.lock().publish_info.get(topic).cloned();
+        let info = self.handle.inner.lock().publish_info.get(topic).cloned();
         let info = if info.is_some() {
             info
         } else {
             let (route_data, changed) = self
+                .handle
                 .client
                 .name_server
                 .update_topic_route_info(topic)
                 .await?;
-            self.client.update_publish_info(topic, route_data, changed);
-            self.inner.lock().publish_info.get(topic).cloned()
+            self.handle
+                .client
+                .update_publish_info(topic, route_data, changed);
+            self.handle.inner.lock().publish_info.get(topic).cloned()
         };
         let info = if info.is_some() {
             info
         } else {
             let (route_data, changed) = self
+                .handle
                 .client
                 .name_server
                 .update_topic_route_info_with_default(
                     topic,
-                    &self.options.create_topic_key,
-                    self.options.default_topic_queue_nums,
+                    &self.handle.options.create_topic_key,
+                    self.handle.options.default_topic_queue_nums,
                 )
                 .await?;
-            self.client.update_publish_info(topic, route_data, changed);
-            self.inner.lock().publish_info.get(topic).cloned()
+            self.handle
+                .client
+                .update_publish_info(topic, route_data, changed);
+            self.handle.inner.lock().publish_info.get(topic).cloned()
         };
         if let Some(info) = info {
             if info.have_topic_router_info && !info.message_queues.is_empty() {
-                return Ok(self.options.selector.select(msg, &info.message_queues));
+                return Ok(self
+                    .handle
+                    .options
+                    .selector
+                    .select(msg, &info.message_queues));
             }
         }
         Ok(None)
     }
 }
 
-impl Drop for Producer {
+impl Drop for ProducerHandle {
     fn drop(&mut self) {
-        self.shutdown();
+        if self.stop() {
+            self.client.release(self.options.group_name(), "");
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Producer, ProducerOptions, SendStatus};
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use byteorder::{BigEndian, WriteBytesExt};
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Framed;
+
+    use super::{LocalTransactionState, Producer, ProducerOptions, SendStatus};
+    use crate::client::ClientOptions;
     use crate::error::{ClientError, Error};
     use crate::message::{Message, MessageQueue};
+    use crate::protocol::compression::CompressionType;
+    use crate::protocol::{MqCodec, RemotingCommand, RequestCode};
+    use crate::route::TopicRouteData;
+    use crate::sys_flag::SysFlag;
+
+    #[test]
+    fn test_publish_route_changed() {
+        let route_data = |write_queue_nums: i32| {
+            let json = format!(
+                r#"{{"queueDatas":[{{"brokerName":"broker-a","readQueueNums":4,"writeQueueNums":{},"perm":6}}],"brokerDatas":[{{"cluster":"c","brokerName":"broker-a","brokerAddrs":{{"0":"127.0.0.1:10911"}}}}],"filterServerTable":{{}}}}"#,
+                write_queue_nums
+            );
+            TopicRouteData::from_bytes(json.as_bytes()).unwrap()
+        };
+        let producer = Producer::new().unwrap();
+        let mut inner = producer.handle.inner.lock();
+        assert!(inner.is_publish_route_changed("TopicA", &route_data(4)));
+        inner.update_topic_publish_info("TopicA", route_data(4).to_publish_info("TopicA"));
+        assert!(!inner.is_publish_route_changed("TopicA", &route_data(4)));
+        // a route cached before by another client of the topic
+        assert!(inner.is_publish_route_changed("TopicA", &route_data(8)));
+    }
 
     #[tokio::test]
     async fn test_producer_send_error_not_started() {
@@ -460,6 +748,20 @@ mod test {
         matches!(ret.unwrap_err(), Error::Client(ClientError::NotStarted));
     }
 
+    #[tokio::test]
+    async fn test_producer_clone() {
+        fn assert_handle<T: Clone + Send + Sync + 'static>() {}
+        assert_handle::<Producer>();
+
+        let producer = Producer::new().unwrap();
+        let handle = producer.clone();
+        assert!(Arc::ptr_eq(&producer.handle, &handle.handle));
+        drop(producer);
+        assert!(!handle.handle.released.load(Ordering::SeqCst));
+        handle.shutdown(Duration::from_secs(1)).await;
+        assert!(handle.handle.released.load(Ordering::SeqCst));
+    }
+
     #[tokio::test]
     async fn test_producer_send_message() {
         // tracing_subscriber::fmt::init();
@@ -671,5 +973,122 @@ mod test {
         };
         let cmd = producer.build_send_request(&mq, &mut msg).unwrap();
         assert_ne!(body, cmd.body);
+        // zlib along with its 5.x compression type
+        assert_eq!("769", cmd.ext_fields()["sysFlag"]);
+    }
+
+    #[test]
+    fn test_producer_options_compression_type() {
+        let mut options = ProducerOptions::new();
+        for compression in &[CompressionType::Lz4, CompressionType::Zstd] {
+            assert_eq!(
+                compression.is_supported(),
+                options.set_compression_type(*compression).is_ok()
+            );
+        }
+        assert!(options.set_compression_type(CompressionType::Zlib).is_ok());
+    }
+
+    /// Half message as stored in broker, with the given properties
+    fn encode_half_message(topic: &str, properties: &str) -> Vec<u8> {
+        let body = b"half";
+        let mut buf = Vec::new();
+        let store_size = 4 + 4 + 4 + 4 + 4 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 4 + 8;
+        let store_size = store_size + 4 + body.len() + 1 + topic.len() + 2 + properties.len();
+        buf.write_i32::<BigEndian>(store_size as i32).unwrap();
+        buf.write_i32::<BigEndian>(-626843481).unwrap(); // magic code
+        buf.write_i32::<BigEndian>(0).unwrap(); // body crc
+        buf.write_i32::<BigEndian>(0).unwrap(); // queue id
+        buf.write_i32::<BigEndian>(0).unwrap(); // flag
+        buf.write_i64::<BigEndian>(0).unwrap(); // queue offset
+        buf.write_i64::<BigEndian>(1024).unwrap(); // physic offset
+        buf.write_i32::<BigEndian>(SysFlag::TRANSACTION_PREPARED.bits())
+            .unwrap();
+        buf.write_i64::<BigEndian>(0).unwrap(); // born timestamp
+        buf.write_all(&[127, 0, 0, 1, 0, 0, 0, 0]).unwrap(); // born host
+        buf.write_i64::<BigEndian>(0).unwrap(); // store timestamp
+        buf.write_all(&[127, 0, 0, 1, 0, 0, 0, 0]).unwrap(); // store host
+        buf.write_i32::<BigEndian>(0).unwrap(); // reconsume times
+        buf.write_i64::<BigEndian>(0).unwrap(); // prepared transaction offset
+        buf.write_i32::<BigEndian>(body.len() as i32).unwrap();
+        buf.write_all(body).unwrap();
+        buf.write_u8(topic.len() as u8).unwrap();
+        buf.write_all(topic.as_bytes()).unwrap();
+        buf.write_i16::<BigEndian>(properties.len() as i16).unwrap();
+        buf.write_all(properties.as_bytes()).unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_producer_check_transaction_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (end_tx, mut end_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, MqCodec::default());
+            // wait for the client to connect
+            framed.next().await.unwrap().unwrap();
+            for (transaction_id, uniq_key) in [("tx-commit", "UNIQ-1"), ("tx-unknown", "UNIQ-2")] {
+                let mut ext_fields = HashMap::new();
+                ext_fields.insert("tranStateTableOffset".to_string(), "7".to_string());
+                ext_fields.insert("commitLogOffset".to_string(), "1024".to_string());
+                ext_fields.insert("msgId".to_string(), "OFFSET-ID".to_string());
+                ext_fields.insert("transactionId".to_string(), transaction_id.to_string());
+                let properties = format!(
+                    "PGROUP\u{1}TX_CHECK_GROUP\u{2}UNIQ_KEY\u{1}{}\u{2}",
+                    uniq_key
+                );
+                let mut req = RemotingCommand::new(
+                    RequestCode::CheckTransactionState.into(),
+                    0,
+                    String::new(),
+                    ext_fields,
+                    encode_half_message("TX_TOPIC", &properties),
+                );
+                req.mark_oneway();
+                framed.send(req).await.unwrap();
+                let end = framed.next().await.unwrap().unwrap();
+                let _ = end_tx.send(end);
+            }
+        });
+
+        let mut client_options = ClientOptions::new("TX_CHECK_GROUP");
+        client_options.set_instance_name("test_producer_check_transaction_state");
+        let mut options = ProducerOptions::with_client_options(client_options);
+        options.set_name_server(vec!["localhost:9876".to_string()]);
+        let producer = Producer::with_options(options).unwrap();
+        producer.set_transaction_checker(|msg| {
+            assert_eq!("TX_TOPIC", msg.message().topic());
+            match msg.message().transaction_id() {
+                "tx-commit" => LocalTransactionState::CommitMessage,
+                _ => LocalTransactionState::Unknown,
+            }
+        });
+        producer.start();
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        producer
+            .handle
+            .client
+            .invoke_oneway(&addr, cmd)
+            .await
+            .unwrap();
+
+        let end = end_rx.recv().await.unwrap();
+        assert!(end.code() == RequestCode::EndTransaction);
+        assert!(end.is_oneway());
+        let fields = &end.header.ext_fields;
+        assert_eq!("TX_CHECK_GROUP", fields["producerGroup"]);
+        assert_eq!("8", fields["commitOrRollback"]);
+        assert_eq!("true", fields["fromTransactionCheck"]);
+        assert_eq!("UNIQ-1", fields["msgId"]);
+        assert_eq!("tx-commit", fields["transactionId"]);
+        assert_eq!("7", fields["tranStateTableOffset"]);
+        assert_eq!("1024", fields["commitLogOffset"]);
+
+        let end = end_rx.recv().await.unwrap();
+        assert_eq!("0", end.header.ext_fields["commitOrRollback"]);
+        assert_eq!("UNIQ-2", end.header.ext_fields["msgId"]);
+        producer.shutdown(Duration::from_secs(1)).await;
     }
 }
@@ -0,0 +1,187 @@
+use std::io::{self, Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+#[cfg(feature = "lz4")]
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+
+use crate::sys_flag::SysFlag;
+
+/// Codec of compressed bodies, told apart by the compression type bits of the sys flag
+/// since 5.x. Bodies flagged `COMPRESSED` without them are zlib compressed by 4.x clients,
+/// which ignore the bits, so zlib bodies stay readable by both
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionType {
+    #[default]
+    Zlib,
+    Lz4,
+    Zstd,
+}
+
+impl CompressionType {
+    /// Compression type of a body flagged `COMPRESSED`
+    pub(crate) fn from_sys_flag(sys_flag: SysFlag) -> Self {
+        match sys_flag.compression_type() {
+            SysFlag::COMPRESSION_LZ4 => CompressionType::Lz4,
+            SysFlag::COMPRESSION_ZSTD => CompressionType::Zstd,
+            _ => CompressionType::Zlib,
+        }
+    }
+
+    /// Sys flag of bodies compressed with the type
+    pub(crate) fn sys_flag(self) -> SysFlag {
+        SysFlag::COMPRESSED
+            | match self {
+                CompressionType::Zlib => SysFlag::COMPRESSION_ZLIB,
+                CompressionType::Lz4 => SysFlag::COMPRESSION_LZ4,
+                CompressionType::Zstd => SysFlag::COMPRESSION_ZSTD,
+            }
+    }
+
+    /// Whether bodies can be compressed and decompressed with the type, zlib is built in,
+    /// lz4 and zstd behind the `lz4` and `zstd` features
+    pub fn is_supported(self) -> bool {
+        match self {
+            CompressionType::Zlib => true,
+            CompressionType::Lz4 => cfg!(feature = "lz4"),
+            CompressionType::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    #[cfg(not(all(feature = "lz4", feature = "zstd")))]
+    fn unsupported(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{:?} compression is not supported", self),
+        )
+    }
+}
+
+/// Compress message body with `compression`
+#[cfg(test)]
+pub fn compress(body: &[u8], compression: CompressionType, level: u32) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    compress_into(body, compression, level, &mut buf)?;
+    Ok(buf)
+}
+
+/// Compress message body with `compression`, appending it to `buf`
+pub fn compress_into(
+    body: &[u8],
+    compression: CompressionType,
+    level: u32,
+    buf: &mut Vec<u8>,
+) -> io::Result<()> {
+    match compression {
+        CompressionType::Zlib => {
+            let mut encoder = ZlibEncoder::new(buf, Compression::new(level));
+            encoder.write_all(body)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        // frame format, same as `LZ4FrameOutputStream` of the Java client, which has no level
+        #[cfg(feature = "lz4")]
+        CompressionType::Lz4 => {
+            let mut encoder = FrameEncoder::new(buf);
+            encoder.write_all(body)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[cfg(feature = "zstd")]
+        CompressionType::Zstd => zstd::stream::copy_encode(body, buf, level as i32),
+        #[cfg(not(all(feature = "lz4", feature = "zstd")))]
+        _ => Err(compression.unsupported()),
+    }
+}
+
+/// Decompress message body flagged `COMPRESSED`
+pub fn decompress(body: &[u8], compression: CompressionType) -> io::Result<Vec<u8>> {
+    match compression {
+        CompressionType::Zlib => {
+            let mut decoder = ZlibDecoder::new(body);
+            let mut buf = Vec::new();
+            decoder.read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        #[cfg(feature = "lz4")]
+        CompressionType::Lz4 => {
+            let mut decoder = FrameDecoder::new(body);
+            let mut buf = Vec::new();
+            decoder.read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        #[cfg(feature = "zstd")]
+        CompressionType::Zstd => zstd::stream::decode_all(body),
+        #[cfg(not(all(feature = "lz4", feature = "zstd")))]
+        _ => Err(compression.unsupported()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress, decompress, CompressionType};
+    use crate::sys_flag::SysFlag;
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let body = b"Hello RocketMQ".repeat(100);
+        let compressed = compress(&body, CompressionType::Zlib, 5).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(
+            body,
+            decompress(&compressed, CompressionType::Zlib).unwrap()
+        );
+        assert!(decompress(b"not compressed", CompressionType::Zlib).is_err());
+        for compression in &[CompressionType::Lz4, CompressionType::Zstd] {
+            assert_eq!(
+                compression.is_supported(),
+                compress(&body, *compression, 5).is_ok()
+            );
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_roundtrip() {
+        let body = b"Hello RocketMQ".repeat(100);
+        let compressed = compress(&body, CompressionType::Lz4, 5).unwrap();
+        assert!(compressed.len() < body.len());
+        // frame magic number
+        assert_eq!([0x04, 0x22, 0x4d, 0x18], compressed[..4]);
+        assert_eq!(body, decompress(&compressed, CompressionType::Lz4).unwrap());
+        assert!(decompress(b"not compressed", CompressionType::Lz4).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_roundtrip() {
+        let body = b"Hello RocketMQ".repeat(100);
+        let compressed = compress(&body, CompressionType::Zstd, 5).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(
+            body,
+            decompress(&compressed, CompressionType::Zstd).unwrap()
+        );
+        assert!(decompress(b"not compressed", CompressionType::Zstd).is_err());
+    }
+
+    #[test]
+    fn test_compression_type_sys_flag() {
+        // 4.x clients set no compression type
+        assert_eq!(
+            CompressionType::Zlib,
+            CompressionType::from_sys_flag(SysFlag::COMPRESSED)
+        );
+        for compression in &[
+            CompressionType::Zlib,
+            CompressionType::Lz4,
+            CompressionType::Zstd,
+        ] {
+            let sys_flag = compression.sys_flag() | SysFlag::TRANSACTION_PREPARED;
+            assert!(sys_flag.is_compressed());
+            assert_eq!(*compression, CompressionType::from_sys_flag(sys_flag));
+        }
+        assert_eq!(0x301, CompressionType::Zlib.sys_flag().bits());
+    }
+}
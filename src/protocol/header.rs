@@ -1,21 +1,25 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{Cursor, Read, Write};
+use std::io::Cursor;
 use std::str::FromStr;
 
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::BufMut;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::Error;
+use super::read_bytes;
+use crate::error::Error;
 
 pub const HEADER_FIXED_LENGTH: usize = 4;
+// brokers send a few dozens of ext fields at most
+const MAX_EXT_FIELDS: usize = 1024;
 
+// variant names are the language names on the wire
+#[allow(clippy::upper_case_acronyms)]
 #[repr(u8)]
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoPrimitive, TryFromPrimitive,
-)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, IntoPrimitive, TryFromPrimitive)]
 pub enum LanguageCode {
     JAVA = 0,
     CPP = 1,
@@ -29,6 +33,15 @@ pub enum LanguageCode {
     GO = 9,
     PHP = 10,
     OMS = 11,
+    /// Only recognized by RocketMQ 5.x brokers
+    RUST = 12,
+}
+
+impl LanguageCode {
+    /// Unknown language codes fall back to `OTHER`
+    pub fn from_u8(code: u8) -> Self {
+        Self::try_from(code).unwrap_or(Self::OTHER)
+    }
 }
 
 impl FromStr for LanguageCode {
@@ -47,6 +60,7 @@ impl FromStr for LanguageCode {
             "GO" => Self::GO,
             "PHP" => Self::PHP,
             "OMS" => Self::OMS,
+            "RUST" => Self::RUST,
             _ => Self::OTHER,
         };
         Ok(code)
@@ -68,27 +82,65 @@ impl fmt::Display for LanguageCode {
             LanguageCode::GO => write!(f, "GO"),
             LanguageCode::PHP => write!(f, "PHP"),
             LanguageCode::OMS => write!(f, "OMS"),
+            LanguageCode::RUST => write!(f, "RUST"),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for LanguageCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.parse().unwrap_or(LanguageCode::OTHER))
+    }
+}
+
+/// Field names follow `RemotingCommand` of Java, which omits null `remark` and `extFields`,
+/// other fields it serializes like `serializeTypeCurrentRPC` are ignored
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Header {
     pub code: i16,
     pub language: LanguageCode,
     pub version: i16,
     pub opaque: i32,
     pub flag: i32,
-    #[serde(default)]
+    #[serde(
+        default,
+        skip_serializing_if = "String::is_empty",
+        deserialize_with = "null_as_default"
+    )]
     pub remark: String,
-    #[serde(default, rename = "extFields")]
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        deserialize_with = "null_as_default"
+    )]
     pub ext_fields: HashMap<String, String>,
 }
 
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 pub trait HeaderCodec {
     fn codec_type(&self) -> HeaderCodecType;
     fn encode(&self, header: &Header) -> Result<Vec<u8>, Error>;
     fn decode(&self, buf: &[u8]) -> Result<Header, Error>;
+
+    /// Length of the encoded header, `None` if it can't be known without encoding
+    fn encoded_len(&self, _header: &Header) -> Option<usize> {
+        None
+    }
+
+    /// Write the encoded header into `buf`
+    fn encode_to<B: BufMut>(&self, header: &Header, buf: &mut B) -> Result<(), Error> {
+        buf.put_slice(&self.encode(header)?);
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -111,18 +163,10 @@ impl HeaderCodec for JsonHeaderCodec {
 pub struct RocketMQHeaderCodec;
 
 impl RocketMQHeaderCodec {
-    fn encode_map(&self, map: &HashMap<String, String>) -> Result<Vec<u8>, Error> {
-        let mut buf = Vec::new();
-        if map.is_empty() {
-            return Ok(buf);
-        }
-        for (key, value) in map.iter() {
-            buf.write_i16::<BigEndian>(key.len() as _)?;
-            buf.write_all(key.as_bytes())?;
-            buf.write_i32::<BigEndian>(value.len() as _)?;
-            buf.write_all(value.as_bytes())?;
-        }
-        Ok(buf)
+    fn encoded_map_len(map: &HashMap<String, String>) -> usize {
+        map.iter()
+            .map(|(key, value)| 2 + key.len() + 4 + value.len())
+            .sum()
     }
 }
 
@@ -132,30 +176,39 @@ impl HeaderCodec for RocketMQHeaderCodec {
     }
 
     fn encode(&self, header: &Header) -> Result<Vec<u8>, Error> {
-        let ext_bytes = self.encode_map(&header.ext_fields)?;
-        let length = HEADER_FIXED_LENGTH + header.remark.len() + ext_bytes.len();
-        let mut buf = Vec::with_capacity(length);
+        let mut buf = Vec::with_capacity(self.encoded_len(header).unwrap_or_default());
+        self.encode_to(header, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn encoded_len(&self, header: &Header) -> Option<usize> {
+        // code 2, language 1, version 2, opaque 4, flag 4, remark length 4, ext fields length 4
+        Some(21 + header.remark.len() + Self::encoded_map_len(&header.ext_fields))
+    }
+
+    fn encode_to<B: BufMut>(&self, header: &Header, buf: &mut B) -> Result<(), Error> {
         // request code, 2 bytes
-        buf.write_i16::<BigEndian>(header.code as _)?;
+        buf.put_i16(header.code);
         // language flag, 1 byte
-        buf.write_u8(LanguageCode::OTHER.into())?;
+        buf.put_u8(header.language.into());
         // version flag, 2 bytes
-        buf.write_i16::<BigEndian>(header.version as _)?;
+        buf.put_i16(header.version);
         // opaque flag, 4 bytes
-        buf.write_i32::<BigEndian>(header.opaque)?;
+        buf.put_i32(header.opaque);
         // request flag, 4 bytes
-        buf.write_i32::<BigEndian>(header.flag as _)?;
+        buf.put_i32(header.flag);
         // remark length flag, 4 bytes
-        buf.write_i32::<BigEndian>(header.remark.len() as _)?;
-        if !header.remark.is_empty() {
-            // write remark
-            buf.write_all(header.remark.as_bytes())?;
-        }
-        buf.write_i32::<BigEndian>(ext_bytes.len() as _)?;
-        if !ext_bytes.is_empty() {
-            buf.write_all(&ext_bytes)?;
+        buf.put_i32(header.remark.len() as _);
+        // write remark
+        buf.put_slice(header.remark.as_bytes());
+        buf.put_i32(Self::encoded_map_len(&header.ext_fields) as _);
+        for (key, value) in header.ext_fields.iter() {
+            buf.put_i16(key.len() as _);
+            buf.put_slice(key.as_bytes());
+            buf.put_i32(value.len() as _);
+            buf.put_slice(value.as_bytes());
         }
-        Ok(buf)
+        Ok(())
     }
 
     fn decode(&self, buf: &[u8]) -> Result<Header, Error> {
@@ -163,8 +216,7 @@ impl HeaderCodec for RocketMQHeaderCodec {
         // request code
         let code = rdr.read_i16::<BigEndian>()?;
         // language flag
-        let language = LanguageCode::try_from(rdr.read_u8()?)
-            .map_err(|err| Error::InvalidHeader(format!("invalid language: {:?}", err)))?;
+        let language = LanguageCode::from_u8(rdr.read_u8()?);
         // version flag
         let version = rdr.read_i16::<BigEndian>()?;
         // opaque falg
@@ -172,38 +224,26 @@ impl HeaderCodec for RocketMQHeaderCodec {
         // request flag
         let flag = rdr.read_i32::<BigEndian>()?;
         // remark
-        let remark_len = rdr.read_i32::<BigEndian>()? as usize;
-        let remark = if remark_len > 0 {
-            let mut remark_bytes = vec![0; remark_len];
-            rdr.read_exact(&mut remark_bytes)?;
-            String::from_utf8(remark_bytes)?
-        } else {
-            String::new()
-        };
-        // ext_fields
-        let ext_len = rdr.read_i32::<BigEndian>()? as usize;
-        let ext_fields = if ext_len > 0 {
-            let mut map = HashMap::new();
-            let mut bytes_read = 0;
-            while bytes_read < ext_len {
-                let key_len = rdr.read_i16::<BigEndian>()? as usize;
-                let mut key_bytes = vec![0; key_len];
-                rdr.read_exact(&mut key_bytes)?;
-                bytes_read += 2 + key_len;
-
-                let val_len = rdr.read_i32::<BigEndian>()? as usize;
-                let mut val_bytes = vec![0; val_len];
-                rdr.read_exact(&mut val_bytes)?;
-                bytes_read += 4 + val_len;
-
-                let key = String::from_utf8(key_bytes)?;
-                let val = String::from_utf8(val_bytes)?;
-                map.insert(key, val);
+        let remark_len = rdr.read_i32::<BigEndian>()?;
+        let remark = String::from_utf8(read_bytes(&mut rdr, remark_len)?)?;
+        // ext_fields, entries must not exceed the declared length
+        let ext_len = rdr.read_i32::<BigEndian>()?;
+        let ext_bytes = read_bytes(&mut rdr, ext_len)?;
+        let mut ext_rdr = Cursor::new(&ext_bytes[..]);
+        let mut ext_fields = HashMap::new();
+        while (ext_rdr.position() as usize) < ext_bytes.len() {
+            if ext_fields.len() >= MAX_EXT_FIELDS {
+                return Err(Error::InvalidHeader(format!(
+                    "too many ext fields, max: {}",
+                    MAX_EXT_FIELDS
+                )));
             }
-            map
-        } else {
-            HashMap::new()
-        };
+            let key_len = ext_rdr.read_i16::<BigEndian>()?;
+            let key = String::from_utf8(read_bytes(&mut ext_rdr, key_len as i32)?)?;
+            let val_len = ext_rdr.read_i32::<BigEndian>()?;
+            let val = String::from_utf8(read_bytes(&mut ext_rdr, val_len)?)?;
+            ext_fields.insert(key, val);
+        }
         let header = Header {
             code,
             language,
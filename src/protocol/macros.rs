@@ -0,0 +1,65 @@
+/// Define a command header along with its conversion to and from `ext_fields`.
+///
+/// Each field is followed by its key in `ext_fields`, fields marked `(default)` fall back
+/// to their default value when missing instead of failing the decoding.
+///
+/// ```ignore
+/// command_header! {
+///     #[derive(Debug, Clone)]
+///     pub struct GetMaxOffsetRequestHeader {
+///         pub topic: String => "topic",
+///         pub queue_id: u32 => "queueId",
+///         pub committed: bool => "committed" (default),
+///     }
+/// }
+/// ```
+macro_rules! command_header {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                pub $field:ident: $ty:ty => $key:literal $(($default:ident))?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::protocol::request::EncodeRequestHeader for $name {
+            fn encode(self) -> ::std::collections::HashMap<String, String> {
+                let mut map = ::std::collections::HashMap::new();
+                $(
+                    map.insert($key.to_string(), self.$field.to_string());
+                )*
+                map
+            }
+        }
+
+        impl $crate::protocol::DecodeHeader for $name {
+            fn decode(
+                ext_fields: &::std::collections::HashMap<String, String>,
+            ) -> Result<Self, $crate::Error> {
+                Ok(Self {
+                    $(
+                        $field: decode_field!(ext_fields, $key $(, $default)?),
+                    )*
+                })
+            }
+        }
+    };
+}
+
+macro_rules! decode_field {
+    ($ext_fields:ident, $key:literal) => {
+        $crate::protocol::parse_field($ext_fields, $key)?
+    };
+    ($ext_fields:ident, $key:literal, default) => {
+        $crate::protocol::parse_field_or_default($ext_fields, $key)?
+    };
+}
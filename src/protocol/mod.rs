@@ -1,29 +1,89 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io::Read;
+use std::io::{Cursor, Read};
+use std::str::FromStr;
 
 use byteorder::{BigEndian, ReadBytesExt};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
+#[macro_use]
+mod macros;
+pub(crate) mod compression;
 mod header;
 pub mod request;
 pub mod response;
 
-use crate::Error;
-use header::{Header, HeaderCodec, LanguageCode, HEADER_FIXED_LENGTH};
-pub use header::{HeaderCodecType, JsonHeaderCodec, RocketMQHeaderCodec};
+use crate::error::{Error, FrameError};
+use header::{Header, HeaderCodec, HEADER_FIXED_LENGTH};
+pub use header::{HeaderCodecType, JsonHeaderCodec, LanguageCode, RocketMQHeaderCodec};
 use request::EncodeRequestHeader;
 pub use request::RequestCode;
 pub use response::ResponseCode;
 
+/// Version reported to brokers, `V4_3_0` of `MQVersion` in Java client
+pub const MQ_VERSION: i16 = 317;
 const _LENGTH: usize = 4;
+/// Same as the default `com.rocketmq.remoting.frameMaxLength` of Java client
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
 const RESPONSE_TYPE: i32 = 1;
+const RPC_ONEWAY: i32 = 1 << 1;
+
+/// Typed header parsed from `ext_fields` of a command
+pub trait DecodeHeader: Sized {
+    fn decode(ext_fields: &HashMap<String, String>) -> Result<Self, Error>;
+}
+
+/// Get a required field of `ext_fields` and parse it
+pub(crate) fn parse_field<T: FromStr>(
+    ext_fields: &HashMap<String, String>,
+    name: &str,
+) -> Result<T, Error> {
+    let value = ext_fields
+        .get(name)
+        .ok_or_else(|| Error::InvalidHeader(format!("missing field {}", name)))?;
+    value
+        .parse()
+        .map_err(|_| Error::InvalidHeader(format!("invalid field {}: {}", name, value)))
+}
+
+/// Get an optional field of `ext_fields` and parse it, falls back to default if missing
+pub(crate) fn parse_field_or_default<T: FromStr + Default>(
+    ext_fields: &HashMap<String, String>,
+    name: &str,
+) -> Result<T, Error> {
+    if ext_fields.contains_key(name) {
+        parse_field(ext_fields, name)
+    } else {
+        Ok(T::default())
+    }
+}
+
+/// Read a length prefixed field, a corrupted length must not allocate more than the buffer holds
+pub(crate) fn read_bytes(rdr: &mut Cursor<&[u8]>, len: i32) -> Result<Vec<u8>, Error> {
+    if len < 0 {
+        return Err(FrameError::NegativeLength(len).into());
+    }
+    let len = len as usize;
+    let remaining = rdr.get_ref().len().saturating_sub(rdr.position() as usize);
+    if len > remaining {
+        return Err(FrameError::LengthMismatch {
+            declared: len,
+            remaining,
+        }
+        .into());
+    }
+    let mut bytes = vec![0; len];
+    rdr.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RemotingCommand {
     pub(crate) header: Header,
-    pub(crate) body: Vec<u8>,
+    pub(crate) body: Bytes,
+    // the version was set for this command, the one of `ClientOptions` isn't applied
+    version_overridden: bool,
 }
 
 impl RemotingCommand {
@@ -38,13 +98,14 @@ impl RemotingCommand {
             header: Header {
                 code,
                 language: LanguageCode::OTHER,
-                version: 317,
+                version: MQ_VERSION,
                 opaque: 0,
                 flag,
                 remark,
                 ext_fields,
             },
-            body,
+            body: body.into(),
+            version_overridden: false,
         }
     }
 
@@ -57,7 +118,28 @@ impl RemotingCommand {
         Self::new(code.into(), 0, String::new(), ext_fields, body)
     }
 
-    fn encode_codec_type(source: i32, codec: impl HeaderCodec) -> [u8; 4] {
+    pub fn decode_header<H: DecodeHeader>(&self) -> Result<H, Error> {
+        H::decode(&self.header.ext_fields)
+    }
+
+    /// Create a response command, the opaque should be set to the one of request
+    pub fn response(code: ResponseCode, remark: String, body: Vec<u8>) -> Self {
+        let mut cmd = Self::new(code.into(), 0, remark, HashMap::new(), body);
+        cmd.mark_response();
+        cmd
+    }
+
+    /// Turn a failed response of the broker or name server at `addr` into a broker error
+    /// carrying its code and remark
+    pub fn into_broker_error(self, addr: &str) -> Error {
+        Error::Broker {
+            code: self.code(),
+            remark: self.header.remark,
+            addr: addr.to_string(),
+        }
+    }
+
+    fn encode_codec_type(source: i32, codec: &impl HeaderCodec) -> [u8; 4] {
         let codec_type: u8 = codec.codec_type().into();
         [
             codec_type,
@@ -71,31 +153,102 @@ impl RemotingCommand {
         self.header.code
     }
 
-    pub fn is_response_type(&self) -> bool {
+    pub fn version(&self) -> i16 {
+        self.header.version
+    }
+
+    pub fn remark(&self) -> &str {
+        &self.header.remark
+    }
+
+    pub fn ext_fields(&self) -> &HashMap<String, String> {
+        &self.header.ext_fields
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Override the version reported to server for this command only,
+    /// otherwise the version configured in `ClientOptions` is used
+    pub fn set_version(&mut self, version: i16) {
+        self.header.version = version;
+        self.version_overridden = true;
+    }
+
+    /// Whether the version was set by `set_version`
+    pub(crate) fn is_version_overridden(&self) -> bool {
+        self.version_overridden
+    }
+
+    pub fn opaque(&self) -> i32 {
+        self.header.opaque
+    }
+
+    /// Stamp the opaque, requests get it allocated by the connection at send time
+    pub fn set_opaque(&mut self, opaque: i32) {
+        self.header.opaque = opaque
+    }
+
+    pub fn is_response(&self) -> bool {
         self.header.flag & RESPONSE_TYPE == RESPONSE_TYPE
     }
 
-    pub fn mark_response_type(&mut self) {
+    pub fn mark_response(&mut self) {
         self.header.flag |= RESPONSE_TYPE
     }
 
-    fn encode_into(&self, wtr: &mut BytesMut, codec: impl HeaderCodec) -> Result<(), Error> {
-        let header_bytes = codec.encode(&self.header)?;
-        let header_len = header_bytes.len();
+    pub fn is_oneway(&self) -> bool {
+        self.header.flag & RPC_ONEWAY == RPC_ONEWAY
+    }
+
+    pub fn mark_oneway(&mut self) {
+        self.header.flag |= RPC_ONEWAY
+    }
+
+    /// Encode the frame into `buf` without intermediate buffers when the header codec
+    /// knows its encoded length, returns the frame length
+    pub fn encode_into<B: BufMut>(
+        &self,
+        buf: &mut B,
+        codec: impl HeaderCodec,
+    ) -> Result<usize, Error> {
+        let (header_len, header_bytes) = match codec.encoded_len(&self.header) {
+            Some(len) => (len, None),
+            None => {
+                let bytes = codec.encode(&self.header)?;
+                (bytes.len(), Some(bytes))
+            }
+        };
         let length = HEADER_FIXED_LENGTH + header_len + self.body.len();
-        wtr.put_i32(length as i32);
-        let header_len_with_codec = Self::encode_codec_type(header_len as i32, codec);
-        wtr.put(&header_len_with_codec[..]);
-        wtr.put(&header_bytes[..]);
-        if !self.body.is_empty() {
-            wtr.put(&self.body[..]);
+        buf.put_i32(length as i32);
+        buf.put_slice(&Self::encode_codec_type(header_len as i32, &codec));
+        match header_bytes {
+            Some(bytes) => buf.put_slice(&bytes),
+            None => codec.encode_to(&self.header, buf)?,
         }
-        Ok(())
+        buf.put_slice(&self.body);
+        Ok(HEADER_FIXED_LENGTH + length)
     }
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct MqCodec;
+pub(crate) struct MqCodec {
+    // frames longer than it are rejected instead of buffered
+    max_frame_length: usize,
+}
+
+impl MqCodec {
+    pub fn new(max_frame_length: usize) -> Self {
+        Self { max_frame_length }
+    }
+}
+
+impl Default for MqCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_LENGTH)
+    }
+}
 
 impl Encoder<RemotingCommand> for MqCodec {
     type Error = Error;
@@ -117,14 +270,31 @@ impl Decoder for MqCodec {
             return Ok(None);
         }
         let length = buf.read_i32::<BigEndian>()?;
-        if buf.len() < length as usize {
-            src.reserve(length as usize);
+        if length < HEADER_FIXED_LENGTH as i32 {
+            return Err(FrameError::ShortFrame(length).into());
+        }
+        let length = length as usize;
+        if length > self.max_frame_length {
+            return Err(FrameError::TooLong(length).into());
+        }
+        if buf.len() < length {
+            // wait for the rest of the frame, which may arrive in several reads
+            src.reserve(length - buf.len());
             return Ok(None);
         }
-        let origin_header_len = buf.read_i32::<BigEndian>()?;
-        let header_len = origin_header_len & 0xffffff;
-        let mut header_buf = vec![0; header_len as usize];
-        buf.read_exact(&mut header_buf)?;
+        // slice the frame out of the read buffer so the body shares its memory instead of copying
+        let mut frame = src.split_to(HEADER_FIXED_LENGTH + length).freeze();
+        frame.advance(HEADER_FIXED_LENGTH);
+        let origin_header_len = frame.get_i32();
+        let header_len = (origin_header_len & 0xffffff) as usize;
+        if header_len > length - HEADER_FIXED_LENGTH {
+            return Err(FrameError::LengthMismatch {
+                declared: header_len,
+                remaining: length - HEADER_FIXED_LENGTH,
+            }
+            .into());
+        }
+        let header_buf = frame.split_to(header_len);
         let codec_type = HeaderCodecType::try_from(((origin_header_len >> 24) & 0xff) as u8)
             .map_err(|_| Error::InvalidHeaderCodec)?;
         let header = match codec_type {
@@ -137,29 +307,50 @@ impl Decoder for MqCodec {
                 codec.decode(&header_buf)?
             }
         };
-        let body_len = length as usize - HEADER_FIXED_LENGTH - header_len as usize;
-        let body = {
-            if body_len > 0 {
-                let mut body_buf = vec![0; body_len];
-                buf.read_exact(&mut body_buf)?;
-                body_buf
-            } else {
-                Vec::new()
-            }
-        };
-        src.advance(HEADER_FIXED_LENGTH + length as usize);
-        Ok(Some(RemotingCommand { header, body }))
+        // the rest of the frame is body
+        let body = frame;
+        Ok(Some(RemotingCommand {
+            header,
+            body,
+            version_overridden: false,
+        }))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::header::{JsonHeaderCodec, RocketMQHeaderCodec};
-    use super::{MqCodec, RemotingCommand};
-    use bytes::BytesMut;
+    use super::header::{HeaderCodec, JsonHeaderCodec, RocketMQHeaderCodec};
+    use super::response::PullMessageResponseHeader;
+    use super::{LanguageCode, MqCodec, RemotingCommand, RequestCode, ResponseCode, MQ_VERSION};
+    use crate::error::{Error, FrameError};
+    use bytes::{BufMut, BytesMut};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::collections::HashMap;
+    use std::convert::TryFrom;
     use tokio_util::codec::{Decoder, Encoder};
 
+    #[test]
+    fn test_json_header_java_compatible() {
+        // header sent by Java broker
+        let json = br#"{"code":0,"extFields":{"queueId":"1","msgId":"ABC"},"flag":1,"language":"JAVA","opaque":3,"serializeTypeCurrentRPC":"JSON","version":395}"#;
+        let header = JsonHeaderCodec.decode(json).unwrap();
+        assert_eq!(LanguageCode::JAVA, header.language);
+        assert_eq!(395, header.version);
+        assert!(header.remark.is_empty());
+        assert_eq!("1", header.ext_fields["queueId"]);
+
+        let json =
+            br#"{"code":1,"flag":1,"language":"JAVA","opaque":3,"remark":null,"version":395}"#;
+        let header = JsonHeaderCodec.decode(json).unwrap();
+        assert!(header.remark.is_empty());
+        assert!(header.ext_fields.is_empty());
+
+        let encoded = String::from_utf8(JsonHeaderCodec.encode(&header).unwrap()).unwrap();
+        assert!(!encoded.contains("remark"));
+        assert!(!encoded.contains("extFields"));
+    }
+
     #[test]
     fn test_remote_command_json_encode_decode_roundtrip() {
         let mut fields = HashMap::new();
@@ -169,7 +360,7 @@ mod test {
             RemotingCommand::new(10, 0, "remark".to_string(), fields, b"Hello World".to_vec());
         let mut encoded = BytesMut::new();
         cmd.encode_into(&mut encoded, JsonHeaderCodec).unwrap();
-        let mut decoder = MqCodec;
+        let mut decoder = MqCodec::default();
         let decoded = decoder.decode(&mut encoded).unwrap().unwrap();
         assert_eq!(cmd, decoded);
     }
@@ -183,7 +374,7 @@ mod test {
             RemotingCommand::new(10, 0, "remark".to_string(), fields, b"Hello World".to_vec());
         let mut encoded = BytesMut::new();
         cmd.encode_into(&mut encoded, RocketMQHeaderCodec).unwrap();
-        let mut decoder = MqCodec;
+        let mut decoder = MqCodec::default();
         let decoded = decoder.decode(&mut encoded).unwrap().unwrap();
         assert_eq!(cmd, decoded);
     }
@@ -195,13 +386,242 @@ mod test {
         fields.insert("offset".to_string(), "456".to_string());
         let cmd =
             RemotingCommand::new(10, 0, "remark".to_string(), fields, b"Hello World".to_vec());
-        let mut codec = MqCodec;
+        let mut codec = MqCodec::default();
         let mut encoded = BytesMut::new();
         codec.encode(cmd.clone(), &mut encoded).unwrap();
         let decoded = codec.decode(&mut encoded).unwrap().unwrap();
         assert_eq!(cmd, decoded);
     }
 
+    #[test]
+    fn test_remote_command_encode_into_buf_mut() {
+        let mut fields = HashMap::new();
+        fields.insert("messageId".to_string(), "123".to_string());
+        let cmd = RemotingCommand::new(10, 0, "remark".to_string(), fields, b"body".to_vec());
+        let header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+        assert_eq!(
+            Some(header.len()),
+            RocketMQHeaderCodec.encoded_len(&cmd.header)
+        );
+
+        let mut buf = Vec::new();
+        let len = cmd.encode_into(&mut buf, RocketMQHeaderCodec).unwrap();
+        assert_eq!(buf.len(), len);
+        assert_eq!(&header[..], &buf[8..8 + header.len()]);
+
+        let mut json_buf = Vec::new();
+        let json_len = cmd.encode_into(&mut json_buf, JsonHeaderCodec).unwrap();
+        assert_eq!(json_buf.len(), json_len);
+        let mut decoded = BytesMut::from(&json_buf[..]);
+        assert_eq!(
+            cmd,
+            MqCodec::default().decode(&mut decoded).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remote_command_codec_partial_and_multiple_frames() {
+        let cmd1 = RemotingCommand::new(10, 0, String::new(), HashMap::new(), b"first".to_vec());
+        let cmd2 = RemotingCommand::new(11, 0, String::new(), HashMap::new(), b"second".to_vec());
+        let mut codec = MqCodec::default();
+        let mut encoded = BytesMut::new();
+        codec.encode(cmd1.clone(), &mut encoded).unwrap();
+        codec.encode(cmd2.clone(), &mut encoded).unwrap();
+
+        // Feed the bytes in two parts, the first part ends in the middle of the first frame
+        let mut rest = encoded.split_off(10);
+        assert!(codec.decode(&mut encoded).unwrap().is_none());
+        encoded.unsplit(rest.split());
+        assert_eq!(cmd1, codec.decode(&mut encoded).unwrap().unwrap());
+        assert_eq!(cmd2, codec.decode(&mut encoded).unwrap().unwrap());
+        assert!(codec.decode(&mut encoded).unwrap().is_none());
+        assert!(encoded.is_empty());
+    }
+
+    #[test]
+    fn test_remote_command_codec_byte_by_byte() {
+        let cmd1 = RemotingCommand::new(10, 0, "remark".to_string(), HashMap::new(), Vec::new());
+        let cmd2 = RemotingCommand::new(11, 0, String::new(), HashMap::new(), b"body".to_vec());
+        let mut codec = MqCodec::default();
+        let mut encoded = BytesMut::new();
+        codec.encode(cmd1.clone(), &mut encoded).unwrap();
+        codec.encode(cmd2.clone(), &mut encoded).unwrap();
+
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+        for byte in encoded.iter() {
+            buf.put_u8(*byte);
+            while let Some(cmd) = codec.decode(&mut buf).unwrap() {
+                decoded.push(cmd);
+            }
+        }
+        assert_eq!(vec![cmd1, cmd2], decoded);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_remote_command_decode_too_long_frame() {
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), vec![0; 64]);
+        let mut encoded = BytesMut::new();
+        MqCodec::default().encode(cmd, &mut encoded).unwrap();
+        // rejected before the whole frame arrives
+        let mut partial = encoded.split_to(8);
+        assert!(matches!(
+            MqCodec::new(32).decode(&mut partial),
+            Err(Error::Frame(FrameError::TooLong(_)))
+        ));
+    }
+
+    #[test]
+    fn test_remote_command_decode_malformed_frame() {
+        let mut codec = MqCodec::default();
+        // negative frame length
+        let mut buf = BytesMut::from(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0][..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::Frame(FrameError::ShortFrame(-1)))
+        ));
+        // header length larger than frame length
+        let mut buf = BytesMut::from(&[0, 0, 0, 6, 1, 0, 0, 100, 0, 0][..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::Frame(FrameError::LengthMismatch { .. }))
+        ));
+        // bad header json
+        let mut buf = BytesMut::from(&[0, 0, 0, 6, 0, 0, 0, 2, b'{', b'x'][..]);
+        assert!(matches!(codec.decode(&mut buf), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn test_rocketmq_header_decode_corrupted_length() {
+        let cmd = RemotingCommand::new(10, 0, "remark".to_string(), HashMap::new(), Vec::new());
+        let mut header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+        // corrupt remark length
+        header[13] = 0x7f;
+        assert!(matches!(
+            RocketMQHeaderCodec.decode(&header),
+            Err(Error::Frame(FrameError::LengthMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_rocketmq_header_decode_negative_length() {
+        let cmd = RemotingCommand::new(10, 0, "remark".to_string(), HashMap::new(), Vec::new());
+        let mut header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+        header[13] = 0xff;
+        assert!(matches!(
+            RocketMQHeaderCodec.decode(&header),
+            Err(Error::Frame(FrameError::NegativeLength(_)))
+        ));
+    }
+
+    #[test]
+    fn test_rocketmq_header_decode_too_many_ext_fields() {
+        let ext_fields = (0..1025).map(|i| (i.to_string(), String::new())).collect();
+        let cmd = RemotingCommand::new(10, 0, String::new(), ext_fields, Vec::new());
+        let header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+        assert!(matches!(
+            RocketMQHeaderCodec.decode(&header),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_rocketmq_header_random_ext_fields_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(334);
+        for _ in 0..200 {
+            let ext_fields: HashMap<String, String> = (0..rng.gen_range(0..16))
+                .map(|_| {
+                    let key_len = rng.gen_range(0..32);
+                    let value_len = rng.gen_range(0..256);
+                    let key = (&mut rng).sample_iter(rand::distributions::Alphanumeric);
+                    let key = key.take(key_len).map(char::from).collect();
+                    let value = (0..value_len).map(|_| rng.gen::<char>()).collect();
+                    (key, value)
+                })
+                .collect();
+            let cmd = RemotingCommand::new(10, 0, "remark".to_string(), ext_fields, Vec::new());
+            let header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+            assert_eq!(cmd.header, RocketMQHeaderCodec.decode(&header).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_mq_codec_decode_corrupted_never_panics() {
+        let mut ext_fields = HashMap::new();
+        ext_fields.insert("topic".to_string(), "TopicTest".to_string());
+        let cmd = RemotingCommand::new(10, 0, "remark".to_string(), ext_fields, vec![1; 16]);
+        let mut encoded = BytesMut::new();
+        MqCodec::default().encode(cmd, &mut encoded).unwrap();
+        let mut rng = StdRng::seed_from_u64(334);
+        for _ in 0..10000 {
+            let mut bytes = encoded.to_vec();
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..bytes.len());
+                bytes[i] = rng.gen();
+            }
+            let mut buf = BytesMut::from(&bytes[..]);
+            let _ = MqCodec::default().decode(&mut buf);
+        }
+    }
+
+    #[test]
+    fn test_mq_codec_decode_body_without_copy() {
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), vec![1; 1024]);
+        let mut codec = MqCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(cmd.clone(), &mut buf).unwrap();
+        let range = buf.as_ptr() as usize..buf.as_ptr() as usize + buf.len();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd, decoded);
+        assert!(range.contains(&(decoded.body.as_ptr() as usize)));
+    }
+
+    #[test]
+    fn test_header_language_code() {
+        let mut cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        assert_eq!(LanguageCode::OTHER, cmd.header.language);
+        assert_eq!(MQ_VERSION, cmd.header.version);
+
+        cmd.header.language = LanguageCode::RUST;
+        let mut header = RocketMQHeaderCodec.encode(&cmd.header).unwrap();
+        assert_eq!(12, header[2]);
+        assert_eq!(
+            LanguageCode::RUST,
+            RocketMQHeaderCodec.decode(&header).unwrap().language
+        );
+        // unknown language falls back to OTHER
+        header[2] = 0xff;
+        assert_eq!(
+            LanguageCode::OTHER,
+            RocketMQHeaderCodec.decode(&header).unwrap().language
+        );
+
+        let json = JsonHeaderCodec.encode(&cmd.header).unwrap();
+        let json = String::from_utf8(json).unwrap().replace("RUST", "UNKNOWN");
+        assert_eq!(
+            LanguageCode::OTHER,
+            JsonHeaderCodec.decode(json.as_bytes()).unwrap().language
+        );
+    }
+
+    #[test]
+    fn test_remote_command_typed_code() {
+        let cmd = RemotingCommand::with_header(RequestCode::Heartbeat, HashMap::new(), Vec::new());
+        assert!(cmd.code() == RequestCode::Heartbeat);
+        assert_eq!(
+            Ok(RequestCode::Heartbeat),
+            RequestCode::try_from(cmd.code())
+        );
+        assert!(!cmd.is_response());
+
+        let res =
+            RemotingCommand::response(ResponseCode::SystemError, "error".to_string(), Vec::new());
+        assert!(res.code() == ResponseCode::SystemError);
+        assert!(res.is_response());
+        assert!(RequestCode::try_from(-1).is_err());
+    }
+
     #[test]
     fn test_remote_command_type() {
         let mut fields = HashMap::new();
@@ -209,9 +629,63 @@ mod test {
         fields.insert("offset".to_string(), "456".to_string());
         let mut cmd =
             RemotingCommand::new(10, 0, "remark".to_string(), fields, b"Hello World".to_vec());
-        assert!(!cmd.is_response_type());
+        assert!(!cmd.is_response());
+
+        cmd.mark_response();
+        assert!(cmd.is_response());
+        assert!(!cmd.is_oneway());
+
+        cmd.mark_oneway();
+        assert!(cmd.is_oneway());
+        assert!(cmd.is_response());
+        assert_eq!(3, cmd.header.flag);
+    }
+
+    #[test]
+    fn test_remote_command_decode_header() {
+        let mut fields = HashMap::new();
+        fields.insert("nextBeginOffset".to_string(), "10".to_string());
+        fields.insert("maxOffset".to_string(), "20".to_string());
+        let cmd = RemotingCommand::new(0, 0, String::new(), fields, Vec::new());
+        let header: PullMessageResponseHeader = cmd.decode_header().unwrap();
+        assert_eq!(10, header.next_begin_offset);
+        assert_eq!(0, header.min_offset);
+        assert_eq!(20, header.max_offset);
+
+        let mut fields = HashMap::new();
+        fields.insert("maxOffset".to_string(), "abc".to_string());
+        let cmd = RemotingCommand::new(0, 0, String::new(), fields, Vec::new());
+        assert!(matches!(
+            cmd.decode_header::<PullMessageResponseHeader>(),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_remote_command_into_broker_error() {
+        let res = RemotingCommand::response(
+            ResponseCode::TopicNotExist,
+            "topic TopicTest not exist".to_string(),
+            Vec::new(),
+        );
+        let err = res.into_broker_error("127.0.0.1:10911");
+        assert_eq!(Some(ResponseCode::TopicNotExist), err.response_code());
+        assert_eq!(
+            "broker error, code: 17, remark: topic TopicTest not exist, addr: 127.0.0.1:10911",
+            err.to_string()
+        );
+        match err {
+            Error::Broker { code, remark, addr } => {
+                assert_eq!(17, code);
+                assert_eq!("topic TopicTest not exist", remark);
+                assert_eq!("127.0.0.1:10911", addr);
+            }
+            _ => panic!("expected broker error"),
+        }
 
-        cmd.mark_response_type();
-        assert!(cmd.is_response_type());
+        let res = RemotingCommand::new(-1, 0, "unknown".to_string(), HashMap::new(), Vec::new());
+        let err = res.into_broker_error("127.0.0.1:10911");
+        assert!(matches!(err, Error::Broker { code: -1, .. }));
+        assert_eq!(None, err.response_code());
     }
 }
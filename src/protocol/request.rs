@@ -104,6 +104,12 @@ pub enum RequestCode {
     SendBatchMessage = 320,
 }
 
+impl PartialEq<RequestCode> for i16 {
+    fn eq(&self, other: &RequestCode) -> bool {
+        *self == *other as i16
+    }
+}
+
 pub trait EncodeRequestHeader {
     fn encode(self) -> HashMap<String, String>;
 }
@@ -114,126 +120,131 @@ impl EncodeRequestHeader for HashMap<String, String> {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct SendMessageRequestHeader {
-    pub producer_group: String,
-    pub topic: String,
-    pub queue_id: u32,
-    pub sys_flag: i32,
-    pub born_timestamp: i64,
-    pub flag: i32,
-    pub properties: String,
-    pub reconsume_times: i32,
-    pub unit_mode: bool,
-    pub max_reconsume_times: i32,
-    pub batch: bool,
-    pub default_topic: String,
-    pub default_topic_queue_nums: i32,
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct SendMessageRequestHeader {
+        pub producer_group: String => "producerGroup",
+        pub topic: String => "topic",
+        pub queue_id: u32 => "queueId",
+        pub sys_flag: i32 => "sysFlag",
+        pub born_timestamp: i64 => "bornTimestamp",
+        pub flag: i32 => "flag",
+        pub properties: String => "properties",
+        pub reconsume_times: i32 => "reconsumeTimes",
+        pub unit_mode: bool => "unitMode",
+        pub max_reconsume_times: i32 => "maxReconsumeTimes",
+        pub batch: bool => "batch",
+        pub default_topic: String => "defaultTopic",
+        pub default_topic_queue_nums: i32 => "defaultTopicQueueNums",
+    }
 }
 
-impl EncodeRequestHeader for SendMessageRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("producerGroup".to_string(), self.producer_group);
-        map.insert("topic".to_string(), self.topic);
-        map.insert("queueId".to_string(), self.queue_id.to_string());
-        map.insert("sysFlag".to_string(), self.sys_flag.to_string());
-        map.insert("bornTimestamp".to_string(), self.born_timestamp.to_string());
-        map.insert("flag".to_string(), self.flag.to_string());
-        map.insert(
-            "reconsumeTimes".to_string(),
-            self.reconsume_times.to_string(),
-        );
-        map.insert("unitMode".to_string(), self.unit_mode.to_string());
-        map.insert(
-            "maxReconsumeTimes".to_string(),
-            self.max_reconsume_times.to_string(),
-        );
-        map.insert("defaultTopic".to_string(), self.default_topic);
-        map.insert(
-            "defaultTopicQueueNums".to_string(),
-            self.default_topic_queue_nums.to_string(),
-        );
-        map.insert("batch".to_string(), self.batch.to_string());
-        map.insert("properties".to_string(), self.properties);
-        map
+command_header! {
+    /// Same as `SendMessageRequestHeader` but with short field names to reduce header size
+    #[derive(Debug, Clone)]
+    pub struct SendMessageRequestV2Header {
+        pub producer_group: String => "a",
+        pub topic: String => "b",
+        pub queue_id: u32 => "e",
+        pub sys_flag: i32 => "f",
+        pub born_timestamp: i64 => "g",
+        pub flag: i32 => "h",
+        pub properties: String => "i",
+        pub reconsume_times: i32 => "j",
+        pub unit_mode: bool => "k",
+        pub max_reconsume_times: i32 => "l",
+        pub batch: bool => "m",
+        pub default_topic: String => "c",
+        pub default_topic_queue_nums: i32 => "d",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct SendMessageRequestV2Header {
-    pub producer_group: String,
-    pub topic: String,
-    pub queue_id: u32,
-    pub sys_flag: i32,
-    pub born_timestamp: i64,
-    pub flag: i32,
-    pub properties: String,
-    pub reconsume_times: i32,
-    pub unit_mode: bool,
-    pub max_reconsume_times: i32,
-    pub batch: bool,
-    pub default_topic: String,
-    pub default_topic_queue_nums: i32,
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct CheckTransactionStateRequestHeader {
+        pub tran_state_table_offset: i64 => "tranStateTableOffset",
+        pub commit_log_offset: i64 => "commitLogOffset",
+        pub msg_id: String => "msgId",
+        pub transaction_id: String => "transactionId" (default),
+        pub offset_msg_id: String => "offsetMsgId" (default),
+    }
 }
 
-impl EncodeRequestHeader for SendMessageRequestV2Header {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("a".to_string(), self.producer_group);
-        map.insert("b".to_string(), self.topic);
-        map.insert("c".to_string(), self.default_topic);
-        map.insert("d".to_string(), self.default_topic_queue_nums.to_string());
-        map.insert("e".to_string(), self.queue_id.to_string());
-        map.insert("f".to_string(), self.sys_flag.to_string());
-        map.insert("g".to_string(), self.born_timestamp.to_string());
-        map.insert("h".to_string(), self.flag.to_string());
-        map.insert("i".to_string(), self.properties);
-        map.insert("j".to_string(), self.reconsume_times.to_string());
-        map.insert("k".to_string(), self.unit_mode.to_string());
-        map.insert("l".to_string(), self.max_reconsume_times.to_string());
-        map.insert("m".to_string(), self.batch.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct EndTransactionRequestHeader {
+        pub producer_group: String => "producerGroup",
+        pub tran_state_table_offset: i64 => "tranStateTableOffset",
+        pub commit_log_offset: i64 => "commitLogOffset",
+        pub commit_or_rollback: i32 => "commitOrRollback",
+        pub from_transaction_check: bool => "fromTransactionCheck",
+        pub msg_id: String => "msgId",
+        pub transaction_id: String => "transactionId",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct CheckTransactionStateRequestHeader {
-    pub tran_state_table_offset: i64,
-    pub commit_log_offset: i64,
-    pub msg_id: String,
-    pub transaction_id: String,
-    pub offset_msg_id: String,
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetRouteInfoRequestHeader {
+        pub topic: String => "topic",
+    }
 }
 
-impl EncodeRequestHeader for CheckTransactionStateRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert(
-            "tranStateTableOffset".to_string(),
-            self.tran_state_table_offset.to_string(),
-        );
-        map.insert(
-            "commitLogOffset".to_string(),
-            self.commit_log_offset.to_string(),
-        );
-        map.insert("msgId".to_string(), self.msg_id);
-        map.insert("transactionId".to_string(), self.transaction_id);
-        map.insert("offsetMsgId".to_string(), self.offset_msg_id);
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct QueryMessageRequestHeader {
+        pub topic: String => "topic",
+        pub key: String => "key",
+        pub max_num: i32 => "maxNum",
+        pub begin_timestamp: i64 => "beginTimestamp",
+        pub end_timestamp: i64 => "endTimestamp",
+        /// Query by unique key rather than keys of messages
+        pub unique_key_query: bool => "_UNIQUE_KEY_QUERY",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GetRouteInfoRequestHeader {
-    pub topic: String,
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetConsumerConnectionListRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+    }
 }
 
-impl EncodeRequestHeader for GetRouteInfoRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("topic".to_string(), self.topic);
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetTopicsByClusterRequestHeader {
+        pub cluster: String => "cluster",
+    }
+}
+
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetConsumeStatsRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+        pub topic: String => "topic",
+    }
+}
+
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetTopicStatsInfoRequestHeader {
+        pub topic: String => "topic",
+    }
+}
+
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct DeleteTopicRequestHeader {
+        pub topic: String => "topic",
+    }
+}
+
+command_header! {
+    /// Name servers before 5.0 ignore the cluster and delete the topic of every cluster
+    #[derive(Debug, Clone)]
+    pub struct DeleteTopicFromNamesrvRequestHeader {
+        pub topic: String => "topic",
+        pub cluster_name: String => "clusterName",
     }
 }
 
@@ -273,134 +284,69 @@ impl EncodeRequestHeader for PullMessageRequestHeader {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct UnregisterClientRequestHeader {
-    pub client_id: String,
-    pub producer_group: String,
-    pub consumer_group: String,
-}
-
-impl EncodeRequestHeader for UnregisterClientRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("clientID".to_string(), self.client_id);
-        map.insert("producerGroup".to_string(), self.producer_group);
-        map.insert("consumerGroup".to_string(), self.consumer_group);
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct UnregisterClientRequestHeader {
+        pub client_id: String => "clientID",
+        pub producer_group: String => "producerGroup",
+        pub consumer_group: String => "consumerGroup",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct CreateTopicRequestHeader {
-    pub topic: String,
-    pub default_topic: String,
-    pub read_queue_nums: u32,
-    pub write_queue_nums: u32,
-    pub permission: i32,
-    pub topic_filter_type: String,
-    pub topic_sys_flag: i32,
-    pub order: bool,
-}
-
-impl EncodeRequestHeader for CreateTopicRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("topic".to_string(), self.topic);
-        map.insert("defaultTopic".to_string(), self.default_topic);
-        map.insert(
-            "readQueueNums".to_string(),
-            self.read_queue_nums.to_string(),
-        );
-        map.insert(
-            "writeQueueNums".to_string(),
-            self.write_queue_nums.to_string(),
-        );
-        map.insert("perm".to_string(), self.permission.to_string());
-        map.insert("topicFilterType".to_string(), self.topic_filter_type);
-        map.insert("topicSysFlag".to_string(), self.topic_sys_flag.to_string());
-        map.insert("order".to_string(), self.order.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct CreateTopicRequestHeader {
+        pub topic: String => "topic",
+        pub default_topic: String => "defaultTopic",
+        pub read_queue_nums: u32 => "readQueueNums",
+        pub write_queue_nums: u32 => "writeQueueNums",
+        pub permission: i32 => "perm",
+        pub topic_filter_type: String => "topicFilterType",
+        pub topic_sys_flag: i32 => "topicSysFlag",
+        pub order: bool => "order",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct QueryConsumerOffsetRequestHeader {
-    pub consumer_group: String,
-    pub topic: String,
-    pub queue_id: u32,
-}
-
-impl EncodeRequestHeader for QueryConsumerOffsetRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("consumerGroup".to_string(), self.consumer_group);
-        map.insert("topic".to_string(), self.topic);
-        map.insert("queueId".to_string(), self.queue_id.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct QueryConsumerOffsetRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+        pub topic: String => "topic",
+        pub queue_id: u32 => "queueId",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct UpdateConsumerOffsetRequestHeader {
-    pub consumer_group: String,
-    pub topic: String,
-    pub queue_id: u32,
-    pub commit_offset: i64,
-}
-
-impl EncodeRequestHeader for UpdateConsumerOffsetRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("consumerGroup".to_string(), self.consumer_group);
-        map.insert("topic".to_string(), self.topic);
-        map.insert("queueId".to_string(), self.queue_id.to_string());
-        map.insert("commitOffset".to_string(), self.commit_offset.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct UpdateConsumerOffsetRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+        pub topic: String => "topic",
+        pub queue_id: u32 => "queueId",
+        pub commit_offset: i64 => "commitOffset",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GetConsumerListRequestHeader {
-    pub consumer_group: String,
-}
-
-impl EncodeRequestHeader for GetConsumerListRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("consumerGroup".to_string(), self.consumer_group);
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetConsumerListRequestHeader {
+        pub consumer_group: String => "consumerGroup",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GetMaxOffsetRequestHeader {
-    pub topic: String,
-    pub queue_id: u32,
-}
-
-impl EncodeRequestHeader for GetMaxOffsetRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("topic".to_string(), self.topic);
-        map.insert("queueId".to_string(), self.queue_id.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct GetMaxOffsetRequestHeader {
+        pub topic: String => "topic",
+        pub queue_id: u32 => "queueId",
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct SearchOffsetByTimestampRequestHeader {
-    pub topic: String,
-    pub queue_id: u32,
-    pub timestamp: i64,
-}
-
-impl EncodeRequestHeader for SearchOffsetByTimestampRequestHeader {
-    fn encode(self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("topic".to_string(), self.topic);
-        map.insert("queueId".to_string(), self.queue_id.to_string());
-        map.insert("timestamp".to_string(), self.timestamp.to_string());
-        map
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct SearchOffsetByTimestampRequestHeader {
+        pub topic: String => "topic",
+        pub queue_id: u32 => "queueId",
+        pub timestamp: i64 => "timestamp",
     }
 }
 
@@ -437,3 +383,80 @@ impl EncodeRequestHeader for ConsumerSendMsgBackRequestHeader {
         map
     }
 }
+
+command_header! {
+    /// Header of `ResetConsumerClientOffset` request sent by broker
+    #[derive(Debug, Clone)]
+    pub struct ResetOffsetRequestHeader {
+        pub topic: String => "topic",
+        pub group: String => "group",
+        pub timestamp: i64 => "timestamp",
+        pub is_force: bool => "isForce" (default),
+    }
+}
+
+command_header! {
+    /// Header of `ConsumeMessageDirectly` request sent by broker
+    #[derive(Debug, Clone)]
+    pub struct ConsumeMessageDirectlyRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+        pub client_id: String => "clientId" (default),
+        pub msg_id: String => "msgId" (default),
+        pub broker_name: String => "brokerName" (default),
+    }
+}
+
+command_header! {
+    /// Header of `GetConsumerRunningInfo` request sent by broker
+    #[derive(Debug, Clone)]
+    pub struct GetConsumerRunningInfoRequestHeader {
+        pub consumer_group: String => "consumerGroup",
+        pub client_id: String => "clientId" (default),
+        pub jstack_enable: bool => "jstackEnable" (default),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        ConsumeMessageDirectlyRequestHeader, EncodeRequestHeader, GetMaxOffsetRequestHeader,
+    };
+    use crate::protocol::DecodeHeader;
+    use crate::Error;
+
+    #[test]
+    fn test_command_header_roundtrip() {
+        let header = GetMaxOffsetRequestHeader {
+            topic: "TopicTest".to_string(),
+            queue_id: 3,
+        };
+        let fields = header.encode();
+        assert_eq!("TopicTest", fields["topic"]);
+        assert_eq!("3", fields["queueId"]);
+        let header = GetMaxOffsetRequestHeader::decode(&fields).unwrap();
+        assert_eq!("TopicTest", header.topic);
+        assert_eq!(3, header.queue_id);
+    }
+
+    #[test]
+    fn test_command_header_required_and_default_fields() {
+        let mut fields = HashMap::new();
+        assert!(matches!(
+            ConsumeMessageDirectlyRequestHeader::decode(&fields),
+            Err(Error::InvalidHeader(_))
+        ));
+        fields.insert("consumerGroup".to_string(), "group".to_string());
+        let header = ConsumeMessageDirectlyRequestHeader::decode(&fields).unwrap();
+        assert_eq!("group", header.consumer_group);
+        assert!(header.msg_id.is_empty());
+
+        fields.insert("queueId".to_string(), "-1".to_string());
+        fields.insert("topic".to_string(), "TopicTest".to_string());
+        assert!(matches!(
+            GetMaxOffsetRequestHeader::decode(&fields),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
+use super::{parse_field, DecodeHeader};
+use crate::message::Property;
 use crate::Error;
 
 #[repr(i16)]
@@ -55,9 +58,10 @@ pub enum ResponseCode {
 
 impl ResponseCode {
     pub fn from_code(code: i16) -> Result<Self, Error> {
-        ResponseCode::try_from(code).map_err(|_| Error::ResponseError {
+        ResponseCode::try_from(code).map_err(|_| Error::Broker {
             code,
-            message: format!("unknown response code {}", code),
+            remark: format!("unknown response code {}", code),
+            addr: String::new(),
         })
     }
 }
@@ -69,18 +73,40 @@ impl PartialEq<ResponseCode> for i16 {
 }
 
 #[derive(Debug, Clone)]
-pub struct SendMessageResponse {
+pub struct SendMessageResponseHeader {
     pub msg_id: String,
-    pub queue_id: i32,
+    pub queue_id: u32,
     pub queue_offset: i64,
-    pub transaction_id: String,
+    pub transaction_id: Option<String>,
     pub msg_region: String,
+    pub trace_on: bool,
 }
 
-#[derive(Debug, Clone)]
-pub struct PullMessageResponse {
-    pub suggest_which_broker_id: i64,
-    pub next_begin_offset: i64,
-    pub min_offset: i64,
-    pub max_offset: i64,
+impl DecodeHeader for SendMessageResponseHeader {
+    fn decode(ext_fields: &HashMap<String, String>) -> Result<Self, Error> {
+        Ok(Self {
+            msg_id: parse_field(ext_fields, "msgId")?,
+            queue_id: parse_field(ext_fields, "queueId")?,
+            queue_offset: parse_field(ext_fields, "queueOffset")?,
+            transaction_id: ext_fields.get("transactionId").cloned(),
+            msg_region: ext_fields
+                .get(Property::MSG_REGION)
+                .cloned()
+                .unwrap_or_else(|| "DefaultRegion".to_string()),
+            trace_on: ext_fields
+                .get(Property::TRACE_SWITCH)
+                .map(|prop| !prop.is_empty() && prop != "false")
+                .unwrap_or(false),
+        })
+    }
+}
+
+command_header! {
+    #[derive(Debug, Clone)]
+    pub struct PullMessageResponseHeader {
+        pub suggest_which_broker_id: i64 => "suggestWhichBrokerId" (default),
+        pub next_begin_offset: i64 => "nextBeginOffset" (default),
+        pub min_offset: i64 => "minOffset" (default),
+        pub max_offset: i64 => "maxOffset" (default),
+    }
 }
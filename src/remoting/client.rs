@@ -1,27 +1,137 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use hmac::{Hmac, Mac, NewMac};
 use parking_lot::Mutex;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
 
 use super::connection::Connection;
+use super::hook::RpcHooks;
+use super::limiter::{RateLimit, RateLimiter};
+use super::listener::ConnectionEvents;
+use crate::buffer;
 use crate::client::Credentials;
 use crate::error::{ConnectionError, Error};
-use crate::protocol::RemotingCommand;
+use crate::protocol::{RemotingCommand, DEFAULT_MAX_FRAME_LENGTH, MQ_VERSION};
+use crate::runtime;
+
+/// Connections idle longer than this are closed, same as the Java client
+pub const DEFAULT_CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Connecting to brokers and name servers fails after this by default, same as the Java
+/// client
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Requests waiting for responses on a connection are capped at this by default, same as
+/// the async semaphore of the Java client
+pub const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 65535;
+
+// delay before redialing an address after the first failed connect, doubled on each
+// further failure up to the max
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// ext fields added by ACL signing
+const ACCESS_KEY: &str = "AccessKey";
+pub(super) const SECURITY_TOKEN: &str = "SecurityToken";
+pub(super) const SIGNATURE: &str = "Signature";
 
 type HmacSha1 = Hmac<sha1::Sha1>;
+type RequestReceiver = mpsc::UnboundedReceiver<(String, RemotingCommand)>;
+
+/// Failed connect attempts to an address, further attempts are rejected until `retry_at`
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl Backoff {
+    /// Capped exponential delay with jitter, half of it is random so clients don't
+    /// reconnect all at once after a broker restarts
+    fn delay(failures: u32) -> Duration {
+        let exp = RECONNECT_MIN_BACKOFF
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(RECONNECT_MAX_BACKOFF);
+        let half = exp / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+}
 
 enum ConnectionStatus {
     Connected(Arc<Connection>),
     Connecting(Vec<oneshot::Sender<Result<Arc<Connection>, Error>>>),
 }
 
+/// Options of the transport shared by all connections of a `RemotingClient`
+#[derive(Debug, Clone)]
+pub struct RemotingOptions {
+    // version reported to servers
+    pub(crate) version: i16,
+    pub(crate) max_frame_length: usize,
+    // connections idle longer than this are evicted
+    pub(crate) idle_timeout: Duration,
+    // requests waiting for responses per connection, further requests wait for a slot
+    pub(crate) max_in_flight_requests: usize,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) tcp_nodelay: bool,
+    // interval of TCP keepalive probes, disabled when unset
+    pub(crate) tcp_keepalive: Option<Duration>,
+    // socket buffer sizes, system defaults when unset
+    pub(crate) send_buffer_size: Option<u32>,
+    pub(crate) recv_buffer_size: Option<u32>,
+    // limits of outbound requests when set
+    pub(crate) rate_limit: Option<RateLimit>,
+    // notified of connections opened, closed and failed
+    pub(crate) events: ConnectionEvents,
+    // called around requests sent and responses received
+    pub(crate) hooks: RpcHooks,
+    // connect through the SOCKS5 proxy when set
+    pub(crate) proxy: Option<super::ProxyConfig>,
+    // connect with TLS when set
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<super::TlsConfig>,
+    // faults injected into requests
+    #[cfg(feature = "fault-injection")]
+    pub(crate) faults: super::Faults,
+}
+
+impl Default for RemotingOptions {
+    fn default() -> Self {
+        Self {
+            version: MQ_VERSION,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            idle_timeout: DEFAULT_CONNECTION_IDLE_TIMEOUT,
+            max_in_flight_requests: DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            rate_limit: None,
+            events: ConnectionEvents::default(),
+            hooks: RpcHooks::default(),
+            proxy: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "fault-injection")]
+            faults: super::Faults::default(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RemotingClient {
     connections: Arc<Mutex<HashMap<String, ConnectionStatus>>>,
+    backoffs: Arc<Mutex<HashMap<String, Backoff>>>,
     credentials: Option<Credentials>,
+    options: RemotingOptions,
+    limiter: Option<Arc<RateLimiter>>,
+    requests_tx: mpsc::UnboundedSender<(String, RemotingCommand)>,
+    requests_rx: Arc<Mutex<Option<RequestReceiver>>>,
 }
 
 impl fmt::Debug for RemotingClient {
@@ -40,29 +150,91 @@ impl Default for RemotingClient {
 
 impl RemotingClient {
     pub fn new<C: Into<Option<Credentials>>>(credentials: C) -> Self {
+        Self::with_options(credentials, RemotingOptions::default())
+    }
+
+    pub fn with_options<C: Into<Option<Credentials>>>(
+        credentials: C,
+        options: RemotingOptions,
+    ) -> Self {
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        let limiter = options
+            .rate_limit
+            .clone()
+            .map(|limit| Arc::new(RateLimiter::new(limit)));
         Self {
             connections: Arc::new(Mutex::new(HashMap::new())),
+            backoffs: Arc::new(Mutex::new(HashMap::new())),
             credentials: credentials.into(),
+            options,
+            limiter,
+            requests_tx,
+            requests_rx: Arc::new(Mutex::new(Some(requests_rx))),
+        }
+    }
+
+    /// Take the receiver of requests initiated by remote servers, paired with the server address
+    pub fn take_request_receiver(&self) -> Option<RequestReceiver> {
+        self.requests_rx.lock().take()
+    }
+
+    pub async fn send_response(&self, addr: &str, cmd: RemotingCommand) -> Result<(), Error> {
+        let conn = self.get_connection(addr).await?;
+        conn.sender().send_response(cmd)
+    }
+
+    /// Wait for the rate limit to allow a request to `addr`
+    async fn throttle(&self, addr: &str) -> Result<(), Error> {
+        match &self.limiter {
+            Some(limiter) => limiter.acquire(addr).await,
+            None => Ok(()),
         }
     }
 
     pub async fn invoke(&self, addr: &str, cmd: RemotingCommand) -> Result<RemotingCommand, Error> {
+        self.throttle(addr).await?;
+        let conn = self.get_connection(addr).await?;
+        let sender = conn.sender();
+        sender.send(self.prepare(cmd)).await
+    }
+
+    pub async fn invoke_timeout(
+        &self,
+        addr: &str,
+        cmd: RemotingCommand,
+        timeout: Duration,
+    ) -> Result<RemotingCommand, Error> {
+        // waiting for the rate limit counts against the timeout
+        let start = Instant::now();
+        runtime::timeout(timeout, self.throttle(addr))
+            .await
+            .map_err(|_| Error::Timeout)??;
         let conn = self.get_connection(addr).await?;
         let sender = conn.sender();
-        Ok(sender.send(self.add_signature(cmd)).await?)
+        sender
+            .send_timeout(self.prepare(cmd), timeout.saturating_sub(start.elapsed()))
+            .await
     }
 
     pub async fn invoke_oneway(&self, addr: &str, cmd: RemotingCommand) -> Result<(), Error> {
+        self.throttle(addr).await?;
         let conn = self.get_connection(addr).await?;
         let sender = conn.sender();
-        Ok(sender.send_oneway(self.add_signature(cmd)).await?)
+        sender.send_oneway(self.prepare(cmd)).await
     }
 
     pub async fn get_connection(&self, addr: &str) -> Result<Arc<Connection>, Error> {
         let rx = {
             match self.connections.lock().get_mut(addr) {
                 None => None,
-                Some(ConnectionStatus::Connected(conn)) => return Ok(conn.clone()),
+                Some(ConnectionStatus::Connected(conn)) if !conn.sender().is_closed() => {
+                    return Ok(conn.clone())
+                }
+                Some(ConnectionStatus::Connected(_)) => {
+                    // closed by the remote side or on IO errors, dial again
+                    debug!("connection to {} was closed, reconnecting", addr);
+                    None
+                }
                 Some(ConnectionStatus::Connecting(ref mut v)) => {
                     let (tx, rx) = oneshot::channel();
                     v.push(tx);
@@ -79,12 +251,96 @@ impl RemotingClient {
         }
     }
 
+    /// Close connections idle longer than the configured idle timeout and those already
+    /// closed, they are dialed again on demand
+    pub fn evict_idle_connections(&self) {
+        let idle_timeout = self.options.idle_timeout;
+        let mut idle = Vec::new();
+        self.connections.lock().retain(|addr, status| match status {
+            ConnectionStatus::Connecting(_) => true,
+            ConnectionStatus::Connected(conn) => {
+                let sender = conn.sender();
+                if sender.is_closed() {
+                    debug!("remove closed connection to {}", addr);
+                    false
+                } else if sender.idle_time() >= idle_timeout {
+                    info!(
+                        "close connection to {} idle for {:?}",
+                        addr,
+                        sender.idle_time()
+                    );
+                    idle.push(addr.clone());
+                    false
+                } else {
+                    true
+                }
+            }
+        });
+        // outside of the lock, listeners may use the client
+        for addr in idle {
+            self.options.events.idle(&addr);
+        }
+    }
+
+    /// Resolve host names of connected addresses again, closing connections whose IP is no
+    /// longer among the resolved ones so they're dialed to the new IP on demand. Addresses
+    /// are resolved on every connect, this catches brokers moved while connected, e.g. by
+    /// DNS failover or rescheduled Kubernetes pods
+    pub async fn refresh_resolved_addresses(&self) {
+        let connected: Vec<(String, SocketAddr)> = self
+            .connections
+            .lock()
+            .iter()
+            .filter_map(|(addr, status)| match status {
+                ConnectionStatus::Connected(conn) => {
+                    conn.peer_addr().map(|peer_addr| (addr.clone(), peer_addr))
+                }
+                ConnectionStatus::Connecting(_) => None,
+            })
+            // IP addresses never change
+            .filter(|(addr, _)| addr.parse::<SocketAddr>().is_err())
+            .collect();
+        for (addr, peer_addr) in connected {
+            let resolved: Vec<SocketAddr> = match runtime::lookup_host(&addr).await {
+                Ok(resolved) => resolved,
+                Err(err) => {
+                    warn!("resolve {} failed, keep the connection: {}", addr, err);
+                    continue;
+                }
+            };
+            if resolved.is_empty() || resolved.contains(&peer_addr) {
+                continue;
+            }
+            let mut connections = self.connections.lock();
+            // it may have been reconnected meanwhile
+            if let Some(ConnectionStatus::Connected(conn)) = connections.get(&addr) {
+                if conn.peer_addr() == Some(peer_addr) {
+                    info!(
+                        "{} resolved to {:?} instead of {}, reconnecting",
+                        addr, resolved, peer_addr
+                    );
+                    connections.remove(&addr);
+                }
+            }
+        }
+    }
+
+    /// Close the connection to `addr` if any
+    pub fn close(&self, addr: &str) {
+        self.connections.lock().remove(addr);
+    }
+
     pub fn shutdown(&self) {
         let mut connections = self.connections.lock();
         connections.clear();
     }
 
     async fn connect(&self, addr: &str) -> Result<Arc<Connection>, Error> {
+        if let Some(backoff) = self.backoffs.lock().get(addr) {
+            if backoff.retry_at > Instant::now() {
+                return Err(Error::Connection(ConnectionError::Reconnecting));
+            }
+        }
         let rx = {
             match self
                 .connections
@@ -101,7 +357,13 @@ impl RemotingClient {
                         Some(rx)
                     }
                 }
-                ConnectionStatus::Connected(_) => None,
+                ConnectionStatus::Connected(conn) if !conn.sender().is_closed() => {
+                    return Ok(conn.clone())
+                }
+                status @ ConnectionStatus::Connected(_) => {
+                    *status = ConnectionStatus::Connecting(Vec::new());
+                    None
+                }
             }
         };
         if let Some(rx) = rx {
@@ -110,8 +372,33 @@ impl RemotingClient {
                 Err(_) => Err(Error::Connection(ConnectionError::Canceled)),
             };
         }
-        // FIXME: connection backoff
-        let conn = Connection::new(addr).await?;
+        let conn = match Connection::new(addr, self.requests_tx.clone(), &self.options).await {
+            Ok(conn) => conn,
+            Err(err) => {
+                let mut backoffs = self.backoffs.lock();
+                let backoff = backoffs.entry(addr.to_string()).or_insert(Backoff {
+                    failures: 0,
+                    retry_at: Instant::now(),
+                });
+                backoff.failures += 1;
+                let delay = Backoff::delay(backoff.failures);
+                backoff.retry_at = Instant::now() + delay;
+                drop(backoffs);
+                warn!("connect to {} failed, retry in {:?}: {}", addr, delay, err);
+                self.options.events.failed(addr, &err);
+                // wake up waiters so the next call dials again
+                if let Some(ConnectionStatus::Connecting(mut v)) =
+                    self.connections.lock().remove(addr)
+                {
+                    for tx in v.drain(..) {
+                        let _ = tx.send(Err(Error::Connection(ConnectionError::Disconnected)));
+                    }
+                }
+                return Err(err);
+            }
+        };
+        self.backoffs.lock().remove(addr);
+        self.options.events.connected(addr);
         let c = Arc::new(conn);
         let old = self.connections.lock().insert(
             addr.to_string(),
@@ -129,38 +416,41 @@ impl RemotingClient {
         Ok(c)
     }
 
+    fn prepare(&self, mut cmd: RemotingCommand) -> RemotingCommand {
+        // commands with a version set explicitly keep it
+        if !cmd.is_version_overridden() {
+            cmd.header.version = self.options.version;
+        }
+        self.add_signature(cmd)
+    }
+
+    /// Sign the command like the Java `AclClientRPCHook`, the HMAC-SHA1 signature covers
+    /// values of the access key, security token and ext fields sorted by key, then the body
     fn add_signature(&self, mut cmd: RemotingCommand) -> RemotingCommand {
         if let Some(credentials) = &self.credentials {
-            let size = cmd.header.ext_fields.len() + 1;
-            let mut m = HashMap::with_capacity(size);
-            m.insert("AccessKey".to_string(), &credentials.access_key);
-            let mut order = Vec::with_capacity(size);
-            order.push("AccessKey");
+            let fields = &mut cmd.header.ext_fields;
+            // fields of a previous signing are not part of the content
+            fields.remove(SIGNATURE);
+            fields.remove(SECURITY_TOKEN);
+            fields.insert(ACCESS_KEY.to_string(), credentials.access_key.clone());
             if let Some(security_token) = &credentials.security_token {
                 if !security_token.is_empty() {
-                    m.insert("SecurityToken".to_string(), security_token);
-                    cmd.header
-                        .ext_fields
-                        .insert("SecurityToken".to_string(), security_token.clone());
+                    fields.insert(SECURITY_TOKEN.to_string(), security_token.clone());
                 }
             }
-            for (k, v) in &cmd.header.ext_fields {
-                m.insert(k.clone(), v);
-                order.push(&k[..]);
-            }
-            order.sort();
-            let mut content = Vec::with_capacity(cmd.body.len());
-            for key in order {
-                content.extend_from_slice(&m[key].as_bytes());
+            let sorted: BTreeMap<&str, &str> = fields
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            let mut content = buffer::get();
+            for value in sorted.values() {
+                content.extend_from_slice(value.as_bytes());
             }
             content.extend_from_slice(&cmd.body);
-            cmd.header.ext_fields.insert(
-                "Signature".to_string(),
-                Self::calculate_signature(&content, credentials.secret_key.as_bytes()),
-            );
+            let signature = Self::calculate_signature(&content, credentials.secret_key.as_bytes());
             cmd.header
                 .ext_fields
-                .insert("AccessKey".to_string(), credentials.access_key.clone());
+                .insert(SIGNATURE.to_string(), signature);
         }
         cmd
     }
@@ -175,7 +465,17 @@ impl RemotingClient {
 
 #[cfg(test)]
 mod test {
-    use super::RemotingClient;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use parking_lot::Mutex;
+
+    use super::{Backoff, RemotingClient, RemotingOptions};
+    use crate::client::Credentials;
+    use crate::error::{ConnectionError, Error};
+    use crate::protocol::{RemotingCommand, MQ_VERSION};
+    use crate::remoting::{ConnectionEvents, ConnectionListener};
 
     #[test]
     fn test_calculate_signature() {
@@ -185,4 +485,220 @@ mod test {
         );
         assert_eq!(signature, "tAb/54Rwwcq+pbH8Loi7FWX4QSQ=");
     }
+
+    #[test]
+    fn test_prepare_command_version() {
+        let options = RemotingOptions {
+            version: 400,
+            ..Default::default()
+        };
+        let client = RemotingClient::with_options(None, options);
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        assert_eq!(MQ_VERSION, cmd.version());
+        assert_eq!(400, client.prepare(cmd).version());
+
+        let mut cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        cmd.set_version(300);
+        assert_eq!(300, client.prepare(cmd).version());
+
+        // the default version asked for explicitly isn't replaced
+        let mut cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        cmd.set_version(MQ_VERSION);
+        assert_eq!(MQ_VERSION, client.prepare(cmd).version());
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_backoff() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let client = RemotingClient::default();
+        let err = match client.get_connection(&addr).await {
+            Err(err) => err,
+            Ok(_) => panic!("connect should fail"),
+        };
+        assert!(matches!(err, Error::Io(_)));
+        assert!(err.is_retriable());
+        assert!(client.connections.lock().is_empty());
+        // not stuck connecting, rejected until the backoff delay passes
+        assert!(matches!(
+            client.get_connection(&addr).await,
+            Err(Error::Connection(ConnectionError::Reconnecting))
+        ));
+
+        client.backoffs.lock().get_mut(&addr).unwrap().retry_at = Instant::now();
+        assert!(matches!(
+            client.get_connection(&addr).await,
+            Err(Error::Io(_))
+        ));
+        assert_eq!(2, client.backoffs.lock()[&addr].failures);
+    }
+
+    #[test]
+    fn test_reconnect_backoff_delay() {
+        for _ in 0..10 {
+            let delay = Backoff::delay(1);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+            let delay = Backoff::delay(4);
+            assert!(delay >= Duration::from_millis(400) && delay <= Duration::from_millis(800));
+            let delay = Backoff::delay(100);
+            assert!(delay >= Duration::from_secs(15) && delay <= Duration::from_secs(30));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evict_idle_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let options = RemotingOptions {
+            idle_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = RemotingClient::with_options(None, options);
+        let conn = client.get_connection(&addr).await.unwrap();
+        client.evict_idle_connections();
+        assert!(Arc::ptr_eq(
+            &conn,
+            &client.get_connection(&addr).await.unwrap()
+        ));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        client.evict_idle_connections();
+        assert!(client.connections.lock().is_empty());
+        // dialed again on demand
+        let new_conn = client.get_connection(&addr).await.unwrap();
+        assert!(!Arc::ptr_eq(&conn, &new_conn));
+    }
+
+    #[derive(Default)]
+    struct RecordingListener {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl ConnectionListener for RecordingListener {
+        fn on_connect(&self, addr: &str) {
+            self.events.lock().push(format!("connect {}", addr));
+        }
+
+        fn on_close(&self, addr: &str) {
+            self.events.lock().push(format!("close {}", addr));
+        }
+
+        fn on_error(&self, addr: &str, _err: &Error) {
+            self.events.lock().push(format!("error {}", addr));
+        }
+
+        fn on_idle(&self, addr: &str) {
+            self.events.lock().push(format!("idle {}", addr));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_listener() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = closed.local_addr().unwrap().to_string();
+        drop(closed);
+
+        let recorder = Arc::new(RecordingListener::default());
+        let options = RemotingOptions {
+            idle_timeout: Duration::from_millis(50),
+            events: ConnectionEvents::new(recorder.clone()),
+            ..Default::default()
+        };
+        let client = RemotingClient::with_options(None, options);
+        let conn = client.get_connection(&addr).await.unwrap();
+        assert!(client.get_connection(&closed_addr).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        client.evict_idle_connections();
+        drop(conn);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(
+            vec![
+                format!("connect {}", addr),
+                format!("error {}", closed_addr),
+                format!("idle {}", addr),
+                format!("close {}", addr),
+            ],
+            *recorder.events.lock()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_resolved_addresses() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let client = RemotingClient::default();
+        let addr = format!("localhost:{}", port);
+        let conn = client.get_connection(&addr).await.unwrap();
+        assert_eq!(
+            Some(format!("127.0.0.1:{}", port).parse().unwrap()),
+            conn.peer_addr()
+        );
+        // still resolved to the same IP, kept
+        client.refresh_resolved_addresses().await;
+        assert!(Arc::ptr_eq(
+            &conn,
+            &client.get_connection(&addr).await.unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_add_signature() {
+        let credentials = Credentials::new("rocketmq", "12345678");
+        let client = RemotingClient::new(credentials);
+        let mut fields = HashMap::new();
+        fields.insert("topic".to_string(), "TopicTest".to_string());
+        fields.insert("queueId".to_string(), "1".to_string());
+        let cmd = RemotingCommand::new(10, 0, String::new(), fields, b"Hello".to_vec());
+        let cmd = client.prepare(cmd);
+        // AccessKey, queueId, topic, then body
+        let expected = RemotingClient::calculate_signature(b"rocketmq1TopicTestHello", b"12345678");
+        assert_eq!("rocketmq", cmd.header.ext_fields["AccessKey"]);
+        assert_eq!(expected, cmd.header.ext_fields["Signature"]);
+        assert!(!cmd.header.ext_fields.contains_key("SecurityToken"));
+
+        // signing again gives the same result
+        let cmd = client.prepare(cmd);
+        assert_eq!(expected, cmd.header.ext_fields["Signature"]);
+        assert_eq!(4, cmd.header.ext_fields.len());
+    }
+
+    #[test]
+    fn test_add_signature_with_security_token() {
+        let mut credentials = Credentials::new("rocketmq", "12345678");
+        credentials.security_token = Some("token".to_string());
+        let client = RemotingClient::new(credentials);
+        let mut fields = HashMap::new();
+        fields.insert("topic".to_string(), "TopicTest".to_string());
+        let cmd = RemotingCommand::new(10, 0, String::new(), fields, Vec::new());
+        let cmd = client.prepare(cmd);
+        // AccessKey, SecurityToken, topic
+        let expected = RemotingClient::calculate_signature(b"rocketmqtokenTopicTest", b"12345678");
+        assert_eq!("token", cmd.header.ext_fields["SecurityToken"]);
+        assert_eq!(expected, cmd.header.ext_fields["Signature"]);
+    }
 }
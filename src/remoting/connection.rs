@@ -1,25 +1,181 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::io;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::{
     task::{Context, Poll},
     Future, Sink, SinkExt, Stream, StreamExt,
 };
-use tokio::net::TcpStream;
-use tokio::sync::{mpsc, oneshot};
-use tracing::{debug, info};
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tracing::{debug, info, warn};
 
 use crate::error::{ConnectionError, Error};
 use crate::protocol::{MqCodec, RemotingCommand};
+use crate::runtime::{self, Interval, TcpStream};
+
+#[cfg(feature = "fault-injection")]
+use super::fault::{self, Fault, Faults};
+use super::hook::RpcHooks;
+use super::listener::ConnectionEvents;
+use super::RemotingOptions;
+
+// how often the receiver removes timed out or abandoned pending requests
+const PENDING_REQUESTS_CLEANUP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Changes to the pending requests of a connection, keyed by opaque
+enum Registration {
+    /// a request waiting for its response
+    Register(i32, PendingRequest),
+    /// the caller stopped waiting, timed out or dropped the request future
+    Cancel(i32),
+}
+
+/// Cancels the pending request unless its response was received, so requests timed out
+/// or abandoned by callers don't stay in the pending table
+struct PendingGuard<'a> {
+    opaque: i32,
+    registrations_tx: &'a mpsc::UnboundedSender<Registration>,
+    resolved: bool,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if !self.resolved {
+            let _ = self
+                .registrations_tx
+                .send(Registration::Cancel(self.opaque));
+        }
+    }
+}
+
+struct PendingRequest {
+    resolver: oneshot::Sender<RemotingCommand>,
+    deadline: Option<Instant>,
+    // injected once the response is received
+    #[cfg(feature = "fault-injection")]
+    fault: Option<Fault>,
+}
+
+impl PendingRequest {
+    #[cfg(test)]
+    fn new(resolver: oneshot::Sender<RemotingCommand>, deadline: Option<Instant>) -> Self {
+        Self {
+            resolver,
+            deadline,
+            #[cfg(feature = "fault-injection")]
+            fault: None,
+        }
+    }
+}
+
+/// Requests sent over a connection waiting for responses, keyed by opaque
+#[derive(Default)]
+struct PendingRequests {
+    requests: HashMap<i32, PendingRequest>,
+}
+
+impl PendingRequests {
+    fn insert(&mut self, opaque: i32, request: PendingRequest) {
+        if self.requests.insert(opaque, request).is_some() {
+            warn!(
+                opaque = opaque,
+                "duplicated opaque, previous pending request dropped"
+            );
+        }
+    }
+
+    /// Route a response to the request it belongs to, fails if a fault injected into the
+    /// request closes the connection
+    fn resolve(&mut self, response: RemotingCommand) -> Result<(), Error> {
+        let opaque = response.header.opaque;
+        match self.requests.remove(&opaque) {
+            Some(request) => {
+                #[cfg(feature = "fault-injection")]
+                if let Some(fault) = request.fault {
+                    return self.resolve_with_fault(request, fault, response);
+                }
+                let _ = request.resolver.send(response);
+            }
+            None => {
+                debug!(
+                    opaque = opaque,
+                    "no pending request found for response, maybe timed out"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver `response` with `fault` injected, fails if the connection is to be closed
+    #[cfg(feature = "fault-injection")]
+    fn resolve_with_fault(
+        &mut self,
+        request: PendingRequest,
+        fault: Fault,
+        mut response: RemotingCommand,
+    ) -> Result<(), Error> {
+        debug!(
+            opaque = response.header.opaque,
+            fault = ?fault,
+            "injecting fault into response"
+        );
+        match fault {
+            // left pending as if the response was lost, the caller times out
+            Fault::DropResponse => self.insert(response.header.opaque, request),
+            Fault::Delay(delay) => {
+                let resolver = request.resolver;
+                runtime::spawn(async move {
+                    runtime::sleep(delay).await;
+                    let _ = resolver.send(response);
+                });
+            }
+            Fault::Disconnect => return Err(Error::Connection(ConnectionError::Disconnected)),
+            Fault::CorruptFrame => {
+                fault::corrupt(&mut response);
+                let _ = request.resolver.send(response);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a request whose caller stopped waiting, a late response is discarded
+    fn cancel(&mut self, opaque: i32) {
+        self.requests.remove(&opaque);
+    }
+
+    /// Remove requests whose deadline passed or whose caller has gone away
+    fn remove_expired(&mut self, now: Instant) {
+        self.requests.retain(|_, request| {
+            !request.resolver.is_closed() && request.deadline.is_none_or(|d| d > now)
+        });
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.requests.len()
+    }
+}
 
 pub struct ConnectionSender {
     addr: String,
     tx: mpsc::UnboundedSender<RemotingCommand>,
-    registrations_tx: mpsc::UnboundedSender<(i32, oneshot::Sender<RemotingCommand>)>,
+    registrations_tx: mpsc::UnboundedSender<Registration>,
     receiver_shutdown: Option<oneshot::Sender<()>>,
     opaque_id: AtomicI32,
+    // slots of requests waiting for responses, writes and responses are multiplexed so
+    // callers only wait here when the connection is saturated
+    in_flight: Semaphore,
+    // last time a command was sent or received, shared with the receiver
+    last_active: Arc<Mutex<Instant>>,
+    hooks: RpcHooks,
+    #[cfg(feature = "fault-injection")]
+    faults: Faults,
 }
 
 impl fmt::Debug for ConnectionSender {
@@ -31,11 +187,13 @@ impl fmt::Debug for ConnectionSender {
 }
 
 impl ConnectionSender {
-    pub fn new(
+    fn new(
         addr: String,
         tx: mpsc::UnboundedSender<RemotingCommand>,
-        registrations_tx: mpsc::UnboundedSender<(i32, oneshot::Sender<RemotingCommand>)>,
+        registrations_tx: mpsc::UnboundedSender<Registration>,
         receiver_shutdown: oneshot::Sender<()>,
+        max_in_flight: usize,
+        hooks: RpcHooks,
     ) -> Self {
         Self {
             addr,
@@ -43,35 +201,136 @@ impl ConnectionSender {
             registrations_tx,
             receiver_shutdown: Some(receiver_shutdown),
             opaque_id: AtomicI32::new(1),
+            in_flight: Semaphore::new(max_in_flight.max(1)),
+            last_active: Arc::new(Mutex::new(Instant::now())),
+            hooks,
+            #[cfg(feature = "fault-injection")]
+            faults: Faults::default(),
         }
     }
 
-    #[tracing::instrument(skip(self, cmd))]
+    /// Inject faults decided by `faults` into requests sent
+    #[cfg(feature = "fault-injection")]
+    fn with_faults(mut self, faults: Faults) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// How long the connection has been idle, neither sending nor receiving any command
+    pub fn idle_time(&self) -> Duration {
+        self.last_active.lock().elapsed()
+    }
+
+    fn touch(&self) {
+        *self.last_active.lock() = Instant::now();
+    }
+
+    /// Allocate the opaque of a request sent over this connection, it's only required to be
+    /// unique among in-flight requests of the connection so the counter simply wraps around
+    fn next_opaque(&self) -> i32 {
+        self.opaque_id.fetch_add(1, Ordering::Relaxed)
+    }
+
     pub async fn send(&self, cmd: RemotingCommand) -> Result<RemotingCommand, Error> {
+        self.send_request(cmd, None).await
+    }
+
+    /// Send a request and wait for its response at most `timeout`
+    pub async fn send_timeout(
+        &self,
+        cmd: RemotingCommand,
+        timeout: Duration,
+    ) -> Result<RemotingCommand, Error> {
+        self.send_request(cmd, Some(timeout)).await
+    }
+
+    #[tracing::instrument(skip(self, cmd))]
+    async fn send_request(
+        &self,
+        cmd: RemotingCommand,
+        timeout: Option<Duration>,
+    ) -> Result<RemotingCommand, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // waiting for a slot counts against the timeout
+        let _permit = match deadline {
+            Some(deadline) => runtime::timeout_at(deadline, self.in_flight.acquire())
+                .await
+                .map_err(|_| Error::Timeout)?,
+            None => self.in_flight.acquire().await,
+        }
+        .map_err(|_| Error::Connection(ConnectionError::Disconnected))?;
         let (sender, receiver) = oneshot::channel();
         let mut cmd = cmd;
-        cmd.header.opaque = self.opaque_id.fetch_add(1, Ordering::SeqCst);
+        cmd.set_opaque(self.next_opaque());
+        self.touch();
         debug!(
             code = cmd.code(),
             opaque = cmd.header.opaque,
-            cmd = ?cmd,
             "sending remoting command to {}",
             &self.addr
         );
+        self.hooks.before_request(&self.addr, &cmd);
+        // kept for hooks called with the response
+        let hooked_request = if self.hooks.is_empty() {
+            None
+        } else {
+            Some(cmd.clone())
+        };
+        let request = PendingRequest {
+            resolver: sender,
+            deadline,
+            #[cfg(feature = "fault-injection")]
+            fault: self.faults.inject(&self.addr, &cmd),
+        };
+        let opaque = cmd.header.opaque;
         match (
-            self.registrations_tx.send((cmd.header.opaque, sender)),
+            self.registrations_tx
+                .send(Registration::Register(opaque, request)),
             self.tx.send(cmd),
         ) {
-            (Ok(_), Ok(_)) => receiver
+            (Ok(_), Ok(_)) => {}
+            _ => return Err(Error::Connection(ConnectionError::Disconnected)),
+        }
+        let mut guard = PendingGuard {
+            opaque,
+            registrations_tx: &self.registrations_tx,
+            resolved: false,
+        };
+        let res = match deadline {
+            Some(deadline) => runtime::timeout_at(deadline, receiver)
                 .await
-                .map_err(|_err| Error::Connection(ConnectionError::Disconnected)),
-            _ => Err(Error::Connection(ConnectionError::Disconnected)),
+                .map_err(|_| Error::Timeout)?,
+            None => receiver.await,
+        };
+        guard.resolved = res.is_ok();
+        let res = res.map_err(|_err| Error::Connection(ConnectionError::Disconnected))?;
+        if let Some(request) = hooked_request {
+            self.hooks.after_response(&self.addr, &request, &res);
         }
+        Ok(res)
+    }
+
+    /// Whether the connection was closed, either side failed or the remote hung up
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Reply to a request sent by the remote side, the opaque of the request is kept
+    pub fn send_response(&self, cmd: RemotingCommand) -> Result<(), Error> {
+        let mut cmd = cmd;
+        cmd.mark_response();
+        self.touch();
+        self.tx
+            .send(cmd)
+            .map_err(|_| Error::Connection(ConnectionError::Disconnected))
     }
 
     pub async fn send_oneway(&self, cmd: RemotingCommand) -> Result<(), Error> {
         let mut cmd = cmd;
-        cmd.header.opaque = self.opaque_id.fetch_add(1, Ordering::SeqCst);
+        cmd.set_opaque(self.next_opaque());
+        cmd.mark_oneway();
+        self.touch();
+        self.hooks.before_request(&self.addr, &cmd);
         self.tx
             .send(cmd)
             .map_err(|_| Error::Connection(ConnectionError::Disconnected))?;
@@ -84,9 +343,13 @@ struct Receiver<S: Stream<Item = Result<RemotingCommand, Error>>> {
     inbound: Pin<Box<S>>,
     // internal sender
     outbound: mpsc::UnboundedSender<RemotingCommand>,
-    pending_requests: HashMap<i32, oneshot::Sender<RemotingCommand>>,
-    registrations: Pin<Box<mpsc::UnboundedReceiver<(i32, oneshot::Sender<RemotingCommand>)>>>,
+    pending_requests: PendingRequests,
+    // requests initiated by the remote side
+    requests: mpsc::UnboundedSender<(String, RemotingCommand)>,
+    registrations: Pin<Box<mpsc::UnboundedReceiver<Registration>>>,
+    cleanup: Interval,
     shutdown: Pin<Box<oneshot::Receiver<()>>>,
+    last_active: Arc<Mutex<Instant>>,
 }
 
 impl<S: Stream<Item = Result<RemotingCommand, Error>>> Receiver<S> {
@@ -94,43 +357,59 @@ impl<S: Stream<Item = Result<RemotingCommand, Error>>> Receiver<S> {
         addr: String,
         inbound: S,
         outbound: mpsc::UnboundedSender<RemotingCommand>,
-        registrations: mpsc::UnboundedReceiver<(i32, oneshot::Sender<RemotingCommand>)>,
+        requests: mpsc::UnboundedSender<(String, RemotingCommand)>,
+        registrations: mpsc::UnboundedReceiver<Registration>,
         shutdown: oneshot::Receiver<()>,
+        last_active: Arc<Mutex<Instant>>,
     ) -> Receiver<S> {
         Self {
             addr,
             inbound: Box::pin(inbound),
             outbound,
-            pending_requests: HashMap::new(),
+            pending_requests: PendingRequests::default(),
+            requests,
             registrations: Box::pin(registrations),
+            cleanup: runtime::interval_at(
+                Instant::now() + PENDING_REQUESTS_CLEANUP_INTERVAL,
+                PENDING_REQUESTS_CLEANUP_INTERVAL,
+            ),
             shutdown: Box::pin(shutdown),
+            last_active,
         }
     }
 }
 
 impl<S: Stream<Item = Result<RemotingCommand, Error>>> Future for Receiver<S> {
-    type Output = Result<(), ()>;
+    /// The error the connection failed with, if any
+    type Output = Result<(), Error>;
 
     fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.shutdown.as_mut().poll(ctx) {
             Poll::Ready(Ok(())) | Poll::Ready(Err(..)) => {
-                return Poll::Ready(Err(()));
+                return Poll::Ready(Ok(()));
             }
             Poll::Pending => {}
         }
         loop {
             match self.registrations.as_mut().poll_recv(ctx) {
-                Poll::Ready(Some((opaque, resolver))) => {
-                    self.pending_requests.insert(opaque, resolver);
+                Poll::Ready(Some(Registration::Register(opaque, request))) => {
+                    self.pending_requests.insert(opaque, request);
                 }
-                Poll::Ready(None) => return Poll::Ready(Err(())),
+                Poll::Ready(Some(Registration::Cancel(opaque))) => {
+                    self.pending_requests.cancel(opaque);
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
                 Poll::Pending => break,
             }
         }
+        while let Poll::Ready(now) = self.cleanup.poll_tick(ctx) {
+            self.pending_requests.remove_expired(now);
+        }
         #[allow(clippy::never_loop)]
         loop {
             match self.inbound.as_mut().poll_next(ctx) {
                 Poll::Ready(Some(Ok(msg))) => {
+                    *self.last_active.lock() = Instant::now();
                     debug!(
                         code = msg.code(),
                         opaque = msg.header.opaque,
@@ -139,47 +418,172 @@ impl<S: Stream<Item = Result<RemotingCommand, Error>>> Future for Receiver<S> {
                         "received remoting command from {}",
                         &self.addr
                     );
-                    if msg.is_response_type() {
-                        if let Some(resolver) = self.pending_requests.remove(&msg.header.opaque) {
-                            let _ = resolver.send(msg);
+                    if msg.is_response() {
+                        if let Err(err) = self.pending_requests.resolve(msg) {
+                            warn!("closing connection to {}: {}", &self.addr, err);
+                            return Poll::Ready(Err(err));
                         }
                     } else {
-                        // FIXME: what to do?
+                        let addr = self.addr.clone();
+                        let _ = self.requests.send((addr, msg));
                     }
                 }
-                Poll::Ready(None) => return Poll::Ready(Err(())),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
                 Poll::Pending => return Poll::Pending,
-                Poll::Ready(Some(Err(_e))) => return Poll::Ready(Err(())),
+                Poll::Ready(Some(Err(err))) => {
+                    warn!(
+                        "decode remoting command from {} failed, closing connection: {}",
+                        &self.addr, err
+                    );
+                    return Poll::Ready(Err(err));
+                }
             }
         }
     }
 }
 
+/// Connect to `addr` trying each resolved address in turn, with the socket options applied,
+/// returns the stream and the address connected to
+async fn connect_tcp(
+    addr: &str,
+    options: &RemotingOptions,
+) -> Result<(TcpStream, SocketAddr), Error> {
+    let mut last_err = None;
+    for sock_addr in runtime::lookup_host(addr).await? {
+        match connect_socket(sock_addr, options).await {
+            Ok(stream) => return Ok((stream, sock_addr)),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address resolved"))
+        .into())
+}
+
+async fn connect_socket(addr: SocketAddr, options: &RemotingOptions) -> io::Result<TcpStream> {
+    runtime::connect(new_socket(addr, options)?, addr).await
+}
+
+/// Socket to connect to `addr` with, the socket options applied
+fn new_socket(addr: SocketAddr, options: &RemotingOptions) -> io::Result<socket2::Socket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    // buffer sizes must be set before connecting for the TCP window scale to take effect
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size as usize)?;
+    }
+    if let Some(size) = options.recv_buffer_size {
+        socket.set_recv_buffer_size(size as usize)?;
+    }
+    socket.set_nodelay(options.tcp_nodelay)?;
+    if let Some(interval) = options.tcp_keepalive {
+        let keepalive = socket2::TcpKeepalive::new().with_time(interval);
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "windows",
+        ))]
+        let keepalive = keepalive.with_interval(interval);
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(socket)
+}
+
 pub struct Connection {
     addr: String,
+    // IP the host name of `addr` resolved to when connecting, unknown through a proxy
+    peer_addr: Option<SocketAddr>,
     sender: ConnectionSender,
 }
 
 impl Connection {
-    pub async fn new(addr: &str) -> Result<Self, Error> {
-        let sender = Connection::prepare_stream(addr.to_string()).await?;
+    pub async fn new(
+        addr: &str,
+        requests: mpsc::UnboundedSender<(String, RemotingCommand)>,
+        options: &RemotingOptions,
+    ) -> Result<Self, Error> {
+        let (sender, peer_addr) =
+            Connection::prepare_stream(addr.to_string(), requests, options).await?;
+        #[cfg(feature = "fault-injection")]
+        let sender = sender.with_faults(options.faults.clone());
         Ok(Self {
             addr: addr.to_string(),
+            peer_addr,
             sender,
         })
     }
 
-    #[tracing::instrument(name = "connect")]
-    async fn prepare_stream(addr: String) -> Result<ConnectionSender, Error> {
+    #[tracing::instrument(name = "connect", skip(requests, options))]
+    async fn prepare_stream(
+        addr: String,
+        requests: mpsc::UnboundedSender<(String, RemotingCommand)>,
+        options: &RemotingOptions,
+    ) -> Result<(ConnectionSender, Option<SocketAddr>), Error> {
         info!("connecting to server");
-        let stream = TcpStream::connect(&addr)
+        let connect = async {
+            match &options.proxy {
+                Some(proxy) => {
+                    let (stream, _) = connect_tcp(proxy.addr(), options).await?;
+                    Ok((proxy.connect(stream, &addr).await?, None))
+                }
+                None => {
+                    let (stream, peer_addr) = connect_tcp(&addr, options).await?;
+                    Ok::<_, Error>((stream, Some(peer_addr)))
+                }
+            }
+        };
+        let (stream, peer_addr) = runtime::timeout(options.connect_timeout, connect)
             .await
-            .map(|stream| tokio_util::codec::Framed::new(stream, MqCodec))?;
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+        let codec = MqCodec::new(options.max_frame_length);
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = &options.tls {
+                let server_name = tls.server_name(&addr)?;
+                let stream = tls.connector()?.connect(server_name, stream).await?;
+                info!("server connected with tls");
+                let stream = tokio_util::codec::Framed::new(stream, codec);
+                let sender = Connection::connect(
+                    addr,
+                    stream,
+                    requests,
+                    options.max_in_flight_requests,
+                    options.events.clone(),
+                    options.hooks.clone(),
+                )
+                .await?;
+                return Ok((sender, peer_addr));
+            }
+        }
         info!("server connected");
-        Connection::connect(addr, stream).await
+        let stream = tokio_util::codec::Framed::new(stream, codec);
+        let sender = Connection::connect(
+            addr,
+            stream,
+            requests,
+            options.max_in_flight_requests,
+            options.events.clone(),
+            options.hooks.clone(),
+        )
+        .await?;
+        Ok((sender, peer_addr))
     }
 
-    async fn connect<S>(addr: String, stream: S) -> Result<ConnectionSender, Error>
+    async fn connect<S>(
+        addr: String,
+        stream: S,
+        requests: mpsc::UnboundedSender<(String, RemotingCommand)>,
+        max_in_flight: usize,
+        events: ConnectionEvents,
+        hooks: RpcHooks,
+    ) -> Result<ConnectionSender, Error>
     where
         S: Stream<Item = Result<RemotingCommand, Error>>,
         S: Sink<RemotingCommand, Error = Error>,
@@ -189,28 +593,66 @@ impl Connection {
         let (tx, mut rx) = mpsc::unbounded_channel();
         let (registrations_tx, registrations_rx) = mpsc::unbounded_channel();
         let (receiver_shutdown_tx, receiver_shutdown_rx) = oneshot::channel();
-        tokio::spawn(Box::pin(Receiver::new(
+        let sender = ConnectionSender::new(
             addr.clone(),
-            stream,
             tx.clone(),
+            registrations_tx,
+            receiver_shutdown_tx,
+            max_in_flight,
+            hooks,
+        );
+        let (closed_tx, mut closed_rx) = oneshot::channel::<()>();
+        let receiver = Receiver::new(
+            addr.clone(),
+            stream,
+            tx,
+            requests,
             registrations_rx,
             receiver_shutdown_rx,
-        )));
-        tokio::spawn(Box::pin(async move {
-            while let Some(msg) = rx.recv().await {
-                if let Err(_e) = sink.send(msg).await {
-                    // FIXME: error handling
+            Arc::clone(&sender.last_active),
+        );
+        let receiver_addr = addr.clone();
+        let receiver_events = events.clone();
+        runtime::spawn(async move {
+            let res = receiver.await;
+            // stop the writer once the read loop is gone
+            drop(closed_tx);
+            if let Err(err) = res {
+                receiver_events.failed(&receiver_addr, &err);
+            }
+            receiver_events.closed(&receiver_addr);
+        });
+        let writer_addr = addr;
+        runtime::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                    _ = &mut closed_rx => break,
+                };
+                if let Err(err) = sink.send(msg).await {
+                    warn!(
+                        "write remoting command to {} failed, closing connection: {}",
+                        &writer_addr, err
+                    );
+                    events.failed(&writer_addr, &err);
                     break;
                 }
             }
-        }));
-        let sender = ConnectionSender::new(addr, tx, registrations_tx, receiver_shutdown_tx);
+            debug!("connection writer to {} stopped", &writer_addr);
+        });
         Ok(sender)
     }
 
     pub fn sender(&self) -> &ConnectionSender {
         &self.sender
     }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
 }
 
 impl Drop for Connection {
@@ -221,3 +663,426 @@ impl Drop for Connection {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use std::time::Instant;
+
+    use tokio::sync::{mpsc, oneshot};
+    use tokio_util::codec::Framed;
+
+    use super::{
+        connect_tcp, new_socket, Connection, ConnectionEvents, ConnectionSender, PendingRequest,
+        PendingRequests, RpcHooks,
+    };
+    use crate::error::{ConnectionError, Error};
+    use crate::protocol::{MqCodec, RemotingCommand};
+    use crate::remoting::{ProxyConfig, RemotingOptions, RpcHook, DEFAULT_MAX_IN_FLIGHT_REQUESTS};
+    use crate::runtime;
+
+    #[tokio::test]
+    async fn test_apply_socket_options() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = RemotingOptions {
+            tcp_nodelay: false,
+            tcp_keepalive: Some(Duration::from_secs(30)),
+            send_buffer_size: Some(256 * 1024),
+            recv_buffer_size: Some(256 * 1024),
+            ..Default::default()
+        };
+        let socket = new_socket(addr, &options).unwrap();
+        assert!(!socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.send_buffer_size().unwrap() >= 256 * 1024);
+        assert!(socket.recv_buffer_size().unwrap() >= 256 * 1024);
+        let socket = new_socket(addr, &RemotingOptions::default()).unwrap();
+        assert!(socket.nodelay().unwrap());
+
+        let (_, peer_addr) = connect_tcp(&addr.to_string(), &options).await.unwrap();
+        assert_eq!(addr, peer_addr);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        // a proxy never completing the handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            futures::future::pending::<()>().await;
+        });
+        let options = RemotingOptions {
+            connect_timeout: Duration::from_millis(50),
+            proxy: Some(ProxyConfig::socks5(proxy_addr)),
+            ..Default::default()
+        };
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        match Connection::new("broker.internal:10911", requests_tx, &options).await {
+            Err(Error::Io(err)) => assert_eq!(std::io::ErrorKind::TimedOut, err.kind()),
+            _ => panic!("connect should time out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_concurrent_responses_by_opaque() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            ConnectionEvents::default(),
+            RpcHooks::default(),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(async move {
+            let mut server = Framed::new(server, MqCodec::default());
+            let first = server.next().await.unwrap().unwrap();
+            let second = server.next().await.unwrap().unwrap();
+            // reply in reverse order, echo the request code as remark
+            for req in [second, first] {
+                let mut res =
+                    RemotingCommand::new(0, 0, req.code().to_string(), HashMap::new(), Vec::new());
+                res.mark_response();
+                res.header.opaque = req.header.opaque;
+                server.send(res).await.unwrap();
+            }
+        });
+        let req1 = RemotingCommand::new(1, 0, String::new(), HashMap::new(), Vec::new());
+        let req2 = RemotingCommand::new(2, 0, String::new(), HashMap::new(), Vec::new());
+        let (res1, res2) = futures::join!(sender.send(req1), sender.send(req2));
+        assert_eq!("1", res1.unwrap().header.remark);
+        assert_eq!("2", res2.unwrap().header.remark);
+    }
+
+    #[derive(Default)]
+    struct RecordingHook {
+        calls: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl RpcHook for RecordingHook {
+        fn before_request(&self, addr: &str, request: &RemotingCommand) {
+            self.calls.lock().push(format!(
+                "before {} {} {}",
+                addr,
+                request.code(),
+                request.opaque()
+            ));
+        }
+
+        fn after_response(
+            &self,
+            addr: &str,
+            request: &RemotingCommand,
+            response: &RemotingCommand,
+        ) {
+            self.calls.lock().push(format!(
+                "after {} {} {} {}",
+                addr,
+                request.code(),
+                response.opaque(),
+                response.remark()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpc_hooks() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let recorder = Arc::new(RecordingHook::default());
+        let mut hooks = RpcHooks::default();
+        hooks.push(recorder.clone());
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            ConnectionEvents::default(),
+            hooks,
+        )
+        .await
+        .unwrap();
+        tokio::spawn(async move {
+            let mut server = Framed::new(server, MqCodec::default());
+            let req = server.next().await.unwrap().unwrap();
+            let mut res = RemotingCommand::new(0, 0, "ok".to_string(), HashMap::new(), Vec::new());
+            res.mark_response();
+            res.header.opaque = req.header.opaque;
+            server.send(res).await.unwrap();
+            server.next().await.unwrap().unwrap();
+        });
+        let req = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        sender.send(req).await.unwrap();
+        let req = RemotingCommand::new(11, 0, String::new(), HashMap::new(), Vec::new());
+        sender.send_oneway(req).await.unwrap();
+        assert_eq!(
+            vec!["before test 10 1", "after test 10 1 ok", "before test 11 2"],
+            *recorder.calls.lock()
+        );
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[tokio::test]
+    async fn test_inject_faults() {
+        use crate::remoting::{Fault, Faults};
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            ConnectionEvents::default(),
+            RpcHooks::default(),
+        )
+        .await
+        .unwrap()
+        .with_faults(Faults::new(Arc::new(
+            |_: &str, request: &RemotingCommand| match request.code() {
+                1 => Some(Fault::DropResponse),
+                2 => Some(Fault::Delay(Duration::from_millis(100))),
+                3 => Some(Fault::CorruptFrame),
+                4 => Some(Fault::Disconnect),
+                _ => None,
+            },
+        )));
+        // echo request bodies
+        tokio::spawn(async move {
+            let mut server = Framed::new(server, MqCodec::default());
+            while let Some(Ok(req)) = server.next().await {
+                let mut res =
+                    RemotingCommand::new(0, 0, String::new(), HashMap::new(), req.body().to_vec());
+                res.mark_response();
+                res.header.opaque = req.header.opaque;
+                server.send(res).await.unwrap();
+            }
+        });
+        let request =
+            |code| RemotingCommand::new(code, 0, String::new(), HashMap::new(), vec![1, 2]);
+
+        let res = sender.send(request(0)).await.unwrap();
+        assert_eq!(&[1, 2], res.body());
+        let res = sender
+            .send_timeout(request(1), Duration::from_millis(50))
+            .await;
+        assert!(matches!(res, Err(Error::Timeout)));
+        let start = Instant::now();
+        let res = sender.send(request(2)).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(&[1, 2], res.body());
+        let res = sender.send(request(3)).await.unwrap();
+        assert_eq!(&[!1, !2], res.body());
+        let res = sender.send(request(4)).await;
+        assert!(matches!(
+            res,
+            Err(Error::Connection(ConnectionError::Disconnected))
+        ));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(sender.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_cap_in_flight_requests() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            1,
+            ConnectionEvents::default(),
+            RpcHooks::default(),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(async move {
+            let mut server = Framed::new(server, MqCodec::default());
+            let first = server.next().await.unwrap().unwrap();
+            // the second request waits until the first one completes
+            assert!(
+                tokio::time::timeout(Duration::from_millis(50), server.next())
+                    .await
+                    .is_err()
+            );
+            let mut res =
+                RemotingCommand::new(0, 0, first.code().to_string(), HashMap::new(), Vec::new());
+            res.mark_response();
+            res.header.opaque = first.header.opaque;
+            server.send(res).await.unwrap();
+            let second = server.next().await.unwrap().unwrap();
+            let mut res =
+                RemotingCommand::new(0, 0, second.code().to_string(), HashMap::new(), Vec::new());
+            res.mark_response();
+            res.header.opaque = second.header.opaque;
+            server.send(res).await.unwrap();
+        });
+        let req1 = RemotingCommand::new(1, 0, String::new(), HashMap::new(), Vec::new());
+        let req2 = RemotingCommand::new(2, 0, String::new(), HashMap::new(), Vec::new());
+        let req3 = RemotingCommand::new(3, 0, String::new(), HashMap::new(), Vec::new());
+        let (res1, res2, res3) = futures::join!(
+            sender.send(req1),
+            sender.send(req2),
+            sender.send_timeout(req3, Duration::from_millis(10))
+        );
+        assert_eq!("1", res1.unwrap().header.remark);
+        assert_eq!("2", res2.unwrap().header.remark);
+        // timed out waiting for a slot
+        assert!(matches!(res3, Err(Error::Timeout)));
+        // every slot is released
+        assert_eq!(1, sender.in_flight.available_permits());
+    }
+
+    #[tokio::test]
+    async fn test_connection_closed_by_remote() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            ConnectionEvents::default(),
+            RpcHooks::default(),
+        )
+        .await
+        .unwrap();
+        assert!(!sender.is_closed());
+
+        let pending = tokio::spawn(async move {
+            let req = RemotingCommand::new(1, 0, String::new(), HashMap::new(), Vec::new());
+            let res = sender.send(req).await;
+            (sender, res)
+        });
+        let mut server = Framed::new(server, MqCodec::default());
+        server.next().await.unwrap().unwrap();
+        drop(server);
+
+        let (sender, res) = pending.await.unwrap();
+        assert!(matches!(
+            res,
+            Err(Error::Connection(ConnectionError::Disconnected))
+        ));
+        // marked closed by the receiver task, which runs on the runtime of the client
+        for _ in 0..100 {
+            if sender.is_closed() {
+                break;
+            }
+            runtime::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(sender.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_discards_late_response() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (requests_tx, _requests_rx) = mpsc::unbounded_channel();
+        let sender = Connection::connect(
+            "test".to_string(),
+            Framed::new(client, MqCodec::default()),
+            requests_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            ConnectionEvents::default(),
+            RpcHooks::default(),
+        )
+        .await
+        .unwrap();
+        let (late_tx, late_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut server = Framed::new(server, MqCodec::default());
+            let first = server.next().await.unwrap().unwrap();
+            // reply to the timed out request only after the second one is sent
+            let second = server.next().await.unwrap().unwrap();
+            for req in [first, second] {
+                let mut res =
+                    RemotingCommand::new(0, 0, req.code().to_string(), HashMap::new(), Vec::new());
+                res.mark_response();
+                res.header.opaque = req.header.opaque;
+                server.send(res).await.unwrap();
+            }
+            let _ = late_tx.send(());
+            server.next().await;
+        });
+        let req = RemotingCommand::new(1, 0, String::new(), HashMap::new(), Vec::new());
+        assert!(matches!(
+            sender.send_timeout(req, Duration::from_millis(10)).await,
+            Err(Error::Timeout)
+        ));
+
+        let req = RemotingCommand::new(2, 0, String::new(), HashMap::new(), Vec::new());
+        let res = sender.send(req).await.unwrap();
+        assert_eq!("2", res.header.remark);
+        late_rx.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_request() {
+        let mut pending = PendingRequests::default();
+        let (tx, rx) = oneshot::channel();
+        pending.insert(1, PendingRequest::new(tx, None));
+        pending.cancel(1);
+        assert_eq!(0, pending.len());
+
+        // late response is discarded
+        let mut res = RemotingCommand::new(0, 0, String::new(), HashMap::new(), Vec::new());
+        res.header.opaque = 1;
+        pending.resolve(res).unwrap();
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remove_expired_pending_requests() {
+        let now = Instant::now();
+        let mut pending = PendingRequests::default();
+        let (expired_tx, _expired_rx) = oneshot::channel();
+        pending.insert(1, PendingRequest::new(expired_tx, Some(now)));
+        let (abandoned_tx, abandoned_rx) = oneshot::channel();
+        pending.insert(2, PendingRequest::new(abandoned_tx, None));
+        drop(abandoned_rx);
+        let (alive_tx, alive_rx) = oneshot::channel();
+        pending.insert(
+            3,
+            PendingRequest::new(alive_tx, Some(now + Duration::from_secs(3))),
+        );
+        pending.remove_expired(now);
+        assert_eq!(1, pending.len());
+
+        let mut res = RemotingCommand::new(0, 0, String::new(), HashMap::new(), Vec::new());
+        res.header.opaque = 3;
+        pending.resolve(res).unwrap();
+        assert_eq!(0, pending.len());
+        assert_eq!(3, alive_rx.await.unwrap().header.opaque);
+    }
+
+    #[test]
+    fn test_opaque_wraps_around() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let (registrations_tx, _registrations_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, _shutdown_rx) = oneshot::channel();
+        let sender = ConnectionSender::new(
+            "test".to_string(),
+            tx,
+            registrations_tx,
+            shutdown_tx,
+            DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            RpcHooks::default(),
+        );
+        assert_eq!(1, sender.next_opaque());
+        assert_eq!(2, sender.next_opaque());
+
+        sender
+            .opaque_id
+            .store(i32::MAX, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(i32::MAX, sender.next_opaque());
+        assert_eq!(i32::MIN, sender.next_opaque());
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::protocol::RemotingCommand;
+
+/// Failure injected into a request sent to a broker or name server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The response is discarded, the caller times out as if it was lost
+    DropResponse,
+    /// The response is delivered after the delay
+    Delay(Duration),
+    /// The connection is closed once the response arrives, pending requests fail with
+    /// `ConnectionError::Disconnected`
+    Disconnect,
+    /// Every byte of the response body is flipped, so decoding it fails or yields garbage
+    CorruptFrame,
+}
+
+/// Decides faults injected into requests to verify how applications behave when brokers
+/// fail, e.g. with `ClientOptions::set_fault_injector`. Called inline by callers sending
+/// requests, oneway requests aren't subject to it
+pub trait FaultInjector: Send + Sync {
+    /// Fault injected into `request` about to be sent to `addr`, if any
+    fn inject(&self, addr: &str, request: &RemotingCommand) -> Option<Fault>;
+}
+
+impl<F> FaultInjector for F
+where
+    F: Fn(&str, &RemotingCommand) -> Option<Fault> + Send + Sync,
+{
+    fn inject(&self, addr: &str, request: &RemotingCommand) -> Option<Fault> {
+        self(addr, request)
+    }
+}
+
+/// Optional injector of faults into requests
+#[derive(Clone, Default)]
+pub(crate) struct Faults {
+    injector: Option<Arc<dyn FaultInjector>>,
+}
+
+impl fmt::Debug for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Faults")
+            .field("injector", &self.injector.is_some())
+            .finish()
+    }
+}
+
+impl Faults {
+    pub fn new(injector: Arc<dyn FaultInjector>) -> Self {
+        Self {
+            injector: Some(injector),
+        }
+    }
+
+    pub fn inject(&self, addr: &str, request: &RemotingCommand) -> Option<Fault> {
+        self.injector
+            .as_ref()
+            .and_then(|injector| injector.inject(addr, request))
+    }
+}
+
+/// Flip every byte of the body of `response`
+pub(crate) fn corrupt(response: &mut RemotingCommand) {
+    let body: Vec<u8> = response.body.iter().map(|b| !b).collect();
+    response.body = body.into();
+}
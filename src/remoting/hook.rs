@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use tracing::debug;
+
+use super::client::{SECURITY_TOKEN, SIGNATURE};
+use crate::protocol::RemotingCommand;
+
+/// Hook around requests sent to brokers and name servers, like `RPCHook` of the Java
+/// client. Methods are called inline by callers sending requests, they shouldn't block
+pub trait RpcHook: Send + Sync {
+    /// `request` is about to be sent to `addr`, signed and with its opaque allocated
+    fn before_request(&self, _addr: &str, _request: &RemotingCommand) {}
+
+    /// `response` to `request` was received from `addr`, not called for oneway requests
+    fn after_response(&self, _addr: &str, _request: &RemotingCommand, _response: &RemotingCommand) {
+    }
+}
+
+/// Logs requests and responses at debug level to diagnose protocol issues. ACL signatures
+/// and security tokens are redacted, bodies are logged by their sizes only
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingRpcHook;
+
+impl LoggingRpcHook {
+    fn redacted_fields(cmd: &RemotingCommand) -> BTreeMap<&str, &str> {
+        cmd.ext_fields()
+            .iter()
+            .map(|(key, value)| match key.as_str() {
+                SIGNATURE | SECURITY_TOKEN => (key.as_str(), "<redacted>"),
+                _ => (key.as_str(), value.as_str()),
+            })
+            .collect()
+    }
+}
+
+impl RpcHook for LoggingRpcHook {
+    fn before_request(&self, addr: &str, request: &RemotingCommand) {
+        debug!(
+            code = request.code(),
+            opaque = request.opaque(),
+            oneway = request.is_oneway(),
+            ext_fields = ?Self::redacted_fields(request),
+            body_size = request.body().len(),
+            "send request to {}",
+            addr
+        );
+    }
+
+    fn after_response(&self, addr: &str, request: &RemotingCommand, response: &RemotingCommand) {
+        debug!(
+            request_code = request.code(),
+            code = response.code(),
+            opaque = response.opaque(),
+            remark = response.remark(),
+            ext_fields = ?Self::redacted_fields(response),
+            body_size = response.body().len(),
+            "receive response from {}",
+            addr
+        );
+    }
+}
+
+/// Hooks called in the order they were added
+#[derive(Clone, Default)]
+pub(crate) struct RpcHooks {
+    hooks: Vec<Arc<dyn RpcHook>>,
+}
+
+impl fmt::Debug for RpcHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcHooks")
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl RpcHooks {
+    pub fn push(&mut self, hook: Arc<dyn RpcHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn before_request(&self, addr: &str, request: &RemotingCommand) {
+        for hook in &self.hooks {
+            hook.before_request(addr, request);
+        }
+    }
+
+    pub fn after_response(
+        &self,
+        addr: &str,
+        request: &RemotingCommand,
+        response: &RemotingCommand,
+    ) {
+        for hook in &self.hooks {
+            hook.after_response(addr, request, response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::LoggingRpcHook;
+    use crate::protocol::RemotingCommand;
+
+    #[test]
+    fn test_logging_hook_redacts_acl_fields() {
+        let mut fields = HashMap::new();
+        fields.insert("topic".to_string(), "test".to_string());
+        fields.insert("AccessKey".to_string(), "rocketmq".to_string());
+        fields.insert("SecurityToken".to_string(), "token".to_string());
+        fields.insert("Signature".to_string(), "c2lnbmF0dXJl".to_string());
+        let cmd = RemotingCommand::new(10, 0, String::new(), fields, b"secret".to_vec());
+        let redacted = LoggingRpcHook::redacted_fields(&cmd);
+        assert_eq!("test", redacted["topic"]);
+        assert_eq!("rocketmq", redacted["AccessKey"]);
+        assert_eq!("<redacted>", redacted["SecurityToken"]);
+        assert_eq!("<redacted>", redacted["Signature"]);
+    }
+}
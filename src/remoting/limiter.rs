@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::runtime;
+use crate::Error;
+
+/// Token bucket rate of requests
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    // tokens added per second
+    rate: f64,
+    burst: f64,
+}
+
+impl Quota {
+    fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            rate: requests_per_second.max(1) as f64,
+            burst: burst.max(1) as f64,
+        }
+    }
+}
+
+/// Limits of the rate requests are sent to brokers, a misbehaving retry loop can't
+/// saturate a broker when set
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    global: Option<Quota>,
+    per_broker: Option<Quota>,
+    blocking: bool,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            global: None,
+            per_broker: None,
+            blocking: true,
+        }
+    }
+}
+
+impl RateLimit {
+    /// No limits, add them with `set_global` and `set_per_broker`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit requests to all brokers to `requests_per_second` on average, allowing bursts
+    /// of up to `burst` requests
+    pub fn set_global(&mut self, requests_per_second: u32, burst: u32) -> &mut Self {
+        self.global = Some(Quota::new(requests_per_second, burst));
+        self
+    }
+
+    /// Limit requests to each broker to `requests_per_second` on average, allowing bursts
+    /// of up to `burst` requests
+    pub fn set_per_broker(&mut self, requests_per_second: u32, burst: u32) -> &mut Self {
+        self.per_broker = Some(Quota::new(requests_per_second, burst));
+        self
+    }
+
+    /// Wait until the budget allows sending a request, enabled by default. Requests fail
+    /// with `Error::Throttled` instead when disabled
+    pub fn set_blocking(&mut self, blocking: bool) -> &mut Self {
+        self.blocking = blocking;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    quota: Quota,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(quota: Quota, now: Instant) -> Self {
+        Self {
+            quota,
+            tokens: quota.burst,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.quota.rate).min(self.quota.burst);
+        self.updated_at = now;
+    }
+
+    /// How long until a token is available, zero if there's one already
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.quota.rate)
+        }
+    }
+}
+
+/// Token buckets of outbound requests, global and per broker address
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    global: Mutex<Option<TokenBucket>>,
+    brokers: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            global: Mutex::new(limit.global.map(|quota| TokenBucket::new(quota, now))),
+            brokers: Mutex::new(HashMap::new()),
+            limit,
+        }
+    }
+
+    /// Take a token for a request to `addr`, waiting for one in blocking mode
+    pub async fn acquire(&self, addr: &str) -> Result<(), Error> {
+        loop {
+            match self.try_acquire(addr, Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(wait) if self.limit.blocking => runtime::sleep(wait).await,
+                Err(_) => return Err(Error::Throttled),
+            }
+        }
+    }
+
+    /// Take a token from both the global and the broker bucket, or neither of them if
+    /// either is exhausted, returning how long until both have one
+    fn try_acquire(&self, addr: &str, now: Instant) -> Result<(), Duration> {
+        let mut global = self.global.lock();
+        let mut brokers = self.brokers.lock();
+        let broker = self.limit.per_broker.map(|quota| {
+            brokers
+                .entry(addr.to_string())
+                .or_insert_with(|| TokenBucket::new(quota, now))
+        });
+        let mut buckets: Vec<&mut TokenBucket> = global.iter_mut().chain(broker).collect();
+        let mut wait = Duration::from_secs(0);
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time());
+        }
+        if wait > Duration::from_secs(0) {
+            return Err(wait);
+        }
+        for bucket in buckets {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimit, RateLimiter};
+    use crate::Error;
+
+    #[test]
+    fn test_global_and_per_broker_limits() {
+        let mut limit = RateLimit::new();
+        limit.set_global(10, 3).set_per_broker(10, 2);
+        let limiter = RateLimiter::new(limit);
+        let now = Instant::now();
+        assert!(limiter.try_acquire("broker-a", now).is_ok());
+        assert!(limiter.try_acquire("broker-a", now).is_ok());
+        // broker budget exhausted, the global one isn't taken
+        assert!(limiter.try_acquire("broker-a", now).is_err());
+        assert!(limiter.try_acquire("broker-b", now).is_ok());
+        // global budget exhausted
+        let wait = limiter.try_acquire("broker-c", now).unwrap_err();
+        assert_eq!(Duration::from_millis(100), wait);
+
+        let now = now + Duration::from_millis(100);
+        assert!(limiter.try_acquire("broker-c", now).is_ok());
+        assert!(limiter.try_acquire("broker-c", now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_blocking_and_non_blocking() {
+        let mut limit = RateLimit::new();
+        limit.set_per_broker(50, 1);
+        let limiter = RateLimiter::new(limit.clone());
+        limiter.acquire("broker-a").await.unwrap();
+        let start = Instant::now();
+        limiter.acquire("broker-a").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(15));
+
+        limit.set_blocking(false);
+        let limiter = RateLimiter::new(limit);
+        limiter.acquire("broker-a").await.unwrap();
+        assert!(matches!(
+            limiter.acquire("broker-a").await,
+            Err(Error::Throttled)
+        ));
+    }
+}
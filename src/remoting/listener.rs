@@ -0,0 +1,73 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Error;
+
+/// Listener of connection lifecycle events of brokers and name servers, e.g. to log or
+/// alert on flapping connections. Methods are called inline by connection tasks, they
+/// shouldn't block
+pub trait ConnectionListener: Send + Sync {
+    /// Connected to `addr`
+    fn on_connect(&self, _addr: &str) {}
+
+    /// Connection to `addr` closed, either side hung up or it failed
+    fn on_close(&self, _addr: &str) {}
+
+    /// Connecting to `addr` failed, or reading from or writing to it failed
+    fn on_error(&self, _addr: &str, _err: &Error) {}
+
+    /// Connection to `addr` is closed for being idle
+    fn on_idle(&self, _addr: &str) {}
+}
+
+/// Optional listener notified of connection events
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionEvents {
+    listener: Option<Arc<dyn ConnectionListener>>,
+}
+
+impl fmt::Debug for ConnectionEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionEvents")
+            .field("listener", &self.listener.is_some())
+            .finish()
+    }
+}
+
+impl ConnectionEvents {
+    pub fn new(listener: Arc<dyn ConnectionListener>) -> Self {
+        Self {
+            listener: Some(listener),
+        }
+    }
+
+    pub fn connected(&self, addr: &str) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_connected();
+        if let Some(listener) = &self.listener {
+            listener.on_connect(addr);
+        }
+    }
+
+    pub fn closed(&self, addr: &str) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_closed();
+        if let Some(listener) = &self.listener {
+            listener.on_close(addr);
+        }
+    }
+
+    pub fn failed(&self, addr: &str, err: &Error) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_connection_error();
+        if let Some(listener) = &self.listener {
+            listener.on_error(addr, err);
+        }
+    }
+
+    pub fn idle(&self, addr: &str) {
+        if let Some(listener) = &self.listener {
+            listener.on_idle(addr);
+        }
+    }
+}
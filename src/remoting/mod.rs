@@ -1,4 +1,27 @@
 mod client;
 mod connection;
+#[cfg(feature = "fault-injection")]
+mod fault;
+mod hook;
+mod limiter;
+mod listener;
+mod proxy;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use client::RemotingClient;
+pub use client::{
+    RemotingClient, RemotingOptions, DEFAULT_CONNECTION_IDLE_TIMEOUT, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+};
+#[cfg(feature = "fault-injection")]
+pub(crate) use fault::Faults;
+#[cfg(feature = "fault-injection")]
+pub use fault::{Fault, FaultInjector};
+pub(crate) use hook::RpcHooks;
+pub use hook::{LoggingRpcHook, RpcHook};
+pub use limiter::RateLimit;
+pub(crate) use listener::ConnectionEvents;
+pub use listener::ConnectionListener;
+pub use proxy::ProxyConfig;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
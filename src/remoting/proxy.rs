@@ -0,0 +1,145 @@
+use std::fmt;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::Error;
+
+/// SOCKS5 proxy broker and name server connections are made through
+#[derive(Clone)]
+pub struct ProxyConfig {
+    addr: String,
+    // username and password
+    credentials: Option<(String, String)>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("addr", &self.addr)
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|(username, _)| username),
+            )
+            .finish()
+    }
+}
+
+impl ProxyConfig {
+    /// SOCKS5 proxy listening on `addr` in `host:port` form, host names of brokers and
+    /// name servers are resolved by the proxy
+    pub fn socks5<S: Into<String>>(addr: S) -> Self {
+        Self {
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticate to the proxy with username and password
+    pub fn set_credentials<S: Into<String>>(&mut self, username: S, password: S) -> &mut Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    pub(crate) fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Ask the proxy to connect to `target` over `stream`, a connection to the proxy
+    pub(crate) async fn connect<S>(&self, stream: S, target: &str) -> Result<S, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let stream = match &self.credentials {
+            Some((username, password)) => {
+                Socks5Stream::connect_with_password_and_socket(stream, target, username, password)
+                    .await
+            }
+            None => Socks5Stream::connect_with_socket(stream, target).await,
+        };
+        match stream {
+            Ok(stream) => Ok(stream.into_inner()),
+            Err(tokio_socks::Error::Io(err)) => Err(Error::Io(err)),
+            Err(err) => Err(Error::Proxy(err.to_string())),
+        }
+    }
+
+    /// Proxy of HTTP requests, used by name server resolvers
+    pub(crate) fn http_proxy(&self) -> Result<reqwest::Proxy, Error> {
+        let proxy = reqwest::Proxy::all(format!("socks5h://{}", self.addr))
+            .map_err(|err| Error::Proxy(err.to_string()))?;
+        Ok(match &self.credentials {
+            Some((username, password)) => proxy.basic_auth(username, password),
+            None => proxy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio_util::codec::Framed;
+
+    use super::ProxyConfig;
+    use crate::protocol::{MqCodec, RemotingCommand, ResponseCode};
+    use crate::remoting::{RemotingClient, RemotingOptions};
+
+    #[tokio::test]
+    async fn test_connect_through_socks5_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap().to_string();
+        let (target_tx, target_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // greeting, no authentication
+            let mut buf = [0u8; 3];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert_eq!([5, 1, 0], buf);
+            socket.write_all(&[5, 0]).await.unwrap();
+            // connect to a domain name target
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert_eq!([5, 1, 0, 3], buf[..4]);
+            let mut host = vec![0u8; buf[4] as usize];
+            socket.read_exact(&mut host).await.unwrap();
+            let port = socket.read_u16().await.unwrap();
+            let _ = target_tx.send(format!("{}:{}", String::from_utf8(host).unwrap(), port));
+            socket
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+            // act as the broker
+            let mut framed = Framed::new(socket, MqCodec::default());
+            while let Some(Ok(req)) = framed.next().await {
+                let mut res =
+                    RemotingCommand::response(ResponseCode::Success, String::new(), Vec::new());
+                res.set_opaque(req.opaque());
+                framed.send(res).await.unwrap();
+            }
+        });
+
+        let options = RemotingOptions {
+            proxy: Some(ProxyConfig::socks5(proxy_addr)),
+            ..Default::default()
+        };
+        let client = RemotingClient::with_options(None, options);
+        let cmd = RemotingCommand::new(10, 0, String::new(), HashMap::new(), Vec::new());
+        let res = client.invoke("broker.internal:10911", cmd).await.unwrap();
+        assert!(res.code() == ResponseCode::Success);
+        assert_eq!("broker.internal:10911", target_rx.await.unwrap());
+    }
+
+    #[test]
+    fn test_http_proxy() {
+        let mut proxy = ProxyConfig::socks5("127.0.0.1:1080");
+        assert!(proxy.http_proxy().is_ok());
+        proxy.set_credentials("user", "password");
+        assert!(proxy.http_proxy().is_ok());
+        assert!(!format!("{:?}", proxy).contains("password"));
+    }
+}
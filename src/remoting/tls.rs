@@ -0,0 +1,181 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::Error;
+
+/// TLS settings of connections to brokers and name servers
+pub struct TlsConfig {
+    roots: RootCertStore,
+    // certificate chain and private key presented for mutual TLS
+    client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+    server_name: Option<String>,
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("roots", &self.roots.len())
+            .field("client_auth", &self.client_auth.is_some())
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+impl Clone for TlsConfig {
+    fn clone(&self) -> Self {
+        Self {
+            roots: self.roots.clone(),
+            client_auth: self
+                .client_auth
+                .as_ref()
+                .map(|(certs, key)| (certs.clone(), key.clone_key())),
+            server_name: self.server_name.clone(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConfig {
+    /// Verify servers against the Mozilla root certificates
+    pub fn new() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Self {
+            roots,
+            client_auth: None,
+            server_name: None,
+        }
+    }
+
+    /// Verify servers only against the PEM encoded CA certificates, usually self-signed ones
+    pub fn with_root_certificates_pem(pem: &[u8]) -> Result<Self, Error> {
+        let mut config = Self {
+            roots: RootCertStore::empty(),
+            client_auth: None,
+            server_name: None,
+        };
+        config.add_root_certificates_pem(pem)?;
+        Ok(config)
+    }
+
+    /// Trust the PEM encoded CA certificates in addition to the current ones
+    pub fn add_root_certificates_pem(&mut self, pem: &[u8]) -> Result<&mut Self, Error> {
+        let certs = parse_certificates(pem)?;
+        if certs.is_empty() {
+            return Err(Error::Tls("no certificate found".to_string()));
+        }
+        for cert in certs {
+            self.roots
+                .add(cert)
+                .map_err(|err| Error::Tls(err.to_string()))?;
+        }
+        Ok(self)
+    }
+
+    /// Present the PEM encoded certificate chain and private key to servers requiring
+    /// mutual TLS
+    pub fn set_client_auth_pem(
+        &mut self,
+        cert_chain_pem: &[u8],
+        key_pem: &[u8],
+    ) -> Result<&mut Self, Error> {
+        let certs = parse_certificates(cert_chain_pem)?;
+        if certs.is_empty() {
+            return Err(Error::Tls("no client certificate found".to_string()));
+        }
+        let key = PrivateKeyDer::from_pem_slice(key_pem)
+            .map_err(|err| Error::Tls(format!("invalid client private key: {}", err)))?;
+        self.client_auth = Some((certs, key));
+        Ok(self)
+    }
+
+    /// Set the name sent as SNI and verified against server certificates, defaults to
+    /// the host of the address connected to
+    pub fn set_server_name<S: Into<String>>(&mut self, server_name: S) -> &mut Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    pub(crate) fn connector(&self) -> Result<TlsConnector, Error> {
+        let builder = ClientConfig::builder().with_root_certificates(self.roots.clone());
+        let config = match &self.client_auth {
+            Some((certs, key)) => builder
+                .with_client_auth_cert(certs.clone(), key.clone_key())
+                .map_err(|err| Error::Tls(err.to_string()))?,
+            None => builder.with_no_client_auth(),
+        };
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    /// Server name of a `host:port` address
+    pub(crate) fn server_name(&self, addr: &str) -> Result<ServerName<'static>, Error> {
+        let host = match &self.server_name {
+            Some(name) => name.as_str(),
+            None => {
+                let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+                host.trim_start_matches('[').trim_end_matches(']')
+            }
+        };
+        ServerName::try_from(host.to_string())
+            .map_err(|_| Error::Tls(format!("invalid server name: {}", host)))
+    }
+}
+
+fn parse_certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, Error> {
+    CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| Error::Tls(format!("invalid certificate: {}", err)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::TlsConfig;
+    use crate::Error;
+
+    #[test]
+    fn test_server_name_from_addr() {
+        let mut config = TlsConfig::new();
+        assert_eq!(
+            "broker.example.com",
+            config
+                .server_name("broker.example.com:10911")
+                .unwrap()
+                .to_str()
+        );
+        assert_eq!(
+            "127.0.0.1",
+            config.server_name("127.0.0.1:10911").unwrap().to_str()
+        );
+        assert_eq!("::1", config.server_name("[::1]:10911").unwrap().to_str());
+
+        config.set_server_name("rocketmq.example.com");
+        assert_eq!(
+            "rocketmq.example.com",
+            config.server_name("127.0.0.1:10911").unwrap().to_str()
+        );
+    }
+
+    #[test]
+    fn test_invalid_pem() {
+        assert!(matches!(
+            TlsConfig::with_root_certificates_pem(b"not a certificate"),
+            Err(Error::Tls(_))
+        ));
+        let mut config = TlsConfig::new();
+        assert!(matches!(
+            config.set_client_auth_pem(b"", b""),
+            Err(Error::Tls(_))
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+//! Minimal DNS client looking up SRV records over UDP
+
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_RESPONSE: u16 = 0x8000;
+const HEADER_LEN: usize = 12;
+const MAX_RESPONSE_LEN: usize = 4096;
+const MAX_POINTERS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+    pub ttl: u32,
+}
+
+impl SrvRecord {
+    /// `host:port` address of the target
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.target.trim_end_matches('.'), self.port)
+    }
+}
+
+/// Name servers of `/etc/resolv.conf`
+pub(crate) fn system_name_servers() -> io::Result<Vec<SocketAddr>> {
+    let conf = fs::read_to_string("/etc/resolv.conf")?;
+    let servers: Vec<SocketAddr> = conf
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("nameserver") => parts.next()?.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect();
+    if servers.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no DNS name server configured",
+        ));
+    }
+    Ok(servers)
+}
+
+/// Look up SRV records of `name`, asking `name_servers` in order until one of them answers.
+/// Blocks the current thread
+pub(crate) fn lookup_srv(
+    name: &str,
+    name_servers: &[SocketAddr],
+    timeout: Duration,
+) -> io::Result<Vec<SrvRecord>> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "no DNS name server configured");
+    for server in name_servers {
+        match query(name, *server, timeout) {
+            Ok(records) => return Ok(records),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn query(name: &str, server: SocketAddr, timeout: Duration) -> io::Result<Vec<SrvRecord>> {
+    let bind_addr: SocketAddr = if server.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(server)?;
+    let id: u16 = rand::random();
+    socket.send(&encode_query(id, name)?)?;
+    let mut buf = vec![0; MAX_RESPONSE_LEN];
+    loop {
+        let len = socket.recv(&mut buf)?;
+        // ignore stale responses of earlier queries
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return decode_response(&buf[..len]);
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn encode_query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // one question, no answer, authority or additional records
+    buf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid DNS name {}", name),
+            ));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(buf)
+}
+
+fn decode_response(buf: &[u8]) -> io::Result<Vec<SrvRecord>> {
+    if buf.len() < HEADER_LEN {
+        return Err(invalid_data("DNS response too short"));
+    }
+    let flags = read_u16(buf, 2)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return Err(invalid_data("not a DNS response"));
+    }
+    match flags & 0x000f {
+        0 => {}
+        3 => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "DNS name not found",
+            ))
+        }
+        rcode => {
+            return Err(io::Error::other(format!(
+                "DNS query failed with rcode {}",
+                rcode
+            )))
+        }
+    }
+    let questions = read_u16(buf, 4)?;
+    let answers = read_u16(buf, 6)?;
+    let mut pos = HEADER_LEN;
+    for _ in 0..questions {
+        pos = read_name(buf, pos)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = read_name(buf, pos)?.1;
+        let rtype = read_u16(buf, pos)?;
+        let ttl = read_u32(buf, pos + 4)?;
+        let rdlength = read_u16(buf, pos + 8)? as usize;
+        let rdata = pos + 10;
+        pos = rdata + rdlength;
+        if pos > buf.len() {
+            return Err(invalid_data("DNS record out of bounds"));
+        }
+        // CNAMEs of the name may come along
+        if rtype != TYPE_SRV {
+            continue;
+        }
+        records.push(SrvRecord {
+            priority: read_u16(buf, rdata)?,
+            weight: read_u16(buf, rdata + 2)?,
+            port: read_u16(buf, rdata + 4)?,
+            target: read_name(buf, rdata + 6)?.0,
+            ttl,
+        });
+    }
+    Ok(records)
+}
+
+/// Read the possibly compressed name at `pos`, returns it with the position after it
+fn read_name(buf: &[u8], mut pos: usize) -> io::Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *buf
+            .get(pos)
+            .ok_or_else(|| invalid_data("DNS name out of bounds"))? as usize;
+        if len & 0xc0 == 0xc0 {
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                return Err(invalid_data("DNS name compression loop"));
+            }
+            end.get_or_insert(pos + 2);
+            pos = (read_u16(buf, pos)? & 0x3fff) as usize;
+        } else if len == 0 {
+            let end = end.unwrap_or(pos + 1);
+            return Ok((labels.join("."), end));
+        } else {
+            let label = buf
+                .get(pos + 1..pos + 1 + len)
+                .ok_or_else(|| invalid_data("DNS label out of bounds"))?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> io::Result<u16> {
+    buf.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid_data("DNS response truncated"))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> io::Result<u32> {
+    buf.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("DNS response truncated"))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{decode_response, encode_query, SrvRecord};
+
+    /// Response to `query` answering SRV records, targets compressed against the question
+    pub(crate) fn encode_response(query: &[u8], records: &[(u16, u16, u16, &str, u32)]) -> Vec<u8> {
+        let mut buf = query.to_vec();
+        buf[2] |= 0x80;
+        buf[7] = records.len() as u8;
+        for (priority, weight, port, host, ttl) in records {
+            // pointer to the question name
+            buf.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1]);
+            buf.extend_from_slice(&ttl.to_be_bytes());
+            let mut rdata = Vec::new();
+            rdata.extend_from_slice(&priority.to_be_bytes());
+            rdata.extend_from_slice(&weight.to_be_bytes());
+            rdata.extend_from_slice(&port.to_be_bytes());
+            rdata.push(host.len() as u8);
+            rdata.extend_from_slice(host.as_bytes());
+            // the host is a label under the question name
+            rdata.extend_from_slice(&[0xc0, 12]);
+            buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            buf.extend_from_slice(&rdata);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_decode_srv_response() {
+        let query = encode_query(7, "_rocketmq._tcp.cluster.internal").unwrap();
+        let response = encode_response(&query, &[(10, 5, 9876, "ns1", 30)]);
+        let records = decode_response(&response).unwrap();
+        assert_eq!(
+            vec![SrvRecord {
+                priority: 10,
+                weight: 5,
+                port: 9876,
+                target: "ns1._rocketmq._tcp.cluster.internal".to_string(),
+                ttl: 30,
+            }],
+            records
+        );
+        assert_eq!(
+            "ns1._rocketmq._tcp.cluster.internal:9876",
+            records[0].address()
+        );
+        assert!(decode_response(&query).is_err());
+        assert!(decode_response(&response[..response.len() - 3]).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Name servers of a Kubernetes Service, from its EndpointSlices on the API server
+
+use std::env;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::NsResolver;
+use crate::remoting::TlsConfig;
+use crate::{runtime, Error};
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Kubernetes resolver, resolves addresses of the ready pods behind a Service, like the
+/// name server StatefulSet, from its EndpointSlices. It polls rather than watches: the
+/// EndpointSlices are listed again every refresh interval, 30 seconds by default, so the
+/// client follows pods restarted with new IPs within an interval
+#[derive(Debug, Clone)]
+pub struct KubernetesResolver {
+    api_server: String,
+    namespace: String,
+    service: String,
+    port_name: Option<String>,
+    token: Option<String>,
+    tls: Option<TlsConfig>,
+    refresh_interval: Duration,
+}
+
+impl KubernetesResolver {
+    /// Resolve `service` in the namespace of the pod, authenticated with its service account
+    pub fn in_cluster(service: &str) -> Result<Self, Error> {
+        let host = env::var("KUBERNETES_SERVICE_HOST").map_err(|_| {
+            io::Error::new(io::ErrorKind::NotFound, "KUBERNETES_SERVICE_HOST not set")
+        })?;
+        let port = env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host
+        };
+        let read = |name: &str| fs::read(format!("{}/{}", SERVICE_ACCOUNT_DIR, name));
+        let namespace = String::from_utf8(read("namespace")?)?;
+        let token = String::from_utf8(read("token")?)?;
+        let mut tls = TlsConfig::with_root_certificates_pem(&read("ca.crt")?)?;
+        // the API server certificate is issued for the service name, not the cluster IP
+        tls.set_server_name("kubernetes.default.svc");
+        let mut resolver = Self::new(
+            format!("{}:{}", host, port),
+            namespace.trim().to_string(),
+            service.to_string(),
+        );
+        resolver.set_token(token.trim().to_string());
+        resolver.set_tls_config(tls);
+        Ok(resolver)
+    }
+
+    /// Resolve `service` in `namespace` with the API server at `api_server` in `host:port`
+    /// form, over plain HTTP unless a TLS config is set, e.g. through `kubectl proxy`
+    pub fn new(api_server: String, namespace: String, service: String) -> Self {
+        Self {
+            api_server,
+            namespace,
+            service,
+            port_name: None,
+            token: None,
+            tls: None,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+        }
+    }
+
+    /// Bearer token to authenticate with
+    pub fn set_token(&mut self, token: String) -> &mut Self {
+        self.token = Some(token);
+        self
+    }
+
+    pub fn set_tls_config(&mut self, tls: TlsConfig) -> &mut Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Interval of listing the EndpointSlices again, 30 seconds by default
+    pub fn set_refresh_interval(&mut self, interval: Duration) -> &mut Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Name of the Service port of name servers, defaults to the first port
+    pub fn set_port_name(&mut self, port_name: String) -> &mut Self {
+        self.port_name = Some(port_name);
+        self
+    }
+
+    pub(crate) fn cache_key(&self) -> String {
+        format!("k8s:{}/{}", self.namespace, self.service)
+    }
+
+    fn path(&self) -> String {
+        format!(
+            "/apis/discovery.k8s.io/v1/namespaces/{}/endpointslices?labelSelector=kubernetes.io%2Fservice-name%3D{}",
+            self.namespace, self.service
+        )
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let mut last_err = None;
+        let mut stream = None;
+        for addr in runtime::lookup_host(&self.api_server).await? {
+            let socket = socket2::Socket::new(
+                socket2::Domain::for_address(addr),
+                socket2::Type::STREAM,
+                Some(socket2::Protocol::TCP),
+            )?;
+            match runtime::connect(socket, addr).await {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        let stream = stream.ok_or_else(|| {
+            last_err.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "no address resolved")
+            })
+        })?;
+        match &self.tls {
+            Some(tls) => {
+                let server_name = tls.server_name(&self.api_server)?;
+                let stream = tls.connector()?.connect(server_name, stream).await?;
+                self.request(stream, path).await
+            }
+            None => self.request(stream, path).await,
+        }
+    }
+
+    /// Send a HTTP/1.0 request so the response is delimited by the end of the stream
+    /// rather than chunked
+    async fn request<S>(&self, mut stream: S, path: &str) -> Result<Vec<u8>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n",
+            path, self.api_server
+        );
+        if let Some(token) = &self.token {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+        let mut response = Vec::new();
+        match stream.read_to_end(&mut response).await {
+            Ok(_) => {}
+            // servers closing without TLS close_notify
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && !response.is_empty() => {}
+            Err(err) => return Err(err.into()),
+        }
+        parse_response(response)
+    }
+}
+
+#[async_trait]
+impl NsResolver for KubernetesResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        let body = runtime::timeout(API_TIMEOUT, self.get(&self.path()))
+            .await
+            .map_err(|_| Error::Timeout)??;
+        let list: EndpointSliceList = serde_json::from_slice(&body)?;
+        Ok(list.addrs(self.port_name.as_deref()))
+    }
+
+    fn description(&self) -> &'static str {
+        "kubernetes resolver"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+}
+
+/// Body of a HTTP response, fails unless its status is 200
+fn parse_response(mut response: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP response"))?;
+    let status = String::from_utf8_lossy(&response[..header_end])
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP status line"))?;
+    let body = response.split_off(header_end + 4);
+    if status != 200 {
+        return Err(io::Error::other(format!(
+            "kubernetes API server responded {}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ))
+        .into());
+    }
+    Ok(body)
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointSliceList {
+    #[serde(default)]
+    items: Vec<EndpointSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointSlice {
+    #[serde(default)]
+    endpoints: Vec<Endpoint>,
+    #[serde(default)]
+    ports: Option<Vec<EndpointPort>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Endpoint {
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    conditions: EndpointConditions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct EndpointConditions {
+    // unknown readiness is treated as ready
+    ready: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointPort {
+    name: Option<String>,
+    port: Option<u16>,
+}
+
+impl EndpointSliceList {
+    /// `ip:port` addresses of ready endpoints
+    fn addrs(&self, port_name: Option<&str>) -> Vec<String> {
+        let mut addrs = Vec::new();
+        for slice in &self.items {
+            let ports = slice.ports.as_deref().unwrap_or_default();
+            let port = match port_name {
+                Some(name) => ports.iter().find(|port| port.name.as_deref() == Some(name)),
+                None => ports.first(),
+            };
+            let port = match port.and_then(|port| port.port) {
+                Some(port) => port,
+                None => continue,
+            };
+            for endpoint in &slice.endpoints {
+                if endpoint.conditions.ready == Some(false) {
+                    continue;
+                }
+                for ip in &endpoint.addresses {
+                    let addr = if ip.contains(':') {
+                        format!("[{}]:{}", ip, port)
+                    } else {
+                        format!("{}:{}", ip, port)
+                    };
+                    if !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
+            }
+        }
+        addrs.sort();
+        addrs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use std::time::Duration;
+
+    use super::KubernetesResolver;
+    use crate::resolver::{NsResolver, Resolver};
+
+    const ENDPOINT_SLICES: &str = r#"{
+        "kind": "EndpointSliceList",
+        "items": [{
+            "addressType": "IPv4",
+            "endpoints": [
+                {"addresses": ["10.0.0.2"], "conditions": {"ready": true}},
+                {"addresses": ["10.0.0.1"], "conditions": {"ready": true}},
+                {"addresses": ["10.0.0.3"], "conditions": {"ready": false}}
+            ],
+            "ports": [{"name": "metrics", "port": 5557}, {"name": "main", "port": 9876}]
+        }]
+    }"#;
+
+    #[tokio::test]
+    async fn test_kubernetes_resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+                ENDPOINT_SLICES
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut resolver =
+            KubernetesResolver::new(addr, "rocketmq".to_string(), "rocketmq-namesrv".to_string());
+        resolver
+            .set_token("token".to_string())
+            .set_port_name("main".to_string());
+        assert_eq!(
+            vec!["10.0.0.1:9876", "10.0.0.2:9876"],
+            resolver.resolve().await.unwrap()
+        );
+        let request = server.await.unwrap();
+        assert!(request.starts_with(
+            "GET /apis/discovery.k8s.io/v1/namespaces/rocketmq/endpointslices?labelSelector=kubernetes.io%2Fservice-name%3Drocketmq-namesrv HTTP/1.0\r\n"
+        ));
+        assert!(request.contains("Authorization: Bearer token\r\n"));
+
+        assert_eq!(Duration::from_secs(30), resolver.refresh_interval());
+        resolver.set_refresh_interval(Duration::from_secs(10));
+        assert_eq!(
+            Duration::from_secs(10),
+            Resolver::Kubernetes(resolver).refresh_interval()
+        );
+    }
+}
@@ -0,0 +1,874 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::remoting::ProxyConfig;
+use crate::{runtime, Error};
+
+mod dns;
+#[cfg(feature = "k8s")]
+mod k8s;
+
+#[cfg(feature = "k8s")]
+pub use k8s::KubernetesResolver;
+
+const DEFAULT_NAMESRV_ADDR: &'static str = "http://jmenv.tbsite.net:8080/rocketmq/nsaddr";
+const NAMESRV_ADDR_ENV: &str = "NAMESRV_ADDR";
+const DNS_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(2 * 60);
+// SRV records with shorter TTLs aren't queried more often than this
+const MIN_SRV_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// RocketMQ name server resolver trait, resolving is async so resolvers doing network I/O
+/// don't block the runtime. Synchronous resolvers can be adapted with [`FnResolver`]
+#[async_trait]
+pub trait NsResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error>;
+    fn description(&self) -> &'static str;
+
+    /// Interval of resolving name servers again
+    fn refresh_interval(&self) -> Duration {
+        DEFAULT_REFRESH_INTERVAL
+    }
+}
+
+/// Pre-defined name server resolvers
+#[derive(Debug, Clone)]
+pub enum Resolver {
+    /// Environment variable resolver
+    Env(EnvResolver),
+    /// Static configuration resolver
+    Static(StaticResolver),
+    /// Passthrough HTTP resolver
+    PassthroughHttp(PassthroughResolver<HttpResolver>),
+    /// HTTP resolver
+    Http(HttpResolver),
+    /// DNS SRV record resolver
+    Srv(SrvResolver),
+    /// Kubernetes Service resolver
+    #[cfg(feature = "k8s")]
+    Kubernetes(KubernetesResolver),
+    /// Synchronous resolver adapter
+    Fn(FnResolver),
+}
+
+#[async_trait]
+impl NsResolver for Resolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        Ok(match self {
+            Resolver::Env(inner) => inner.resolve().await?,
+            Resolver::Static(inner) => inner.resolve().await?,
+            Resolver::PassthroughHttp(inner) => inner.resolve().await?,
+            Resolver::Http(inner) => inner.resolve().await?,
+            Resolver::Srv(inner) => inner.resolve().await?,
+            #[cfg(feature = "k8s")]
+            Resolver::Kubernetes(inner) => inner.resolve().await?,
+            Resolver::Fn(inner) => inner.resolve().await?,
+        })
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Resolver::Env(inner) => inner.description(),
+            Resolver::Static(inner) => inner.description(),
+            Resolver::PassthroughHttp(inner) => inner.description(),
+            Resolver::Http(inner) => inner.description(),
+            Resolver::Srv(inner) => inner.description(),
+            #[cfg(feature = "k8s")]
+            Resolver::Kubernetes(inner) => inner.description(),
+            Resolver::Fn(inner) => inner.description(),
+        }
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        match self {
+            Resolver::PassthroughHttp(inner) => inner.refresh_interval(),
+            Resolver::Http(inner) => inner.refresh_interval(),
+            Resolver::Srv(inner) => inner.refresh_interval(),
+            #[cfg(feature = "k8s")]
+            Resolver::Kubernetes(inner) => inner.refresh_interval(),
+            _ => DEFAULT_REFRESH_INTERVAL,
+        }
+    }
+}
+
+impl Default for Resolver {
+    /// Name servers of `NAMESRV_ADDR` like other RocketMQ clients, those of the default
+    /// HTTP domain if it's not set
+    fn default() -> Self {
+        Resolver::PassthroughHttp(PassthroughResolver::new(
+            EnvResolver::addrs(),
+            HttpResolver::new("DEFAULT".to_string()),
+        ))
+    }
+}
+
+impl Resolver {
+    /// Identity of the name servers resolved, clients are shared by producers and
+    /// consumers using the same name servers
+    pub(crate) fn cache_key(&self) -> String {
+        match self {
+            Resolver::Env(_) => "env".to_string(),
+            Resolver::Static(inner) => inner.addrs.join(";"),
+            Resolver::PassthroughHttp(inner) if inner.addrs.is_empty() => inner.fallback.url(),
+            Resolver::PassthroughHttp(inner) => inner.addrs.join(";"),
+            Resolver::Http(inner) => inner.url(),
+            Resolver::Srv(inner) => inner.name.clone(),
+            #[cfg(feature = "k8s")]
+            Resolver::Kubernetes(inner) => inner.cache_key(),
+            Resolver::Fn(inner) => inner.name.clone(),
+        }
+    }
+
+    /// Name server addresses configured statically, empty for those resolved at runtime
+    pub(crate) fn static_addrs(&self) -> &[String] {
+        match self {
+            Resolver::Static(inner) => &inner.addrs,
+            Resolver::PassthroughHttp(inner) => &inner.addrs,
+            _ => &[],
+        }
+    }
+
+    /// Turn endpoints configured statically, like `http://MQ_INST_xxx_yyy.region.mq.aliyuncs.com:80`
+    /// of cloud instances, into `host:port` addresses
+    pub(crate) fn strip_endpoint_scheme(&mut self) {
+        let addrs = match self {
+            Resolver::Static(inner) => &mut inner.addrs,
+            Resolver::PassthroughHttp(inner) => &mut inner.addrs,
+            _ => return,
+        };
+        for addr in addrs.iter_mut() {
+            if let Some((_, host)) = addr.split_once("://") {
+                *addr = host.trim_end_matches('/').to_string();
+            }
+        }
+    }
+
+    /// Fetch name servers of the unit and the cloud instance from HTTP endpoints, both
+    /// optional
+    pub(crate) fn set_http_tenant(&mut self, unit_name: &str, instance_id: &str) {
+        let inner = match self {
+            Resolver::PassthroughHttp(inner) => &mut inner.fallback,
+            Resolver::Http(inner) => inner,
+            _ => return,
+        };
+        if !unit_name.is_empty() {
+            inner.set_unit_name(unit_name);
+        }
+        if !instance_id.is_empty() {
+            inner.set_instance_id(instance_id);
+        }
+    }
+
+    /// Send HTTP requests of name server resolving through the proxy
+    pub(crate) fn set_proxy(&mut self, proxy: &ProxyConfig) -> Result<(), Error> {
+        match self {
+            Resolver::PassthroughHttp(inner) => inner.fallback.set_proxy(proxy),
+            Resolver::Http(inner) => inner.set_proxy(proxy),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Environment variable resolver, reads `;` separated addresses of `NAMESRV_ADDR`
+#[derive(Debug, Clone, Copy)]
+pub struct EnvResolver;
+
+impl EnvResolver {
+    fn addrs() -> Vec<String> {
+        env::var(NAMESRV_ADDR_ENV)
+            .map(|value| split_addrs(&value))
+            .unwrap_or_default()
+    }
+}
+
+/// Trim resolved name server addresses and drop empty, duplicated and malformed ones,
+/// keeping the order of the others
+pub(crate) fn normalize_addrs(addrs: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(addrs.len());
+    for addr in &addrs {
+        let addr = addr.trim();
+        if addr.is_empty() || normalized.iter().any(|a| a == addr) {
+            continue;
+        }
+        if !is_host_port(addr) {
+            warn!(addr = %addr, "skip malformed name server address");
+            continue;
+        }
+        normalized.push(addr.to_string());
+    }
+    normalized
+}
+
+/// Whether `addr` is in `host:port` form, with IPv6 hosts in brackets
+fn is_host_port(addr: &str) -> bool {
+    let (host, port) = match addr.rsplit_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if !matches!(port.parse::<u16>(), Ok(port) if port != 0) {
+        return false;
+    }
+    if let Some(ip) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return ip.parse::<std::net::Ipv6Addr>().is_ok();
+    }
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// Split `;` separated name server addresses, dropping empty ones
+fn split_addrs(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[async_trait]
+impl NsResolver for EnvResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        Ok(Self::addrs())
+    }
+
+    fn description(&self) -> &'static str {
+        "envvar resolver"
+    }
+}
+
+/// Static configuration resolver
+#[derive(Debug, Clone)]
+pub struct StaticResolver {
+    addrs: Vec<String>,
+}
+
+impl StaticResolver {
+    pub fn new(addrs: Vec<String>) -> Self {
+        Self { addrs }
+    }
+}
+
+#[async_trait]
+impl NsResolver for StaticResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        Ok(self.addrs.clone())
+    }
+
+    fn description(&self) -> &'static str {
+        "static resolver"
+    }
+}
+
+/// Adapter of a synchronous function resolving name servers, e.g. from the configuration
+/// of the application. It's called on the runtime so it mustn't block
+#[derive(Clone)]
+pub struct FnResolver {
+    name: String,
+    resolve: Arc<dyn Fn() -> Vec<String> + Send + Sync>,
+}
+
+impl fmt::Debug for FnResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FnResolver")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl FnResolver {
+    /// `name` identifies the name servers resolved, clients are shared by producers and
+    /// consumers with resolvers of the same name
+    pub fn new<F>(name: &str, resolve: F) -> Self
+    where
+        F: Fn() -> Vec<String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            resolve: Arc::new(resolve),
+        }
+    }
+}
+
+#[async_trait]
+impl NsResolver for FnResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        Ok((self.resolve)())
+    }
+
+    fn description(&self) -> &'static str {
+        "fn resolver"
+    }
+}
+
+/// Passthrough HTTP resolver
+#[derive(Debug, Clone)]
+pub struct PassthroughResolver<T: NsResolver> {
+    addrs: Vec<String>,
+    fallback: T,
+}
+
+impl<T: NsResolver> PassthroughResolver<T> {
+    pub fn new(addrs: Vec<String>, fallback: T) -> Self {
+        Self { addrs, fallback }
+    }
+}
+
+#[async_trait]
+impl<T: NsResolver + Clone + Send + Sync> NsResolver for PassthroughResolver<T> {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        if self.addrs.is_empty() {
+            Ok(self.fallback.resolve().await?)
+        } else {
+            Ok(self.addrs.clone())
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        "passthrough resolver"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        if self.addrs.is_empty() {
+            self.fallback.refresh_interval()
+        } else {
+            DEFAULT_REFRESH_INTERVAL
+        }
+    }
+}
+
+/// HTTP resolver, fetches `;` separated addresses from a URL like the `nsaddr` web server
+/// of the Java client. Addresses fetched are saved to a snapshot file, read when the URL is
+/// unreachable, e.g. at startup
+#[derive(Debug, Clone)]
+pub struct HttpResolver {
+    domain: String,
+    path: Option<String>,
+    unit_name: Option<String>,
+    instance_id: Option<String>,
+    instance: String,
+    // the async client of reqwest needs a tokio runtime, other runtimes build the blocking
+    // one on a blocking thread for each fetch, it can't be built or dropped in async contexts
+    #[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+    http: reqwest::Client,
+    proxy: Option<reqwest::Proxy>,
+    timeout: Duration,
+    refresh_interval: Duration,
+    snapshot_path: Option<PathBuf>,
+    fallback: EnvResolver,
+}
+
+impl HttpResolver {
+    pub fn new(instance: String) -> Self {
+        Self::with_domain(instance, DEFAULT_NAMESRV_ADDR.to_string())
+    }
+
+    pub fn with_domain(instance: String, domain: String) -> Self {
+        let snapshot_path = env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("logs/rocketmq-rust/snapshot")
+                .join(format!("nameserver_addr-{}", instance))
+        });
+        Self {
+            domain,
+            path: None,
+            unit_name: None,
+            instance_id: None,
+            instance,
+            #[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+            http: reqwest::Client::builder()
+                .timeout(DEFAULT_HTTP_TIMEOUT)
+                .build()
+                .expect("build HTTP client"),
+            proxy: None,
+            timeout: DEFAULT_HTTP_TIMEOUT,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            snapshot_path,
+            fallback: EnvResolver,
+        }
+    }
+
+    /// Fetch from `path`, with its query if any, on the host of the domain instead of the
+    /// URL of the domain, e.g. `/rocketmq/nsaddr-unit?nofix=1`
+    pub fn set_path(&mut self, path: &str) -> &mut Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Fetch name servers of the unit from `<url>-<unit name>?nofix=1` like the Java client
+    pub fn set_unit_name(&mut self, unit_name: &str) -> &mut Self {
+        self.unit_name = Some(unit_name.to_string());
+        self
+    }
+
+    /// Fetch name servers of the cloud instance, passed as the `instanceId` query parameter
+    pub fn set_instance_id(&mut self, instance_id: &str) -> &mut Self {
+        self.instance_id = Some(instance_id.to_string());
+        self
+    }
+
+    /// Timeout of fetching addresses, 3 seconds by default
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<&mut Self, Error> {
+        self.timeout = timeout;
+        self.rebuild_http()?;
+        Ok(self)
+    }
+
+    /// Interval of fetching addresses again, 2 minutes by default
+    pub fn set_refresh_interval(&mut self, interval: Duration) -> &mut Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// File to save addresses fetched to, `None` to disable the snapshot. Defaults to
+    /// `~/logs/rocketmq-rust/snapshot/nameserver_addr-<instance>`
+    pub fn set_snapshot_path(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.snapshot_path = path;
+        self
+    }
+
+    /// URL addresses are fetched from
+    pub fn url(&self) -> String {
+        let url = match &self.path {
+            Some(path) => {
+                let host_start = self.domain.find("://").map_or(0, |pos| pos + 3);
+                let host_end = self.domain[host_start..]
+                    .find('/')
+                    .map_or(self.domain.len(), |pos| host_start + pos);
+                format!(
+                    "{}/{}",
+                    &self.domain[..host_end],
+                    path.trim_start_matches('/')
+                )
+            }
+            None => self.domain.clone(),
+        };
+        if self.unit_name.is_none() && self.instance_id.is_none() {
+            return url;
+        }
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (url, String::new()),
+        };
+        let mut params: Vec<String> = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(str::to_string)
+            .collect();
+        let mut path = path;
+        if let Some(unit_name) = &self.unit_name {
+            path.push('-');
+            path.push_str(unit_name);
+            if !params.iter().any(|param| param.starts_with("nofix=")) {
+                params.push("nofix=1".to_string());
+            }
+        }
+        if let Some(instance_id) = &self.instance_id {
+            params.push(format!("instanceId={}", instance_id));
+        }
+        format!("{}?{}", path, params.join("&"))
+    }
+
+    fn set_proxy(&mut self, proxy: &ProxyConfig) -> Result<(), Error> {
+        self.proxy = Some(proxy.http_proxy()?);
+        self.rebuild_http()
+    }
+
+    #[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+    fn rebuild_http(&mut self) -> Result<(), Error> {
+        let mut builder = reqwest::Client::builder().timeout(self.timeout);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        self.http = builder
+            .build()
+            .map_err(|err| Error::Proxy(err.to_string()))?;
+        Ok(())
+    }
+
+    #[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+    fn rebuild_http(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Addresses fetched from the URL, `None` if fetching failed
+    #[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+    async fn get(&self) -> Option<Vec<String>> {
+        let res = self.http.get(self.url()).send().await.ok()?;
+        let body = res.error_for_status().ok()?.text().await.ok()?;
+        Some(split_addrs(&body))
+    }
+
+    #[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+    async fn get(&self) -> Option<Vec<String>> {
+        let url = self.url();
+        let timeout = self.timeout;
+        let proxy = self.proxy.clone();
+        let body = runtime::spawn_blocking(move || {
+            let mut builder = reqwest::blocking::Client::builder().timeout(timeout);
+            if let Some(proxy) = proxy {
+                builder = builder.proxy(proxy);
+            }
+            builder.build()?.get(url).send()?.error_for_status()?.text()
+        })
+        .await;
+        match body {
+            Ok(Ok(body)) => Some(split_addrs(&body)),
+            _ => None,
+        }
+    }
+
+    async fn save_snapshot(&self, addrs: &[String]) {
+        let path = match &self.snapshot_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let data = addrs.join(";").into_bytes();
+        let res = runtime::spawn_blocking(move || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, data)
+        })
+        .await;
+        match res {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!("save name server address snapshot failed: {:?}", err),
+            Err(err) => warn!("save name server address snapshot failed: {:?}", err),
+        }
+    }
+
+    async fn load_snapshot(&self) -> Vec<String> {
+        let path = match &self.snapshot_path {
+            Some(path) => path.clone(),
+            None => return Vec::new(),
+        };
+        match runtime::read_file(path).await {
+            Ok(data) => split_addrs(&String::from_utf8_lossy(&data)),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl NsResolver for HttpResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        if let Some(addrs) = self.get().await {
+            if !addrs.is_empty() {
+                self.save_snapshot(&addrs).await;
+                return Ok(addrs);
+            }
+        }
+        let addrs = self.load_snapshot().await;
+        if !addrs.is_empty() {
+            warn!(url = %self.url(), "fetch name server addresses failed, use the snapshot");
+            return Ok(addrs);
+        }
+        Ok(self.fallback.resolve().await?)
+    }
+
+    fn description(&self) -> &'static str {
+        "http resolver"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+}
+
+/// DNS SRV record resolver, resolves `host:port` targets of a record like
+/// `_rocketmq._tcp.cluster.internal` ordered by priority and weight. Records are cached
+/// and queried again after their TTL, and kept when the DNS query fails
+#[derive(Debug, Clone)]
+pub struct SrvResolver {
+    name: String,
+    name_servers: Option<Vec<SocketAddr>>,
+    cache: Arc<Mutex<Option<SrvCache>>>,
+}
+
+#[derive(Debug)]
+struct SrvCache {
+    addrs: Vec<String>,
+    ttl: Duration,
+    expires_at: Instant,
+}
+
+impl SrvResolver {
+    /// Look up `name` with name servers of `/etc/resolv.conf`
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            name_servers: None,
+            cache: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Look up `name` with the given DNS name servers
+    pub fn with_name_servers(name: String, name_servers: Vec<SocketAddr>) -> Self {
+        Self {
+            name,
+            name_servers: Some(name_servers),
+            cache: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn lookup(&self) -> Result<(Vec<String>, Duration), Error> {
+        let name = self.name.clone();
+        let name_servers = self.name_servers.clone();
+        let mut records = runtime::spawn_blocking(move || {
+            let name_servers = match name_servers {
+                Some(name_servers) => name_servers,
+                None => dns::system_name_servers()?,
+            };
+            dns::lookup_srv(&name, &name_servers, DNS_TIMEOUT)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        let ttl = records.iter().map(|record| record.ttl).min().unwrap_or(0);
+        records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+        let addrs = records.iter().map(dns::SrvRecord::address).collect();
+        Ok((addrs, Duration::from_secs(ttl.into())))
+    }
+}
+
+#[async_trait]
+impl NsResolver for SrvResolver {
+    async fn resolve(&self) -> Result<Vec<String>, Error> {
+        if let Some(cache) = &*self.cache.lock() {
+            if cache.expires_at > Instant::now() {
+                return Ok(cache.addrs.clone());
+            }
+        }
+        match self.lookup().await {
+            Ok((addrs, ttl)) => {
+                *self.cache.lock() = Some(SrvCache {
+                    addrs: addrs.clone(),
+                    ttl,
+                    expires_at: Instant::now() + ttl,
+                });
+                Ok(addrs)
+            }
+            Err(err) => match &*self.cache.lock() {
+                Some(cache) => {
+                    warn!(name = %self.name, "DNS SRV lookup failed, keep resolved name servers: {:?}", err);
+                    Ok(cache.addrs.clone())
+                }
+                None => Err(err),
+            },
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        "dns srv resolver"
+    }
+
+    /// TTL of the records last resolved, at least 5 seconds
+    fn refresh_interval(&self) -> Duration {
+        match &*self.cache.lock() {
+            Some(cache) => cache.ttl.max(MIN_SRV_REFRESH_INTERVAL),
+            None => DEFAULT_REFRESH_INTERVAL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::UdpSocket;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::dns::test::encode_response;
+    use super::{
+        normalize_addrs, split_addrs, FnResolver, HttpResolver, NsResolver, Resolver, SrvResolver,
+    };
+
+    #[test]
+    fn test_split_addrs() {
+        assert_eq!(
+            vec!["127.0.0.1:9876", "127.0.0.2:9876"],
+            split_addrs("127.0.0.1:9876; 127.0.0.2:9876;")
+        );
+        assert!(split_addrs("").is_empty());
+        assert!(split_addrs(" ;\n").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fn_resolver() {
+        let resolver = Resolver::Fn(FnResolver::new("app-config", || {
+            vec!["127.0.0.1:9876".to_string()]
+        }));
+        assert_eq!(vec!["127.0.0.1:9876"], resolver.resolve().await.unwrap());
+        assert_eq!("app-config", resolver.cache_key());
+    }
+
+    #[test]
+    fn test_normalize_addrs() {
+        let addrs = vec![
+            " 127.0.0.2:9876".to_string(),
+            "namesrv-0.namesrv:9876".to_string(),
+            "".to_string(),
+            "127.0.0.2:9876".to_string(),
+            "[::1]:9876".to_string(),
+            "127.0.0.1".to_string(),
+            "127.0.0.1:0".to_string(),
+            "127.0.0.1:98765".to_string(),
+            "::1:9876".to_string(),
+            "http://127.0.0.1:9876".to_string(),
+            "<html>:80".to_string(),
+        ];
+        assert_eq!(
+            vec!["127.0.0.2:9876", "namesrv-0.namesrv:9876", "[::1]:9876"],
+            normalize_addrs(addrs)
+        );
+    }
+
+    #[test]
+    fn test_http_resolver_url() {
+        let mut resolver = HttpResolver::with_domain(
+            "DEFAULT".to_string(),
+            "http://jmenv.tbsite.net:8080/rocketmq/nsaddr".to_string(),
+        );
+        assert_eq!(
+            "http://jmenv.tbsite.net:8080/rocketmq/nsaddr",
+            resolver.url()
+        );
+        resolver.set_path("/rocketmq/nsaddr-unit?nofix=1");
+        assert_eq!(
+            "http://jmenv.tbsite.net:8080/rocketmq/nsaddr-unit?nofix=1",
+            resolver.url()
+        );
+        let mut resolver =
+            HttpResolver::with_domain("DEFAULT".to_string(), "http://127.0.0.1:8080".to_string());
+        resolver.set_path("nsaddr");
+        assert_eq!("http://127.0.0.1:8080/nsaddr", resolver.url());
+
+        let mut resolver = HttpResolver::with_domain(
+            "DEFAULT".to_string(),
+            "http://onsaddr.mq.aliyuncs.com:80/rocketmq/nsaddr4client-internet".to_string(),
+        );
+        resolver
+            .set_unit_name("unit1")
+            .set_instance_id("MQ_INST_1234_abcd");
+        assert_eq!(
+            "http://onsaddr.mq.aliyuncs.com:80/rocketmq/nsaddr4client-internet-unit1?nofix=1&instanceId=MQ_INST_1234_abcd",
+            resolver.url()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_resolver_snapshot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let body = "127.0.0.1:9876;127.0.0.2:9876";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        let snapshot = std::env::temp_dir().join(format!(
+            "rocketmq-rs-test-{}/nameserver_addr-DEFAULT",
+            std::process::id()
+        ));
+        let mut resolver =
+            HttpResolver::with_domain("DEFAULT".to_string(), format!("http://{}", addr));
+        resolver.set_path("/rocketmq/nsaddr");
+        resolver.set_snapshot_path(Some(snapshot.clone()));
+        let expected = vec!["127.0.0.1:9876", "127.0.0.2:9876"];
+        assert_eq!(expected, resolver.resolve().await.unwrap());
+        assert_eq!(
+            "127.0.0.1:9876;127.0.0.2:9876",
+            std::fs::read_to_string(&snapshot).unwrap()
+        );
+
+        // the server is gone, addresses come from the snapshot
+        assert_eq!(expected, resolver.resolve().await.unwrap());
+        std::fs::remove_dir_all(snapshot.parent().unwrap()).unwrap();
+    }
+
+    /// DNS server answering SRV queries with `records`, counts the queries
+    fn fake_dns_server(
+        records: Vec<(u16, u16, u16, &'static str, u32)>,
+    ) -> (String, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = encode_response(&buf[..len], &records);
+                socket.send_to(&response, peer).unwrap();
+            }
+        });
+        (addr, queries)
+    }
+
+    #[tokio::test]
+    async fn test_srv_resolver() {
+        let (addr, queries) = fake_dns_server(vec![
+            (20, 0, 9876, "ns3", 60),
+            (10, 1, 9876, "ns1", 60),
+            (10, 5, 9877, "ns2", 60),
+        ]);
+        let resolver = SrvResolver::with_name_servers(
+            "_rocketmq._tcp.cluster.internal".to_string(),
+            vec![addr.parse().unwrap()],
+        );
+        let expected = vec![
+            "ns2._rocketmq._tcp.cluster.internal:9877",
+            "ns1._rocketmq._tcp.cluster.internal:9876",
+            "ns3._rocketmq._tcp.cluster.internal:9876",
+        ];
+        assert_eq!(expected, resolver.resolve().await.unwrap());
+        // cached within the TTL
+        assert_eq!(expected, resolver.resolve().await.unwrap());
+        assert_eq!(1, queries.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_srv_resolver_requery_on_ttl() {
+        let (addr, queries) = fake_dns_server(vec![(10, 0, 9876, "ns1", 0)]);
+        let resolver = SrvResolver::with_name_servers(
+            "_rocketmq._tcp.cluster.internal".to_string(),
+            vec![addr.parse().unwrap()],
+        );
+        resolver.resolve().await.unwrap();
+        resolver.resolve().await.unwrap();
+        assert_eq!(2, queries.load(Ordering::SeqCst));
+        // refreshed on the TTL, but not more often than the minimum
+        assert_eq!(
+            Duration::from_secs(5),
+            Resolver::Srv(resolver).refresh_interval()
+        );
+
+        let (addr, _) = fake_dns_server(vec![(10, 0, 9876, "ns1", 60)]);
+        let resolver = SrvResolver::with_name_servers(
+            "_rocketmq._tcp.cluster.internal".to_string(),
+            vec![addr.parse().unwrap()],
+        );
+        resolver.resolve().await.unwrap();
+        assert_eq!(Duration::from_secs(60), resolver.refresh_interval());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use serde::Deserialize;
 
@@ -30,6 +30,83 @@ pub struct BrokerData {
     pub broker_addrs: HashMap<i64, String>,
 }
 
+impl BrokerData {
+    /// Address of the master, or of the slave with the smallest ID if the master is down
+    pub fn select_addr(&self) -> Option<&str> {
+        self.broker_addrs
+            .get(&MASTER_ID)
+            .or_else(|| {
+                self.broker_addrs
+                    .iter()
+                    .min_by_key(|(id, _)| **id)
+                    .map(|(_, addr)| addr)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Brokers of clusters served by name servers
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct ClusterInfo {
+    // broker name -> BrokerData
+    #[serde(default, rename = "brokerAddrTable")]
+    pub broker_addr_table: HashMap<String, BrokerData>,
+    // cluster name -> broker names
+    #[serde(default, rename = "clusterAddrTable")]
+    pub cluster_addr_table: HashMap<String, Vec<String>>,
+}
+
+impl ClusterInfo {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        let s = String::from_utf8(bytes.to_vec())?;
+        // fixup fastjson mess
+        let json = dirty_json::fix(&s);
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Cluster name -> broker name -> BrokerData
+    pub fn clusters(&self) -> HashMap<String, HashMap<String, BrokerData>> {
+        self.cluster_addr_table
+            .iter()
+            .map(|(cluster, broker_names)| {
+                let brokers = broker_names
+                    .iter()
+                    .filter_map(|name| {
+                        self.broker_addr_table
+                            .get(name)
+                            .map(|data| (name.clone(), data.clone()))
+                    })
+                    .collect();
+                (cluster.clone(), brokers)
+            })
+            .collect()
+    }
+}
+
+/// Queues and brokers added to or removed from a topic route, and brokers whose addresses
+/// changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TopicRouteDiff {
+    pub added_queues: Vec<MessageQueue>,
+    pub removed_queues: Vec<MessageQueue>,
+    pub added_brokers: Vec<String>,
+    pub removed_brokers: Vec<String>,
+    pub changed_brokers: Vec<String>,
+}
+
+impl TopicRouteDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_queues.is_empty()
+            && self.removed_queues.is_empty()
+            && self.added_brokers.is_empty()
+            && self.removed_brokers.is_empty()
+            && self.changed_brokers.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct TopicRouteData {
     #[serde(default, rename = "orderTopicConf")]
@@ -44,21 +121,41 @@ pub struct TopicRouteData {
 
 impl TopicRouteData {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
-        let s = std::str::from_utf8(bytes).unwrap();
+        let s = String::from_utf8(bytes.to_vec())?;
         // fixup fastjson mess
-        let json = dirty_json::fix(s);
+        let json = dirty_json::fix(&s);
         let data: TopicRouteData = serde_json::from_str(&json)?;
+        data.order_topic_queues()?;
         Ok(data)
     }
 
+    /// Brokers and their queue numbers of `orderTopicConf` like `broker-a:4;broker-b:4`,
+    /// empty entries are skipped like Java clients do
+    fn order_topic_queues(&self) -> Result<Vec<(&str, u32)>, Error> {
+        self.order_topic_conf
+            .split(';')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let mut item = entry.split(':');
+                match (item.next(), item.next().map(str::parse), item.next()) {
+                    (Some(broker_name), Some(Ok(nums)), None) if !broker_name.is_empty() => {
+                        Ok((broker_name, nums))
+                    }
+                    _ => Err(Error::InvalidMessage(format!(
+                        "invalid orderTopicConf entry {:?}",
+                        entry
+                    ))),
+                }
+            })
+            .collect()
+    }
+
     pub fn to_publish_info(&self, topic: &str) -> TopicPublishInfo {
         let mut mqs = Vec::new();
         if !self.order_topic_conf.is_empty() {
-            let brokers = self.order_topic_conf.split(';');
-            for broker in brokers {
-                let mut item = broker.split(':');
-                let broker_name = item.next().unwrap();
-                let nums: u32 = item.next().unwrap().parse().unwrap();
+            // validated when decoded
+            let brokers = self.order_topic_queues().unwrap_or_default();
+            for (broker_name, nums) in brokers {
                 for i in 0..nums {
                     mqs.push(MessageQueue {
                         topic: topic.to_string(),
@@ -109,6 +206,72 @@ impl TopicRouteData {
             queue_index: 0,
         }
     }
+
+    /// Difference from the `old` route of `topic`, everything is added without an old route
+    pub fn diff(&self, topic: &str, old: Option<&TopicRouteData>) -> TopicRouteDiff {
+        let new_queues = self.queues(topic);
+        let old_queues = old.map(|old| old.queues(topic)).unwrap_or_default();
+        let new_brokers: HashMap<_, _> = self
+            .broker_datas
+            .iter()
+            .map(|bd| (&bd.broker_name, &bd.broker_addrs))
+            .collect();
+        let old_brokers: HashMap<_, _> = old
+            .map(|old| {
+                old.broker_datas
+                    .iter()
+                    .map(|bd| (&bd.broker_name, &bd.broker_addrs))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut diff = TopicRouteDiff {
+            added_queues: new_queues.difference(&old_queues).cloned().collect(),
+            removed_queues: old_queues.difference(&new_queues).cloned().collect(),
+            ..Default::default()
+        };
+        for (name, addrs) in &new_brokers {
+            match old_brokers.get(name) {
+                None => diff.added_brokers.push(name.to_string()),
+                Some(old_addrs) if old_addrs != addrs => {
+                    diff.changed_brokers.push(name.to_string())
+                }
+                Some(_) => {}
+            }
+        }
+        for name in old_brokers.keys() {
+            if !new_brokers.contains_key(name) {
+                diff.removed_brokers.push(name.to_string());
+            }
+        }
+        diff.added_brokers.sort();
+        diff.removed_brokers.sort();
+        diff.changed_brokers.sort();
+        diff
+    }
+
+    /// Queues either readable or writable
+    fn queues(&self, topic: &str) -> BTreeSet<MessageQueue> {
+        let mut queues: BTreeSet<_> = self.to_subscribe_info(topic).into_iter().collect();
+        queues.extend(self.to_publish_info(topic).message_queues);
+        queues
+    }
+
+    pub fn to_subscribe_info(&self, topic: &str) -> Vec<MessageQueue> {
+        let mut mqs = Vec::new();
+        for qd in &self.queue_datas {
+            if !qd.perm.is_readable() {
+                continue;
+            }
+            for i in 0..qd.read_queue_nums {
+                mqs.push(MessageQueue {
+                    topic: topic.to_string(),
+                    broker_name: qd.broker_name.clone(),
+                    queue_id: i as u32,
+                });
+            }
+        }
+        mqs
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -119,3 +282,94 @@ pub struct TopicPublishInfo {
     pub route_data: TopicRouteData,
     pub queue_index: usize,
 }
+
+#[cfg(test)]
+mod test {
+    use super::TopicRouteData;
+    use crate::message::MessageQueue;
+    use crate::Error;
+
+    fn route_data(queue_nums: i32, brokers: &[(&str, &str)]) -> TopicRouteData {
+        let queue_datas: Vec<String> = brokers
+            .iter()
+            .map(|(name, _)| {
+                format!(
+                    r#"{{"brokerName":"{}","readQueueNums":{},"writeQueueNums":{},"perm":6}}"#,
+                    name, queue_nums, queue_nums
+                )
+            })
+            .collect();
+        let broker_datas: Vec<String> = brokers
+            .iter()
+            .map(|(name, addr)| {
+                format!(
+                    r#"{{"cluster":"c","brokerName":"{}","brokerAddrs":{{"0":"{}"}}}}"#,
+                    name, addr
+                )
+            })
+            .collect();
+        let json = format!(
+            r#"{{"queueDatas":[{}],"brokerDatas":[{}],"filterServerTable":{{}}}}"#,
+            queue_datas.join(","),
+            broker_datas.join(",")
+        );
+        TopicRouteData::from_bytes(json.as_bytes()).unwrap()
+    }
+
+    fn queue(broker_name: &str, queue_id: u32) -> MessageQueue {
+        MessageQueue {
+            topic: "TopicA".to_string(),
+            broker_name: broker_name.to_string(),
+            queue_id,
+        }
+    }
+
+    #[test]
+    fn test_order_topic_conf() {
+        let json = |conf: &str| {
+            format!(
+                r#"{{"orderTopicConf":"{}","queueDatas":[],"brokerDatas":[],"filterServerTable":{{}}}}"#,
+                conf
+            )
+        };
+        let data = TopicRouteData::from_bytes(json("a:2;b:1;").as_bytes()).unwrap();
+        let publish_info = data.to_publish_info("TopicA");
+        assert!(publish_info.order_topic);
+        assert_eq!(
+            vec![queue("a", 0), queue("a", 1), queue("b", 0)],
+            publish_info.message_queues
+        );
+        // corrupted
+        for conf in &["a", "a:", "a:x", ":2", "a:-1", "a:2:3"] {
+            assert!(
+                matches!(
+                    TopicRouteData::from_bytes(json(conf).as_bytes()),
+                    Err(Error::InvalidMessage(_))
+                ),
+                "{}",
+                conf
+            );
+        }
+    }
+
+    #[test]
+    fn test_topic_route_diff() {
+        let old = route_data(2, &[("a", "10.0.0.1:10911"), ("b", "10.0.0.2:10911")]);
+        assert!(old.diff("TopicA", Some(&old)).is_empty());
+
+        let diff = old.diff("TopicA", None);
+        assert_eq!(4, diff.added_queues.len());
+        assert_eq!(vec!["a", "b"], diff.added_brokers);
+
+        let new = route_data(3, &[("a", "10.0.0.3:10911"), ("c", "10.0.0.4:10911")]);
+        let diff = new.diff("TopicA", Some(&old));
+        assert_eq!(
+            vec![queue("a", 2), queue("c", 0), queue("c", 1), queue("c", 2)],
+            diff.added_queues
+        );
+        assert_eq!(vec![queue("b", 0), queue("b", 1)], diff.removed_queues);
+        assert_eq!(vec!["c"], diff.added_brokers);
+        assert_eq!(vec!["b"], diff.removed_brokers);
+        assert_eq!(vec!["a"], diff.changed_brokers);
+    }
+}
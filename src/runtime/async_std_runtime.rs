@@ -0,0 +1,45 @@
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt};
+
+use super::{catch_unwind, connect_async_io, AsyncIoTcpStream, JoinError, Runtime, Sleep, Task};
+
+pub(crate) struct AsyncStdRuntime;
+
+impl Runtime for AsyncStdRuntime {
+    type TcpStream = AsyncIoTcpStream;
+
+    fn spawn<F>(future: F) -> Task<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        async_std::task::spawn(catch_unwind(future)).boxed()
+    }
+
+    fn spawn_blocking<F, T>(f: F) -> Task<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        async_std::task::spawn_blocking(move || {
+            panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| JoinError::Panicked)
+        })
+        .boxed()
+    }
+
+    fn sleep(duration: Duration) -> Sleep {
+        async_std::task::sleep(duration).boxed()
+    }
+
+    fn connect(
+        socket: socket2::Socket,
+        addr: SocketAddr,
+    ) -> BoxFuture<'static, io::Result<AsyncIoTcpStream>> {
+        connect_async_io(socket, addr).boxed()
+    }
+}
@@ -0,0 +1,335 @@
+//! Tasks, timers and TCP connections of the async runtime the client runs on, tokio by
+//! default, async-std or smol with the `async-std-runtime` or `smol-runtime` feature.
+//! Channels, codecs and TLS streams of tokio are runtime agnostic and used with all of them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, AbortHandle, Abortable, Aborted, BoxFuture, Either};
+use futures::{pin_mut, ready, Future};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(all(feature = "async-std-runtime", feature = "smol-runtime"))]
+compile_error!("features `async-std-runtime` and `smol-runtime` are mutually exclusive");
+
+#[cfg(feature = "async-std-runtime")]
+mod async_std_runtime;
+#[cfg(feature = "smol-runtime")]
+mod smol_runtime;
+#[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+mod tokio_runtime;
+
+#[cfg(feature = "async-std-runtime")]
+type Rt = async_std_runtime::AsyncStdRuntime;
+#[cfg(all(feature = "smol-runtime", not(feature = "async-std-runtime")))]
+type Rt = smol_runtime::SmolRuntime;
+#[cfg(not(any(feature = "async-std-runtime", feature = "smol-runtime")))]
+type Rt = tokio_runtime::TokioRuntime;
+
+/// Output of a spawned task, fails if the task panicked
+pub(crate) type Task<T> = BoxFuture<'static, Result<T, JoinError>>;
+
+/// Completes once the duration slept passed
+pub(crate) type Sleep = BoxFuture<'static, ()>;
+
+/// TCP stream of the runtime
+pub(crate) type TcpStream = <Rt as Runtime>::TcpStream;
+
+/// Primitives of an async runtime
+pub(crate) trait Runtime {
+    /// TCP stream, adapted to the IO traits of tokio used by codecs, proxies and TLS
+    type TcpStream: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+
+    /// Spawn a task running in the background even if its output is never awaited
+    fn spawn<F>(future: F) -> Task<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static;
+
+    /// Run blocking `f` on a thread of its own
+    fn spawn_blocking<F, T>(f: F) -> Task<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
+
+    fn sleep(duration: Duration) -> Sleep;
+
+    /// Connect `socket` with its options set to `addr`
+    fn connect(
+        socket: socket2::Socket,
+        addr: SocketAddr,
+    ) -> BoxFuture<'static, io::Result<Self::TcpStream>>;
+
+    /// Whether tasks can be spawned on the current thread
+    fn can_spawn() -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum JoinError {
+    Cancelled,
+    Panicked,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+            JoinError::Panicked => write!(f, "task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Handle of a spawned task, the task keeps running when it's dropped
+pub(crate) struct JoinHandle<T> {
+    task: Task<Result<T, Aborted>>,
+    abort: AbortHandle,
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("aborted", &self.abort.is_aborted())
+            .finish()
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancel the task at its next await point
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    #[cfg(test)]
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.task.as_mut().poll(cx).map(|res| match res {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(Aborted)) => Err(JoinError::Cancelled),
+            Err(err) => Err(err),
+        })
+    }
+}
+
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (abort, registration) = AbortHandle::new_pair();
+    JoinHandle {
+        task: Rt::spawn(Abortable::new(future, registration)),
+        abort,
+    }
+}
+
+pub(crate) fn spawn_blocking<F, T>(f: F) -> Task<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Rt::spawn_blocking(f)
+}
+
+/// Whether tasks can be spawned, e.g. not in `Drop` of a value outside of a tokio runtime
+pub(crate) fn can_spawn() -> bool {
+    Rt::can_spawn()
+}
+
+pub(crate) fn sleep(duration: Duration) -> Sleep {
+    Rt::sleep(duration)
+}
+
+pub(crate) fn sleep_until(deadline: Instant) -> Sleep {
+    Rt::sleep(deadline.saturating_duration_since(Instant::now()))
+}
+
+/// The future didn't complete before its deadline
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+/// Wait for `future` at most `duration`
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    pin_mut!(future);
+    match future::select(future, sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+/// Wait for `future` until `deadline` at most
+pub(crate) async fn timeout_at<F: Future>(
+    deadline: Instant,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    timeout(deadline.saturating_duration_since(Instant::now()), future).await
+}
+
+/// Ticks every `period`, the first tick completes right away
+pub(crate) fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Ticks every `period` starting at `start`
+pub(crate) fn interval_at(start: Instant, period: Duration) -> Interval {
+    Interval {
+        next: start,
+        period,
+        delay: sleep_until(start),
+    }
+}
+
+/// Ticks missed while the owner was busy are skipped rather than fired in a burst
+pub(crate) struct Interval {
+    next: Instant,
+    period: Duration,
+    delay: Sleep,
+}
+
+impl Interval {
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        ready!(self.delay.as_mut().poll(cx));
+        let tick = self.next;
+        let now = Instant::now();
+        self.next = tick + self.period;
+        if self.next <= now {
+            self.next = now + self.period;
+        }
+        self.delay = sleep_until(self.next);
+        Poll::Ready(tick)
+    }
+
+    pub async fn tick(&mut self) -> Instant {
+        future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+}
+
+/// Resolve `addr` in `host:port` form, IP addresses are returned as is
+pub(crate) async fn lookup_host(addr: &str) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(addr) = addr.parse() {
+        return Ok(vec![addr]);
+    }
+    let addr = addr.to_string();
+    spawn_blocking(move || addr.to_socket_addrs().map(Iterator::collect))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Read the whole file at `path` on a blocking thread
+pub(crate) async fn read_file(path: PathBuf) -> io::Result<Vec<u8>> {
+    spawn_blocking(move || fs::read(path))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Replace the file at `path` with `data` on a blocking thread
+pub(crate) async fn write_file(path: PathBuf, data: Vec<u8>) -> io::Result<()> {
+    spawn_blocking(move || fs::write(path, data))
+        .await
+        .map_err(io::Error::other)?
+}
+
+pub(crate) async fn connect(socket: socket2::Socket, addr: SocketAddr) -> io::Result<TcpStream> {
+    Rt::connect(socket, addr).await
+}
+
+/// TCP stream of runtimes built on async-io
+#[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+type AsyncIoTcpStream = tokio_util::compat::Compat<async_io::Async<std::net::TcpStream>>;
+
+#[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+async fn connect_async_io(
+    socket: socket2::Socket,
+    addr: SocketAddr,
+) -> io::Result<AsyncIoTcpStream> {
+    use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+    socket.set_nonblocking(true)?;
+    match socket.connect(&addr.into()) {
+        Ok(()) => {}
+        #[cfg(unix)]
+        Err(err) if err.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+        Err(err) => return Err(err),
+    }
+    let stream = async_io::Async::new(std::net::TcpStream::from(socket))?;
+    // writable once connected or failed
+    stream.writable().await?;
+    if let Some(err) = stream.get_ref().take_error()? {
+        return Err(err);
+    }
+    Ok(stream.compat())
+}
+
+#[cfg(any(feature = "async-std-runtime", feature = "smol-runtime"))]
+fn catch_unwind<F: Future>(future: F) -> impl Future<Output = Result<F::Output, JoinError>> {
+    use futures::FutureExt;
+
+    std::panic::AssertUnwindSafe(future)
+        .catch_unwind()
+        .map(|res| res.map_err(|_| JoinError::Panicked))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{interval, spawn, timeout, JoinError};
+
+    #[tokio::test]
+    async fn test_timeout() {
+        assert_eq!(
+            1,
+            timeout(Duration::from_millis(50), async { 1 })
+                .await
+                .unwrap()
+        );
+        let start = Instant::now();
+        assert!(
+            timeout(Duration::from_millis(20), futures::future::pending::<()>())
+                .await
+                .is_err()
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_interval() {
+        let start = Instant::now();
+        let mut interval = interval(Duration::from_millis(20));
+        interval.tick().await;
+        assert!(start.elapsed() < Duration::from_millis(20));
+        interval.tick().await;
+        interval.tick().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_and_abort() {
+        assert_eq!(1, spawn(async { 1 }).await.unwrap());
+        let task = spawn(futures::future::pending::<()>());
+        task.abort();
+        assert!(matches!(task.await, Err(JoinError::Cancelled)));
+        let task = spawn(async { panic!("boom") });
+        assert!(matches!(task.await, Err(JoinError::Panicked)));
+    }
+}
@@ -0,0 +1,70 @@
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt};
+
+use super::{catch_unwind, connect_async_io, AsyncIoTcpStream, JoinError, Runtime, Sleep, Task};
+
+pub(crate) struct SmolRuntime;
+
+impl Runtime for SmolRuntime {
+    type TcpStream = AsyncIoTcpStream;
+
+    fn spawn<F>(future: F) -> Task<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        Detached(Some(smol::spawn(catch_unwind(future)))).boxed()
+    }
+
+    fn spawn_blocking<F, T>(f: F) -> Task<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        Detached(Some(smol::unblock(move || {
+            panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| JoinError::Panicked)
+        })))
+        .boxed()
+    }
+
+    fn sleep(duration: Duration) -> Sleep {
+        smol::Timer::after(duration).map(|_| ()).boxed()
+    }
+
+    fn connect(
+        socket: socket2::Socket,
+        addr: SocketAddr,
+    ) -> BoxFuture<'static, io::Result<AsyncIoTcpStream>> {
+        connect_async_io(socket, addr).boxed()
+    }
+}
+
+/// smol cancels tasks when their handle is dropped, detach them instead so they keep
+/// running like tasks of other runtimes
+struct Detached<T>(Option<smol::Task<T>>);
+
+impl<T> Future for Detached<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        match self.0.as_mut() {
+            Some(task) => Pin::new(task).poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Detached<T> {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.detach();
+        }
+    }
+}
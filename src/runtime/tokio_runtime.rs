@@ -0,0 +1,66 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt};
+use tokio::net::{TcpSocket, TcpStream};
+
+use super::{JoinError, Runtime, Sleep, Task};
+
+pub(crate) struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    type TcpStream = TcpStream;
+
+    fn spawn<F>(future: F) -> Task<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        tokio::spawn(future)
+            .map(|res| res.map_err(JoinError::from))
+            .boxed()
+    }
+
+    fn spawn_blocking<F, T>(f: F) -> Task<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        tokio::task::spawn_blocking(f)
+            .map(|res| res.map_err(JoinError::from))
+            .boxed()
+    }
+
+    fn sleep(duration: Duration) -> Sleep {
+        tokio::time::sleep(duration).boxed()
+    }
+
+    fn connect(
+        socket: socket2::Socket,
+        addr: SocketAddr,
+    ) -> BoxFuture<'static, io::Result<TcpStream>> {
+        async move {
+            socket.set_nonblocking(true)?;
+            TcpSocket::from_std_stream(socket.into())
+                .connect(addr)
+                .await
+        }
+        .boxed()
+    }
+
+    fn can_spawn() -> bool {
+        tokio::runtime::Handle::try_current().is_ok()
+    }
+}
+
+impl From<tokio::task::JoinError> for JoinError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        if err.is_cancelled() {
+            JoinError::Cancelled
+        } else {
+            JoinError::Panicked
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// System flags of a message, set by producers and brokers. Transaction types take two
+    /// bits, read them with [`SysFlag::transaction_type`] rather than `contains`
+    pub struct SysFlag: i32 {
+        const COMPRESSED = 0x1;
+        const MULTI_TAGS = 0x1 << 1;
+        const TRANSACTION_PREPARED = 0x1 << 2;
+        const TRANSACTION_COMMIT = 0x2 << 2;
+        const TRANSACTION_ROLLBACK = 0x3 << 2;
+        /// Born host is an IPv6 address
+        const BORNHOST_V6 = 0x1 << 4;
+        /// Store host is an IPv6 address
+        const STOREHOST_V6 = 0x1 << 5;
+        const NEED_UNWRAP = 0x1 << 6;
+        const INNER_BATCH = 0x1 << 7;
+        /// Compression types of 5.x, along with `COMPRESSED`
+        const COMPRESSION_LZ4 = 0x1 << 8;
+        const COMPRESSION_ZSTD = 0x2 << 8;
+        const COMPRESSION_ZLIB = 0x3 << 8;
+    }
+}
+
+impl SysFlag {
+    const COMPRESSION_TYPE: SysFlag = SysFlag { bits: 0x7 << 8 };
+
+    /// Transaction bits only, empty if the message is not transactional
+    pub fn transaction_type(&self) -> SysFlag {
+        *self & Self::TRANSACTION_ROLLBACK
+    }
+
+    /// Replace the transaction type, an empty `transaction_type` clears it
+    pub fn set_transaction_type(&mut self, transaction_type: SysFlag) {
+        self.remove(Self::TRANSACTION_ROLLBACK);
+        self.insert(transaction_type.transaction_type());
+    }
+
+    /// Compression type bits only, empty for bodies compressed by 4.x clients
+    pub fn compression_type(&self) -> SysFlag {
+        *self & Self::COMPRESSION_TYPE
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.contains(Self::COMPRESSED)
+    }
+
+    pub fn is_transaction_prepared(&self) -> bool {
+        self.transaction_type() == Self::TRANSACTION_PREPARED
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SysFlag;
+
+    #[test]
+    fn test_transaction_type() {
+        let mut flag = SysFlag::COMPRESSED | SysFlag::TRANSACTION_ROLLBACK;
+        assert_eq!(SysFlag::TRANSACTION_ROLLBACK, flag.transaction_type());
+        // rollback shares its bits with prepared and commit
+        assert!(flag.contains(SysFlag::TRANSACTION_COMMIT));
+        assert!(!flag.is_transaction_prepared());
+        flag.set_transaction_type(SysFlag::TRANSACTION_PREPARED);
+        assert!(flag.is_transaction_prepared());
+        assert!(flag.is_compressed());
+        flag.set_transaction_type(SysFlag::empty());
+        assert_eq!(SysFlag::COMPRESSED, flag);
+        assert_eq!(
+            0x18,
+            (SysFlag::BORNHOST_V6 | SysFlag::TRANSACTION_COMMIT).bits()
+        );
+    }
+}
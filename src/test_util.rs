@@ -0,0 +1,802 @@
+//! In-process mock of a name server and master broker speaking the remoting protocol, to
+//! integration test producers and consumers without a RocketMQ cluster. It serves routes
+//! of the topics created on it, acknowledges and stores sent messages, answers pulls and
+//! consumer offsets, and responses can be scripted or failed per request code.
+//!
+//! Runs on the tokio runtime of the test.
+//!
+//! ```no_run
+//! use rocketmq::test_util::MockBroker;
+//! use rocketmq::{Message, Producer, ProducerOptions};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let broker = MockBroker::start().await?;
+//! broker.create_topic("TopicTest", 4);
+//! let mut options = ProducerOptions::default();
+//! options.set_name_server(vec![broker.addr().to_string()]);
+//! let producer = Producer::with_options(options)?;
+//! producer.start();
+//! let msg = Message::builder().topic("TopicTest").body(b"hello".to_vec()).build()?;
+//! producer.send(msg).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio_util::codec::Framed;
+
+use crate::message::{body_crc, Message, MessageId, MESSAGE_MAGIC_CODE};
+use crate::protocol::request::{
+    GetMaxOffsetRequestHeader, QueryConsumerOffsetRequestHeader, SendMessageRequestHeader,
+    SendMessageRequestV2Header, UnregisterClientRequestHeader, UpdateConsumerOffsetRequestHeader,
+};
+use crate::protocol::{parse_field, MqCodec, RemotingCommand};
+pub use crate::protocol::{RequestCode, ResponseCode};
+use crate::Error;
+
+/// Name of the broker in routes
+pub const BROKER_NAME: &str = "broker-a";
+/// Name of the cluster of the broker
+pub const CLUSTER_NAME: &str = "DefaultCluster";
+/// How long pulls without new messages are held before `PULL_NOT_FOUND`, like the long
+/// polling of brokers but short
+const PULL_HOLD: Duration = Duration::from_millis(200);
+
+/// Reply of a scripted handler to a request
+#[derive(Debug)]
+pub enum Reply {
+    /// Respond with the command, its opaque is set to the one of the request
+    Response(RemotingCommand),
+    /// Close the connection without responding
+    Close,
+    /// Leave the request unanswered so it times out
+    NoResponse,
+}
+
+type Handler = Arc<dyn Fn(&RemotingCommand) -> Reply + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    /// queue numbers by topic
+    topics: BTreeMap<String, u32>,
+    /// messages in the store format by topic and queue ID
+    queues: HashMap<(String, i32), Vec<Vec<u8>>>,
+    commit_log_offset: i64,
+    /// committed offsets by consumer group, topic and queue ID
+    offsets: HashMap<(String, String, i32), i64>,
+    client_ids: BTreeSet<String>,
+    handlers: HashMap<i16, Handler>,
+    /// injected error responses by request code, each used once
+    errors: HashMap<i16, Vec<(ResponseCode, String)>>,
+    requests: Vec<RemotingCommand>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// Mock of a name server and the master broker `BROKER_NAME` of `CLUSTER_NAME` on one
+/// local address, set as the name server of clients. Stopped once dropped
+pub struct MockBroker {
+    addr: String,
+    state: Arc<Mutex<State>>,
+    messages_added: Arc<Notify>,
+}
+
+impl fmt::Debug for MockBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockBroker")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+impl MockBroker {
+    /// Listen on a free port of `127.0.0.1`
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local_addr = listener.local_addr()?;
+        let broker = Self {
+            addr: local_addr.to_string(),
+            state: Arc::new(Mutex::new(State::default())),
+            messages_added: Arc::new(Notify::new()),
+        };
+        let conn = Connection {
+            addr: local_addr,
+            state: Arc::clone(&broker.state),
+            messages_added: Arc::clone(&broker.messages_added),
+        };
+        let state = Arc::clone(&broker.state);
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let task = tokio::spawn(conn.clone().serve(stream));
+                state.lock().tasks.push(task);
+            }
+        });
+        broker.state.lock().tasks.push(task);
+        Ok(broker)
+    }
+
+    /// Address of the name server and the broker
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Create `topic` with `queue_nums` read and write queues, routes of other topics are
+    /// not found
+    pub fn create_topic(&self, topic: &str, queue_nums: u32) {
+        self.state
+            .lock()
+            .topics
+            .insert(topic.to_string(), queue_nums);
+    }
+
+    /// Store `msg` in queue `queue_id` of its topic, returns its queue offset
+    pub fn add_message(&self, queue_id: i32, msg: &Message) -> Result<i64, Error> {
+        let properties = msg.dump_properties()?;
+        let born_timestamp = now_millis();
+        let offset = self.state.lock().store(
+            self.addr.parse().expect("socket address"),
+            StoredMessage {
+                topic: msg.topic(),
+                queue_id,
+                sys_flag: 0,
+                flag: msg.flag,
+                born_timestamp,
+                properties: &properties,
+                body: &msg.body,
+            },
+        );
+        self.messages_added.notify_waiters();
+        Ok(offset)
+    }
+
+    /// Reply to requests of `code` with `handler` instead of the built-in handling
+    pub fn on<F>(&self, code: RequestCode, handler: F)
+    where
+        F: Fn(&RemotingCommand) -> Reply + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .handlers
+            .insert(code.into(), Arc::new(handler));
+    }
+
+    /// Fail the next `times` requests of `code` with `response` and `remark`
+    pub fn fail(&self, code: RequestCode, response: ResponseCode, remark: &str, times: usize) {
+        let mut state = self.state.lock();
+        let errors = state.errors.entry(code.into()).or_default();
+        for _ in 0..times {
+            errors.push((response, remark.to_string()));
+        }
+    }
+
+    /// Requests of `code` received so far, in order
+    pub fn requests(&self, code: RequestCode) -> Vec<RemotingCommand> {
+        self.state
+            .lock()
+            .requests
+            .iter()
+            .filter(|req| req.code() == code)
+            .cloned()
+            .collect()
+    }
+
+    /// Offset of queue `queue_id` of `topic` committed by consumers of `group`
+    pub fn consumer_offset(&self, group: &str, topic: &str, queue_id: i32) -> Option<i64> {
+        self.state
+            .lock()
+            .offsets
+            .get(&(group.to_string(), topic.to_string(), queue_id))
+            .copied()
+    }
+}
+
+impl Drop for MockBroker {
+    fn drop(&mut self) {
+        for task in self.state.lock().tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+struct StoredMessage<'a> {
+    topic: &'a str,
+    queue_id: i32,
+    sys_flag: i32,
+    flag: i32,
+    born_timestamp: i64,
+    properties: &'a str,
+    body: &'a [u8],
+}
+
+impl State {
+    /// Append `msg` to its queue in the store format brokers respond to pulls with,
+    /// returns its queue offset
+    fn store(&mut self, store_host: SocketAddr, msg: StoredMessage<'_>) -> i64 {
+        let queue = self
+            .queues
+            .entry((msg.topic.to_string(), msg.queue_id))
+            .or_default();
+        let queue_offset = queue.len() as i64;
+        let store_size = 91 + msg.body.len() + msg.topic.len() + msg.properties.len();
+        let mut buf = Vec::with_capacity(store_size);
+        buf.write_i32::<BigEndian>(store_size as i32).unwrap();
+        buf.write_i32::<BigEndian>(MESSAGE_MAGIC_CODE).unwrap();
+        buf.write_i32::<BigEndian>(body_crc(msg.body)).unwrap();
+        buf.write_i32::<BigEndian>(msg.queue_id).unwrap();
+        buf.write_i32::<BigEndian>(msg.flag).unwrap();
+        buf.write_i64::<BigEndian>(queue_offset).unwrap();
+        buf.write_i64::<BigEndian>(self.commit_log_offset).unwrap();
+        buf.write_i32::<BigEndian>(msg.sys_flag).unwrap();
+        buf.write_i64::<BigEndian>(msg.born_timestamp).unwrap();
+        write_host(&mut buf, store_host);
+        buf.write_i64::<BigEndian>(now_millis()).unwrap();
+        write_host(&mut buf, store_host);
+        // reconsume times and prepared transaction offset
+        buf.extend_from_slice(&[0; 12]);
+        buf.write_i32::<BigEndian>(msg.body.len() as i32).unwrap();
+        buf.extend_from_slice(msg.body);
+        buf.push(msg.topic.len() as u8);
+        buf.extend_from_slice(msg.topic.as_bytes());
+        buf.write_i16::<BigEndian>(msg.properties.len() as i16)
+            .unwrap();
+        buf.extend_from_slice(msg.properties.as_bytes());
+        queue.push(buf);
+        self.commit_log_offset += store_size as i64;
+        queue_offset
+    }
+
+    fn max_offset(&self, topic: &str, queue_id: i32) -> i64 {
+        self.queues
+            .get(&(topic.to_string(), queue_id))
+            .map(|queue| queue.len() as i64)
+            .unwrap_or(0)
+    }
+}
+
+fn write_host(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr {
+        SocketAddr::V4(addr) => buf.extend_from_slice(&addr.ip().octets()),
+        SocketAddr::V6(_) => buf.extend_from_slice(&[127, 0, 0, 1]),
+    }
+    buf.write_i32::<BigEndian>(addr.port() as i32).unwrap();
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn response(code: ResponseCode, remark: String, fields: &[(&str, String)]) -> RemotingCommand {
+    let mut res = RemotingCommand::response(code, remark, Vec::new());
+    for (name, value) in fields {
+        res.header
+            .ext_fields
+            .insert(name.to_string(), value.clone());
+    }
+    res
+}
+
+/// Built-in reply, a pull to hold until new messages are added or a response
+enum Handled {
+    Reply(Reply),
+    HoldPull(PullRequest),
+}
+
+struct PullRequest {
+    topic: String,
+    queue_id: i32,
+    queue_offset: i64,
+    max_msg_nums: i32,
+}
+
+#[derive(Clone)]
+struct Connection {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    messages_added: Arc<Notify>,
+}
+
+impl Connection {
+    async fn serve(self, stream: TcpStream) {
+        let (mut sink, mut stream) = Framed::new(stream, MqCodec::default()).split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<RemotingCommand>();
+        let writer = tokio::spawn(async move {
+            while let Some(res) = rx.recv().await {
+                if sink.send(res).await.is_err() {
+                    break;
+                }
+            }
+        });
+        while let Some(Ok(req)) = stream.next().await {
+            let opaque = req.opaque();
+            let oneway = req.is_oneway();
+            let reply = match self.handle(&req) {
+                Handled::Reply(reply) => reply,
+                Handled::HoldPull(pull) => {
+                    let conn = self.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        let _ =
+                            tokio::time::timeout(PULL_HOLD, conn.messages_added.notified()).await;
+                        let mut res = conn.pull(&pull);
+                        res.set_opaque(opaque);
+                        let _ = tx.send(res);
+                    });
+                    continue;
+                }
+            };
+            match reply {
+                Reply::Response(mut res) if !oneway => {
+                    res.set_opaque(opaque);
+                    if tx.send(res).is_err() {
+                        break;
+                    }
+                }
+                Reply::Response(_) | Reply::NoResponse => {}
+                Reply::Close => break,
+            }
+        }
+        writer.abort();
+    }
+
+    fn handle(&self, req: &RemotingCommand) -> Handled {
+        let handler = {
+            let mut state = self.state.lock();
+            state.requests.push(req.clone());
+            let error = state
+                .errors
+                .get_mut(&req.code())
+                .filter(|errors| !errors.is_empty())
+                .map(|errors| errors.remove(0));
+            if let Some((code, remark)) = error {
+                return Handled::Reply(Reply::Response(response(code, remark, &[])));
+            }
+            state.handlers.get(&req.code()).cloned()
+        };
+        if let Some(handler) = handler {
+            return Handled::Reply(handler(req));
+        }
+        match self.handle_builtin(req) {
+            Ok(handled) => handled,
+            Err(err) => Handled::Reply(Reply::Response(response(
+                ResponseCode::SystemError,
+                err.to_string(),
+                &[],
+            ))),
+        }
+    }
+
+    fn handle_builtin(&self, req: &RemotingCommand) -> Result<Handled, Error> {
+        use std::convert::TryFrom;
+
+        let fields = req.ext_fields();
+        let res = match RequestCode::try_from(req.code()) {
+            Ok(RequestCode::GetRouteInfoByTopic) => {
+                let topic: String = parse_field(fields, "topic")?;
+                match self.state.lock().topics.get(&topic) {
+                    Some(queue_nums) => {
+                        let route = format!(
+                            r#"{{"queueDatas":[{{"brokerName":"{0}","readQueueNums":{1},"writeQueueNums":{1},"perm":6,"topicSyncFlag":0}}],"brokerDatas":[{{"cluster":"{2}","brokerName":"{0}","brokerAddrs":{{"0":"{3}"}}}}],"filterServerTable":{{}}}}"#,
+                            BROKER_NAME, queue_nums, CLUSTER_NAME, self.addr
+                        );
+                        RemotingCommand::response(
+                            ResponseCode::Success,
+                            String::new(),
+                            route.into_bytes(),
+                        )
+                    }
+                    None => response(
+                        ResponseCode::TopicNotExist,
+                        format!(
+                            "No topic route info in name server for the topic: {}",
+                            topic
+                        ),
+                        &[],
+                    ),
+                }
+            }
+            Ok(RequestCode::GetBrokerClusterInfo) => {
+                let cluster_info = format!(
+                    r#"{{"brokerAddrTable":{{"{0}":{{"brokerAddrs":{{0:"{2}"}},"brokerName":"{0}","cluster":"{1}"}}}},"clusterAddrTable":{{"{1}":["{0}"]}}}}"#,
+                    BROKER_NAME, CLUSTER_NAME, self.addr
+                );
+                RemotingCommand::response(
+                    ResponseCode::Success,
+                    String::new(),
+                    cluster_info.into_bytes(),
+                )
+            }
+            Ok(RequestCode::SendMessage) => {
+                let header: SendMessageRequestHeader = req.decode_header()?;
+                let mut state = self.state.lock();
+                if !state.topics.contains_key(&header.topic) {
+                    return Ok(Handled::Reply(Reply::Response(response(
+                        ResponseCode::TopicNotExist,
+                        format!("topic[{}] not exist", header.topic),
+                        &[],
+                    ))));
+                }
+                let commit_log_offset = state.commit_log_offset;
+                let queue_offset = state.store(
+                    self.addr,
+                    StoredMessage {
+                        topic: &header.topic,
+                        queue_id: header.queue_id as i32,
+                        sys_flag: header.sys_flag,
+                        flag: header.flag,
+                        born_timestamp: header.born_timestamp,
+                        properties: &header.properties,
+                        body: req.body(),
+                    },
+                );
+                drop(state);
+                self.messages_added.notify_waiters();
+                self.send_ack(header.queue_id, queue_offset, commit_log_offset)
+            }
+            Ok(RequestCode::SendMessageV2) => {
+                // batches are acknowledged without being stored
+                let header: SendMessageRequestV2Header = req.decode_header()?;
+                let state = self.state.lock();
+                let queue_offset = state.max_offset(&header.topic, header.queue_id as i32);
+                self.send_ack(header.queue_id, queue_offset, state.commit_log_offset)
+            }
+            Ok(RequestCode::PullMessage) => {
+                let pull = PullRequest {
+                    topic: parse_field(fields, "topic")?,
+                    queue_id: parse_field(fields, "queueId")?,
+                    queue_offset: parse_field(fields, "queueOffset")?,
+                    max_msg_nums: parse_field(fields, "maxMsgNums")?,
+                };
+                let max_offset = self.state.lock().max_offset(&pull.topic, pull.queue_id);
+                if pull.queue_offset == max_offset {
+                    return Ok(Handled::HoldPull(pull));
+                }
+                self.pull(&pull)
+            }
+            Ok(RequestCode::Heartbeat) => {
+                let heartbeat: serde_json::Value = serde_json::from_slice(req.body())?;
+                if let Some(client_id) = heartbeat.get("clientID").and_then(|id| id.as_str()) {
+                    self.state.lock().client_ids.insert(client_id.to_string());
+                }
+                response(ResponseCode::Success, String::new(), &[])
+            }
+            Ok(RequestCode::UnregisterClient) => {
+                let header: UnregisterClientRequestHeader = req.decode_header()?;
+                self.state.lock().client_ids.remove(&header.client_id);
+                response(ResponseCode::Success, String::new(), &[])
+            }
+            Ok(RequestCode::GetConsumerListByGroup) => {
+                let client_ids: Vec<String> =
+                    self.state.lock().client_ids.iter().cloned().collect();
+                let body = serde_json::json!({ "consumerIdList": client_ids });
+                RemotingCommand::response(
+                    ResponseCode::Success,
+                    String::new(),
+                    serde_json::to_vec(&body)?,
+                )
+            }
+            Ok(RequestCode::QueryConsumerOffset) => {
+                let header: QueryConsumerOffsetRequestHeader = req.decode_header()?;
+                let key = (header.consumer_group, header.topic, header.queue_id as i32);
+                match self.state.lock().offsets.get(&key) {
+                    Some(offset) => response(
+                        ResponseCode::Success,
+                        String::new(),
+                        &[("offset", offset.to_string())],
+                    ),
+                    None => response(
+                        ResponseCode::QueryNotFound,
+                        "Not found, maybe this group consumer boot first".to_string(),
+                        &[],
+                    ),
+                }
+            }
+            Ok(RequestCode::UpdateConsumerOffset) => {
+                let header: UpdateConsumerOffsetRequestHeader = req.decode_header()?;
+                let key = (header.consumer_group, header.topic, header.queue_id as i32);
+                self.state.lock().offsets.insert(key, header.commit_offset);
+                response(ResponseCode::Success, String::new(), &[])
+            }
+            Ok(RequestCode::GetMaxOffset) => {
+                let header: GetMaxOffsetRequestHeader = req.decode_header()?;
+                let offset = self
+                    .state
+                    .lock()
+                    .max_offset(&header.topic, header.queue_id as i32);
+                response(
+                    ResponseCode::Success,
+                    String::new(),
+                    &[("offset", offset.to_string())],
+                )
+            }
+            Ok(RequestCode::GetMinOffset) | Ok(RequestCode::SearchOffsetByTimestamp) => response(
+                ResponseCode::Success,
+                String::new(),
+                &[("offset", "0".to_string())],
+            ),
+            _ => response(ResponseCode::Success, String::new(), &[]),
+        };
+        Ok(Handled::Reply(Reply::Response(res)))
+    }
+
+    fn send_ack(
+        &self,
+        queue_id: u32,
+        queue_offset: i64,
+        commit_log_offset: i64,
+    ) -> RemotingCommand {
+        let msg_id = MessageId {
+            address: self.addr,
+            offset: commit_log_offset,
+        };
+        response(
+            ResponseCode::Success,
+            String::new(),
+            &[
+                ("msgId", msg_id.to_string()),
+                ("queueId", queue_id.to_string()),
+                ("queueOffset", queue_offset.to_string()),
+            ],
+        )
+    }
+
+    /// Messages of the queue from the offset of `pull`, `PULL_NOT_FOUND` if there's none
+    fn pull(&self, pull: &PullRequest) -> RemotingCommand {
+        let state = self.state.lock();
+        let max_offset = state.max_offset(&pull.topic, pull.queue_id);
+        let offsets = |next: i64| {
+            [
+                ("nextBeginOffset", next.to_string()),
+                ("minOffset", "0".to_string()),
+                ("maxOffset", max_offset.to_string()),
+                ("suggestWhichBrokerId", "0".to_string()),
+            ]
+        };
+        if pull.queue_offset < 0 || pull.queue_offset > max_offset {
+            let next = pull.queue_offset.max(0).min(max_offset);
+            return response(ResponseCode::PullOffsetMoved, String::new(), &offsets(next));
+        }
+        if pull.queue_offset == max_offset {
+            return response(
+                ResponseCode::PullNotFound,
+                String::new(),
+                &offsets(max_offset),
+            );
+        }
+        let queue = &state.queues[&(pull.topic.clone(), pull.queue_id)];
+        let msgs: Vec<u8> = queue
+            .iter()
+            .skip(pull.queue_offset as usize)
+            .take(pull.max_msg_nums.max(1) as usize)
+            .flatten()
+            .copied()
+            .collect();
+        let next = (pull.queue_offset + pull.max_msg_nums.max(1) as i64).min(max_offset);
+        let mut res = response(ResponseCode::Success, String::new(), &offsets(next));
+        res.body = msgs.into();
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+    use tokio_util::codec::Framed;
+
+    use super::{MockBroker, Reply, RequestCode, ResponseCode};
+    use crate::client::ClientOptions;
+    use crate::consumer::ConsumeFrom;
+    use crate::message::MessageExt;
+    use crate::protocol::{MqCodec, RemotingCommand};
+    use crate::{
+        ConsumeResult, ConsumerOptions, Error, Message, MessageSelector, Producer, ProducerOptions,
+        PushConsumer,
+    };
+
+    async fn start_producer(broker: &MockBroker, instance_name: &str) -> Producer {
+        let mut client_options = ClientOptions::default();
+        client_options.set_instance_name(instance_name);
+        let mut options = ProducerOptions::with_client_options(client_options);
+        options.set_name_server(vec![broker.addr().to_string()]);
+        let producer = Producer::with_options(options).unwrap();
+        producer.start();
+        producer
+    }
+
+    async fn invoke(addr: &str, cmd: RemotingCommand) -> RemotingCommand {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut framed = Framed::new(stream, MqCodec::default());
+        framed.send(cmd).await.unwrap();
+        framed.next().await.unwrap().unwrap()
+    }
+
+    fn new_message(topic: &str, body: &str) -> Message {
+        Message::new(
+            topic.to_string(),
+            String::new(),
+            String::new(),
+            0,
+            body.as_bytes().to_vec(),
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_mock_broker_send_and_pull() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("TopicTest", 1);
+        let producer = start_producer(&broker, "test_mock_broker_send_and_pull").await;
+        let result = producer
+            .send(new_message("TopicTest", "hello"))
+            .await
+            .unwrap();
+        assert_eq!(0, result.queue_offset);
+        assert_eq!(1, broker.requests(RequestCode::SendMessage).len());
+
+        let header = crate::protocol::request::PullMessageRequestHeader {
+            consumer_group: "group".to_string(),
+            topic: "TopicTest".to_string(),
+            queue_id: 0,
+            queue_offset: 0,
+            max_msg_nums: 32,
+            sys_flag: 0,
+            commit_offset: 0,
+            suspend_timeout_millis: Duration::from_secs(1),
+            sub_expression: "*".to_string(),
+            sub_version: 0,
+            expression_type: "TAG".to_string(),
+        };
+        let pull = RemotingCommand::with_header(RequestCode::PullMessage, header, Vec::new());
+        let res = invoke(broker.addr(), pull).await;
+        assert!(res.code() == ResponseCode::Success);
+        assert_eq!("1", res.ext_fields()["nextBeginOffset"]);
+        let msgs = MessageExt::decode(res.body()).unwrap();
+        assert_eq!(1, msgs.len());
+        assert_eq!(b"hello", &msgs[0].message.body[..]);
+        assert_eq!(result.msg_id, msgs[0].msg_id);
+
+        assert!(producer
+            .send(new_message("OtherTopic", "hello"))
+            .await
+            .is_err());
+        producer.shutdown(Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_mock_broker_scripted_responses() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("TopicTest", 1);
+        let producer = start_producer(&broker, "test_mock_broker_scripted_responses").await;
+
+        broker.fail(
+            RequestCode::SendMessage,
+            ResponseCode::SystemBusy,
+            "broker busy",
+            1,
+        );
+        match producer.send(new_message("TopicTest", "busy")).await {
+            Err(Error::Broker { remark, addr, .. }) => {
+                assert_eq!("broker busy", remark);
+                assert_eq!(broker.addr(), addr);
+            }
+            res => panic!("expected broker error, got {:?}", res),
+        }
+
+        broker.on(RequestCode::SendMessage, |_| {
+            let mut fields = HashMap::new();
+            fields.insert("msgId".to_string(), "OFFSET-ID".to_string());
+            fields.insert("queueId".to_string(), "0".to_string());
+            fields.insert("queueOffset".to_string(), "42".to_string());
+            let mut res = RemotingCommand::new(0, 0, String::new(), fields, Vec::new());
+            res.mark_response();
+            Reply::Response(res)
+        });
+        let result = producer
+            .send(new_message("TopicTest", "scripted"))
+            .await
+            .unwrap();
+        assert_eq!(42, result.queue_offset);
+        assert_eq!("OFFSET-ID", result.offset_msg_id);
+        producer.shutdown(Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_mock_broker_unregister_on_shutdown() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("TopicTest", 1);
+        let mut client_options = ClientOptions::new("ProducerGroup");
+        client_options.set_instance_name("test_mock_broker_unregister_on_shutdown");
+        let mut options = ProducerOptions::with_client_options(client_options);
+        options.set_name_server(vec![broker.addr().to_string()]);
+        let producer = Producer::with_options(options).unwrap();
+        producer.start();
+        producer
+            .send(new_message("TopicTest", "hello"))
+            .await
+            .unwrap();
+        producer.shutdown(Duration::from_secs(3)).await;
+        // unregistered before shutdown returns
+        let requests = broker.requests(RequestCode::UnregisterClient);
+        assert_eq!(1, requests.len());
+        assert_eq!("ProducerGroup", requests[0].ext_fields()["producerGroup"]);
+        assert!(producer
+            .send(new_message("TopicTest", "closed"))
+            .await
+            .is_err());
+
+        let mut client_options = ClientOptions::new("ConsumerGroup");
+        client_options.set_instance_name("test_mock_broker_unregister_on_shutdown_consumer");
+        let mut options = ConsumerOptions::with_client_options(client_options);
+        options.set_name_server(vec![broker.addr().to_string()]);
+        let consumer = PushConsumer::with_options(options).unwrap();
+        consumer.subscribe("TopicTest", MessageSelector::default(), |_| {
+            ConsumeResult::Success
+        });
+        let heartbeats = broker.requests(RequestCode::Heartbeat).len();
+        consumer.start();
+        while broker.requests(RequestCode::Heartbeat).len() == heartbeats {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        consumer.shutdown(Duration::from_secs(3)).await;
+        let requests = broker.requests(RequestCode::UnregisterClient);
+        assert_eq!(2, requests.len());
+        assert_eq!("ConsumerGroup", requests[1].ext_fields()["consumerGroup"]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_broker_persist_offset_on_shutdown() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("TopicTest", 1);
+        broker
+            .add_message(0, &new_message("TopicTest", "hello"))
+            .unwrap();
+        let mut client_options = ClientOptions::new("ConsumerGroup");
+        client_options.set_instance_name("test_mock_broker_persist_offset_on_shutdown");
+        let mut options = ConsumerOptions::with_client_options(client_options);
+        options
+            .set_name_server(vec![broker.addr().to_string()])
+            .set_consume_from(ConsumeFrom::FirstOffset);
+        let consumer = PushConsumer::with_options(options).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        consumer.subscribe("TopicTest", MessageSelector::default(), move |msgs| {
+            let _ = tx.send(msgs.len());
+            ConsumeResult::Success
+        });
+        consumer.start();
+        assert_eq!(Some(1), rx.recv().await);
+        // the offset is updated in memory before the next pull
+        while !broker
+            .requests(RequestCode::PullMessage)
+            .iter()
+            .any(|req| req.ext_fields()["queueOffset"] == "1")
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let updates = broker.requests(RequestCode::UpdateConsumerOffset).len();
+        consumer.shutdown(Duration::from_secs(3)).await;
+        // persisted before the consumer is unregistered
+        assert!(broker.requests(RequestCode::UpdateConsumerOffset).len() > updates);
+        assert_eq!(
+            Some(1),
+            broker.consumer_offset("ConsumerGroup", "TopicTest", 0)
+        );
+    }
+}
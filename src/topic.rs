@@ -0,0 +1,165 @@
+use thiserror::Error;
+
+/// Prefix of the topic messages failed to consume are retried from, `%RETRY%{group}`
+pub const RETRY_GROUP_TOPIC_PREFIX: &str = "%RETRY%";
+/// Prefix of the topic messages retried too many times end up in, `%DLQ%{group}`
+pub const DLQ_GROUP_TOPIC_PREFIX: &str = "%DLQ%";
+/// Topic brokers create topics from when auto creation is enabled
+pub const AUTO_CREATE_TOPIC_KEY: &str = "TBW102";
+/// Topic message traces are sent to on self-hosted brokers
+pub const TRACE_TOPIC: &str = "RMQ_SYS_TRACE_TOPIC";
+/// Prefix of trace topics of cloud instances, followed by the region ID
+pub const CLOUD_TRACE_TOPIC_PREFIX: &str = "TRACE_DATA_";
+/// Topics are at most 127 characters, same as the Java client
+pub const TOPIC_MAX_LENGTH: usize = 127;
+/// Groups are at most 255 characters, same as the Java client
+pub const GROUP_MAX_LENGTH: usize = 255;
+/// Topics and groups of brokers themselves, never wrapped with the namespace
+pub const SYSTEM_RESOURCES: &[&str] = &[
+    AUTO_CREATE_TOPIC_KEY,
+    "SCHEDULE_TOPIC_XXXX",
+    "BenchmarkTest",
+    "OFFSET_MOVED_EVENT",
+    "SELF_TEST_TOPIC",
+    "DEFAULT_PRODUCER",
+    "DEFAULT_CONSUMER",
+    "TOOLS_CONSUMER",
+    "CLIENT_INNER_PRODUCER",
+];
+/// Prefixes of system topics and groups
+pub const SYSTEM_RESOURCE_PREFIXES: &[&str] = &["RMQ_SYS_", "rmq_sys_", "CID_RMQ_SYS_"];
+
+/// Topic or group name breaking the naming rules
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NameError {
+    #[error("name is empty")]
+    Empty,
+    /// Longer than the max length
+    #[error("name is longer than {0} characters")]
+    TooLong(usize),
+    #[error("name {0} contains characters other than `%|a-zA-Z0-9_-`")]
+    InvalidCharacters(String),
+}
+
+/// Retry topic of consumer `group`
+pub fn retry_topic(group: &str) -> String {
+    format!("{}{}", RETRY_GROUP_TOPIC_PREFIX, group)
+}
+
+/// Dead letter queue topic of consumer `group`
+pub fn dlq_topic(group: &str) -> String {
+    format!("{}{}", DLQ_GROUP_TOPIC_PREFIX, group)
+}
+
+pub fn is_retry_topic(topic: &str) -> bool {
+    topic.starts_with(RETRY_GROUP_TOPIC_PREFIX)
+}
+
+pub fn is_dlq_topic(topic: &str) -> bool {
+    topic.starts_with(DLQ_GROUP_TOPIC_PREFIX)
+}
+
+/// Consumer group of a retry or dead letter queue topic
+pub fn group_of(topic: &str) -> Option<&str> {
+    topic
+        .strip_prefix(RETRY_GROUP_TOPIC_PREFIX)
+        .or_else(|| topic.strip_prefix(DLQ_GROUP_TOPIC_PREFIX))
+}
+
+/// Whether message traces are sent to `topic`, of self-hosted brokers or cloud instances
+pub fn is_trace_topic(topic: &str) -> bool {
+    topic == TRACE_TOPIC || topic.starts_with(CLOUD_TRACE_TOPIC_PREFIX)
+}
+
+/// Whether `resource` is a topic or group of brokers themselves
+pub fn is_system_resource(resource: &str) -> bool {
+    SYSTEM_RESOURCES.contains(&resource)
+        || SYSTEM_RESOURCE_PREFIXES
+            .iter()
+            .any(|prefix| resource.starts_with(prefix))
+}
+
+/// Whether `topic` is a topic of brokers themselves, including trace topics of cloud
+/// instances
+pub fn is_system_topic(topic: &str) -> bool {
+    is_system_resource(topic) || is_trace_topic(topic)
+}
+
+/// Topics are made of `%|a-zA-Z0-9_-` and at most 127 characters
+pub fn validate_topic(topic: &str) -> Result<(), NameError> {
+    validate_name(topic, TOPIC_MAX_LENGTH)
+}
+
+/// Groups are made of `%|a-zA-Z0-9_-` and at most 255 characters
+pub fn validate_group(group: &str) -> Result<(), NameError> {
+    validate_name(group, GROUP_MAX_LENGTH)
+}
+
+fn validate_name(name: &str, max_length: usize) -> Result<(), NameError> {
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if name.len() > max_length {
+        return Err(NameError::TooLong(max_length));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '%' | '|' | '_' | '-'))
+    {
+        return Err(NameError::InvalidCharacters(name.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retry_and_dlq_topics() {
+        assert_eq!("%RETRY%GroupA", retry_topic("GroupA"));
+        assert_eq!("%DLQ%GroupA", dlq_topic("GroupA"));
+        assert!(is_retry_topic("%RETRY%GroupA"));
+        assert!(!is_retry_topic("%DLQ%GroupA"));
+        assert!(is_dlq_topic("%DLQ%GroupA"));
+        assert_eq!(Some("GroupA"), group_of("%RETRY%GroupA"));
+        assert_eq!(Some("GroupA"), group_of("%DLQ%GroupA"));
+        assert_eq!(None, group_of("TopicTest"));
+        assert!(is_trace_topic("RMQ_SYS_TRACE_TOPIC"));
+        assert!(is_trace_topic("TRACE_DATA_cn-hangzhou"));
+        assert!(!is_trace_topic("TopicTest"));
+    }
+
+    #[test]
+    fn test_system_resources() {
+        assert!(is_system_resource("TBW102"));
+        assert!(is_system_resource("CID_RMQ_SYS_TRANS"));
+        assert!(is_system_resource("DEFAULT_CONSUMER"));
+        assert!(!is_system_resource("GroupA"));
+        assert!(is_system_topic("SCHEDULE_TOPIC_XXXX"));
+        assert!(is_system_topic("RMQ_SYS_TRANS_HALF_TOPIC"));
+        assert!(is_system_topic("TRACE_DATA_cn-hangzhou"));
+        assert!(!is_system_topic("TopicTest"));
+    }
+
+    #[test]
+    fn test_validate_names() {
+        assert_eq!(Ok(()), validate_topic("Topic-Test_1"));
+        assert_eq!(Ok(()), validate_topic(&retry_topic("GroupA")));
+        assert_eq!(Err(NameError::Empty), validate_topic(""));
+        assert_eq!(
+            Err(NameError::InvalidCharacters("Topic Test".to_string())),
+            validate_topic("Topic Test")
+        );
+        assert_eq!(
+            Err(NameError::TooLong(TOPIC_MAX_LENGTH)),
+            validate_topic(&"t".repeat(128))
+        );
+        assert_eq!(Ok(()), validate_group(&"g".repeat(255)));
+        assert_eq!(
+            Err(NameError::TooLong(GROUP_MAX_LENGTH)),
+            validate_group(&"g".repeat(256))
+        );
+        assert!(validate_group("group.a").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! W3C trace context carried by messages in `traceparent` and `tracestate` properties
+
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId};
+use tracing::Span;
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::message::{Message, MessageExt};
+use crate::Error;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// Validated `traceparent` and optional `tracestate` of a W3C trace context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    traceparent: String,
+    tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// `traceparent` in `version-trace_id-parent_id-flags` form of lowercase hex
+    pub fn new(traceparent: &str, tracestate: Option<&str>) -> Result<Self, Error> {
+        if !is_valid_traceparent(traceparent) {
+            return Err(Error::InvalidMessage(format!(
+                "invalid traceparent {:?}",
+                traceparent
+            )));
+        }
+        Ok(Self {
+            traceparent: traceparent.to_string(),
+            tracestate: tracestate
+                .map(str::trim)
+                .filter(|state| !state.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    pub fn traceparent(&self) -> &str {
+        &self.traceparent
+    }
+
+    pub fn tracestate(&self) -> Option<&str> {
+        self.tracestate.as_deref()
+    }
+
+    pub fn trace_id(&self) -> &str {
+        &self.traceparent[3..35]
+    }
+
+    pub fn parent_id(&self) -> &str {
+        &self.traceparent[36..52]
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        u8::from_str_radix(&self.traceparent[53..55], 16).unwrap_or(0) & 0x1 == 0x1
+    }
+
+    /// Trace context of `msg`, `None` if it has none or an invalid one
+    pub fn extract(msg: &Message) -> Option<Self> {
+        let traceparent = msg.get_property(TRACEPARENT)?;
+        let tracestate = msg.get_property(TRACESTATE).map(String::as_str);
+        Self::new(traceparent, tracestate).ok()
+    }
+
+    /// Set the properties of `msg` to the context
+    pub fn inject(&self, msg: &mut Message) {
+        msg.set_property(TRACEPARENT.to_string(), self.traceparent.clone());
+        match &self.tracestate {
+            Some(state) => {
+                msg.set_property(TRACESTATE.to_string(), state.clone());
+            }
+            None => {
+                msg.remove_property(TRACESTATE);
+            }
+        }
+    }
+}
+
+fn is_valid_traceparent(traceparent: &str) -> bool {
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    let is_hex = |part: &str, len: usize| {
+        part.len() == len && part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let is_zero = |part: &str| part.bytes().all(|b| b == b'0');
+    parts.len() == 4
+        && is_hex(parts[0], 2)
+        // version ff is forbidden, later versions only append fields
+        && parts[0] == "00"
+        && is_hex(parts[1], 32)
+        && !is_zero(parts[1])
+        && is_hex(parts[2], 16)
+        && !is_zero(parts[2])
+        && is_hex(parts[3], 2)
+}
+
+/// Bridge between the trace context of messages and the tracing system of the
+/// application, e.g. OpenTelemetry through `tracing-opentelemetry`, where `inject` returns
+/// the context of `Span::current()`, the send span while messages are sent, and `extract`
+/// sets the remote parent of the consume span or adds a link to it
+pub trait TracePropagator: Send + Sync {
+    /// Context of the current trace, injected into messages sent without one
+    fn inject(&self) -> Option<TraceContext>;
+
+    /// Called with the span listeners consume messages of `context` in, e.g. to set the
+    /// remote parent of the span
+    fn extract(&self, _span: &Span, _context: &TraceContext) {}
+}
+
+/// Propagator of OpenTelemetry contexts of `tracing-opentelemetry` spans: messages are sent
+/// with the context of the current span and consume spans become children of the producer
+/// spans, or link to them when built with `linked`
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use rocketmq::{ClientOptions, OpenTelemetryPropagator, ProducerOptions};
+/// let mut client_options = ClientOptions::new("ProducerGroup");
+/// client_options.set_trace_propagator(Arc::new(OpenTelemetryPropagator::new()));
+/// let options = ProducerOptions::with_client_options(client_options);
+/// ```
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Default)]
+pub struct OpenTelemetryPropagator {
+    link: bool,
+}
+
+#[cfg(feature = "otel")]
+impl OpenTelemetryPropagator {
+    pub fn new() -> Self {
+        Self { link: false }
+    }
+
+    /// Link consume spans to the producer spans, keeping them in the trace of the consumer
+    pub fn linked() -> Self {
+        Self { link: true }
+    }
+}
+
+#[cfg(feature = "otel")]
+impl TracePropagator for OpenTelemetryPropagator {
+    fn inject(&self) -> Option<TraceContext> {
+        let context = Span::current().context();
+        let span_context = context.span().span_context().clone();
+        if !span_context.is_valid() {
+            return None;
+        }
+        let traceparent = format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        );
+        let tracestate = span_context.trace_state().header();
+        TraceContext::new(&traceparent, Some(&tracestate)).ok()
+    }
+
+    fn extract(&self, span: &Span, context: &TraceContext) {
+        let span_context = SpanContext::new(
+            TraceId::from_hex(context.trace_id()).unwrap_or(TraceId::INVALID),
+            SpanId::from_hex(context.parent_id()).unwrap_or(SpanId::INVALID),
+            if context.is_sampled() {
+                TraceFlags::SAMPLED
+            } else {
+                TraceFlags::default()
+            },
+            true,
+            context
+                .tracestate()
+                .and_then(|state| state.parse().ok())
+                .unwrap_or_default(),
+        );
+        if self.link {
+            span.add_link(span_context);
+        } else {
+            span.set_parent(opentelemetry::Context::new().with_remote_span_context(span_context));
+        }
+    }
+}
+
+/// Propagator of a client, if any
+#[derive(Clone, Default)]
+pub(crate) struct TracePropagation {
+    propagator: Option<Arc<dyn TracePropagator>>,
+}
+
+impl fmt::Debug for TracePropagation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TracePropagation")
+            .field("propagator", &self.propagator.is_some())
+            .finish()
+    }
+}
+
+impl TracePropagation {
+    pub fn new(propagator: Arc<dyn TracePropagator>) -> Self {
+        Self {
+            propagator: Some(propagator),
+        }
+    }
+
+    /// Inject the current context into `msg` unless it carries one already
+    pub fn inject(&self, msg: &mut Message) {
+        if TraceContext::extract(msg).is_some() {
+            return;
+        }
+        if let Some(context) = self.propagator.as_ref().and_then(|p| p.inject()) {
+            context.inject(msg);
+        }
+    }
+
+    /// Span to consume `msg` in, recording its trace context. With the `otel` feature it
+    /// carries the fields of the OpenTelemetry messaging semantic conventions of consumer
+    /// spans, the propagator links it to the producer span through the context
+    pub fn consume_span(&self, msg: &MessageExt) -> Span {
+        let context = TraceContext::extract(&msg.message);
+        #[cfg(feature = "otel")]
+        let span = tracing::info_span!(
+            "consume",
+            otel.name = %format_args!("{} process", msg.message.topic()),
+            otel.kind = "consumer",
+            messaging.system = "rocketmq",
+            messaging.operation = "process",
+            messaging.destination.name = %msg.message.topic(),
+            messaging.message.id = %msg.msg_id,
+            topic = %msg.message.topic(),
+            traceparent = context.as_ref().map(TraceContext::traceparent),
+            tracestate = context.as_ref().and_then(TraceContext::tracestate),
+        );
+        #[cfg(not(feature = "otel"))]
+        let span = tracing::info_span!(
+            "consume",
+            topic = %msg.message.topic(),
+            traceparent = context.as_ref().map(TraceContext::traceparent),
+            tracestate = context.as_ref().and_then(TraceContext::tracestate),
+        );
+        if let (Some(propagator), Some(context)) = (&self.propagator, &context) {
+            propagator.extract(&span, context);
+        }
+        span
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{TraceContext, TracePropagation, TracePropagator};
+    use crate::message::Message;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn new_message() -> Message {
+        Message::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            0,
+            b"hello".to_vec(),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_trace_context() {
+        let context = TraceContext::new(TRACEPARENT, Some("rojo=00f067aa0ba902b7")).unwrap();
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id());
+        assert_eq!("00f067aa0ba902b7", context.parent_id());
+        assert!(context.is_sampled());
+        assert_eq!(Some("rojo=00f067aa0ba902b7"), context.tracestate());
+
+        for invalid in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceContext::new(invalid, None).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_inject_and_extract() {
+        struct Fixed;
+
+        impl TracePropagator for Fixed {
+            fn inject(&self) -> Option<TraceContext> {
+                TraceContext::new(TRACEPARENT, None).ok()
+            }
+        }
+
+        let mut msg = new_message();
+        assert_eq!(None, TraceContext::extract(&msg));
+        TracePropagation::default().inject(&mut msg);
+        assert_eq!(None, TraceContext::extract(&msg));
+
+        let propagation = TracePropagation::new(Arc::new(Fixed));
+        propagation.inject(&mut msg);
+        assert_eq!(
+            TRACEPARENT,
+            TraceContext::extract(&msg).unwrap().traceparent()
+        );
+
+        // contexts set by users are kept
+        let other = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+        let mut msg = new_message();
+        TraceContext::new(other, Some("congo=t61rcWkgMzE"))
+            .unwrap()
+            .inject(&mut msg);
+        propagation.inject(&mut msg);
+        let context = TraceContext::extract(&msg).unwrap();
+        assert_eq!(other, context.traceparent());
+        assert!(!context.is_sampled());
+        assert_eq!(Some("congo=t61rcWkgMzE"), context.tracestate());
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_open_telemetry_propagator() {
+        use opentelemetry::trace::{SpanId, TraceId, TracerProvider};
+        use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        use super::OpenTelemetryPropagator;
+        use crate::message::MessageExt;
+        use crate::sys_flag::SysFlag;
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let mut msg = new_message();
+        TraceContext::new(TRACEPARENT, None)
+            .unwrap()
+            .inject(&mut msg);
+        let addr = "127.0.0.1:10911".parse().unwrap();
+        let msg = MessageExt {
+            message: msg,
+            queue_id: 0,
+            store_size: 0,
+            queue_offset: 0,
+            sys_flag: SysFlag::empty(),
+            born_host: addr,
+            born_timestamp: 0,
+            store_host: addr,
+            store_timestamp: 0,
+            msg_id: "7F00000100002A9F0000000000000000".to_string(),
+            commit_log_offset: 0,
+            body_crc: 0,
+            reconsume_times: 0,
+            prepared_transaction_offset: 0,
+            raw_body: None,
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            let propagation = TracePropagation::new(Arc::new(OpenTelemetryPropagator::new()));
+            let span = propagation.consume_span(&msg);
+            // messages sent while consuming carry the context of the consume span
+            let mut sent = new_message();
+            span.in_scope(|| propagation.inject(&mut sent));
+            let context = TraceContext::extract(&sent).unwrap();
+            assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id());
+            assert_ne!("00f067aa0ba902b7", context.parent_id());
+
+            let linked = TracePropagation::new(Arc::new(OpenTelemetryPropagator::linked()));
+            drop(linked.consume_span(&msg));
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(2, spans.len());
+        let linked = &spans[0];
+        assert_eq!(SpanId::INVALID, linked.parent_span_id);
+        assert_eq!(
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            linked.links.links[0].span_context.span_id()
+        );
+        let child = &spans[1];
+        assert_eq!(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            child.span_context.trace_id()
+        );
+        assert_eq!(
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            child.parent_span_id
+        );
+    }
+}